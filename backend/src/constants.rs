@@ -15,3 +15,15 @@ pub const DEFAULT_DATABASE_PORT: u16 = 49381;
 
 /// Default LDAP port (LDAPS)
 pub const DEFAULT_LDAP_PORT: u16 = 636;
+
+/// Default lifetime of an Idempotency-Key on the transfer endpoints (hours)
+pub const DEFAULT_IDEMPOTENCY_KEY_TTL_HOURS: i64 = 24;
+
+/// Seconds a pending Idempotency-Key is held for its attempt before a retry may take it over
+pub const IDEMPOTENCY_KEY_LEASE_SECONDS: i64 = 60;
+
+/// Quantity decimals for items whose stock UOM has no INUOMD.UMQtyDP (BME shows quantities as DECIMAL(22,4))
+pub const DEFAULT_QTY_DECIMALS: i64 = 4;
+
+/// UOM weight capacities of bins are kept in (stock is converted to it through INQTYCNV)
+pub const CAPACITY_WEIGHT_UOM: &str = "KG";
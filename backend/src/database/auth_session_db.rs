@@ -0,0 +1,292 @@
+use crate::database::Database;
+use crate::types::User;
+use crate::utils::auth::AuthSource;
+use crate::utils::bangkok_now;
+use anyhow::{Context, Result};
+use chrono::{Duration, NaiveDateTime};
+use std::future::Future;
+
+/// A stored refresh token (dbo.putaway_refresh_tokens)
+pub struct RefreshTokenRecord {
+    pub session_id: String,
+    pub user: User,
+    /// Directory of the login; None for sessions stored before it was recorded
+    pub source: Option<AuthSource>,
+    pub expires_at: NaiveDateTime,
+    /// Already exchanged - presenting it again means the token was copied
+    pub replaced: bool,
+    pub revoked: bool,
+}
+
+/// Persistence for login sessions and their rotating refresh tokens
+pub trait SessionRepository: Clone + Send + Sync + 'static {
+    /// Store the first refresh token of a new login session
+    fn create_session(
+        &self,
+        session_id: &str,
+        token_hash: &str,
+        user: &User,
+        source: AuthSource,
+        lifetime: Duration,
+    ) -> impl Future<Output = Result<NaiveDateTime>> + Send;
+
+    /// Look up a refresh token by its hash
+    fn find_refresh_token(&self, token_hash: &str) -> impl Future<Output = Result<Option<RefreshTokenRecord>>> + Send;
+
+    /// Exchange a refresh token for its successor, stored with the user's current roles.
+    /// Returns None (and writes nothing) if the old token was exchanged or revoked in the meantime.
+    fn rotate(
+        &self,
+        old_token_hash: &str,
+        new_token_hash: &str,
+        session_id: &str,
+        user: &User,
+        source: AuthSource,
+        lifetime: Duration,
+    ) -> impl Future<Output = Result<Option<NaiveDateTime>>> + Send;
+
+    /// Revoke every refresh token of a session
+    fn revoke_session(&self, session_id: &str) -> impl Future<Output = Result<()>> + Send;
+
+    /// Revoke all live sessions of a user, returning their IDs
+    fn revoke_user_sessions(&self, user_id: &str) -> impl Future<Output = Result<Vec<String>>> + Send;
+
+    /// Sessions revoked recently enough that some of their access tokens may still be unexpired,
+    /// with when they were revoked
+    fn recently_revoked_sessions(
+        &self,
+        token_lifetime: Duration,
+    ) -> impl Future<Output = Result<Vec<(String, NaiveDateTime)>>> + Send;
+}
+
+/// Sessions in dbo.putaway_refresh_tokens
+#[derive(Clone)]
+pub struct AuthSessionDatabase {
+    db: Database,
+}
+
+impl AuthSessionDatabase {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+impl SessionRepository for AuthSessionDatabase {
+    async fn create_session(
+        &self,
+        session_id: &str,
+        token_hash: &str,
+        user: &User,
+        source: AuthSource,
+        lifetime: Duration,
+    ) -> Result<NaiveDateTime> {
+        let mut client = self.db.get_client().await?;
+        let now = bangkok_now().naive_local();
+        let expires_at = now + lifetime;
+
+        insert_token(&mut client, session_id, token_hash, user, source, &now, &expires_at).await?;
+
+        Ok(expires_at)
+    }
+
+    async fn find_refresh_token(&self, token_hash: &str) -> Result<Option<RefreshTokenRecord>> {
+        let mut client = self.db.get_client().await?;
+
+        let query = r#"
+            SELECT session_id, user_id, username, email, display_name, roles, auth_source, expires_at,
+                   CASE WHEN replaced_by IS NULL THEN 0 ELSE 1 END AS replaced,
+                   CASE WHEN revoked_at IS NULL THEN 0 ELSE 1 END AS revoked
+            FROM dbo.putaway_refresh_tokens
+            WHERE token_hash = @P1
+        "#;
+
+        let row = client
+            .query(query, &[&token_hash])
+            .await
+            .context("Failed to look up refresh token")?
+            .into_row()
+            .await
+            .context("Failed to read refresh token")?;
+
+        Ok(row.map(|row| {
+            let roles = row.get::<&str, _>("roles").unwrap_or("");
+            RefreshTokenRecord {
+                session_id: row.get::<&str, _>("session_id").unwrap_or("").to_string(),
+                user: User {
+                    user_id: row.get::<&str, _>("user_id").unwrap_or("").to_string(),
+                    username: row.get::<&str, _>("username").unwrap_or("").to_string(),
+                    email: row.get::<&str, _>("email").unwrap_or("").to_string(),
+                    display_name: row.get::<&str, _>("display_name").unwrap_or("").to_string(),
+                    is_active: true,
+                    roles: roles
+                        .split(',')
+                        .filter(|role| !role.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                },
+                source: row.get::<&str, _>("auth_source").and_then(AuthSource::from_name),
+                expires_at: row.get::<NaiveDateTime, _>("expires_at").unwrap_or_default(),
+                replaced: row.get::<i32, _>("replaced").unwrap_or(0) == 1,
+                revoked: row.get::<i32, _>("revoked").unwrap_or(0) == 1,
+            }
+        }))
+    }
+
+    async fn rotate(
+        &self,
+        old_token_hash: &str,
+        new_token_hash: &str,
+        session_id: &str,
+        user: &User,
+        source: AuthSource,
+        lifetime: Duration,
+    ) -> Result<Option<NaiveDateTime>> {
+        let mut client = self.db.get_client().await?;
+        let now = bangkok_now().naive_local();
+        let expires_at = now + lifetime;
+
+        client.simple_query("BEGIN TRANSACTION").await.context("Failed to begin transaction")?;
+
+        let result: Result<bool> = async {
+            let updated = client
+                .execute(
+                    r#"
+                    UPDATE dbo.putaway_refresh_tokens SET replaced_by = @P2
+                    WHERE token_hash = @P1 AND replaced_by IS NULL AND revoked_at IS NULL
+                    "#,
+                    &[&old_token_hash, &new_token_hash],
+                )
+                .await
+                .context("Failed to retire refresh token")?
+                .total();
+
+            if updated == 0 {
+                return Ok(false);
+            }
+
+            insert_token(&mut client, session_id, new_token_hash, user, source, &now, &expires_at).await?;
+            Ok(true)
+        }
+        .await;
+
+        match result {
+            Ok(true) => {
+                client.simple_query("COMMIT").await.context("Failed to commit refresh token")?;
+                Ok(Some(expires_at))
+            }
+            Ok(false) => {
+                let _ = client.simple_query("ROLLBACK").await;
+                Ok(None)
+            }
+            Err(e) => {
+                let _ = client.simple_query("ROLLBACK").await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn revoke_session(&self, session_id: &str) -> Result<()> {
+        let mut client = self.db.get_client().await?;
+        let now = bangkok_now().naive_local();
+
+        client
+            .execute(
+                "UPDATE dbo.putaway_refresh_tokens SET revoked_at = @P2 WHERE session_id = @P1 AND revoked_at IS NULL",
+                &[&session_id, &now],
+            )
+            .await
+            .context("Failed to revoke session")?;
+
+        Ok(())
+    }
+
+    async fn revoke_user_sessions(&self, user_id: &str) -> Result<Vec<String>> {
+        let mut client = self.db.get_client().await?;
+        let now = bangkok_now().naive_local();
+
+        let rows = client
+            .query(
+                r#"
+                UPDATE dbo.putaway_refresh_tokens SET revoked_at = @P2
+                OUTPUT INSERTED.session_id
+                WHERE user_id = @P1 AND revoked_at IS NULL
+                "#,
+                &[&user_id, &now],
+            )
+            .await
+            .context("Failed to revoke user sessions")?
+            .into_first_result()
+            .await
+            .context("Failed to read revoked sessions")?;
+
+        let mut session_ids: Vec<String> = rows
+            .iter()
+            .filter_map(|row| row.get::<&str, _>("session_id").map(str::to_string))
+            .collect();
+        session_ids.sort();
+        session_ids.dedup();
+        Ok(session_ids)
+    }
+
+    async fn recently_revoked_sessions(&self, token_lifetime: Duration) -> Result<Vec<(String, NaiveDateTime)>> {
+        let mut client = self.db.get_client().await?;
+        let since = bangkok_now().naive_local() - token_lifetime;
+
+        let rows = client
+            .query(
+                "SELECT session_id, MAX(revoked_at) AS revoked_at FROM dbo.putaway_refresh_tokens WHERE revoked_at >= @P1 GROUP BY session_id",
+                &[&since],
+            )
+            .await
+            .context("Failed to load revoked sessions")?
+            .into_first_result()
+            .await
+            .context("Failed to read revoked sessions")?;
+
+        Ok(rows
+            .iter()
+            .filter_map(|row| {
+                let session_id = row.get::<&str, _>("session_id")?;
+                Some((session_id.to_string(), row.get::<NaiveDateTime, _>("revoked_at")?))
+            })
+            .collect())
+    }
+}
+
+async fn insert_token(
+    client: &mut tiberius::Client<tokio_util::compat::Compat<tokio::net::TcpStream>>,
+    session_id: &str,
+    token_hash: &str,
+    user: &User,
+    source: AuthSource,
+    issued_at: &NaiveDateTime,
+    expires_at: &NaiveDateTime,
+) -> Result<()> {
+    let roles = user.roles.join(",");
+
+    client
+        .execute(
+            r#"
+            INSERT INTO dbo.putaway_refresh_tokens (
+                token_hash, session_id, user_id, username, email, display_name, roles, auth_source,
+                issued_at, expires_at, replaced_by, revoked_at
+            ) VALUES (@P1, @P2, @P3, @P4, @P5, @P6, @P7, @P8, @P9, @P10, NULL, NULL)
+            "#,
+            &[
+                &token_hash,
+                &session_id,
+                &user.user_id.as_str(),
+                &user.username.as_str(),
+                &user.email.as_str(),
+                &user.display_name.as_str(),
+                &roles.as_str(),
+                &source.name(),
+                issued_at,
+                expires_at,
+            ],
+        )
+        .await
+        .context("Failed to store refresh token")?;
+
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use crate::database::putaway_db::SqlConnection;
+use crate::database::Database;
+use crate::models::putaway_models::PutawayError;
+use crate::utils::bangkok_now;
+use chrono::Duration;
+
+/// Outcome of claiming an Idempotency-Key
+pub enum IdempotencyReservation {
+    /// First time the key is seen (or its lease ran out) - the caller owns it as `attempt_id`.
+    /// The transfer's transaction completes it; a failed attempt releases it.
+    New { attempt_id: String },
+    /// The request already succeeded - replay the stored response
+    Completed(String),
+    /// The key's transfer wrote this BT document but left no response to replay
+    Applied(String),
+    /// Another attempt with this key is still running
+    InProgress,
+    /// The key was used with a different request body
+    Mismatch,
+}
+
+/// A key claimed by one attempt. Only the attempt holding it may complete or release the key,
+/// so an attempt whose lease was taken over by a retry cannot commit a second move.
+#[derive(Debug, Clone)]
+pub struct IdempotencyClaim {
+    pub scope: &'static str,
+    pub user_id: String,
+    pub key: String,
+    pub attempt_id: String,
+}
+
+/// Persistence for Idempotency-Key headers (dbo.putaway_idempotency_keys)
+pub struct IdempotencyDatabase {
+    db: Database,
+}
+
+impl IdempotencyDatabase {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// Claim a user's key for a request, or report what an earlier attempt with the same key left
+    /// behind. Expired keys are purged first so they can be reused. A pending claim whose lease has
+    /// run out (its attempt died with the process, or is still waiting on locks) is taken over by the
+    /// same request under a new attempt id, unless a BT document was already written for it.
+    pub async fn reserve(
+        &self,
+        scope: &str,
+        user_id: &str,
+        key: &str,
+        request_hash: &str,
+        ttl_hours: i64,
+        lease_seconds: i64,
+    ) -> Result<IdempotencyReservation, PutawayError> {
+        let mut client = self
+            .db
+            .get_client()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        let now = bangkok_now().naive_local();
+        let expires_at = now + Duration::hours(ttl_hours);
+        let lease_expires_at = now + Duration::seconds(lease_seconds);
+        let attempt_id = uuid::Uuid::new_v4().to_string();
+
+        client
+            .execute("DELETE FROM dbo.putaway_idempotency_keys WHERE expires_at < @P1", &[&now])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to purge idempotency keys: {e}")))?;
+
+        // Single statement so two concurrent retries cannot both claim the key
+        let insert_query = r#"
+            INSERT INTO dbo.putaway_idempotency_keys (
+                scope, user_id, idempotency_key, request_hash, status, response_body, created_at, expires_at,
+                lease_expires_at, attempt_id, document_no
+            )
+            SELECT @P1, @P2, @P3, @P4, 'pending', NULL, @P5, @P6, @P7, @P8, NULL
+            WHERE NOT EXISTS (
+                SELECT 1 FROM dbo.putaway_idempotency_keys WITH (UPDLOCK, HOLDLOCK)
+                WHERE scope = @P1 AND user_id = @P2 AND idempotency_key = @P3
+            )
+        "#;
+
+        let inserted = client
+            .execute(
+                insert_query,
+                &[&scope, &user_id, &key, &request_hash, &now, &expires_at, &lease_expires_at, &attempt_id.as_str()],
+            )
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to reserve idempotency key: {e}")))?
+            .total();
+
+        if inserted > 0 {
+            return Ok(IdempotencyReservation::New { attempt_id });
+        }
+
+        let take_over_query = r#"
+            UPDATE dbo.putaway_idempotency_keys SET lease_expires_at = @P5, attempt_id = @P7
+            WHERE scope = @P1 AND user_id = @P2 AND idempotency_key = @P3 AND request_hash = @P4
+              AND status = 'pending' AND document_no IS NULL AND lease_expires_at < @P6
+        "#;
+
+        let taken_over = client
+            .execute(
+                take_over_query,
+                &[&scope, &user_id, &key, &request_hash, &lease_expires_at, &now, &attempt_id.as_str()],
+            )
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to take over idempotency key: {e}")))?
+            .total();
+
+        if taken_over > 0 {
+            tracing::warn!(scope, idempotency_key = key, user = user_id, "Took over an idempotency key whose lease ran out");
+            return Ok(IdempotencyReservation::New { attempt_id });
+        }
+
+        let row = client
+            .query(
+                "SELECT request_hash, status, response_body, document_no FROM dbo.putaway_idempotency_keys WHERE scope = @P1 AND user_id = @P2 AND idempotency_key = @P3",
+                &[&scope, &user_id, &key],
+            )
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .into_row()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        // Released between our INSERT and SELECT - treat as still running, the client retries
+        let Some(row) = row else {
+            return Ok(IdempotencyReservation::InProgress);
+        };
+
+        if row.get::<&str, _>("request_hash").unwrap_or("") != request_hash {
+            return Ok(IdempotencyReservation::Mismatch);
+        }
+
+        match (row.get::<&str, _>("status"), row.get::<&str, _>("response_body"), row.get::<&str, _>("document_no")) {
+            (Some("completed"), Some(body), _) => Ok(IdempotencyReservation::Completed(body.to_string())),
+            (_, _, Some(document_no)) => Ok(IdempotencyReservation::Applied(document_no.to_string())),
+            _ => Ok(IdempotencyReservation::InProgress),
+        }
+    }
+
+    /// Record the transfer's BT document and successful response for replay, on the transfer's own
+    /// transaction so the key is completed exactly when the move commits. Without a response the key
+    /// stays pending with its document, and is never taken over. Fails (and so rolls the move back)
+    /// when the claim is no longer this attempt's.
+    pub async fn complete(
+        conn: &mut SqlConnection,
+        claim: &IdempotencyClaim,
+        document_no: &str,
+        response_body: Option<&str>,
+    ) -> Result<(), PutawayError> {
+        let query = r#"
+            UPDATE dbo.putaway_idempotency_keys
+            SET status = CASE WHEN @P5 IS NULL THEN 'pending' ELSE 'completed' END, response_body = @P5, document_no = @P6
+            WHERE scope = @P1 AND user_id = @P2 AND idempotency_key = @P3 AND attempt_id = @P4 AND status = 'pending'
+        "#;
+
+        let updated = conn
+            .execute(
+                query,
+                &[&claim.scope, &claim.user_id, &claim.key, &claim.attempt_id, &response_body, &document_no],
+            )
+            .await
+            .map_err(|e| PutawayError::TransactionError(format!("Failed to store idempotent response: {e}")))?
+            .total();
+
+        if updated == 0 {
+            return Err(PutawayError::RequestInProgress { key: claim.key.clone() });
+        }
+
+        Ok(())
+    }
+
+    /// Drop a claim whose request did not move any stock, so the key can be retried
+    pub async fn release(&self, claim: &IdempotencyClaim) -> Result<(), PutawayError> {
+        let mut client = self
+            .db
+            .get_client()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        client
+            .execute(
+                "DELETE FROM dbo.putaway_idempotency_keys WHERE scope = @P1 AND user_id = @P2 AND idempotency_key = @P3 AND attempt_id = @P4 AND status = 'pending'",
+                &[&claim.scope, &claim.user_id, &claim.key, &claim.attempt_id],
+            )
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to release idempotency key: {e}")))?;
+
+        Ok(())
+    }
+}
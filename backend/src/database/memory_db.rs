@@ -0,0 +1,1588 @@
+use crate::database::auth_session_db::{RefreshTokenRecord, SessionRepository};
+use crate::database::idempotency_db::{IdempotencyClaim, IdempotencyReservation};
+use crate::database::repository::{
+    BinCandidateExclusions, BinCandidateRecord, BinTransferLink, BtDocumentLine, BtDocumentRecords, BtLotTransaction, LotBinRecord, LotKey, PendingPutawayFilter, PutawayRepository, TransferHistoryFilter,
+    TransferHistoryLine, TransferHistorySort,
+};
+use crate::models::putaway_models::{
+    BinBlockEvent, BinBlockRecord, BinCapacityRecord, BinMasterRecord, BinSearchItem, BinTransferRecord, BinTransferRequest, InlocRecord, ItemMasterRecord, LotMasterRecord,
+    LotSearchItem, LotSnapshotRecord, LotTransactionItem, LotTransactionRecord, MintxdhRecord, MovedCommitmentLine,
+    PutawayError, UomConversionRecord,
+};
+use crate::types::User;
+use crate::utils::auth::AuthSource;
+use crate::utils::bangkok_now;
+use bigdecimal::{BigDecimal, Signed};
+use chrono::{Duration, NaiveDateTime};
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+/// Transaction types BME counts as pending issues (commitment)
+const COMMITMENT_TRANSACTION_TYPES: [u8; 11] = [2, 3, 5, 7, 9, 10, 12, 16, 17, 20, 21];
+
+/// The BME tables the putaway service touches, held in memory
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTables {
+    /// Seqnum 'BT'
+    pub bt_sequence: i32,
+    /// DistributionParameter.Freeze_Inventory
+    pub freeze_inventory: bool,
+    /// INMAST
+    pub items: Vec<ItemMasterRecord>,
+    pub inloc: Vec<InlocRecord>,
+    /// INUOMD: (UOMKey, UMQtyDP)
+    pub uom_decimals: Vec<(String, i64)>,
+    /// INQTYCNV
+    pub uom_conversions: Vec<UomConversionRecord>,
+    /// (ItemKey, Location) pairs with INLOC.Physinprogress set
+    pub physical_counts: Vec<(String, String)>,
+    pub bins: Vec<BinMasterRecord>,
+    /// putaway_bin_capacity
+    pub bin_capacities: Vec<BinCapacityRecord>,
+    /// putaway_bin_blocks
+    pub bin_blocks: Vec<BinBlockRecord>,
+    /// putaway_bin_block_history, oldest first
+    pub bin_block_history: Vec<BinBlockEvent>,
+    pub lot_master: Vec<LotMasterRecord>,
+    pub lot_transactions: Vec<LotTransactionRecord>,
+    pub qc_lot_transactions: Vec<LotTransactionRecord>,
+    pub bin_transfers: Vec<BinTransferRecord>,
+    pub mintxdh: Vec<MintxdhRecord>,
+    /// putaway_bt_reversals: (original, reversal, reason)
+    pub bt_reversals: Vec<(String, String, String)>,
+    /// putaway_commitment_moves: (BT document, moved line)
+    pub commitment_moves: Vec<(String, MovedCommitmentLine)>,
+    /// putaway_bt_lot_snapshots
+    pub lot_snapshots: Vec<LotSnapshotRecord>,
+    /// putawaylist: (id, remark_name) of the active remarks
+    pub remarks: Vec<(i32, String)>,
+    pub idempotency_keys: Vec<MemoryIdempotencyKey>,
+}
+
+/// putaway_idempotency_keys row
+#[derive(Debug, Clone)]
+pub struct MemoryIdempotencyKey {
+    pub scope: String,
+    pub user_id: String,
+    pub key: String,
+    pub request_hash: String,
+    pub status: String,
+    pub response_body: Option<String>,
+    pub expires_at: NaiveDateTime,
+    pub lease_expires_at: NaiveDateTime,
+    pub attempt_id: String,
+    /// BT document the key's transfer wrote
+    pub document_no: Option<String>,
+}
+
+impl MemoryTables {
+    /// INMAST item with its INLOC row (inventory class RM) in a location
+    pub fn add_item(&mut self, item_key: &str, description: &str, uom: &str, location: &str) {
+        self.items.push(ItemMasterRecord {
+            item_key: item_key.to_string(),
+            desc1: description.to_string(),
+            desc2: String::new(),
+            stock_uom_code: uom.to_string(),
+            purchase_uom_code: uom.to_string(),
+            sales_uom_code: uom.to_string(),
+        });
+        self.inloc.push(InlocRecord {
+            item_key: item_key.to_string(),
+            location: location.to_string(),
+            inclasskey: "RM".to_string(),
+            revacct: String::new(),
+            cogsacct: String::new(),
+            stdcost: 0.0,
+        });
+    }
+
+    /// INQTYCNV row for every item (UMScope 0): `qty in to_uom = qty in from_uom * conv_factor`
+    pub fn add_uom_conversion(&mut self, from_uom: &str, to_uom: &str, conv_factor: BigDecimal) {
+        self.uom_conversions.push(UomConversionRecord {
+            scope: 0,
+            item_class_key: String::new(),
+            item_key: String::new(),
+            from_uom: from_uom.to_string(),
+            to_uom: to_uom.to_string(),
+            conv_factor,
+            operation: "*".to_string(),
+        });
+    }
+
+    pub fn add_bin(&mut self, location: &str, bin_no: &str) {
+        self.bins.push(BinMasterRecord {
+            location: location.to_string(),
+            bin_no: bin_no.to_string(),
+            description: format!("Bin {bin_no}"),
+            aisle: String::new(),
+            row: String::new(),
+            rack: String::new(),
+            rec_date: None,
+        });
+    }
+
+    /// LotMaster row as received: QtyReceived = QtyOnHand, nothing issued or committed
+    pub fn add_lot(&mut self, lot_no: &str, item_key: &str, location: &str, bin_no: &str, qty_on_hand: BigDecimal) {
+        self.lot_master.push(LotMasterRecord {
+            lot_no: lot_no.to_string(),
+            item_key: item_key.to_string(),
+            location_key: location.to_string(),
+            bin_no: bin_no.to_string(),
+            date_received: None,
+            date_expiry: None,
+            qty_received: qty_on_hand.clone(),
+            qty_on_hand,
+            qty_issued: BigDecimal::default(),
+            qty_commit_sales: BigDecimal::default(),
+            vendor_key: String::new(),
+            vendor_lot_no: String::new(),
+            document_no: String::new(),
+            document_line_no: 1,
+            transaction_type: 1,
+            lot_status: Some("P".to_string()),
+            rec_user_id: String::new(),
+            rec_date: None,
+        });
+    }
+
+    pub fn lot(&self, lot: LotKey<'_>, bin_no: &str) -> Option<&LotMasterRecord> {
+        self.lot_master.iter().find(|row| is_lot_row(row, lot, bin_no))
+    }
+
+    fn lot_mut(&mut self, lot: LotKey<'_>, bin_no: &str) -> Option<&mut LotMasterRecord> {
+        self.lot_master.iter_mut().find(|row| is_lot_row(row, lot, bin_no))
+    }
+
+    fn transaction_table(&mut self, table: &str) -> &mut Vec<LotTransactionRecord> {
+        match table {
+            "QCLotTransaction" => &mut self.qc_lot_transactions,
+            _ => &mut self.lot_transactions,
+        }
+    }
+}
+
+/// In-memory `PutawayRepository` with the same row semantics as `PutawayDatabase`, for tests.
+///
+/// `begin` holds the tables until `commit`/`rollback` and keeps a snapshot to roll back to,
+/// so a transfer is all or nothing here too. Lookups that take no connection wait for it.
+#[derive(Clone, Default)]
+pub struct MemoryPutawayDatabase {
+    state: Arc<Mutex<MemoryTables>>,
+}
+
+pub enum MemoryConn {
+    Direct(Arc<Mutex<MemoryTables>>),
+    Transaction {
+        tables: OwnedMutexGuard<MemoryTables>,
+        /// Tables as they were at `begin`; taken when the transaction finishes
+        snapshot: Option<Box<MemoryTables>>,
+        state: Arc<Mutex<MemoryTables>>,
+    },
+}
+
+/// Like `SqlConnection`: a transaction dropped before commit or rollback is rolled back
+impl Drop for MemoryConn {
+    fn drop(&mut self) {
+        if let MemoryConn::Transaction { tables, snapshot: Some(snapshot), .. } = self {
+            **tables = std::mem::take(&mut **snapshot);
+        }
+    }
+}
+
+impl MemoryConn {
+    async fn with<T>(&mut self, f: impl FnOnce(&mut MemoryTables) -> T) -> T {
+        match self {
+            MemoryConn::Direct(state) => f(&mut *state.lock().await),
+            MemoryConn::Transaction { tables, .. } => f(tables),
+        }
+    }
+
+    /// Leave the transaction, restoring the snapshot on rollback
+    fn finish(&mut self, keep: bool) {
+        let state = match self {
+            MemoryConn::Direct(_) => return,
+            MemoryConn::Transaction { tables, snapshot, state } => {
+                if let Some(snapshot) = snapshot.take().filter(|_| !keep) {
+                    **tables = *snapshot;
+                }
+                state.clone()
+            }
+        };
+        *self = MemoryConn::Direct(state);
+    }
+}
+
+impl MemoryPutawayDatabase {
+    pub fn new(tables: MemoryTables) -> Self {
+        Self { state: Arc::new(Mutex::new(tables)) }
+    }
+
+    /// Copy of the current tables, for assertions
+    pub async fn tables(&self) -> MemoryTables {
+        self.state.lock().await.clone()
+    }
+}
+
+/// Exact quantity literal for fixtures and assertions
+pub fn qty(value: &str) -> BigDecimal {
+    BigDecimal::from_str(value).expect("quantity literal")
+}
+
+/// Lot the shared fixtures receive: Batter Mix 2600107-1 in TFC1
+pub const LOT: LotKey<'static> = LotKey { lot_no: "2600107-1", item_key: "INBC1403", location: "TFC1" };
+
+/// Warehouse most service and handler tests start from: Seqnum BT at 26112173, INBC1403 Batter Mix
+/// (KG) in TFC1, bins K0802-4B, K0803-1A and WHKON1, and 1000 of `LOT` received into `lot_bin`
+pub fn warehouse(lot_bin: &str) -> MemoryTables {
+    let mut tables = MemoryTables { bt_sequence: 26112173, ..Default::default() };
+    tables.add_item(LOT.item_key, "Batter Mix", "KG", LOT.location);
+    for bin_no in ["K0802-4B", "K0803-1A", "WHKON1"] {
+        tables.add_bin(LOT.location, bin_no);
+    }
+    tables.add_lot(LOT.lot_no, LOT.item_key, LOT.location, lot_bin, qty("1000"));
+    tables
+}
+
+/// Putaway-screen transfer of `transfer_qty` of a lot, as deachawat
+pub fn transfer(lot: LotKey<'_>, bin_from: &str, bin_to: &str, transfer_qty: &str) -> BinTransferRequest {
+    BinTransferRequest {
+        lot_no: lot.lot_no.to_string(),
+        item_key: lot.item_key.to_string(),
+        location: lot.location.to_string(),
+        bin_from: bin_from.to_string(),
+        bin_to: bin_to.to_string(),
+        transfer_qty: qty(transfer_qty),
+        uom: None,
+        user_id: "deachawat".to_string(),
+        remarks: Some("Putaway".to_string()),
+        referenced: None,
+        override_capacity: false,
+    }
+}
+
+/// Pending sales issue (type 5, Processed N) committing `qty_issued` of a lot in a bin
+pub fn pending_issue(lot_tran_no: i32, lot: LotKey<'_>, bin_no: &str, qty_issued: &str) -> LotTransactionRecord {
+    LotTransactionRecord {
+        lot_tran_no: Some(lot_tran_no),
+        lot_no: lot.lot_no.to_string(),
+        item_key: lot.item_key.to_string(),
+        location_key: lot.location.to_string(),
+        date_received: bangkok_now().naive_local(),
+        date_expiry: None,
+        transaction_type: 5,
+        issue_doc_no: Some("BP-0001".to_string()),
+        issue_doc_line_no: Some(1),
+        issue_date: None,
+        qty_issued: Some(qty(qty_issued)),
+        receipt_doc_no: None,
+        receipt_doc_line_no: None,
+        qty_received: None,
+        vendor_key: None,
+        vendor_lot_no: String::new(),
+        customer_key: None,
+        bin_no: bin_no.to_string(),
+        rec_userid: "picker".to_string(),
+        rec_date: bangkok_now().naive_local(),
+        processed: "N".to_string(),
+        date_quarantine: None,
+        temp_qty: None,
+        qty_for_lot_assignment: None,
+        qty_used: None,
+    }
+}
+
+fn is_lot_row(row: &LotMasterRecord, lot: LotKey<'_>, bin_no: &str) -> bool {
+    row.lot_no == lot.lot_no && row.item_key == lot.item_key && row.location_key == lot.location && row.bin_no == bin_no
+}
+
+fn bin_transfer_link(row: &BinTransferRecord) -> BinTransferLink {
+    BinTransferLink {
+        lot_no: row.lot_no.clone(),
+        item_key: row.item_key.clone(),
+        location: row.location.clone(),
+        bin_no_from: row.bin_no_from.clone(),
+        bin_no_to: row.bin_no_to.clone(),
+        rec_date: row.rec_date,
+        lot_tran_no: row.lot_tran_no,
+        in_trans_id: row.in_trans_id,
+    }
+}
+
+/// Pending issue line (Processed N/P with a commitment transaction type)
+fn is_pending_issue(row: &LotTransactionRecord) -> bool {
+    matches!(row.processed.as_str(), "N" | "P") && COMMITMENT_TRANSACTION_TYPES.contains(&row.transaction_type)
+}
+
+/// Pending issue counted in its bin's commitment, like `BIN_COMMITMENT_APPLY`: BT document issues are not
+fn is_commitment_issue(row: &LotTransactionRecord) -> bool {
+    is_pending_issue(row)
+        && !(row.transaction_type == 9 && row.issue_doc_no.as_deref().is_some_and(|doc| doc.starts_with("BT-")))
+}
+
+/// The lot's row in a bin, joined on the lot keys and BinNo only like `BIN_LOT_CONTEXT_JOIN`
+fn lot_in_bin<'t>(t: &'t MemoryTables, lot: LotKey<'_>, bin_no: &str) -> Option<&'t LotMasterRecord> {
+    t.lot_master.iter().find(|row| {
+        row.lot_no == lot.lot_no && row.item_key == lot.item_key && row.location_key == lot.location && row.bin_no == bin_no
+    })
+}
+
+/// LotMaster row joined with its item and the bin's pending issues, like `LOT_BIN_SELECT`
+fn lot_bin_record(t: &MemoryTables, row: &LotMasterRecord) -> Option<LotBinRecord> {
+    let item = t.items.iter().find(|item| item.item_key == row.item_key)?;
+    let committed = t
+        .lot_transactions
+        .iter()
+        .chain(t.qc_lot_transactions.iter())
+        .filter(|tran| {
+            is_commitment_issue(tran)
+                && tran.item_key == row.item_key
+                && tran.location_key == row.location_key
+                && tran.lot_no == row.lot_no
+                && tran.bin_no == row.bin_no
+        })
+        .filter_map(|tran| tran.qty_issued.as_ref())
+        .sum();
+    Some(LotBinRecord { lot: row.clone(), item: item.clone(), committed })
+}
+
+/// SQL `LIKE '%term%'` under the default case-insensitive collation
+fn like(value: &str, term: &str) -> bool {
+    value.to_lowercase().contains(&term.to_lowercase())
+}
+
+fn transaction_type_name(transaction_type: u8) -> &'static str {
+    match transaction_type {
+        1 => "Purchase Receipt",
+        2 => "Purchase Return",
+        3 => "Sales Issue",
+        4 => "Sales Return",
+        5 => "Mfg. Issue",
+        6 => "Mfg. Return",
+        7 => "Inventory Transfer",
+        8 => "Inventory Adj. Positive",
+        9 => "Inventory Adj. Negative",
+        10 => "Damaged",
+        11 => "Warehouse Move In",
+        12 => "Warehouse Move Out",
+        14 => "Physical Count",
+        15 => "Transfer In",
+        16 => "Transfer Out",
+        17 => "Move",
+        18 => "Mfg. Receipt",
+        21 => "Sales Provisional",
+        _ => "Unknown",
+    }
+}
+
+/// OFFSET/FETCH over an already ordered result
+fn page_of<T>(rows: Vec<T>, page: i32, limit: i32) -> Vec<T> {
+    let offset = ((page - 1) * limit).max(0) as usize;
+    rows.into_iter().skip(offset).take(limit.max(0) as usize).collect()
+}
+
+impl PutawayRepository for MemoryPutawayDatabase {
+    type Conn = MemoryConn;
+
+    async fn connect(&self) -> Result<MemoryConn, PutawayError> {
+        Ok(MemoryConn::Direct(self.state.clone()))
+    }
+
+    async fn begin(&self) -> Result<MemoryConn, PutawayError> {
+        let tables = self.state.clone().lock_owned().await;
+        let snapshot = Some(Box::new(tables.clone()));
+        Ok(MemoryConn::Transaction { tables, snapshot, state: self.state.clone() })
+    }
+
+    async fn commit(&self, conn: &mut MemoryConn) -> Result<(), PutawayError> {
+        conn.finish(true);
+        Ok(())
+    }
+
+    async fn rollback(&self, conn: &mut MemoryConn) {
+        conn.finish(false);
+    }
+
+    async fn next_bt_sequence(&self, conn: &mut MemoryConn) -> Result<i32, PutawayError> {
+        let bt_number = conn
+            .with(|t| {
+                t.bt_sequence += 1;
+                t.bt_sequence
+            })
+            .await;
+        // Suspend here like a round trip to SQL Server would, so tests can drop a transfer holding its BT number
+        tokio::task::yield_now().await;
+        Ok(bt_number)
+    }
+
+    async fn current_bt_sequence(&self) -> Result<i32, PutawayError> {
+        Ok(self.state.lock().await.bt_sequence)
+    }
+
+    async fn inventory_frozen(&self, conn: &mut MemoryConn) -> Result<bool, PutawayError> {
+        Ok(conn.with(|t| t.freeze_inventory).await)
+    }
+
+    async fn physical_count_in_progress(
+        &self,
+        conn: &mut MemoryConn,
+        item_key: &str,
+        location: &str,
+    ) -> Result<bool, PutawayError> {
+        Ok(conn
+            .with(|t| t.physical_counts.iter().any(|(item, loc)| item == item_key && loc == location))
+            .await)
+    }
+
+    async fn inloc_record(&self, conn: &mut MemoryConn, item_key: &str, location: &str) -> Result<InlocRecord, PutawayError> {
+        conn.with(|t| {
+            t.inloc
+                .iter()
+                .find(|row| row.item_key == item_key && row.location == location)
+                .cloned()
+                .ok_or_else(|| {
+                    PutawayError::DatabaseError(format!(
+                        "INLOC record not found for item {item_key} in location {location}"
+                    ))
+                })
+        })
+        .await
+    }
+
+    async fn item_qty_decimals(&self, conn: &mut MemoryConn, item_key: &str) -> Result<Option<i64>, PutawayError> {
+        Ok(conn
+            .with(|t| {
+                let item = t.items.iter().find(|item| item.item_key == item_key)?;
+                t.uom_decimals
+                    .iter()
+                    .filter(|(uom, _)| *uom == item.stock_uom_code)
+                    .map(|(_, decimals)| *decimals)
+                    .max()
+            })
+            .await)
+    }
+
+    async fn item_master(&self, conn: &mut MemoryConn, item_key: &str) -> Result<Option<ItemMasterRecord>, PutawayError> {
+        Ok(conn.with(|t| t.items.iter().find(|item| item.item_key == item_key).cloned()).await)
+    }
+
+    async fn uom_conversion(
+        &self,
+        conn: &mut MemoryConn,
+        item_key: &str,
+        from_uom: &str,
+        to_uom: &str,
+    ) -> Result<Option<UomConversionRecord>, PutawayError> {
+        Ok(conn
+            .with(|t| {
+                t.uom_conversions
+                    .iter()
+                    .filter(|row| row.from_uom == from_uom && row.to_uom == to_uom)
+                    .filter(|row| {
+                        row.scope == 0
+                            || row.item_key == item_key
+                            || t.inloc.iter().any(|inloc| inloc.item_key == item_key && inloc.inclasskey == row.item_class_key)
+                    })
+                    .max_by_key(|row| row.scope)
+                    .cloned()
+            })
+            .await)
+    }
+
+    async fn bin_exists(&self, conn: &mut MemoryConn, location: &str, bin_no: &str) -> Result<bool, PutawayError> {
+        Ok(conn
+            .with(|t| t.bins.iter().any(|bin| bin.location == location && bin.bin_no == bin_no))
+            .await)
+    }
+
+    async fn bin_capacity(
+        &self,
+        conn: &mut MemoryConn,
+        location: &str,
+        bin_no: &str,
+    ) -> Result<Option<BinCapacityRecord>, PutawayError> {
+        Ok(conn
+            .with(|t| t.bin_capacities.iter().find(|row| row.location == location && row.bin_no == bin_no).cloned())
+            .await)
+    }
+
+    async fn bin_block(
+        &self,
+        conn: &mut MemoryConn,
+        location: &str,
+        bin_no: &str,
+    ) -> Result<Option<BinBlockRecord>, PutawayError> {
+        Ok(conn
+            .with(|t| t.bin_blocks.iter().find(|row| row.location == location && row.bin_no == bin_no).cloned())
+            .await)
+    }
+
+    async fn save_bin_block(&self, conn: &mut MemoryConn, row: &BinBlockRecord) -> Result<(), PutawayError> {
+        conn.with(|t| {
+            t.bin_blocks.retain(|existing| !(existing.location == row.location && existing.bin_no == row.bin_no));
+            t.bin_blocks.push(row.clone());
+        })
+        .await;
+        Ok(())
+    }
+
+    async fn delete_bin_block(&self, conn: &mut MemoryConn, location: &str, bin_no: &str) -> Result<(), PutawayError> {
+        conn.with(|t| t.bin_blocks.retain(|row| !(row.location == location && row.bin_no == bin_no))).await;
+        Ok(())
+    }
+
+    async fn insert_bin_block_event(&self, conn: &mut MemoryConn, event: &BinBlockEvent) -> Result<(), PutawayError> {
+        conn.with(|t| t.bin_block_history.push(event.clone())).await;
+        Ok(())
+    }
+
+    async fn lock_lot_rows(
+        &self,
+        conn: &mut MemoryConn,
+        lot: LotKey<'_>,
+        bins: &[&str],
+    ) -> Result<Vec<LotMasterRecord>, PutawayError> {
+        Ok(conn
+            .with(|t| {
+                let mut rows: Vec<LotMasterRecord> = bins
+                    .iter()
+                    .collect::<HashSet<_>>()
+                    .into_iter()
+                    .filter_map(|bin| t.lot(lot, bin).cloned())
+                    .collect();
+                rows.sort_by(|a, b| a.bin_no.cmp(&b.bin_no));
+                rows
+            })
+            .await)
+    }
+
+    async fn lock_bin_lot_rows(
+        &self,
+        conn: &mut MemoryConn,
+        location: &str,
+        bin_no: &str,
+    ) -> Result<Vec<LotMasterRecord>, PutawayError> {
+        Ok(conn
+            .with(|t| {
+                t.lot_master
+                    .iter()
+                    .filter(|row| row.location_key == location && row.bin_no == bin_no)
+                    .cloned()
+                    .collect()
+            })
+            .await)
+    }
+
+    async fn lot_row(
+        &self,
+        conn: &mut MemoryConn,
+        lot: LotKey<'_>,
+        bin_no: &str,
+    ) -> Result<Option<LotMasterRecord>, PutawayError> {
+        Ok(conn.with(|t| t.lot(lot, bin_no).cloned()).await)
+    }
+
+    async fn insert_lot_row(&self, conn: &mut MemoryConn, row: &LotMasterRecord) -> Result<(), PutawayError> {
+        conn.with(|t| {
+            let key = LotKey { lot_no: &row.lot_no, item_key: &row.item_key, location: &row.location_key };
+            if t.lot(key, &row.bin_no).is_some() {
+                return Err(PutawayError::TransactionError(
+                    "Failed to create destination record: duplicate LotMaster key".to_string(),
+                ));
+            }
+            t.lot_master.push(row.clone());
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_lot_row(&self, conn: &mut MemoryConn, row: &LotMasterRecord) -> Result<(), PutawayError> {
+        conn.with(|t| {
+            let key = LotKey { lot_no: &row.lot_no, item_key: &row.item_key, location: &row.location_key };
+            // Like an UPDATE that matches no row, a missing row is not an error
+            if let Some(existing) = t.lot_mut(key, &row.bin_no) {
+                existing.qty_on_hand = row.qty_on_hand.clone();
+                existing.qty_commit_sales = row.qty_commit_sales.clone();
+                existing.document_no = row.document_no.clone();
+                existing.transaction_type = row.transaction_type;
+                existing.rec_user_id = row.rec_user_id.clone();
+                existing.rec_date = row.rec_date;
+            }
+        })
+        .await;
+        Ok(())
+    }
+
+    async fn delete_lot_row(&self, conn: &mut MemoryConn, lot: LotKey<'_>, bin_no: &str) -> Result<(), PutawayError> {
+        conn.with(|t| t.lot_master.retain(|row| !is_lot_row(row, lot, bin_no))).await;
+        Ok(())
+    }
+
+    async fn insert_lot_transaction(&self, conn: &mut MemoryConn, row: &LotTransactionRecord) -> Result<i32, PutawayError> {
+        Ok(conn
+            .with(|t| {
+                let lot_tran_no = t.lot_transactions.iter().filter_map(|r| r.lot_tran_no).max().unwrap_or(0) + 1;
+                t.lot_transactions.push(LotTransactionRecord { lot_tran_no: Some(lot_tran_no), ..row.clone() });
+                lot_tran_no
+            })
+            .await)
+    }
+
+    async fn bin_commitment(&self, conn: &mut MemoryConn, lot: LotKey<'_>, bin_no: &str) -> Result<BigDecimal, PutawayError> {
+        Ok(conn
+            .with(|t| {
+                t.lot_transactions
+                    .iter()
+                    .chain(t.qc_lot_transactions.iter())
+                    .filter(|row| {
+                        is_commitment_issue(row)
+                            && row.item_key == lot.item_key
+                            && row.location_key == lot.location
+                            && row.lot_no == lot.lot_no
+                            && row.bin_no == bin_no
+                    })
+                    .filter_map(|row| row.qty_issued.as_ref())
+                    .sum()
+            })
+            .await)
+    }
+
+    async fn lock_pending_commitment_line(
+        &self,
+        conn: &mut MemoryConn,
+        lot_tran_no: i32,
+        lot: LotKey<'_>,
+        source_bin: &str,
+        target_bin: &str,
+    ) -> Result<Option<MovedCommitmentLine>, PutawayError> {
+        Ok(conn
+            .with(|t| {
+                for (table, rows) in [("LotTransaction", &t.lot_transactions), ("QCLotTransaction", &t.qc_lot_transactions)] {
+                    let found = rows.iter().find(|row| {
+                        row.lot_tran_no == Some(lot_tran_no)
+                            && row.lot_no == lot.lot_no
+                            && row.item_key == lot.item_key
+                            && row.location_key == lot.location
+                            && row.bin_no == source_bin
+                            && is_pending_issue(row)
+                    });
+                    if let Some(row) = found {
+                        return Some(MovedCommitmentLine {
+                            lot_tran_no,
+                            source_table: table.to_string(),
+                            doc_no: row.issue_doc_no.clone().unwrap_or_default(),
+                            doc_line_no: row.issue_doc_line_no,
+                            transaction_type: row.transaction_type,
+                            qty: row.qty_issued.clone().unwrap_or_default(),
+                            bin_from: source_bin.to_string(),
+                            bin_to: target_bin.to_string(),
+                        });
+                    }
+                }
+                None
+            })
+            .await)
+    }
+
+    async fn repoint_commitment_line(&self, conn: &mut MemoryConn, line: &MovedCommitmentLine) -> Result<(), PutawayError> {
+        conn.with(|t| {
+            for row in t.transaction_table(&line.source_table).iter_mut() {
+                if row.lot_tran_no == Some(line.lot_tran_no) && row.bin_no == line.bin_from {
+                    row.bin_no = line.bin_to.clone();
+                }
+            }
+        })
+        .await;
+        Ok(())
+    }
+
+    async fn insert_commitment_move(
+        &self,
+        conn: &mut MemoryConn,
+        document_no: &str,
+        line: &MovedCommitmentLine,
+        _lot: LotKey<'_>,
+        _user_id: &str,
+        _now: &NaiveDateTime,
+    ) -> Result<(), PutawayError> {
+        conn.with(|t| t.commitment_moves.push((document_no.to_string(), line.clone()))).await;
+        Ok(())
+    }
+
+    async fn bt_document_lines(&self, conn: &mut MemoryConn, document_no: &str) -> Result<Vec<BtDocumentLine>, PutawayError> {
+        Ok(conn
+            .with(|t| {
+                let t = &*t;
+                let mut lines: Vec<BtDocumentLine> = t
+                    .lot_transactions
+                    .iter()
+                    .filter(|issue| issue.transaction_type == 9 && issue.issue_doc_no.as_deref() == Some(document_no))
+                    .flat_map(|issue| {
+                        t.lot_transactions
+                            .iter()
+                            .filter(move |receipt| {
+                                receipt.transaction_type == 8
+                                    && receipt.receipt_doc_no.as_deref() == Some(document_no)
+                                    && receipt.receipt_doc_line_no == issue.issue_doc_line_no
+                                    && receipt.lot_no == issue.lot_no
+                                    && receipt.item_key == issue.item_key
+                                    && receipt.location_key == issue.location_key
+                            })
+                            .map(move |receipt| BtDocumentLine {
+                                line_no: issue.issue_doc_line_no.unwrap_or(1),
+                                lot_no: issue.lot_no.clone(),
+                                item_key: issue.item_key.clone(),
+                                location: issue.location_key.clone(),
+                                bin_from: issue.bin_no.clone(),
+                                bin_to: receipt.bin_no.clone(),
+                                qty: issue.qty_issued.clone().unwrap_or_default(),
+                                processed: issue.processed.clone(),
+                                bin_transfer_count: t
+                                    .bin_transfers
+                                    .iter()
+                                    .filter(|bt| Some(bt.lot_tran_no) == issue.lot_tran_no)
+                                    .count() as i32,
+                            })
+                    })
+                    .collect();
+                lines.sort_by_key(|line| line.line_no);
+                lines
+            })
+            .await)
+    }
+
+    async fn insert_bin_transfer(&self, conn: &mut MemoryConn, row: &BinTransferRecord) -> Result<(), PutawayError> {
+        conn.with(|t| {
+            let bin_tran_id = t.bin_transfers.iter().filter_map(|r| r.bin_tran_id).max().unwrap_or(0) + 1;
+            t.bin_transfers.push(BinTransferRecord { bin_tran_id: Some(bin_tran_id), ..row.clone() });
+        })
+        .await;
+        Ok(())
+    }
+
+    async fn insert_mintxdh(&self, conn: &mut MemoryConn, row: &MintxdhRecord) -> Result<i32, PutawayError> {
+        Ok(conn
+            .with(|t| {
+                let in_trans_id = t.mintxdh.iter().filter_map(|r| r.in_trans_id).max().unwrap_or(0) + 1;
+                t.mintxdh.push(MintxdhRecord { in_trans_id: Some(in_trans_id), ..row.clone() });
+                in_trans_id
+            })
+            .await)
+    }
+
+    async fn mintxdh_exists(&self, conn: &mut MemoryConn, document_no: &str) -> Result<bool, PutawayError> {
+        Ok(conn.with(|t| t.mintxdh.iter().any(|row| row.doc_no == document_no)).await)
+    }
+
+    async fn mintxdh_headers(&self, conn: &mut MemoryConn, document_no: &str) -> Result<Vec<(i32, NaiveDateTime)>, PutawayError> {
+        Ok(conn
+            .with(|t| {
+                t.mintxdh
+                    .iter()
+                    .filter(|row| row.doc_no == document_no)
+                    .filter_map(|row| Some((row.in_trans_id?, row.rec_date)))
+                    .collect()
+            })
+            .await)
+    }
+
+    async fn unlinked_bin_transfers(&self, conn: &mut MemoryConn) -> Result<Vec<BinTransferLink>, PutawayError> {
+        Ok(conn
+            .with(|t| {
+                t.bin_transfers
+                    .iter()
+                    .filter(|row| row.lot_tran_no == 0 || row.in_trans_id == 0)
+                    .map(bin_transfer_link)
+                    .collect()
+            })
+            .await)
+    }
+
+    async fn lot_bin_transfers(&self, conn: &mut MemoryConn, lot: LotKey<'_>) -> Result<Vec<BinTransferLink>, PutawayError> {
+        Ok(conn
+            .with(|t| {
+                t.bin_transfers
+                    .iter()
+                    .filter(|row| row.lot_no == lot.lot_no && row.item_key == lot.item_key && row.location == lot.location)
+                    .map(bin_transfer_link)
+                    .collect()
+            })
+            .await)
+    }
+
+    async fn bt_lot_transactions(&self, conn: &mut MemoryConn, lot: LotKey<'_>) -> Result<Vec<BtLotTransaction>, PutawayError> {
+        Ok(conn
+            .with(|t| {
+                t.lot_transactions
+                    .iter()
+                    .filter(|row| row.lot_no == lot.lot_no && row.item_key == lot.item_key && row.location_key == lot.location)
+                    .filter_map(|row| {
+                        let (document_no, line_no) = match row.transaction_type {
+                            9 => (row.issue_doc_no.as_deref()?, row.issue_doc_line_no),
+                            8 => (row.receipt_doc_no.as_deref()?, row.receipt_doc_line_no),
+                            _ => return None,
+                        };
+                        document_no.starts_with("BT-").then(|| BtLotTransaction {
+                            lot_tran_no: row.lot_tran_no.unwrap_or_default(),
+                            transaction_type: row.transaction_type,
+                            document_no: document_no.to_string(),
+                            line_no: line_no.unwrap_or_default(),
+                            bin_no: row.bin_no.clone(),
+                            rec_date: row.rec_date,
+                        })
+                    })
+                    .collect()
+            })
+            .await)
+    }
+
+    async fn link_bin_transfer(
+        &self,
+        conn: &mut MemoryConn,
+        row: &BinTransferLink,
+        lot_tran_no: i32,
+        in_trans_id: i32,
+    ) -> Result<(), PutawayError> {
+        conn.with(|t| {
+            for stored in t.bin_transfers.iter_mut().filter(|stored| bin_transfer_link(stored) == *row) {
+                stored.lot_tran_no = lot_tran_no;
+                stored.in_trans_id = in_trans_id;
+            }
+        })
+        .await;
+        Ok(())
+    }
+
+    async fn insert_lot_snapshot(&self, conn: &mut MemoryConn, row: &LotSnapshotRecord) -> Result<(), PutawayError> {
+        conn.with(|t| t.lot_snapshots.push(row.clone())).await;
+        Ok(())
+    }
+
+    async fn find_bt_reversal(&self, conn: &mut MemoryConn, document_no: &str) -> Result<Option<(String, String)>, PutawayError> {
+        Ok(conn
+            .with(|t| {
+                t.bt_reversals
+                    .iter()
+                    .find(|(original, reversal, _)| original == document_no || reversal == document_no)
+                    .map(|(original, reversal, _)| (original.clone(), reversal.clone()))
+            })
+            .await)
+    }
+
+    async fn insert_bt_reversal(
+        &self,
+        conn: &mut MemoryConn,
+        original_document_no: &str,
+        reversal_document_no: &str,
+        reason: &str,
+        _user_id: &str,
+        _now: &NaiveDateTime,
+    ) -> Result<(), PutawayError> {
+        conn.with(|t| {
+            t.bt_reversals.push((original_document_no.to_string(), reversal_document_no.to_string(), reason.to_string()))
+        })
+        .await;
+        Ok(())
+    }
+
+    async fn find_lot_bins(&self, lot_no: &str) -> Result<Vec<LotBinRecord>, PutawayError> {
+        let t = self.state.lock().await;
+        let mut bins: Vec<LotBinRecord> = t
+            .lot_master
+            .iter()
+            .filter(|row| row.lot_no == lot_no && row.qty_on_hand.is_positive())
+            .filter_map(|row| lot_bin_record(&t, row))
+            .collect();
+        bins.sort_by(|a, b| (&a.lot.location_key, &a.lot.bin_no).cmp(&(&b.lot.location_key, &b.lot.bin_no)));
+        Ok(bins)
+    }
+
+    async fn bt_document_records(&self, document_no: &str) -> Result<BtDocumentRecords, PutawayError> {
+        let t = self.state.lock().await;
+        let is_document = |doc: &Option<String>| doc.as_deref() == Some(document_no);
+
+        let mut lot_transactions: Vec<LotTransactionRecord> = t
+            .lot_transactions
+            .iter()
+            .filter(|row| {
+                (row.transaction_type == 9 && is_document(&row.issue_doc_no))
+                    || (row.transaction_type == 8 && is_document(&row.receipt_doc_no))
+            })
+            .cloned()
+            .collect();
+        lot_transactions.sort_by_key(|row| {
+            (row.issue_doc_line_no.or(row.receipt_doc_line_no), Reverse(row.transaction_type), row.lot_tran_no)
+        });
+
+        let issues: Vec<(&LotTransactionRecord, &LotTransactionRecord)> = lot_transactions
+            .iter()
+            .filter(|issue| issue.transaction_type == 9)
+            .flat_map(|issue| {
+                lot_transactions
+                    .iter()
+                    .filter(move |receipt| {
+                        receipt.transaction_type == 8
+                            && receipt.receipt_doc_line_no == issue.issue_doc_line_no
+                            && receipt.lot_no == issue.lot_no
+                            && receipt.item_key == issue.item_key
+                            && receipt.location_key == issue.location_key
+                    })
+                    .map(move |receipt| (issue, receipt))
+            })
+            .collect();
+        let bin_transfers = t
+            .bin_transfers
+            .iter()
+            .filter(|bt| {
+                issues.iter().any(|(issue, receipt)| {
+                    bt.lot_no == issue.lot_no
+                        && bt.item_key == issue.item_key
+                        && bt.location == issue.location_key
+                        && bt.bin_no_from == issue.bin_no
+                        && bt.bin_no_to == receipt.bin_no
+                        && (Some(bt.lot_tran_no) == issue.lot_tran_no || (bt.lot_tran_no == 0 && bt.rec_date == issue.rec_date))
+                })
+            })
+            .cloned()
+            .collect();
+
+        Ok(BtDocumentRecords {
+            header: t.mintxdh.iter().find(|row| row.doc_no == document_no).cloned(),
+            bin_transfers,
+            lot_snapshots: t.lot_snapshots.iter().filter(|row| row.document_no == document_no).cloned().collect(),
+            commitment_moves: t
+                .commitment_moves
+                .iter()
+                .filter(|(doc, _)| doc == document_no)
+                .map(|(_, line)| line.clone())
+                .collect(),
+            reversal: t
+                .bt_reversals
+                .iter()
+                .find(|(original, reversal, _)| original == document_no || reversal == document_no)
+                .map(|(original, reversal, _)| (original.clone(), reversal.clone())),
+            lot_transactions,
+        })
+    }
+
+    async fn find_lots_in_bin(&self, location: &str, bin_no: &str) -> Result<Vec<LotMasterRecord>, PutawayError> {
+        let t = self.state.lock().await;
+        let mut rows: Vec<LotMasterRecord> = t
+            .lot_master
+            .iter()
+            .filter(|row| row.location_key == location && row.bin_no == bin_no && row.qty_on_hand.is_positive())
+            .cloned()
+            .collect();
+        rows.sort_by(|a, b| a.date_expiry.cmp(&b.date_expiry).then_with(|| a.lot_no.cmp(&b.lot_no)));
+        Ok(rows)
+    }
+
+    async fn find_bin_contents(&self, location: &str, bin_no: &str) -> Result<Vec<LotBinRecord>, PutawayError> {
+        let t = self.state.lock().await;
+        let mut rows: Vec<LotBinRecord> = t
+            .lot_master
+            .iter()
+            .filter(|row| row.location_key == location && row.bin_no == bin_no && row.qty_on_hand.is_positive())
+            .filter_map(|row| lot_bin_record(&t, row))
+            .collect();
+        rows.sort_by(|a, b| a.lot.date_expiry.cmp(&b.lot.date_expiry).then_with(|| a.lot.lot_no.cmp(&b.lot.lot_no)));
+        Ok(rows)
+    }
+
+    async fn find_bin(&self, location: &str, bin_no: &str) -> Result<Option<BinMasterRecord>, PutawayError> {
+        let t = self.state.lock().await;
+        Ok(t.bins.iter().find(|bin| bin.location == location && bin.bin_no == bin_no).cloned())
+    }
+
+    async fn find_bins_by_number(&self, bin_no: &str) -> Result<Vec<BinMasterRecord>, PutawayError> {
+        let t = self.state.lock().await;
+        let mut bins: Vec<BinMasterRecord> = t.bins.iter().filter(|bin| bin.bin_no == bin_no).cloned().collect();
+        bins.sort_by(|a, b| a.location.cmp(&b.location));
+        Ok(bins)
+    }
+
+    async fn get_pending_putaway_items(
+        &self,
+        filter: PendingPutawayFilter<'_>,
+        page: i32,
+        limit: i32,
+    ) -> Result<(Vec<(LotMasterRecord, ItemMasterRecord)>, i32), PutawayError> {
+        let t = self.state.lock().await;
+        let mut rows: Vec<(&LotMasterRecord, &ItemMasterRecord)> = t
+            .lot_master
+            .iter()
+            .filter(|row| row.qty_on_hand.is_positive() && filter.bins.contains(&row.bin_no))
+            .filter(|row| {
+                filter.statuses.is_empty()
+                    || filter.statuses.iter().any(|status| row.lot_status.as_deref().map(str::trim) == Some(status.as_str()))
+            })
+            .filter(|row| filter.location.is_none_or(|location| row.location_key == location))
+            .filter_map(|row| t.items.iter().find(|item| item.item_key == row.item_key).map(|item| (row, item)))
+            .collect();
+
+        // Earliest expiry first, lots without an expiry date last
+        rows.sort_by(|(a, _), (b, _)| {
+            (a.date_expiry.is_none(), a.date_expiry, a.date_received, &a.lot_no, &a.bin_no)
+                .cmp(&(b.date_expiry.is_none(), b.date_expiry, b.date_received, &b.lot_no, &b.bin_no))
+        });
+
+        let total_count = rows.len() as i32;
+        let lots = page_of(rows, page, limit)
+            .into_iter()
+            .map(|(row, item)| (row.clone(), item.clone()))
+            .collect();
+        Ok((lots, total_count))
+    }
+
+    async fn get_transfer_history(
+        &self,
+        filter: TransferHistoryFilter<'_>,
+        page: i32,
+        limit: i32,
+    ) -> Result<(Vec<TransferHistoryLine>, i32), PutawayError> {
+        let t = self.state.lock().await;
+        let mut lines: Vec<TransferHistoryLine> = t
+            .lot_transactions
+            .iter()
+            .filter(|issue| {
+                issue.transaction_type == 9 && issue.issue_doc_no.as_deref().is_some_and(|doc| doc.starts_with("BT-"))
+            })
+            .flat_map(|issue| {
+                t.lot_transactions
+                    .iter()
+                    .filter(move |receipt| {
+                        receipt.transaction_type == 8
+                            && receipt.receipt_doc_no == issue.issue_doc_no
+                            && receipt.receipt_doc_line_no == issue.issue_doc_line_no
+                            && receipt.lot_no == issue.lot_no
+                            && receipt.item_key == issue.item_key
+                            && receipt.location_key == issue.location_key
+                    })
+                    .map(move |receipt| (issue, receipt))
+            })
+            .filter(|(issue, receipt)| {
+                filter.user_id.is_none_or(|user| issue.rec_userid.eq_ignore_ascii_case(user))
+                    && filter.lot_no.is_none_or(|lot_no| issue.lot_no == lot_no)
+                    && filter.item_key.is_none_or(|item_key| issue.item_key == item_key)
+                    && filter.location.is_none_or(|location| issue.location_key == location)
+                    && filter.bin_no.is_none_or(|bin_no| issue.bin_no == bin_no || receipt.bin_no == bin_no)
+                    && filter.date_from.is_none_or(|from| issue.rec_date >= from)
+                    && filter.date_to.is_none_or(|to| issue.rec_date < to)
+            })
+            .map(|(issue, receipt)| {
+                let item = t.items.iter().find(|item| item.item_key == issue.item_key);
+                let bin_transfer = t.bin_transfers.iter().find(|bt| {
+                    bt.lot_no == issue.lot_no
+                        && bt.item_key == issue.item_key
+                        && bt.location == issue.location_key
+                        && bt.bin_no_from == issue.bin_no
+                        && bt.bin_no_to == receipt.bin_no
+                        && (Some(bt.lot_tran_no) == issue.lot_tran_no || (bt.lot_tran_no == 0 && bt.rec_date == issue.rec_date))
+                });
+                TransferHistoryLine {
+                    document_no: issue.issue_doc_no.clone().unwrap_or_default(),
+                    line_no: issue.issue_doc_line_no.unwrap_or(1),
+                    lot_no: issue.lot_no.clone(),
+                    item_key: issue.item_key.clone(),
+                    item_description: item.map(|item| item.desc1.clone()).unwrap_or_default(),
+                    uom: item.map(|item| item.stock_uom_code.clone()).unwrap_or_default(),
+                    location: issue.location_key.clone(),
+                    bin_from: issue.bin_no.clone(),
+                    bin_to: receipt.bin_no.clone(),
+                    qty: issue.qty_issued.clone().unwrap_or_default(),
+                    user_id: issue.rec_userid.clone(),
+                    rec_date: issue.rec_date,
+                    remarks: bin_transfer.map(|bt| bt.user1.clone()).unwrap_or_default(),
+                    referenced: bin_transfer.map(|bt| bt.user5.clone()).unwrap_or_default(),
+                }
+            })
+            .collect();
+
+        lines.sort_by(|a, b| {
+            let by_column = match filter.sort {
+                TransferHistorySort::Date => a.rec_date.cmp(&b.rec_date),
+                TransferHistorySort::DocumentNo => a.document_no.cmp(&b.document_no),
+                TransferHistorySort::LotNo => a.lot_no.cmp(&b.lot_no),
+                TransferHistorySort::ItemKey => a.item_key.cmp(&b.item_key),
+                TransferHistorySort::UserId => a.user_id.cmp(&b.user_id),
+            };
+            let by_column = by_column.then_with(|| a.document_no.cmp(&b.document_no));
+            let by_column = if filter.descending { by_column.reverse() } else { by_column };
+            by_column.then_with(|| a.line_no.cmp(&b.line_no))
+        });
+
+        let total_count = lines.len() as i32;
+        Ok((page_of(lines, page, limit), total_count))
+    }
+
+    async fn search_lots_paginated(&self, query: Option<&str>, page: i32, limit: i32) -> Result<(Vec<LotSearchItem>, i32), PutawayError> {
+        let t = self.state.lock().await;
+        let mut rows: Vec<(&LotMasterRecord, &ItemMasterRecord)> = t
+            .lot_master
+            .iter()
+            .filter(|row| row.qty_on_hand.is_positive())
+            .filter_map(|row| t.items.iter().find(|item| item.item_key == row.item_key).map(|item| (row, item)))
+            .filter(|(row, item)| {
+                query.is_none_or(|term| {
+                    like(&row.lot_no, term) || like(&item.desc1, term) || like(&row.item_key, term) || like(&row.bin_no, term)
+                })
+            })
+            .collect();
+
+        // The unfiltered count does not join INMAST
+        let total_count = match query {
+            Some(_) => rows.len() as i32,
+            None => t.lot_master.iter().filter(|row| row.qty_on_hand.is_positive()).count() as i32,
+        };
+
+        match query {
+            Some(_) => rows.sort_by(|a, b| a.0.lot_no.cmp(&b.0.lot_no)),
+            None => rows.sort_by(|a, b| b.0.lot_no.cmp(&a.0.lot_no)),
+        }
+
+        let lots = page_of(rows, page, limit)
+            .into_iter()
+            .map(|(row, item)| LotSearchItem {
+                lot_no: row.lot_no.clone(),
+                item_key: row.item_key.clone(),
+                item_description: item.desc1.clone(),
+                location: row.location_key.clone(),
+                current_bin: row.bin_no.clone(),
+                qty_on_hand: row.qty_on_hand.clone(),
+                qty_commit_sales: row.qty_commit_sales.clone(),
+                qty_available: &row.qty_on_hand - &row.qty_commit_sales,
+                date_received: row.date_received.map(|dt| dt.format("%Y-%m-%d").to_string()),
+                expiry_date: row.date_expiry.map(|dt| dt.format("%Y-%m-%d").to_string()),
+                uom: item.stock_uom_code.clone(),
+                lot_status: row.lot_status.clone().unwrap_or_default(),
+            })
+            .collect();
+
+        Ok((lots, total_count))
+    }
+
+    async fn search_bins_paginated(
+        &self,
+        query: Option<&str>,
+        page: i32,
+        limit: i32,
+        lot: Option<LotKey<'_>>,
+    ) -> Result<(Vec<BinSearchItem>, i32), PutawayError> {
+        let t = self.state.lock().await;
+        let mut bins: Vec<&BinMasterRecord> = t
+            .bins
+            .iter()
+            .filter(|bin| {
+                query.is_none_or(|term| like(&bin.bin_no, term) || like(&bin.location, term) || like(&bin.description, term))
+            })
+            .collect();
+        let total_count = bins.len() as i32;
+
+        // ORDER BY RecDate DESC puts NULLs last
+        bins.sort_by_key(|bin| Reverse(bin.rec_date));
+
+        let now = bangkok_now().naive_local();
+        let items = page_of(bins, page, limit)
+            .into_iter()
+            .map(|bin| {
+                let block_reason = t
+                    .bin_blocks
+                    .iter()
+                    .find(|block| block.location == bin.location && block.bin_no == bin.bin_no && block.is_active(&now))
+                    .map(|block| block.reason.clone());
+                BinSearchItem {
+                    bin_no: bin.bin_no.clone(),
+                    location: bin.location.clone(),
+                    description: bin.description.clone(),
+                    aisle: bin.aisle.clone(),
+                    row: bin.row.clone(),
+                    rack: bin.rack.clone(),
+                    lot_status: lot.and_then(|lot| lot_in_bin(&t, lot, &bin.bin_no)).and_then(|row| row.lot_status.clone()),
+                    blocked: block_reason.is_some(),
+                    block_reason,
+                }
+            })
+            .collect();
+
+        Ok((items, total_count))
+    }
+
+    async fn bin_candidates(
+        &self,
+        lot: LotKey<'_>,
+        exclusions: BinCandidateExclusions<'_>,
+    ) -> Result<Vec<BinCandidateRecord>, PutawayError> {
+        let t = self.state.lock().await;
+        let excluded = |bin: &BinMasterRecord| {
+            let pattern_matches = |pattern: &String| match pattern.strip_suffix('*') {
+                Some(prefix) => bin.bin_no.starts_with(prefix),
+                None => bin.bin_no == *pattern,
+            };
+            exclusions.bins.iter().any(pattern_matches)
+                || (!bin.aisle.trim().is_empty() && exclusions.aisles.iter().any(|aisle| aisle == bin.aisle.trim()))
+                || t.bin_blocks.iter().any(|block| {
+                    block.location == bin.location && block.bin_no == bin.bin_no && block.is_active(&exclusions.now)
+                })
+        };
+        Ok(t.bins
+            .iter()
+            .filter(|bin| bin.location == lot.location && !excluded(bin))
+            .map(|bin| {
+                let context = lot_in_bin(&t, lot, &bin.bin_no);
+                let stocked: Vec<&LotMasterRecord> = t
+                    .lot_master
+                    .iter()
+                    .filter(|row| row.location_key == bin.location && row.bin_no == bin.bin_no && row.qty_on_hand.is_positive())
+                    .collect();
+                BinCandidateRecord {
+                    bin: bin.clone(),
+                    lot_status: context.and_then(|row| row.lot_status.clone()),
+                    lot_qty_on_hand: context.map(|row| row.qty_on_hand.clone()).unwrap_or_default(),
+                    item_lots: stocked.iter().filter(|row| row.item_key == lot.item_key && row.lot_no != lot.lot_no).count() as i32,
+                    stocked_lots: stocked.len() as i32,
+                }
+            })
+            .filter(|candidate| candidate.lot_qty_on_hand.is_positive() || candidate.item_lots > 0 || candidate.stocked_lots == 0)
+            .collect())
+    }
+
+    async fn get_active_remarks(&self) -> Result<Vec<serde_json::Value>, PutawayError> {
+        let t = self.state.lock().await;
+        let mut remarks = t.remarks.clone();
+        remarks.sort_by_key(|(id, _)| *id);
+        Ok(remarks
+            .into_iter()
+            .map(|(id, remark_name)| serde_json::json!({ "id": id, "remark_name": remark_name }))
+            .collect())
+    }
+
+    async fn find_transactions_by_lot_and_bin(&self, lot_no: &str, bin_no: &str) -> Result<Vec<LotTransactionItem>, PutawayError> {
+        let t = self.state.lock().await;
+        let mut rows: Vec<(&str, &LotTransactionRecord)> = t
+            .lot_transactions
+            .iter()
+            .map(|row| ("LotTransaction", row))
+            .chain(t.qc_lot_transactions.iter().map(|row| ("QCLotTransaction", row)))
+            .filter(|(_, row)| is_pending_issue(row) && row.lot_no == lot_no && row.bin_no == bin_no)
+            .collect();
+        rows.sort_by_key(|(_, row)| Reverse(row.rec_date));
+
+        Ok(rows
+            .into_iter()
+            .map(|(table, row)| LotTransactionItem {
+                lot_tran_no: row.lot_tran_no.unwrap_or(0),
+                lot_no: row.lot_no.clone(),
+                bin_no: row.bin_no.clone(),
+                doc_no: row.issue_doc_no.clone().unwrap_or_default(),
+                issue_doc_line_no: row.issue_doc_line_no,
+                qty: row.qty_issued.clone().unwrap_or_default(),
+                transaction_type: row.transaction_type,
+                tran_typ: transaction_type_name(row.transaction_type).to_string(),
+                transaction_date: row.rec_date.to_string(),
+                status: row.processed.clone(),
+                source_table: table.to_string(),
+            })
+            .collect())
+    }
+
+    async fn find_bt_documents_in_range(&self, from_doc: &str, to_doc: &str) -> Result<(HashSet<String>, HashSet<String>), PutawayError> {
+        let t = self.state.lock().await;
+        let in_range = |doc: &&str| (from_doc..=to_doc).contains(doc);
+
+        let with_lot_transactions = t
+            .lot_transactions
+            .iter()
+            .flat_map(|row| [row.issue_doc_no.as_deref(), row.receipt_doc_no.as_deref()])
+            .flatten()
+            .filter(in_range)
+            .map(|doc| doc.trim().to_string())
+            .collect();
+        let with_mintxdh = t
+            .mintxdh
+            .iter()
+            .map(|row| row.doc_no.as_str())
+            .filter(in_range)
+            .map(|doc| doc.trim().to_string())
+            .collect();
+
+        Ok((with_lot_transactions, with_mintxdh))
+    }
+
+    async fn find_bin_blocks(&self, location: Option<&str>) -> Result<Vec<BinBlockRecord>, PutawayError> {
+        let t = self.state.lock().await;
+        let mut rows: Vec<BinBlockRecord> = t
+            .bin_blocks
+            .iter()
+            .filter(|row| location.is_none_or(|location| row.location == location))
+            .cloned()
+            .collect();
+        rows.sort_by(|a, b| a.location.cmp(&b.location).then_with(|| a.bin_no.cmp(&b.bin_no)));
+        Ok(rows)
+    }
+
+    async fn find_bin_block_history(&self, location: &str, bin_no: &str) -> Result<Vec<BinBlockEvent>, PutawayError> {
+        let t = self.state.lock().await;
+        Ok(t.bin_block_history
+            .iter()
+            .rev()
+            .filter(|event| event.location == location && event.bin_no == bin_no)
+            .cloned()
+            .collect())
+    }
+
+    async fn find_bin_capacities(
+        &self,
+        location: &str,
+    ) -> Result<Vec<(BinCapacityRecord, BinMasterRecord)>, PutawayError> {
+        let t = self.state.lock().await;
+        let mut rows: Vec<(BinCapacityRecord, BinMasterRecord)> = t
+            .bin_capacities
+            .iter()
+            .filter(|row| row.location == location)
+            .filter_map(|row| {
+                let bin = t.bins.iter().find(|bin| bin.location == row.location && bin.bin_no == row.bin_no)?;
+                Some((row.clone(), bin.clone()))
+            })
+            .collect();
+        rows.sort_by(|a, b| a.0.bin_no.cmp(&b.0.bin_no));
+        Ok(rows)
+    }
+
+    async fn save_bin_capacity(&self, row: &BinCapacityRecord) -> Result<(), PutawayError> {
+        let mut t = self.state.lock().await;
+        t.bin_capacities.retain(|existing| !(existing.location == row.location && existing.bin_no == row.bin_no));
+        t.bin_capacities.push(row.clone());
+        Ok(())
+    }
+
+    async fn delete_bin_capacity(&self, location: &str, bin_no: &str) -> Result<bool, PutawayError> {
+        let mut t = self.state.lock().await;
+        let before = t.bin_capacities.len();
+        t.bin_capacities.retain(|row| !(row.location == location && row.bin_no == bin_no));
+        Ok(t.bin_capacities.len() < before)
+    }
+
+    async fn reserve_idempotency_key(
+        &self,
+        scope: &str,
+        user_id: &str,
+        key: &str,
+        request_hash: &str,
+        ttl_hours: i64,
+        lease_seconds: i64,
+    ) -> Result<IdempotencyReservation, PutawayError> {
+        let mut t = self.state.lock().await;
+        let now = bangkok_now().naive_local();
+        let lease_expires_at = now + Duration::seconds(lease_seconds);
+        let attempt_id = uuid::Uuid::new_v4().to_string();
+        t.idempotency_keys.retain(|row| row.expires_at >= now);
+
+        let Some(row) = t
+            .idempotency_keys
+            .iter_mut()
+            .find(|row| row.scope == scope && row.user_id == user_id && row.key == key)
+        else {
+            t.idempotency_keys.push(MemoryIdempotencyKey {
+                scope: scope.to_string(),
+                user_id: user_id.to_string(),
+                key: key.to_string(),
+                request_hash: request_hash.to_string(),
+                status: "pending".to_string(),
+                response_body: None,
+                expires_at: now + Duration::hours(ttl_hours),
+                lease_expires_at,
+                attempt_id: attempt_id.clone(),
+                document_no: None,
+            });
+            return Ok(IdempotencyReservation::New { attempt_id });
+        };
+
+        if row.request_hash != request_hash {
+            return Ok(IdempotencyReservation::Mismatch);
+        }
+        match (row.status.as_str(), &row.response_body, &row.document_no) {
+            ("completed", Some(body), _) => Ok(IdempotencyReservation::Completed(body.clone())),
+            (_, _, Some(document_no)) => Ok(IdempotencyReservation::Applied(document_no.clone())),
+            ("pending", _, None) if row.lease_expires_at < now => {
+                row.lease_expires_at = lease_expires_at;
+                row.attempt_id = attempt_id.clone();
+                Ok(IdempotencyReservation::New { attempt_id })
+            }
+            _ => Ok(IdempotencyReservation::InProgress),
+        }
+    }
+
+    async fn complete_idempotency_key(
+        &self,
+        conn: &mut MemoryConn,
+        claim: &IdempotencyClaim,
+        document_no: &str,
+        response_body: Option<&str>,
+    ) -> Result<(), PutawayError> {
+        let completed = conn
+            .with(|t| {
+                let row = t.idempotency_keys.iter_mut().find(|row| {
+                    row.scope == claim.scope
+                        && row.user_id == claim.user_id
+                        && row.key == claim.key
+                        && row.attempt_id == claim.attempt_id
+                        && row.status == "pending"
+                });
+                row.map(|row| {
+                    if let Some(body) = response_body {
+                        row.status = "completed".to_string();
+                        row.response_body = Some(body.to_string());
+                    }
+                    row.document_no = Some(document_no.to_string());
+                })
+                .is_some()
+            })
+            .await;
+
+        if completed {
+            Ok(())
+        } else {
+            Err(PutawayError::RequestInProgress { key: claim.key.clone() })
+        }
+    }
+
+    async fn release_idempotency_key(&self, claim: &IdempotencyClaim) -> Result<(), PutawayError> {
+        let mut t = self.state.lock().await;
+        t.idempotency_keys.retain(|row| {
+            !(row.scope == claim.scope
+                && row.user_id == claim.user_id
+                && row.key == claim.key
+                && row.attempt_id == claim.attempt_id
+                && row.status == "pending")
+        });
+        Ok(())
+    }
+}
+
+/// putaway_refresh_tokens row
+#[derive(Debug, Clone)]
+pub struct MemoryRefreshToken {
+    pub token_hash: String,
+    pub session_id: String,
+    pub user: User,
+    pub source: Option<AuthSource>,
+    pub expires_at: NaiveDateTime,
+    pub replaced_by: Option<String>,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+/// In-memory `SessionRepository` for session tests
+#[derive(Clone, Default)]
+pub struct MemorySessionDatabase {
+    tokens: Arc<Mutex<Vec<MemoryRefreshToken>>>,
+}
+
+impl MemorySessionDatabase {
+    /// Copy of the stored refresh tokens, oldest first
+    pub async fn tokens(&self) -> Vec<MemoryRefreshToken> {
+        self.tokens.lock().await.clone()
+    }
+
+    fn token(session_id: &str, token_hash: &str, user: &User, source: AuthSource, lifetime: Duration) -> MemoryRefreshToken {
+        MemoryRefreshToken {
+            token_hash: token_hash.to_string(),
+            session_id: session_id.to_string(),
+            user: user.clone(),
+            source: Some(source),
+            expires_at: bangkok_now().naive_local() + lifetime,
+            replaced_by: None,
+            revoked_at: None,
+        }
+    }
+}
+
+impl SessionRepository for MemorySessionDatabase {
+    async fn create_session(
+        &self,
+        session_id: &str,
+        token_hash: &str,
+        user: &User,
+        source: AuthSource,
+        lifetime: Duration,
+    ) -> anyhow::Result<NaiveDateTime> {
+        let token = Self::token(session_id, token_hash, user, source, lifetime);
+        let expires_at = token.expires_at;
+        self.tokens.lock().await.push(token);
+        Ok(expires_at)
+    }
+
+    async fn find_refresh_token(&self, token_hash: &str) -> anyhow::Result<Option<RefreshTokenRecord>> {
+        let tokens = self.tokens.lock().await;
+        Ok(tokens.iter().find(|token| token.token_hash == token_hash).map(|token| RefreshTokenRecord {
+            session_id: token.session_id.clone(),
+            user: token.user.clone(),
+            source: token.source,
+            expires_at: token.expires_at,
+            replaced: token.replaced_by.is_some(),
+            revoked: token.revoked_at.is_some(),
+        }))
+    }
+
+    async fn rotate(
+        &self,
+        old_token_hash: &str,
+        new_token_hash: &str,
+        session_id: &str,
+        user: &User,
+        source: AuthSource,
+        lifetime: Duration,
+    ) -> anyhow::Result<Option<NaiveDateTime>> {
+        let mut tokens = self.tokens.lock().await;
+        let Some(old) = tokens
+            .iter_mut()
+            .find(|token| token.token_hash == old_token_hash && token.replaced_by.is_none() && token.revoked_at.is_none())
+        else {
+            return Ok(None);
+        };
+        old.replaced_by = Some(new_token_hash.to_string());
+
+        let token = Self::token(session_id, new_token_hash, user, source, lifetime);
+        let expires_at = token.expires_at;
+        tokens.push(token);
+        Ok(Some(expires_at))
+    }
+
+    async fn revoke_session(&self, session_id: &str) -> anyhow::Result<()> {
+        let now = bangkok_now().naive_local();
+        for token in self.tokens.lock().await.iter_mut().filter(|token| token.session_id == session_id) {
+            token.revoked_at.get_or_insert(now);
+        }
+        Ok(())
+    }
+
+    async fn revoke_user_sessions(&self, user_id: &str) -> anyhow::Result<Vec<String>> {
+        let now = bangkok_now().naive_local();
+        let mut session_ids = Vec::new();
+        for token in self.tokens.lock().await.iter_mut() {
+            if token.user.user_id == user_id && token.revoked_at.is_none() {
+                token.revoked_at = Some(now);
+                session_ids.push(token.session_id.clone());
+            }
+        }
+        session_ids.sort();
+        session_ids.dedup();
+        Ok(session_ids)
+    }
+
+    async fn recently_revoked_sessions(&self, token_lifetime: Duration) -> anyhow::Result<Vec<(String, NaiveDateTime)>> {
+        let since = bangkok_now().naive_local() - token_lifetime;
+        let mut revoked: Vec<(String, NaiveDateTime)> = Vec::new();
+        for token in self.tokens.lock().await.iter() {
+            let Some(revoked_at) = token.revoked_at.filter(|at| *at >= since) else {
+                continue;
+            };
+            match revoked.iter_mut().find(|(session_id, _)| *session_id == token.session_id) {
+                Some((_, latest)) => *latest = (*latest).max(revoked_at),
+                None => revoked.push((token.session_id.clone(), revoked_at)),
+            }
+        }
+        Ok(revoked)
+    }
+}
@@ -7,8 +7,13 @@ use std::time::Duration;
 use tiberius::{AuthMethod, Config, EncryptionLevel, Query, Row};
 use tracing::{info, warn};
 
-pub mod putaway;
+pub mod auth_session_db;
+pub mod idempotency_db;
+#[cfg(test)]
+pub mod memory_db;
 pub mod putaway_db;
+pub mod repository;
+pub mod schema;
 
 /// Database configuration with connection pooling
 #[derive(Clone, Debug)]
@@ -166,6 +171,12 @@ impl Database {
             .with_context(|| format!("Failed to get connection from pool for database: {}", self.config.database))
     }
 
+    /// Get a pooled connection that is not borrowed from `self` (held across a repository transaction)
+    pub async fn get_owned_client(&self) -> Result<bb8::PooledConnection<'static, ConnectionManager>> {
+        self.pool.get_owned().await
+            .with_context(|| format!("Failed to get connection from pool for database: {}", self.config.database))
+    }
+
     /// Get database name
     pub fn get_database_name(&self) -> &str {
         &self.config.database
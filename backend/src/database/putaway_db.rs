@@ -1,13 +1,83 @@
+use crate::database::idempotency_db::{IdempotencyClaim, IdempotencyDatabase, IdempotencyReservation};
+use crate::database::repository::{
+    BinCandidateExclusions, BinCandidateRecord, BinTransferLink, BtDocumentLine, BtDocumentRecords, BtLotTransaction, LotBinRecord, LotKey, PendingPutawayFilter, PutawayRepository, TransferHistoryFilter,
+    TransferHistoryLine, TransferHistorySort,
+};
 use crate::database::Database;
 use crate::models::putaway_models::{
-    map_inclasskey_to_inacct, BinSearchItem, InlocRecord, ItemMasterRecord, LotMasterRecord,
-    LotSearchItem, LotTransactionItem, PutawayError,
+    bin_block_active, BinBlockAction, BinBlockEvent, BinBlockRecord, BinCapacityRecord, BinMasterRecord, BinSearchItem,
+    BinTransferRecord, CapacityUnit, InlocRecord, ItemMasterRecord, LotMasterRecord, LotSearchItem, LotSnapshotRecord,
+    LotTransactionItem, LotTransactionRecord, MintxdhRecord, MovedCommitmentLine, PutawayError, UomConversionRecord,
 };
 use crate::utils::bangkok_now;
-use anyhow::Result;
-use chrono::{DateTime, NaiveDateTime, Utc};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use std::collections::HashSet;
+use tiberius::ToSql;
+
+type PooledClient = bb8::PooledConnection<'static, bb8_tiberius::ConnectionManager>;
+
+/// Pooled connection a repository transaction runs on. One dropped with its transaction still
+/// open (the request was cancelled mid-transfer) is rolled back before it goes back to the pool,
+/// so the abandoned transaction cannot keep holding the BT Seqnum and LotMaster locks.
+pub struct SqlConnection {
+    client: Option<PooledClient>,
+    in_transaction: bool,
+}
+
+impl SqlConnection {
+    fn new(client: PooledClient) -> Self {
+        Self { client: Some(client), in_transaction: false }
+    }
+}
+
+impl std::ops::Deref for SqlConnection {
+    type Target = PooledClient;
+
+    fn deref(&self) -> &PooledClient {
+        self.client.as_ref().expect("connection is only taken on drop")
+    }
+}
+
+impl std::ops::DerefMut for SqlConnection {
+    fn deref_mut(&mut self) -> &mut PooledClient {
+        self.client.as_mut().expect("connection is only taken on drop")
+    }
+}
 
+impl Drop for SqlConnection {
+    fn drop(&mut self) {
+        if !self.in_transaction {
+            return;
+        }
+        let Some(mut client) = self.client.take() else {
+            return;
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(async move {
+                    tracing::warn!("Rolling back a transaction abandoned mid-request");
+                    if let Err(e) = client.simple_query("ROLLBACK").await {
+                        tracing::error!("Failed to roll back an abandoned transaction: {e}");
+                    }
+                });
+            }
+            Err(_) => tracing::error!("Transaction dropped outside the runtime; its connection returns to the pool uncommitted"),
+        }
+    }
+}
 
+/// LotMaster columns read into a `LotMasterRecord`. The float quantity columns are read as
+/// DECIMAL so they arrive as exact `BigDecimal`s; the service rounds them to the UOM scale.
+const LOT_MASTER_COLUMNS: &str = r#"
+    LotNo, ItemKey, LocationKey, BinNo, DateReceived, DateExpiry,
+    CAST(QtyReceived AS DECIMAL(28,10)) AS QtyReceived, CAST(QtyOnHand AS DECIMAL(28,10)) AS QtyOnHand,
+    CAST(QtyIssued AS DECIMAL(28,10)) AS QtyIssued, CAST(QtyCommitSales AS DECIMAL(28,10)) AS QtyCommitSales,
+    VendorKey, VendorLotNo, DocumentNo, DocumentLineNo, TransactionType, LotStatus, RecUserId, Recdate
+"#;
+
+/// SQL Server implementation of `PutawayRepository` (BME database)
+#[derive(Clone)]
 pub struct PutawayDatabase {
     db: Database,
 }
@@ -17,122 +87,238 @@ impl PutawayDatabase {
         Self { db }
     }
 
-    /// Search for lot details by lot number
-    pub async fn find_lot_by_number(
-        &self,
-        lot_no: &str,
-    ) -> Result<Option<(LotMasterRecord, ItemMasterRecord)>, PutawayError> {
-        let mut client = self
-            .db
-            .get_client()
+    fn idempotency(&self) -> IdempotencyDatabase {
+        IdempotencyDatabase::new(self.db.clone())
+    }
+
+    async fn owned_client(&self) -> Result<SqlConnection, PutawayError> {
+        self.db
+            .get_owned_client()
             .await
-            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+            .map(SqlConnection::new)
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))
+    }
+}
 
-        let query = r#"
-            SELECT
-                l.LotNo, l.ItemKey, l.LocationKey, l.BinNo, l.QtyOnHand,
-                l.QtyIssued, l.QtyCommitSales, l.DateExpiry, l.VendorKey, l.VendorLotNo,
-                l.DocumentNo, l.DocumentLineNo, l.TransactionType, l.LotStatus,
-                i.Desc1, i.Desc2, i.Stockuomcode, i.Purchaseuomcode, i.Salesuomcode
-            FROM LotMaster l WITH (NOLOCK)
-            JOIN INMAST i WITH (NOLOCK) ON l.ItemKey = i.Itemkey
-            WHERE l.LotNo = @P1 AND l.QtyOnHand > 0
-        "#;
+/// Map a row selected with `LOT_MASTER_COLUMNS`
+fn lot_master_from_row(row: &tiberius::Row) -> LotMasterRecord {
+    LotMasterRecord {
+        lot_no: row.get::<&str, _>("LotNo").unwrap_or("").to_string(),
+        item_key: row.get::<&str, _>("ItemKey").unwrap_or("").to_string(),
+        location_key: row.get::<&str, _>("LocationKey").unwrap_or("").to_string(),
+        bin_no: row.get::<&str, _>("BinNo").unwrap_or("").to_string(),
+        date_received: row.get::<NaiveDateTime, _>("DateReceived"),
+        date_expiry: row.get::<NaiveDateTime, _>("DateExpiry"),
+        qty_received: row.get::<BigDecimal, _>("QtyReceived").unwrap_or_default(),
+        qty_on_hand: row.get::<BigDecimal, _>("QtyOnHand").unwrap_or_default(),
+        qty_issued: row.get::<BigDecimal, _>("QtyIssued").unwrap_or_default(),
+        qty_commit_sales: row.get::<BigDecimal, _>("QtyCommitSales").unwrap_or_default(),
+        vendor_key: row.get::<&str, _>("VendorKey").unwrap_or("").to_string(),
+        vendor_lot_no: row.get::<&str, _>("VendorLotNo").unwrap_or("").to_string(),
+        document_no: row.get::<&str, _>("DocumentNo").unwrap_or("").to_string(),
+        document_line_no: row.get::<i16, _>("DocumentLineNo").unwrap_or(0),
+        transaction_type: row.get::<u8, _>("TransactionType").unwrap_or(0),
+        lot_status: row.get::<&str, _>("LotStatus").map(|s| s.to_string()),
+        rec_user_id: row.get::<&str, _>("RecUserId").unwrap_or("").to_string(),
+        rec_date: row.get::<NaiveDateTime, _>("Recdate"),
+    }
+}
 
-        let result = client
-            .query(query, &[&lot_no])
-            .await
-            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+/// Map a row carrying ItemKey and the INMAST Desc1, Desc2 and UOM code columns
+fn item_master_from_row(row: &tiberius::Row) -> ItemMasterRecord {
+    ItemMasterRecord {
+        item_key: row.get::<&str, _>("ItemKey").unwrap_or("").to_string(),
+        desc1: row.get::<&str, _>("Desc1").unwrap_or("").to_string(),
+        desc2: row.get::<&str, _>("Desc2").unwrap_or("").to_string(),
+        stock_uom_code: row.get::<&str, _>("Stockuomcode").unwrap_or("").to_string(),
+        purchase_uom_code: row
+            .get::<&str, _>("Purchaseuomcode")
+            .unwrap_or("")
+            .to_string(),
+        sales_uom_code: row.get::<&str, _>("Salesuomcode").unwrap_or("").to_string(),
+    }
+}
 
-        if let Some(row) = result
-            .into_row()
-            .await
-            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
-        {
-            let lot_record = LotMasterRecord {
-                lot_no: row.get::<&str, _>("LotNo").unwrap_or("").to_string(),
-                item_key: row.get::<&str, _>("ItemKey").unwrap_or("").to_string(),
-                location_key: row.get::<&str, _>("LocationKey").unwrap_or("").to_string(),
-                bin_no: row.get::<&str, _>("BinNo").unwrap_or("").to_string(),
-                qty_on_hand: row.get::<f64, _>("QtyOnHand").unwrap_or(0.0),
-                qty_issued: row.get::<f64, _>("QtyIssued").unwrap_or(0.0),
-                qty_commit_sales: row.get::<f64, _>("QtyCommitSales").unwrap_or(0.0),
-                date_expiry: DateTime::from_naive_utc_and_offset(
-                    row.get::<NaiveDateTime, _>("DateExpiry")
-                        .unwrap_or_default(),
-                    Utc,
-                ),
-                vendor_key: row.get::<&str, _>("VendorKey").unwrap_or("").to_string(),
-                vendor_lot_no: row.get::<&str, _>("VendorLotNo").unwrap_or("").to_string(),
-                document_no: row.get::<&str, _>("DocumentNo").unwrap_or("").to_string(),
-                document_line_no: row.get::<i16, _>("DocumentLineNo").unwrap_or(0),
-                transaction_type: row.get::<u8, _>("TransactionType").unwrap_or(0),
-                lot_status: row.get::<&str, _>("LotStatus").unwrap_or("").to_string(),
-            };
+/// Map a BINMaster row (Location, BinNo, Description, aisle, row, rack, RecDate)
+fn bin_master_from_row(row: &tiberius::Row) -> BinMasterRecord {
+    BinMasterRecord {
+        location: row.get::<&str, _>("Location").unwrap_or("").to_string(),
+        bin_no: row.get::<&str, _>("BinNo").unwrap_or("").to_string(),
+        description: row.get::<&str, _>("Description").unwrap_or("").to_string(),
+        aisle: row.get::<&str, _>("aisle").unwrap_or("").to_string(),
+        row: row.get::<&str, _>("row").unwrap_or("").to_string(),
+        rack: row.get::<&str, _>("rack").unwrap_or("").to_string(),
+        rec_date: row.get::<NaiveDateTime, _>("RecDate"),
+    }
+}
 
-            let item_record = ItemMasterRecord {
-                item_key: row.get::<&str, _>("ItemKey").unwrap_or("").to_string(),
-                desc1: row.get::<&str, _>("Desc1").unwrap_or("").to_string(),
-                desc2: row.get::<&str, _>("Desc2").unwrap_or("").to_string(),
-                stock_uom_code: row.get::<&str, _>("Stockuomcode").unwrap_or("").to_string(),
-                purchase_uom_code: row
-                    .get::<&str, _>("Purchaseuomcode")
-                    .unwrap_or("")
-                    .to_string(),
-                sales_uom_code: row.get::<&str, _>("Salesuomcode").unwrap_or("").to_string(),
-            };
+/// putaway_bin_capacity columns read into a `BinCapacityRecord`
+const BIN_CAPACITY_COLUMNS: &str = "c.location, c.bin_no, c.capacity_unit, c.capacity, c.rec_user_id, c.rec_date";
+
+/// Map a row selected with `BIN_CAPACITY_COLUMNS`. None for a capacity unit this version does not know.
+fn bin_capacity_from_row(row: &tiberius::Row) -> Option<BinCapacityRecord> {
+    Some(BinCapacityRecord {
+        location: row.get::<&str, _>("location").unwrap_or("").to_string(),
+        bin_no: row.get::<&str, _>("bin_no").unwrap_or("").to_string(),
+        unit: CapacityUnit::from_code(row.get::<&str, _>("capacity_unit").unwrap_or(""))?,
+        capacity: row.get::<BigDecimal, _>("capacity").unwrap_or_default(),
+        rec_user_id: row.get::<&str, _>("rec_user_id").unwrap_or("").to_string(),
+        rec_date: row.get::<NaiveDateTime, _>("rec_date").unwrap_or_default(),
+    })
+}
 
-            Ok(Some((lot_record, item_record)))
-        } else {
-            Ok(None)
-        }
+/// putaway_bin_blocks columns read into a `BinBlockRecord`
+const BIN_BLOCK_COLUMNS: &str = "k.location, k.bin_no, k.reason, k.blocked_until, k.rec_user_id, k.rec_date";
+
+/// Block of BINMaster `b`, expired or not: the bin search works out whether it still holds
+const BIN_BLOCK_JOIN: &str = r#"
+    LEFT JOIN dbo.putaway_bin_blocks k WITH (NOLOCK) ON
+        k.location = b.Location AND
+        k.bin_no = b.BinNo
+"#;
+
+/// Map a row selected with `BIN_BLOCK_COLUMNS`
+fn bin_block_from_row(row: &tiberius::Row) -> BinBlockRecord {
+    BinBlockRecord {
+        location: row.get::<&str, _>("location").unwrap_or("").to_string(),
+        bin_no: row.get::<&str, _>("bin_no").unwrap_or("").to_string(),
+        reason: row.get::<&str, _>("reason").unwrap_or("").to_string(),
+        blocked_until: row.get::<NaiveDateTime, _>("blocked_until"),
+        rec_user_id: row.get::<&str, _>("rec_user_id").unwrap_or("").to_string(),
+        rec_date: row.get::<NaiveDateTime, _>("rec_date").unwrap_or_default(),
     }
+}
 
-    /// Validate if a bin exists and is valid for the location
-    pub async fn validate_bin_location(
-        &self,
-        location: &str,
-        bin_no: &str,
-    ) -> Result<bool, PutawayError> {
-        let mut client = self
-            .db
-            .get_client()
-            .await
-            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+/// Pending issues of LotMaster `l`'s lot/bin counted in its BME commitment (Phase 1.3: pending
+/// LotTransaction + QCLotTransaction issues), less the type 9 issues of BT documents - see
+/// `PutawayRepository::bin_commitment`. Shared by `LOT_BIN_SELECT` callers and `bin_commitment`.
+const BIN_COMMITMENT_APPLY: &str = r#"
+    OUTER APPLY (
+        SELECT SUM(x.QtyIssued) AS Commitment
+        FROM (
+            SELECT t.QtyIssued FROM LotTransaction t
+            WHERE t.Processed IN ('N','P')
+              AND t.TransactionType IN (2,3,5,7,9,10,12,16,17,20,21)
+              AND (t.TransactionType <> 9 OR ISNULL(t.IssueDocNo, '') NOT LIKE 'BT-%')
+              AND t.ItemKey = l.ItemKey AND t.LocationKey = l.LocationKey AND t.LotNo = l.LotNo AND t.BinNo = l.BinNo
+            UNION ALL
+            SELECT q.QtyIssued FROM QCLotTransaction q
+            WHERE q.Processed IN ('N','P')
+              AND q.TransactionType IN (2,3,5,7,9,10,12,16,17,20,21)
+              AND (q.TransactionType <> 9 OR ISNULL(q.IssueDocNo, '') NOT LIKE 'BT-%')
+              AND q.ItemKey = l.ItemKey AND q.LocationKey = l.LocationKey AND q.LotNo = l.LotNo AND q.BinNo = l.BinNo
+        ) AS x
+    ) AS c
+"#;
+
+/// LotMaster rows joined with INMAST and the bin's BME commitment. Callers append
+/// `BIN_COMMITMENT_APPLY`, then WHERE and ORDER BY on `l`.
+const LOT_BIN_SELECT: &str = r#"
+    SELECT
+        l.LotNo, l.ItemKey, l.LocationKey, l.BinNo, l.DateReceived, l.DateExpiry,
+        CAST(l.QtyReceived AS DECIMAL(28,10)) AS QtyReceived, CAST(l.QtyOnHand AS DECIMAL(28,10)) AS QtyOnHand,
+        CAST(l.QtyIssued AS DECIMAL(28,10)) AS QtyIssued,
+        CAST(l.QtyCommitSales AS DECIMAL(28,10)) AS QtyCommitSales, l.VendorKey, l.VendorLotNo,
+        l.DocumentNo, l.DocumentLineNo, l.TransactionType, l.LotStatus, l.RecUserId, l.Recdate,
+        i.Desc1, i.Desc2, i.Stockuomcode, i.Purchaseuomcode, i.Salesuomcode,
+        CAST(COALESCE(c.Commitment, 0) AS DECIMAL(28,10)) AS Commitment
+    FROM LotMaster l WITH (NOLOCK)
+    JOIN INMAST i WITH (NOLOCK) ON l.ItemKey = i.Itemkey
+"#;
+
+/// Lot context of the bin search and the destination suggestions: the lot's row in BINMaster `b`,
+/// if any. Binds @P1 LotNo, @P2 ItemKey, @P3 LocationKey.
+const BIN_LOT_CONTEXT_JOIN: &str = r#"
+    LEFT JOIN LotMaster l WITH (NOLOCK) ON
+        l.LotNo = @P1 AND
+        l.ItemKey = @P2 AND
+        l.LocationKey = @P3 AND
+        l.BinNo = b.BinNo
+"#;
+
+fn bin_transfer_link_from_row(row: &tiberius::Row) -> BinTransferLink {
+    BinTransferLink {
+        lot_no: row.get::<&str, _>("LotNo").unwrap_or("").to_string(),
+        item_key: row.get::<&str, _>("ItemKey").unwrap_or("").to_string(),
+        location: row.get::<&str, _>("Location").unwrap_or("").to_string(),
+        bin_no_from: row.get::<&str, _>("BinNoFrom").unwrap_or("").to_string(),
+        bin_no_to: row.get::<&str, _>("BinNoTo").unwrap_or("").to_string(),
+        rec_date: row.get::<NaiveDateTime, _>("RecDate").unwrap_or_default(),
+        lot_tran_no: row.get::<i32, _>("LotTranNo").unwrap_or(0),
+        in_trans_id: row.get::<i32, _>("InTransID").unwrap_or(0),
+    }
+}
 
-        // Check if bin exists in BINMaster table
-        let query = r#"
-            SELECT COUNT(*) as count
-            FROM BINMaster WITH (NOLOCK)
-            WHERE Location = @P1 AND BinNo = @P2
-        "#;
+fn lot_bin_from_row(row: &tiberius::Row) -> LotBinRecord {
+    LotBinRecord {
+        lot: lot_master_from_row(row),
+        item: item_master_from_row(row),
+        committed: row.get::<BigDecimal, _>("Commitment").unwrap_or_default(),
+    }
+}
 
-        let result = client
-            .query(query, &[&location, &bin_no])
+/// Bind a list of values and return their placeholders for an IN (...) clause
+fn push_params<'a>(params: &mut Vec<&'a dyn ToSql>, values: &'a [String]) -> String {
+    let start = params.len();
+    params.extend(values.iter().map(|value| value as &dyn ToSql));
+    (start..params.len()).map(|i| format!("@P{}", i + 1)).collect::<Vec<_>>().join(", ")
+}
+
+/// Rows of one audit query keyed on a BT document number
+async fn document_rows(
+    conn: &mut SqlConnection,
+    query: &str,
+    document_no: &str,
+    what: &str,
+) -> Result<Vec<tiberius::Row>, PutawayError> {
+    conn.query(query, &[&document_no])
+        .await
+        .map_err(|e| PutawayError::DatabaseError(format!("Failed to read {what} of {document_no}: {e}")))?
+        .into_first_result()
+        .await
+        .map_err(|e| PutawayError::DatabaseError(e.to_string()))
+}
+
+impl PutawayRepository for PutawayDatabase {
+    type Conn = SqlConnection;
+
+    async fn connect(&self) -> Result<SqlConnection, PutawayError> {
+        self.owned_client().await
+    }
+
+    async fn begin(&self) -> Result<SqlConnection, PutawayError> {
+        let mut client = self.owned_client().await?;
+
+        // Set REPEATABLE READ isolation level for stronger consistency
+        client
+            .simple_query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
             .await
-            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to set isolation level: {e}")))?;
 
-        if let Some(row) = result
-            .into_row()
+        // Flagged before BEGIN is sent: a drop while it is in flight still rolls back
+        client.in_transaction = true;
+        client
+            .simple_query("BEGIN TRANSACTION")
             .await
-            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
-        {
-            let count: i32 = row.get("count").unwrap_or(0);
-            Ok(count > 0)
-        } else {
-            Ok(false)
-        }
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to begin transaction: {e}")))?;
+
+        Ok(client)
     }
 
-    /// Get next sequence number for BT documents
-    /// This method handles atomic sequence increment with proper transaction safety
-    pub async fn get_next_bt_sequence(&self) -> Result<i32, PutawayError> {
-        let mut client = self
-            .db
-            .get_client()
+    async fn commit(&self, conn: &mut SqlConnection) -> Result<(), PutawayError> {
+        conn.simple_query("COMMIT")
             .await
-            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to commit transaction: {e}")))?;
+        conn.in_transaction = false;
+        Ok(())
+    }
+
+    async fn rollback(&self, conn: &mut SqlConnection) {
+        let _ = conn.simple_query("ROLLBACK").await;
+        conn.in_transaction = false;
+    }
 
+    async fn next_bt_sequence(&self, conn: &mut SqlConnection) -> Result<i32, PutawayError> {
         // Use atomic increment with OUTPUT to get the new sequence number
         let query = r#"
             UPDATE Seqnum 
@@ -141,7 +327,7 @@ impl PutawayDatabase {
             WHERE SeqName = 'BT'
         "#;
 
-        let result = client.query(query, &[]).await.map_err(|e| {
+        let result = conn.query(query, &[]).await.map_err(|e| {
             PutawayError::TransactionError(format!("Failed to increment BT sequence: {e}"))
         })?;
 
@@ -159,25 +345,73 @@ impl PutawayDatabase {
         }
     }
 
-    /// Get INLOC record for GL account mapping
-    pub async fn get_inloc_record(
-        &self,
-        item_key: &str,
-        location: &str,
-    ) -> Result<InlocRecord, PutawayError> {
+    async fn current_bt_sequence(&self) -> Result<i32, PutawayError> {
         let mut client = self
             .db
             .get_client()
             .await
             .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
 
+        let row = client
+            .query("SELECT SeqNum FROM Seqnum WITH (NOLOCK) WHERE SeqName = 'BT'", &[])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .into_row()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .ok_or_else(|| PutawayError::DatabaseError("BT sequence not found".to_string()))?;
+
+        Ok(row.get::<i32, _>("SeqNum").unwrap_or(0))
+    }
+
+    async fn inventory_frozen(&self, conn: &mut SqlConnection) -> Result<bool, PutawayError> {
+        let freeze_row = conn
+            .query(
+                "SELECT Default_Value FROM DistributionParameter WITH (NOLOCK) WHERE Default_Key = 'Freeze_Inventory'",
+                &[],
+            )
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to read Freeze_Inventory: {e}")))?
+            .into_row()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(freeze_row.is_some_and(|row| flag_column_is_set(&row, "Default_Value")))
+    }
+
+    async fn physical_count_in_progress(
+        &self,
+        conn: &mut SqlConnection,
+        item_key: &str,
+        location: &str,
+    ) -> Result<bool, PutawayError> {
+        let count_row = conn
+            .query(
+                "SELECT Physinprogress FROM INLOC WITH (NOLOCK) WHERE ItemKey = @P1 AND Location = @P2",
+                &[&item_key, &location],
+            )
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to read INLOC.Physinprogress: {e}")))?
+            .into_row()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(count_row.is_some_and(|row| flag_column_is_set(&row, "Physinprogress")))
+    }
+
+    async fn inloc_record(
+        &self,
+        conn: &mut SqlConnection,
+        item_key: &str,
+        location: &str,
+    ) -> Result<InlocRecord, PutawayError> {
         let query = r#"
             SELECT ItemKey, Location, Inclasskey, Revacct, Cogsacct, Stdcost
             FROM INLOC WITH (NOLOCK)
             WHERE ItemKey = @P1 AND Location = @P2
         "#;
 
-        let result = client
+        let result = conn
             .query(query, &[&item_key, &location])
             .await
             .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
@@ -217,592 +451,1390 @@ impl PutawayDatabase {
         }
     }
 
-    /// Execute complete bin transfer transaction with lot consolidation
-    #[allow(clippy::too_many_arguments)]
-    pub async fn execute_bin_transfer_transaction(
-        &self,
-        lot_no: &str,
-        item_key: &str,
-        location: &str,
-        bin_from: &str,
-        bin_to: &str,
-        transfer_qty: f64,
-        user_id: &str,
-        remarks: &str,
-        referenced: &str,
-    ) -> Result<(String, Option<String>, Option<String>), PutawayError> {
-        // Get database client (TFCPILOT3 primary)
-        let mut client = self
-            .db
-            .get_client()
-            .await
-            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+    async fn item_qty_decimals(&self, conn: &mut SqlConnection, item_key: &str) -> Result<Option<i64>, PutawayError> {
+        let query = r#"
+            SELECT TOP 1 CAST(u.UMQtyDP AS INT) AS QtyDecimals
+            FROM INMAST i
+            JOIN INUOMD u ON u.UOMKey = i.Stockuomcode
+            WHERE i.Itemkey = @P1
+            ORDER BY u.UMQtyDP DESC
+        "#;
 
-        // **🔒 BEGIN TRANSACTION** - Ensure atomic 6-step putaway operation
-        // Set REPEATABLE READ isolation level for stronger consistency
-        client
-            .simple_query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+        let row = conn
+            .query(query, &[&item_key])
             .await
-            .map_err(|e| PutawayError::DatabaseError(format!("Failed to set isolation level: {e}")))?;
-
-        client
-            .simple_query("BEGIN TRANSACTION")
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to read UOM precision of {item_key}: {e}")))?
+            .into_row()
             .await
-            .map_err(|e| PutawayError::DatabaseError(format!("Failed to begin transaction: {e}")))?;
-
-        // Execute all 6 steps in a transaction
-        let transaction_result: Result<String, PutawayError> = async {
-        // 1. Get next BT document number
-        let bt_number = self.get_next_bt_sequence().await?;
-        let document_no = format!("BT-{bt_number:08}");
-        let now = bangkok_now().naive_local();
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
 
-        // Truncate user ID to 8 characters for database field compatibility
-        let user_id_truncated = if user_id.len() > 8 {
-            &user_id[0..8]
-        } else {
-            user_id
-        };
+        Ok(row.and_then(|row| row.get::<i32, _>("QtyDecimals")).map(i64::from))
+    }
 
-        // **🔒 STEP 1.5: LOCK LOTMASTER FIRST** - Prevent deadlocks by acquiring locks in global order
-        // Lock BOTH source and destination bins in alphabetical order to prevent circular waits
-        let lock_lots_query = r#"
-            SELECT LotNo, ItemKey, LocationKey, BinNo, QtyOnHand, QtyCommitSales
-            FROM LotMaster WITH (UPDLOCK, ROWLOCK)
-            WHERE (LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3 AND BinNo = @P4)
-               OR (LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3 AND BinNo = @P5)
-            ORDER BY BinNo ASC
+    async fn item_master(&self, conn: &mut SqlConnection, item_key: &str) -> Result<Option<ItemMasterRecord>, PutawayError> {
+        let query = r#"
+            SELECT Itemkey AS ItemKey, Desc1, Desc2, Stockuomcode, Purchaseuomcode, Salesuomcode
+            FROM INMAST
+            WHERE Itemkey = @P1
         "#;
 
-        let locked_lots = client.query(lock_lots_query, &[&lot_no, &item_key, &location, &bin_from, &bin_to])
+        let row = conn
+            .query(query, &[&item_key])
             .await
-            .map_err(|e| PutawayError::DatabaseError(format!("Failed to lock LotMaster records: {e}")))?
-            .into_results()
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to read INMAST {item_key}: {e}")))?
+            .into_row()
             .await
-            .map_err(|e| PutawayError::DatabaseError(format!("Failed to get locked lots: {e}")))?;
-
-        if locked_lots.is_empty() || locked_lots[0].is_empty() {
-            return Err(PutawayError::ValidationError("Source bin not found for locking".to_string()));
-        }
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
 
-        // 2. Create Mintxdh record for audit trail
-        let inloc_record = self.get_inloc_record(item_key, location).await?;
-        let in_acct = map_inclasskey_to_inacct(&inloc_record.inclasskey);
-        let std_cost = inloc_record.stdcost;
-        let trn_desc = "Bin Transfer";
+        Ok(row.as_ref().map(item_master_from_row))
+    }
 
-        let mintxdh_query = r#"
-            INSERT INTO Mintxdh (
-                ItemKey, Location, ToLocation, SysID, ProcessID, SysDocID, SysLinSq,
-                TrnTyp, TrnSubTyp, DocNo, DocDate, AplDate, TrnDesc, TrnQty, TrnAmt,
-                NLAcct, INAcct, CreatedSerlot, RecUserID, RecDate, Updated_FinTable,
-                SortField, JrnlBtchNo, StdCost, Stdcostupdated, GLtrnAmt
-            ) VALUES (
-                @P1, @P2, '', '7', 'M', @P3, 1, 'A', '', @P4, @P5, @P5, @P6, 0, 0.000000,
-                '1100', @P7, 'Y', @P8, @P9, 0, '', '', @P10, 0, 0.000000
-            )
+    async fn uom_conversion(
+        &self,
+        conn: &mut SqlConnection,
+        item_key: &str,
+        from_uom: &str,
+        to_uom: &str,
+    ) -> Result<Option<UomConversionRecord>, PutawayError> {
+        // Same scope rule as the BME conversion lookup (ORDER BY UMScope DESC)
+        let query = r#"
+            SELECT TOP 1
+                CAST(A.UMScope AS INT) AS UMScope, A.UMItemClassKey, A.UMItemKey, A.FromKey, A.ToKey,
+                CAST(A.convfctr AS DECIMAL(28,10)) AS ConvFact, A.Operation
+            FROM INQTYCNV A
+            WHERE A.FromKey = @P2 AND A.ToKey = @P3
+              AND (A.UMScope = 0
+                   OR A.UMItemClassKey IN (SELECT InClassKey FROM INLOC WHERE ItemKey = @P1)
+                   OR A.UMItemKey = @P1)
+            ORDER BY A.UMScope DESC
         "#;
 
-        client
-            .execute(
-                mintxdh_query,
-                &[
-                    &item_key,
-                    &location,
-                    &document_no,
-                    &document_no,
-                    &now,
-                    &trn_desc,
-                    &in_acct,
-                    &user_id_truncated,
-                    &now,
-                    &std_cost,
-                ],
-            )
+        let row = conn
+            .query(query, &[&item_key, &from_uom, &to_uom])
             .await
             .map_err(|e| {
-                PutawayError::TransactionError(format!("Failed to create Mintxdh record: {e}"))
-            })?;
-
-        // Get current source bin quantity for QtyOnHand field in BinTransfer
-        let source_qty_result = client.query(
-            "SELECT QtyOnHand FROM LotMaster WHERE LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3 AND BinNo = @P4",
-            &[&lot_no, &item_key, &location, &bin_from]
-        ).await.map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
-
-        let source_qty_on_hand: f64 = if let Some(row) = source_qty_result
+                PutawayError::DatabaseError(format!("Failed to read INQTYCNV {from_uom} -> {to_uom}: {e}"))
+            })?
             .into_row()
             .await
-            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
-        {
-            row.get("QtyOnHand").unwrap_or(0.0)
-        } else {
-            return Err(PutawayError::ValidationError(
-                "Source bin record not found".to_string(),
-            ));
-        };
-
-        // 3. Create Issue Transaction (Type 9 - Remove from source bin)
-        let issue_transaction_query = r#"
-            INSERT INTO LotTransaction (
-                LotNo, ItemKey, LocationKey, TransactionType, 
-                IssueDocNo, IssueDocLineNo, IssueDate, QtyIssued,
-                BinNo, RecUserid, RecDate, Processed,
-                DateReceived, DateExpiry, Vendorkey, VendorlotNo,
-                CustomerKey, TempQty, QtyForLotAssignment, QtyUsed
-            ) OUTPUT INSERTED.LotTranNo
-            VALUES (@P1, @P2, @P3, 9, @P4, 1, @P5, @P6, @P7, @P8, @P9, 'Y',
-                    @P10, @P11, @P12, @P13, '', 0, 0, 0)
-        "#;
-
-        // Get lot details including vendor info for transaction
-        let lot_details_result = client
-            .query("SELECT DateReceived, DateExpiry, VendorKey, VendorLotNo FROM LotMaster WHERE LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3 AND BinNo = @P4",
-                   &[&lot_no, &item_key, &location, &bin_from]).await
             .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
 
-        let (date_received, date_expiry, vendor_key, vendor_lot_no) = if let Some(row) =
-            lot_details_result
-                .into_row()
-                .await
-                .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
-        {
-            (
-                row.get::<NaiveDateTime, _>("DateReceived")
-                    .unwrap_or(now),
-                row.get::<NaiveDateTime, _>("DateExpiry")
-                    .unwrap_or(now),
-                row.get::<&str, _>("VendorKey").unwrap_or("").to_string(),
-                row.get::<&str, _>("VendorLotNo").unwrap_or("").to_string(),
-            )
-        } else {
-            return Err(PutawayError::ValidationError(
-                "Cannot get lot details for transaction".to_string(),
-            ));
-        };
+        Ok(row.map(|row| UomConversionRecord {
+            scope: row.get::<i32, _>("UMScope").unwrap_or(0),
+            item_class_key: row.get::<&str, _>("UMItemClassKey").unwrap_or("").to_string(),
+            item_key: row.get::<&str, _>("UMItemKey").unwrap_or("").to_string(),
+            from_uom: row.get::<&str, _>("FromKey").unwrap_or("").to_string(),
+            to_uom: row.get::<&str, _>("ToKey").unwrap_or("").to_string(),
+            conv_factor: row.get::<BigDecimal, _>("ConvFact").unwrap_or_default(),
+            operation: row.get::<&str, _>("Operation").unwrap_or("").trim().to_string(),
+        }))
+    }
 
-        let issue_result = client
+    async fn bin_exists(
+        &self,
+        conn: &mut SqlConnection,
+        location: &str,
+        bin_no: &str,
+    ) -> Result<bool, PutawayError> {
+        let row = conn
             .query(
-                issue_transaction_query,
-                &[
-                    &lot_no,
-                    &item_key,
-                    &location,
-                    &document_no,
-                    &now,
-                    &transfer_qty,
-                    &bin_from,
-                    &user_id_truncated,
-                    &now,
-                    &date_received,
-                    &date_expiry,
-                    &vendor_key,
-                    &vendor_lot_no,
-                ],
+                "SELECT COUNT(*) as count FROM BINMaster WHERE Location = @P1 AND BinNo = @P2",
+                &[&location, &bin_no],
             )
             .await
-            .map_err(|e| {
-                PutawayError::TransactionError(format!("Failed to create issue transaction: {e}"))
-            })?;
-
-        let issue_lot_tran_no: i32 = if let Some(row) = issue_result
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
             .into_row()
             .await
-            .map_err(|e| PutawayError::TransactionError(e.to_string()))?
-        {
-            row.get("LotTranNo").unwrap_or(0)
-        } else {
-            return Err(PutawayError::TransactionError(
-                "Failed to get issue LotTranNo".to_string(),
-            ));
-        };
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
 
-        // 4. Create Receipt Transaction (Type 8 - Add to destination bin)
-        let receipt_transaction_query = r#"
-            INSERT INTO LotTransaction (
-                LotNo, ItemKey, LocationKey, TransactionType,
-                ReceiptDocNo, ReceiptDocLineNo, QtyReceived,
-                BinNo, RecUserid, RecDate, Processed,
-                DateReceived, DateExpiry, Vendorkey, VendorlotNo,
-                CustomerKey, TempQty, QtyForLotAssignment, QtyUsed
-            ) VALUES (@P1, @P2, @P3, 8, @P4, 1, @P5, @P6, @P7, @P8, 'Y',
-                     @P9, @P10, @P11, @P12, '', 0, 0, 0)
-        "#;
+        Ok(row.and_then(|row| row.get::<i32, _>("count")).unwrap_or(0) > 0)
+    }
 
-        client
-            .execute(
-                receipt_transaction_query,
-                &[
-                    &lot_no,
-                    &item_key,
-                    &location,
-                    &document_no,
-                    &transfer_qty,
-                    &bin_to,
-                    &user_id_truncated,
-                    &now,
-                    &date_received,
-                    &date_expiry,
-                    &vendor_key,
-                    &vendor_lot_no,
-                ],
-            )
+    async fn bin_capacity(
+        &self,
+        conn: &mut SqlConnection,
+        location: &str,
+        bin_no: &str,
+    ) -> Result<Option<BinCapacityRecord>, PutawayError> {
+        let query = format!(
+            "SELECT {BIN_CAPACITY_COLUMNS} FROM dbo.putaway_bin_capacity c WHERE c.location = @P1 AND c.bin_no = @P2"
+        );
+
+        let row = conn
+            .query(query, &[&location, &bin_no])
             .await
-            .map_err(|e| {
-                PutawayError::TransactionError(format!(
-                    "Failed to create receipt transaction: {e}"
-                ))
-            })?;
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to read capacity of bin {bin_no}: {e}")))?
+            .into_row()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
 
-        // 5. Create BinTransfer record (with issue LotTranNo reference)
-        let bin_transfer_query = r#"
-            INSERT INTO BinTransfer (
-                ItemKey, Location, LotNo, BinNoFrom, BinNoTo, 
-                LotTranNo, QtyOnHand, TransferQty, InTransID, 
-                RecUserID, RecDate, ContainerNo, User1, User5
-            ) VALUES (@P1, @P2, @P3, @P4, @P5, @P6, @P7, @P8, 0, @P9, @P10, '0', @P11, @P12)
-        "#;
+        Ok(row.as_ref().and_then(bin_capacity_from_row))
+    }
 
-        // Use truncated user_id for BinTransfer.RecUserID field
+    async fn bin_block(
+        &self,
+        conn: &mut SqlConnection,
+        location: &str,
+        bin_no: &str,
+    ) -> Result<Option<BinBlockRecord>, PutawayError> {
+        let query = format!(
+            "SELECT {BIN_BLOCK_COLUMNS} FROM dbo.putaway_bin_blocks k WITH (UPDLOCK, ROWLOCK) WHERE k.location = @P1 AND k.bin_no = @P2"
+        );
 
-        client
-            .execute(
-                bin_transfer_query,
-                &[
-                    &item_key,
-                    &location,
-                    &lot_no,
-                    &bin_from,
-                    &bin_to,
-                    &issue_lot_tran_no,
-                    &source_qty_on_hand,
-                    &transfer_qty,
-                    &user_id_truncated,
-                    &now,
-                    &remarks,
-                    &referenced,
-                ],
-            )
+        let row = conn
+            .query(query, &[&location, &bin_no])
             .await
-            .map_err(|e| {
-                PutawayError::TransactionError(format!(
-                    "Failed to create bin transfer record: {e}"
-                ))
-            })?;
-
-        // 6. Handle LotMaster lot consolidation logic
-        self.handle_lot_consolidation(
-            &mut client,
-            lot_no,
-            item_key,
-            location,
-            bin_from,
-            bin_to,
-            transfer_qty,
-            &document_no,
-            user_id,
-            &now,
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to read block of bin {bin_no}: {e}")))?
+            .into_row()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(row.as_ref().map(bin_block_from_row))
+    }
+
+    async fn save_bin_block(&self, conn: &mut SqlConnection, row: &BinBlockRecord) -> Result<(), PutawayError> {
+        let query = r#"
+            MERGE dbo.putaway_bin_blocks WITH (HOLDLOCK) AS k
+            USING (SELECT @P1 AS location, @P2 AS bin_no) AS s
+               ON k.location = s.location AND k.bin_no = s.bin_no
+            WHEN MATCHED THEN
+                UPDATE SET reason = @P3, blocked_until = @P4, rec_user_id = @P5, rec_date = @P6
+            WHEN NOT MATCHED THEN
+                INSERT (location, bin_no, reason, blocked_until, rec_user_id, rec_date)
+                VALUES (@P1, @P2, @P3, @P4, @P5, @P6);
+        "#;
+
+        conn.execute(
+            query,
+            &[&row.location, &row.bin_no, &row.reason, &row.blocked_until, &row.rec_user_id, &row.rec_date],
         )
-        .await?;
+        .await
+        .map_err(|e| PutawayError::TransactionError(format!("Failed to block bin {}: {e}", row.bin_no)))?;
 
-        Ok(document_no)
-        }.await;
+        Ok(())
+    }
 
-        // **🔒 COMMIT or ROLLBACK** - Atomic transaction handling
-        match transaction_result {
-            Ok(doc_no) => {
-                // All 6 steps succeeded - commit the transaction
-                client
-                    .simple_query("COMMIT")
-                    .await
-                    .map_err(|e| PutawayError::DatabaseError(format!("Failed to commit transaction: {e}")))?;
+    async fn delete_bin_block(&self, conn: &mut SqlConnection, location: &str, bin_no: &str) -> Result<(), PutawayError> {
+        conn.execute(
+            "DELETE FROM dbo.putaway_bin_blocks WHERE location = @P1 AND bin_no = @P2",
+            &[&location, &bin_no],
+        )
+        .await
+        .map_err(|e| PutawayError::TransactionError(format!("Failed to unblock bin {bin_no}: {e}")))?;
 
-                // Query source lot status (from original bin, may have been deleted if full transfer)
-                let source_status = self.get_lot_status(&mut client, lot_no, item_key, location, bin_from).await;
+        Ok(())
+    }
 
-                // Query destination lot status (should exist after transfer)
-                let dest_status = self.get_lot_status(&mut client, lot_no, item_key, location, bin_to).await;
+    async fn insert_bin_block_event(&self, conn: &mut SqlConnection, event: &BinBlockEvent) -> Result<(), PutawayError> {
+        let query = r#"
+            INSERT INTO dbo.putaway_bin_block_history (
+                location, bin_no, action, reason, blocked_until, rec_user_id, rec_date
+            ) VALUES (@P1, @P2, @P3, @P4, @P5, @P6, @P7)
+        "#;
 
-                Ok((doc_no, source_status, dest_status))
-            }
-            Err(e) => {
-                // Any step failed - rollback the entire transaction
-                let _ = client.simple_query("ROLLBACK").await;
-                Err(e)
-            }
+        conn.execute(
+            query,
+            &[
+                &event.location, &event.bin_no, &event.action.code(), &event.reason, &event.blocked_until,
+                &event.user_id, &event.event_date,
+            ],
+        )
+        .await
+        .map_err(|e| PutawayError::TransactionError(format!("Failed to record block history of bin {}: {e}", event.bin_no)))?;
+
+        Ok(())
+    }
+
+    async fn lock_lot_rows(
+        &self,
+        conn: &mut SqlConnection,
+        lot: LotKey<'_>,
+        bins: &[&str],
+    ) -> Result<Vec<LotMasterRecord>, PutawayError> {
+        if bins.is_empty() {
+            return Ok(Vec::new());
         }
+
+        let bin_params = (0..bins.len()).map(|i| format!("@P{}", i + 4)).collect::<Vec<_>>().join(", ");
+        let query = format!(
+            "SELECT {LOT_MASTER_COLUMNS} FROM LotMaster WITH (UPDLOCK, ROWLOCK)
+             WHERE LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3 AND BinNo IN ({bin_params})
+             ORDER BY BinNo ASC"
+        );
+
+        let mut params: Vec<&dyn ToSql> = vec![&lot.lot_no, &lot.item_key, &lot.location];
+        params.extend(bins.iter().map(|bin| bin as &dyn ToSql));
+
+        let rows = conn
+            .query(query, &params)
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to lock LotMaster records: {e}")))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to get locked lots: {e}")))?;
+
+        Ok(rows.iter().map(lot_master_from_row).collect())
     }
 
-    /// Get lot status from LotMaster for a specific bin
-    async fn get_lot_status(
+    async fn lock_bin_lot_rows(
         &self,
-        client: &mut tiberius::Client<tokio_util::compat::Compat<tokio::net::TcpStream>>,
-        lot_no: &str,
-        item_key: &str,
+        conn: &mut SqlConnection,
         location: &str,
         bin_no: &str,
-    ) -> Option<String> {
-        let query = "SELECT LotStatus FROM LotMaster WHERE LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3 AND BinNo = @P4";
+    ) -> Result<Vec<LotMasterRecord>, PutawayError> {
+        let query = format!(
+            "SELECT {LOT_MASTER_COLUMNS} FROM LotMaster WITH (UPDLOCK, HOLDLOCK)
+             WHERE LocationKey = @P1 AND BinNo = @P2
+             ORDER BY LotNo ASC, ItemKey ASC"
+        );
+
+        let rows = conn
+            .query(query, &[&location, &bin_no])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to lock LotMaster records of bin {bin_no}: {e}")))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to get locked bin contents: {e}")))?;
 
-        match client.query(query, &[&lot_no, &item_key, &location, &bin_no]).await {
-            Ok(stream) => {
-                match stream.into_row().await {
-                    Ok(Some(row)) => row.get::<&str, _>("LotStatus").map(|s| s.to_string()),
-                    _ => None,
-                }
+        Ok(rows.iter().map(lot_master_from_row).collect())
+    }
+
+    async fn lot_row(
+        &self,
+        conn: &mut SqlConnection,
+        lot: LotKey<'_>,
+        bin_no: &str,
+    ) -> Result<Option<LotMasterRecord>, PutawayError> {
+        let query = format!(
+            "SELECT {LOT_MASTER_COLUMNS} FROM LotMaster
+             WHERE LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3 AND BinNo = @P4"
+        );
+
+        let row = conn
+            .query(query, &[&lot.lot_no, &lot.item_key, &lot.location, &bin_no])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .into_row()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(row.as_ref().map(lot_master_from_row))
+    }
+
+    async fn insert_lot_row(&self, conn: &mut SqlConnection, row: &LotMasterRecord) -> Result<(), PutawayError> {
+        let insert_query = r#"
+            INSERT INTO LotMaster (
+                LotNo, ItemKey, LocationKey, DateReceived, DateExpiry,
+                QtyReceived, QtyIssued, QtyCommitSales, QtyOnHand,
+                DocumentNo, DocumentLineNo, TransactionType, VendorKey, VendorLotNo,
+                QtyOnOrder, RecUserId, Recdate, BinNo, LotStatus
+            ) VALUES (
+                @P1, @P2, @P3, @P4, @P5, @P6, @P7, @P8, @P9, @P10, @P11, @P12, @P13, @P14,
+                0, @P15, @P16, @P17, @P18
+            )
+        "#;
+
+        conn.execute(
+            insert_query,
+            &[
+                &row.lot_no,
+                &row.item_key,
+                &row.location_key,
+                &row.date_received,
+                &row.date_expiry,
+                &row.qty_received,
+                &row.qty_issued,
+                &row.qty_commit_sales,
+                &row.qty_on_hand,
+                &row.document_no,
+                &row.document_line_no,
+                &row.transaction_type,
+                &row.vendor_key,
+                &row.vendor_lot_no,
+                &row.rec_user_id,
+                &row.rec_date,
+                &row.bin_no,
+                &row.lot_status,
+            ],
+        )
+        .await
+        .map_err(|e| PutawayError::TransactionError(format!("Failed to create destination record: {e}")))?;
+
+        Ok(())
+    }
+
+    async fn update_lot_row(&self, conn: &mut SqlConnection, row: &LotMasterRecord) -> Result<(), PutawayError> {
+        let update_query = r#"
+            UPDATE LotMaster
+            SET QtyOnHand = @P1, QtyCommitSales = @P2,
+                DocumentNo = @P3, TransactionType = @P4,
+                RecUserId = @P5, Recdate = @P6
+            WHERE LotNo = @P7 AND ItemKey = @P8 AND LocationKey = @P9 AND BinNo = @P10
+        "#;
+
+        conn.execute(
+            update_query,
+            &[
+                &row.qty_on_hand,
+                &row.qty_commit_sales,
+                &row.document_no,
+                &row.transaction_type,
+                &row.rec_user_id,
+                &row.rec_date,
+                &row.lot_no,
+                &row.item_key,
+                &row.location_key,
+                &row.bin_no,
+            ],
+        )
+        .await
+        .map_err(|e| PutawayError::TransactionError(format!("Failed to update LotMaster bin {}: {e}", row.bin_no)))?;
+
+        Ok(())
+    }
+
+    async fn delete_lot_row(&self, conn: &mut SqlConnection, lot: LotKey<'_>, bin_no: &str) -> Result<(), PutawayError> {
+        conn.execute(
+            "DELETE FROM LotMaster WHERE LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3 AND BinNo = @P4",
+            &[&lot.lot_no, &lot.item_key, &lot.location, &bin_no],
+        )
+        .await
+        .map_err(|e| PutawayError::TransactionError(format!("Failed to delete source record: {e}")))?;
+
+        Ok(())
+    }
+
+    async fn insert_lot_transaction(
+        &self,
+        conn: &mut SqlConnection,
+        row: &LotTransactionRecord,
+    ) -> Result<i32, PutawayError> {
+        let mut columns: Vec<(&str, &dyn ToSql)> = vec![
+            ("LotNo", &row.lot_no),
+            ("ItemKey", &row.item_key),
+            ("LocationKey", &row.location_key),
+            ("DateReceived", &row.date_received),
+            ("TransactionType", &row.transaction_type),
+            ("VendorlotNo", &row.vendor_lot_no),
+            ("BinNo", &row.bin_no),
+            ("RecUserid", &row.rec_userid),
+            ("RecDate", &row.rec_date),
+            ("Processed", &row.processed),
+        ];
+
+        // Optional columns are only written when set, like the hand-written BME statements
+        let optional: [(&str, Option<&dyn ToSql>); 14] = [
+            ("DateExpiry", row.date_expiry.as_ref().map(|v| v as &dyn ToSql)),
+            ("IssueDocNo", row.issue_doc_no.as_ref().map(|v| v as &dyn ToSql)),
+            ("IssueDocLineNo", row.issue_doc_line_no.as_ref().map(|v| v as &dyn ToSql)),
+            ("IssueDate", row.issue_date.as_ref().map(|v| v as &dyn ToSql)),
+            ("QtyIssued", row.qty_issued.as_ref().map(|v| v as &dyn ToSql)),
+            ("ReceiptDocNo", row.receipt_doc_no.as_ref().map(|v| v as &dyn ToSql)),
+            ("ReceiptDocLineNo", row.receipt_doc_line_no.as_ref().map(|v| v as &dyn ToSql)),
+            ("QtyReceived", row.qty_received.as_ref().map(|v| v as &dyn ToSql)),
+            ("Vendorkey", row.vendor_key.as_ref().map(|v| v as &dyn ToSql)),
+            ("CustomerKey", row.customer_key.as_ref().map(|v| v as &dyn ToSql)),
+            ("DateQuarantine", row.date_quarantine.as_ref().map(|v| v as &dyn ToSql)),
+            ("TempQty", row.temp_qty.as_ref().map(|v| v as &dyn ToSql)),
+            ("QtyForLotAssignment", row.qty_for_lot_assignment.as_ref().map(|v| v as &dyn ToSql)),
+            ("QtyUsed", row.qty_used.as_ref().map(|v| v as &dyn ToSql)),
+        ];
+        columns.extend(optional.into_iter().filter_map(|(name, value)| value.map(|value| (name, value))));
+
+        let names = columns.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ");
+        let values = (1..=columns.len()).map(|i| format!("@P{i}")).collect::<Vec<_>>().join(", ");
+        let query = format!("INSERT INTO LotTransaction ({names}) OUTPUT INSERTED.LotTranNo VALUES ({values})");
+        let params: Vec<&dyn ToSql> = columns.iter().map(|(_, value)| *value).collect();
+
+        let kind = if row.transaction_type == 9 { "issue" } else { "receipt" };
+        let inserted = conn
+            .query(query, &params)
+            .await
+            .map_err(|e| PutawayError::TransactionError(format!("Failed to create {kind} transaction: {e}")))?
+            .into_row()
+            .await
+            .map_err(|e| PutawayError::TransactionError(e.to_string()))?;
+
+        inserted
+            .and_then(|row| row.get::<i32, _>("LotTranNo"))
+            .ok_or_else(|| PutawayError::TransactionError(format!("Failed to get {kind} LotTranNo")))
+    }
+
+    async fn bin_commitment(
+        &self,
+        conn: &mut SqlConnection,
+        lot: LotKey<'_>,
+        bin_no: &str,
+    ) -> Result<BigDecimal, PutawayError> {
+        let query = format!(
+            r#"
+            SELECT CAST(COALESCE(c.Commitment, 0) AS DECIMAL(28,10)) AS Commitment
+            FROM (SELECT @P1 AS ItemKey, @P2 AS LocationKey, @P3 AS LotNo, @P4 AS BinNo) AS l
+            {BIN_COMMITMENT_APPLY}
+            "#
+        );
+
+        let row = conn
+            .query(query.as_str(), &[&lot.item_key, &lot.location, &lot.lot_no, &bin_no])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to calculate commitment: {e}")))?
+            .into_row()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(row.and_then(|r| r.get::<BigDecimal, _>("Commitment")).unwrap_or_default())
+    }
+
+    async fn lock_pending_commitment_line(
+        &self,
+        conn: &mut SqlConnection,
+        lot_tran_no: i32,
+        lot: LotKey<'_>,
+        source_bin: &str,
+        target_bin: &str,
+    ) -> Result<Option<MovedCommitmentLine>, PutawayError> {
+        for table in ["LotTransaction", "QCLotTransaction"] {
+            let query = format!(
+                r#"
+                SELECT LotTranNo, IssueDocNo, IssueDocLineNo, CAST(QtyIssued AS DECIMAL(28,10)) AS QtyIssued, TransactionType
+                FROM {table} WITH (UPDLOCK, ROWLOCK)
+                WHERE LotTranNo = @P1
+                  AND LotNo = @P2 AND ItemKey = @P3 AND LocationKey = @P4 AND BinNo = @P5
+                  AND Processed IN ('N','P')
+                  AND TransactionType IN (2,3,5,7,9,10,12,16,17,20,21)
+                "#
+            );
+            let row = conn
+                .query(query.as_str(), &[&lot_tran_no, &lot.lot_no, &lot.item_key, &lot.location, &source_bin])
+                .await
+                .map_err(|e| PutawayError::DatabaseError(format!("Failed to lock {table} line {lot_tran_no}: {e}")))?
+                .into_row()
+                .await
+                .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+            if let Some(row) = row {
+                return Ok(Some(MovedCommitmentLine {
+                    lot_tran_no,
+                    source_table: table.to_string(),
+                    doc_no: row.get::<&str, _>("IssueDocNo").unwrap_or("").to_string(),
+                    doc_line_no: row.get::<i16, _>("IssueDocLineNo"),
+                    transaction_type: row.get::<u8, _>("TransactionType").unwrap_or(0),
+                    qty: row.get::<BigDecimal, _>("QtyIssued").unwrap_or_default(),
+                    bin_from: source_bin.to_string(),
+                    bin_to: target_bin.to_string(),
+                }));
             }
-            Err(_) => None,
         }
+
+        Ok(None)
     }
 
-    /// Handle lot consolidation logic for LotMaster records
-    #[allow(clippy::too_many_arguments)]
-    async fn handle_lot_consolidation(
+    async fn repoint_commitment_line(
         &self,
-        client: &mut tiberius::Client<tokio_util::compat::Compat<tokio::net::TcpStream>>,
-        lot_no: &str,
-        item_key: &str,
-        location: &str,
-        bin_from: &str,
-        bin_to: &str,
-        transfer_qty: f64,
+        conn: &mut SqlConnection,
+        line: &MovedCommitmentLine,
+    ) -> Result<(), PutawayError> {
+        // Table name comes from lock_pending_commitment_line, never from the request
+        let table = match line.source_table.as_str() {
+            "QCLotTransaction" => "QCLotTransaction",
+            _ => "LotTransaction",
+        };
+        let update = format!("UPDATE {table} SET BinNo = @P1 WHERE LotTranNo = @P2 AND BinNo = @P3");
+        conn.execute(update.as_str(), &[&line.bin_to, &line.lot_tran_no, &line.bin_from])
+            .await
+            .map_err(|e| PutawayError::TransactionError(format!(
+                "Failed to re-point {} line {}: {e}", line.source_table, line.lot_tran_no
+            )))?;
+
+        Ok(())
+    }
+
+    async fn insert_commitment_move(
+        &self,
+        conn: &mut SqlConnection,
         document_no: &str,
+        line: &MovedCommitmentLine,
+        lot: LotKey<'_>,
         user_id: &str,
         now: &NaiveDateTime,
     ) -> Result<(), PutawayError> {
-        // Truncate user ID to 8 characters for database field compatibility
-        let user_id_truncated = if user_id.len() > 8 {
-            &user_id[0..8]
-        } else {
-            user_id
-        };
+        let audit = r#"
+            INSERT INTO dbo.putaway_commitment_moves (
+                bt_document_no, source_table, lot_tran_no, doc_no, doc_line_no, transaction_type,
+                lot_no, item_key, location, bin_from, bin_to, qty, rec_user_id, rec_date
+            ) VALUES (@P1, @P2, @P3, @P4, @P5, @P6, @P7, @P8, @P9, @P10, @P11, @P12, @P13, @P14)
+        "#;
+        conn.execute(audit, &[
+                &document_no, &line.source_table.as_str(), &line.lot_tran_no, &line.doc_no.as_str(),
+                &line.doc_line_no, &line.transaction_type, &lot.lot_no, &lot.item_key, &lot.location,
+                &line.bin_from.as_str(), &line.bin_to.as_str(), &line.qty, &user_id, now,
+            ])
+            .await
+            .map_err(|e| PutawayError::TransactionError(format!("Failed to audit moved line {}: {e}", line.lot_tran_no)))?;
+
+        Ok(())
+    }
+
+    async fn bt_document_lines(
+        &self,
+        conn: &mut SqlConnection,
+        document_no: &str,
+    ) -> Result<Vec<BtDocumentLine>, PutawayError> {
+        let lines_query = r#"
+            SELECT
+                i.LotTranNo, i.IssueDocLineNo, i.LotNo, i.ItemKey, i.LocationKey,
+                i.BinNo AS BinFrom, r.BinNo AS BinTo,
+                CAST(i.QtyIssued AS DECIMAL(28,10)) AS Qty, i.Processed,
+                (SELECT COUNT(*) FROM BinTransfer bt WHERE bt.LotTranNo = i.LotTranNo) AS BinTransferCount
+            FROM LotTransaction i
+            JOIN LotTransaction r
+              ON r.ReceiptDocNo = i.IssueDocNo
+             AND r.ReceiptDocLineNo = i.IssueDocLineNo
+             AND r.TransactionType = 8
+             AND r.LotNo = i.LotNo AND r.ItemKey = i.ItemKey AND r.LocationKey = i.LocationKey
+            WHERE i.IssueDocNo = @P1 AND i.TransactionType = 9
+            ORDER BY i.IssueDocLineNo
+        "#;
+        let rows = conn
+            .query(lines_query, &[&document_no])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| BtDocumentLine {
+                line_no: row.get::<i16, _>("IssueDocLineNo").unwrap_or(1),
+                lot_no: row.get::<&str, _>("LotNo").unwrap_or("").to_string(),
+                item_key: row.get::<&str, _>("ItemKey").unwrap_or("").to_string(),
+                location: row.get::<&str, _>("LocationKey").unwrap_or("").to_string(),
+                bin_from: row.get::<&str, _>("BinFrom").unwrap_or("").to_string(),
+                bin_to: row.get::<&str, _>("BinTo").unwrap_or("").to_string(),
+                qty: row.get::<BigDecimal, _>("Qty").unwrap_or_default(),
+                processed: row.get::<&str, _>("Processed").unwrap_or("").to_string(),
+                bin_transfer_count: row.get::<i32, _>("BinTransferCount").unwrap_or(0),
+            })
+            .collect())
+    }
+
+    async fn insert_bin_transfer(&self, conn: &mut SqlConnection, row: &BinTransferRecord) -> Result<(), PutawayError> {
+        let bin_transfer_query = r#"
+            INSERT INTO BinTransfer (
+                ItemKey, Location, LotNo, BinNoFrom, BinNoTo, 
+                LotTranNo, QtyOnHand, TransferQty, InTransID, 
+                RecUserID, RecDate, ContainerNo, User1, User5
+            ) VALUES (@P1, @P2, @P3, @P4, @P5, @P6, @P7, @P8, @P9, @P10, @P11, @P12, @P13, @P14)
+        "#;
+
+        conn.execute(
+            bin_transfer_query,
+            &[
+                &row.item_key,
+                &row.location,
+                &row.lot_no,
+                &row.bin_no_from,
+                &row.bin_no_to,
+                &row.lot_tran_no,
+                &row.qty_on_hand,
+                &row.transfer_qty,
+                &row.in_trans_id,
+                &row.rec_user_id,
+                &row.rec_date,
+                &row.container_no,
+                &row.user1,
+                &row.user5,
+            ],
+        )
+        .await
+        .map_err(|e| PutawayError::TransactionError(format!("Failed to create bin transfer record: {e}")))?;
+
+        Ok(())
+    }
+
+    async fn insert_mintxdh(&self, conn: &mut SqlConnection, row: &MintxdhRecord) -> Result<i32, PutawayError> {
+        let mintxdh_query = r#"
+            INSERT INTO Mintxdh (
+                ItemKey, Location, ToLocation, SysID, ProcessID, SysDocID, SysLinSq,
+                TrnTyp, TrnSubTyp, DocNo, DocDate, AplDate, TrnDesc, TrnQty, TrnAmt,
+                NLAcct, INAcct, CreatedSerlot, RecUserID, RecDate, Updated_FinTable,
+                SortField, JrnlBtchNo, StdCost, Stdcostupdated, GLtrnAmt
+            ) OUTPUT INSERTED.InTransID VALUES (
+                @P1, @P2, '', '7', 'M', @P3, 1, 'A', '', @P3, @P4, @P4, @P5, 0, 0.000000,
+                '1100', @P6, 'Y', @P7, @P8, 0, '', '', @P9, 0, 0.000000
+            )
+        "#;
+
+        let inserted = conn
+            .query(
+                mintxdh_query,
+                &[
+                    &row.item_key,
+                    &row.location,
+                    &row.doc_no,
+                    &row.doc_date,
+                    &row.trn_desc,
+                    &row.in_acct,
+                    &row.rec_user_id,
+                    &row.rec_date,
+                    &row.std_cost,
+                ],
+            )
+            .await
+            .map_err(|e| PutawayError::TransactionError(format!("Failed to create Mintxdh record: {e}")))?
+            .into_row()
+            .await
+            .map_err(|e| PutawayError::TransactionError(e.to_string()))?;
+
+        inserted
+            .and_then(|row| row.get::<i32, _>("InTransID"))
+            .ok_or_else(|| PutawayError::TransactionError("Failed to get Mintxdh InTransID".to_string()))
+    }
+
+    async fn mintxdh_exists(&self, conn: &mut SqlConnection, document_no: &str) -> Result<bool, PutawayError> {
+        let header_row = conn
+            .query("SELECT COUNT(*) AS HeaderCount FROM Mintxdh WHERE DocNo = @P1", &[&document_no])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .into_row()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(header_row.and_then(|row| row.get::<i32, _>("HeaderCount")).unwrap_or(0) > 0)
+    }
+
+    async fn mintxdh_headers(&self, conn: &mut SqlConnection, document_no: &str) -> Result<Vec<(i32, NaiveDateTime)>, PutawayError> {
+        let rows = conn
+            .query("SELECT InTransID, RecDate FROM Mintxdh WHERE DocNo = @P1", &[&document_no])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(rows
+            .iter()
+            .filter_map(|row| Some((row.get::<i32, _>("InTransID")?, row.get::<NaiveDateTime, _>("RecDate")?)))
+            .collect())
+    }
+
+    async fn unlinked_bin_transfers(&self, conn: &mut SqlConnection) -> Result<Vec<BinTransferLink>, PutawayError> {
+        let unlinked_query = r#"
+            SELECT LotNo, ItemKey, Location, BinNoFrom, BinNoTo, RecDate, LotTranNo, ISNULL(InTransID, 0) AS InTransID
+            FROM BinTransfer
+            WHERE LotTranNo = 0 OR InTransID = 0 OR InTransID IS NULL
+        "#;
+        let rows = conn
+            .query(unlinked_query, &[])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.iter().map(bin_transfer_link_from_row).collect())
+    }
+
+    async fn lot_bin_transfers(&self, conn: &mut SqlConnection, lot: LotKey<'_>) -> Result<Vec<BinTransferLink>, PutawayError> {
+        let lot_query = r#"
+            SELECT LotNo, ItemKey, Location, BinNoFrom, BinNoTo, RecDate, LotTranNo, ISNULL(InTransID, 0) AS InTransID
+            FROM BinTransfer
+            WHERE LotNo = @P1 AND ItemKey = @P2 AND Location = @P3
+        "#;
+        let rows = conn
+            .query(lot_query, &[&lot.lot_no, &lot.item_key, &lot.location])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.iter().map(bin_transfer_link_from_row).collect())
+    }
+
+    async fn bt_lot_transactions(&self, conn: &mut SqlConnection, lot: LotKey<'_>) -> Result<Vec<BtLotTransaction>, PutawayError> {
+        let transactions_query = r#"
+            SELECT LotTranNo, TransactionType, BinNo, RecDate,
+                   CASE WHEN TransactionType = 9 THEN IssueDocNo ELSE ReceiptDocNo END AS DocumentNo,
+                   CASE WHEN TransactionType = 9 THEN IssueDocLineNo ELSE ReceiptDocLineNo END AS DocumentLineNo
+            FROM LotTransaction
+            WHERE LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3
+              AND ((TransactionType = 9 AND IssueDocNo LIKE 'BT-%') OR (TransactionType = 8 AND ReceiptDocNo LIKE 'BT-%'))
+        "#;
+        let rows = conn
+            .query(transactions_query, &[&lot.lot_no, &lot.item_key, &lot.location])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| BtLotTransaction {
+                lot_tran_no: row.get::<i32, _>("LotTranNo").unwrap_or(0),
+                transaction_type: row.get::<u8, _>("TransactionType").unwrap_or(0),
+                document_no: row.get::<&str, _>("DocumentNo").unwrap_or("").to_string(),
+                line_no: row.get::<i16, _>("DocumentLineNo").unwrap_or(0),
+                bin_no: row.get::<&str, _>("BinNo").unwrap_or("").to_string(),
+                rec_date: row.get::<NaiveDateTime, _>("RecDate").unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    async fn link_bin_transfer(
+        &self,
+        conn: &mut SqlConnection,
+        row: &BinTransferLink,
+        lot_tran_no: i32,
+        in_trans_id: i32,
+    ) -> Result<(), PutawayError> {
+        let link_query = r#"
+            UPDATE BinTransfer SET LotTranNo = @P1, InTransID = @P2
+            WHERE LotNo = @P3 AND ItemKey = @P4 AND Location = @P5 AND BinNoFrom = @P6 AND BinNoTo = @P7
+              AND RecDate = @P8 AND LotTranNo = @P9 AND ISNULL(InTransID, 0) = @P10
+        "#;
+        conn.execute(link_query, &[
+                &lot_tran_no, &in_trans_id, &row.lot_no, &row.item_key, &row.location, &row.bin_no_from, &row.bin_no_to,
+                &row.rec_date, &row.lot_tran_no, &row.in_trans_id,
+            ])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to link BinTransfer row: {e}")))?;
+        Ok(())
+    }
+
+    async fn insert_lot_snapshot(&self, conn: &mut SqlConnection, row: &LotSnapshotRecord) -> Result<(), PutawayError> {
+        let snapshot = r#"
+            INSERT INTO dbo.putaway_bt_lot_snapshots (
+                bt_document_no, line_no, lot_no, item_key, location, bin_no, side,
+                qty_on_hand_before, qty_on_hand_after, qty_commit_before, qty_commit_after, lot_tran_no,
+                rec_user_id, rec_date
+            ) VALUES (@P1, @P2, @P3, @P4, @P5, @P6, @P7, @P8, @P9, @P10, @P11, @P12, @P13, @P14)
+        "#;
+        conn.execute(snapshot, &[
+                &row.document_no, &row.line_no, &row.lot_no, &row.item_key, &row.location, &row.bin_no, &row.side,
+                &row.qty_on_hand_before, &row.qty_on_hand_after, &row.qty_commit_before, &row.qty_commit_after,
+                &row.lot_tran_no, &row.rec_user_id, &row.rec_date,
+            ])
+            .await
+            .map_err(|e| PutawayError::TransactionError(format!(
+                "Failed to record LotMaster snapshot of bin {}: {e}", row.bin_no
+            )))?;
+
+        Ok(())
+    }
+
+    async fn find_bt_reversal(
+        &self,
+        conn: &mut SqlConnection,
+        document_no: &str,
+    ) -> Result<Option<(String, String)>, PutawayError> {
+        let reversal_row = conn
+            .query(
+                r#"
+                SELECT original_document_no, reversal_document_no
+                FROM dbo.putaway_bt_reversals WITH (UPDLOCK, HOLDLOCK)
+                WHERE original_document_no = @P1 OR reversal_document_no = @P1
+                "#,
+                &[&document_no],
+            )
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .into_row()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(reversal_row.map(|row| {
+            (
+                row.get::<&str, _>("original_document_no").unwrap_or("").to_string(),
+                row.get::<&str, _>("reversal_document_no").unwrap_or("").to_string(),
+            )
+        }))
+    }
+
+    async fn insert_bt_reversal(
+        &self,
+        conn: &mut SqlConnection,
+        original_document_no: &str,
+        reversal_document_no: &str,
+        reason: &str,
+        user_id: &str,
+        now: &NaiveDateTime,
+    ) -> Result<(), PutawayError> {
+        conn.execute(
+            r#"
+            INSERT INTO dbo.putaway_bt_reversals (
+                original_document_no, reversal_document_no, reason, rec_user_id, rec_date
+            ) VALUES (@P1, @P2, @P3, @P4, @P5)
+            "#,
+            &[&original_document_no, &reversal_document_no, &reason, &user_id, now],
+        )
+        .await
+        .map_err(|e| PutawayError::TransactionError(format!("Failed to record reversal: {e}")))?;
+
+        Ok(())
+    }
+
+    async fn find_lot_bins(&self, lot_no: &str) -> Result<Vec<LotBinRecord>, PutawayError> {
+        let mut client = self
+            .db
+            .get_client()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        let query = format!(
+            "{LOT_BIN_SELECT} {BIN_COMMITMENT_APPLY} WHERE l.LotNo = @P1 AND l.QtyOnHand > 0 ORDER BY l.LocationKey, l.BinNo"
+        );
+
+        let rows = client
+            .query(query, &[&lot_no])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.iter().map(lot_bin_from_row).collect())
+    }
+
+    async fn bt_document_records(&self, document_no: &str) -> Result<BtDocumentRecords, PutawayError> {
+        let mut conn = self.owned_client().await?;
+
+        let header_query = r#"
+            SELECT TOP 1 InTransID, ItemKey, Location, DocNo, DocDate, TrnDesc, INAcct, RecUserID, RecDate,
+                   CAST(StdCost AS FLOAT) AS StdCost
+            FROM Mintxdh WITH (NOLOCK)
+            WHERE DocNo = @P1
+            ORDER BY InTransID
+        "#;
+        let header = document_rows(&mut conn, header_query, document_no, "Mintxdh header")
+            .await?
+            .first()
+            .map(|row| MintxdhRecord {
+                in_trans_id: row.get::<i32, _>("InTransID"),
+                item_key: row.get::<&str, _>("ItemKey").unwrap_or("").to_string(),
+                location: row.get::<&str, _>("Location").unwrap_or("").to_string(),
+                doc_no: row.get::<&str, _>("DocNo").unwrap_or("").trim().to_string(),
+                doc_date: row.get::<NaiveDateTime, _>("DocDate").unwrap_or_default(),
+                trn_desc: row.get::<&str, _>("TrnDesc").unwrap_or("").to_string(),
+                in_acct: row.get::<&str, _>("INAcct").unwrap_or("").to_string(),
+                rec_user_id: row.get::<&str, _>("RecUserID").unwrap_or("").to_string(),
+                rec_date: row.get::<NaiveDateTime, _>("RecDate").unwrap_or_default(),
+                std_cost: row.get::<f64, _>("StdCost").unwrap_or(0.0),
+            });
+
+        let lot_transactions_query = r#"
+            SELECT LotTranNo, LotNo, ItemKey, LocationKey, DateReceived, DateExpiry, TransactionType,
+                   IssueDocNo, IssueDocLineNo, IssueDate, CAST(QtyIssued AS DECIMAL(28,10)) AS QtyIssued,
+                   ReceiptDocNo, ReceiptDocLineNo, CAST(QtyReceived AS DECIMAL(28,10)) AS QtyReceived,
+                   Vendorkey, VendorlotNo, CustomerKey, BinNo, RecUserid, RecDate, Processed
+            FROM LotTransaction WITH (NOLOCK)
+            WHERE (IssueDocNo = @P1 AND TransactionType = 9) OR (ReceiptDocNo = @P1 AND TransactionType = 8)
+            ORDER BY COALESCE(IssueDocLineNo, ReceiptDocLineNo), TransactionType DESC, LotTranNo
+        "#;
+        let lot_transactions = document_rows(&mut conn, lot_transactions_query, document_no, "LotTransaction rows")
+            .await?
+            .iter()
+            .map(|row| LotTransactionRecord {
+                lot_tran_no: row.get::<i32, _>("LotTranNo"),
+                lot_no: row.get::<&str, _>("LotNo").unwrap_or("").to_string(),
+                item_key: row.get::<&str, _>("ItemKey").unwrap_or("").to_string(),
+                location_key: row.get::<&str, _>("LocationKey").unwrap_or("").to_string(),
+                date_received: row.get::<NaiveDateTime, _>("DateReceived").unwrap_or_default(),
+                date_expiry: row.get::<NaiveDateTime, _>("DateExpiry"),
+                transaction_type: row.get::<u8, _>("TransactionType").unwrap_or(0),
+                issue_doc_no: row.get::<&str, _>("IssueDocNo").map(|doc| doc.trim().to_string()),
+                issue_doc_line_no: row.get::<i16, _>("IssueDocLineNo"),
+                issue_date: row.get::<NaiveDateTime, _>("IssueDate"),
+                qty_issued: row.get::<BigDecimal, _>("QtyIssued"),
+                receipt_doc_no: row.get::<&str, _>("ReceiptDocNo").map(|doc| doc.trim().to_string()),
+                receipt_doc_line_no: row.get::<i16, _>("ReceiptDocLineNo"),
+                qty_received: row.get::<BigDecimal, _>("QtyReceived"),
+                vendor_key: row.get::<&str, _>("Vendorkey").map(str::to_string),
+                vendor_lot_no: row.get::<&str, _>("VendorlotNo").unwrap_or("").to_string(),
+                customer_key: row.get::<&str, _>("CustomerKey").map(str::to_string),
+                bin_no: row.get::<&str, _>("BinNo").unwrap_or("").to_string(),
+                rec_userid: row.get::<&str, _>("RecUserid").unwrap_or("").to_string(),
+                rec_date: row.get::<NaiveDateTime, _>("RecDate").unwrap_or_default(),
+                processed: row.get::<&str, _>("Processed").unwrap_or("").to_string(),
+                date_quarantine: None,
+                temp_qty: None,
+                qty_for_lot_assignment: None,
+                qty_used: None,
+            })
+            .collect();
+
+        let bin_transfers_query = r#"
+            SELECT b.ItemKey, b.Location, b.LotNo, b.BinNoFrom, b.BinNoTo, b.LotTranNo,
+                   CAST(b.QtyOnHand AS DECIMAL(28,10)) AS QtyOnHand, CAST(b.TransferQty AS DECIMAL(28,10)) AS TransferQty,
+                   b.InTransID, b.RecUserID, b.RecDate, b.ContainerNo, b.User1, b.User5
+            FROM BinTransfer b WITH (NOLOCK)
+            WHERE EXISTS (
+                SELECT 1
+                FROM LotTransaction i WITH (NOLOCK)
+                JOIN LotTransaction r WITH (NOLOCK)
+                  ON r.ReceiptDocNo = i.IssueDocNo
+                 AND r.ReceiptDocLineNo = i.IssueDocLineNo
+                 AND r.TransactionType = 8
+                 AND r.LotNo = i.LotNo AND r.ItemKey = i.ItemKey AND r.LocationKey = i.LocationKey
+                WHERE i.IssueDocNo = @P1 AND i.TransactionType = 9
+                  AND b.LotNo = i.LotNo AND b.ItemKey = i.ItemKey AND b.Location = i.LocationKey
+                  AND b.BinNoFrom = i.BinNo AND b.BinNoTo = r.BinNo
+                  AND (b.LotTranNo = i.LotTranNo OR (b.LotTranNo = 0 AND b.RecDate = i.RecDate))
+            )
+            ORDER BY b.RecDate, b.LotTranNo
+        "#;
+        let bin_transfers = document_rows(&mut conn, bin_transfers_query, document_no, "BinTransfer rows")
+            .await?
+            .iter()
+            .map(|row| BinTransferRecord {
+                bin_tran_id: None,
+                item_key: row.get::<&str, _>("ItemKey").unwrap_or("").to_string(),
+                location: row.get::<&str, _>("Location").unwrap_or("").to_string(),
+                lot_no: row.get::<&str, _>("LotNo").unwrap_or("").to_string(),
+                bin_no_from: row.get::<&str, _>("BinNoFrom").unwrap_or("").to_string(),
+                bin_no_to: row.get::<&str, _>("BinNoTo").unwrap_or("").to_string(),
+                lot_tran_no: row.get::<i32, _>("LotTranNo").unwrap_or(0),
+                qty_on_hand: row.get::<BigDecimal, _>("QtyOnHand").unwrap_or_default(),
+                transfer_qty: row.get::<BigDecimal, _>("TransferQty").unwrap_or_default(),
+                in_trans_id: row.get::<i32, _>("InTransID").unwrap_or(0),
+                rec_user_id: row.get::<&str, _>("RecUserID").unwrap_or("").to_string(),
+                rec_date: row.get::<NaiveDateTime, _>("RecDate").unwrap_or_default(),
+                container_no: row.get::<&str, _>("ContainerNo").unwrap_or("").to_string(),
+                user1: row.get::<&str, _>("User1").unwrap_or("").to_string(),
+                user5: row.get::<&str, _>("User5").unwrap_or("").to_string(),
+            })
+            .collect();
+
+        let snapshots_query = r#"
+            SELECT bt_document_no, line_no, lot_no, item_key, location, bin_no, side,
+                   CAST(qty_on_hand_before AS DECIMAL(28,10)) AS qty_on_hand_before,
+                   CAST(qty_on_hand_after AS DECIMAL(28,10)) AS qty_on_hand_after,
+                   CAST(qty_commit_before AS DECIMAL(28,10)) AS qty_commit_before,
+                   CAST(qty_commit_after AS DECIMAL(28,10)) AS qty_commit_after,
+                   lot_tran_no, rec_user_id, rec_date
+            FROM dbo.putaway_bt_lot_snapshots WITH (NOLOCK)
+            WHERE bt_document_no = @P1
+            ORDER BY line_no, id
+        "#;
+        let lot_snapshots = document_rows(&mut conn, snapshots_query, document_no, "LotMaster snapshots")
+            .await?
+            .iter()
+            .map(|row| LotSnapshotRecord {
+                document_no: row.get::<&str, _>("bt_document_no").unwrap_or("").to_string(),
+                line_no: row.get::<i16, _>("line_no").unwrap_or(1),
+                lot_no: row.get::<&str, _>("lot_no").unwrap_or("").to_string(),
+                item_key: row.get::<&str, _>("item_key").unwrap_or("").to_string(),
+                location: row.get::<&str, _>("location").unwrap_or("").to_string(),
+                bin_no: row.get::<&str, _>("bin_no").unwrap_or("").to_string(),
+                side: row.get::<&str, _>("side").unwrap_or("").to_string(),
+                qty_on_hand_before: row.get::<BigDecimal, _>("qty_on_hand_before").unwrap_or_default(),
+                qty_on_hand_after: row.get::<BigDecimal, _>("qty_on_hand_after").unwrap_or_default(),
+                qty_commit_before: row.get::<BigDecimal, _>("qty_commit_before").unwrap_or_default(),
+                qty_commit_after: row.get::<BigDecimal, _>("qty_commit_after").unwrap_or_default(),
+                lot_tran_no: row.get::<i32, _>("lot_tran_no"),
+                rec_user_id: row.get::<&str, _>("rec_user_id").unwrap_or("").to_string(),
+                rec_date: row.get::<NaiveDateTime, _>("rec_date").unwrap_or_default(),
+            })
+            .collect();
+
+        let commitment_moves_query = r#"
+            SELECT source_table, lot_tran_no, doc_no, doc_line_no, transaction_type,
+                   CAST(qty AS DECIMAL(28,10)) AS qty, bin_from, bin_to
+            FROM dbo.putaway_commitment_moves WITH (NOLOCK)
+            WHERE bt_document_no = @P1
+            ORDER BY id
+        "#;
+        let commitment_moves = document_rows(&mut conn, commitment_moves_query, document_no, "moved commitment lines")
+            .await?
+            .iter()
+            .map(|row| MovedCommitmentLine {
+                lot_tran_no: row.get::<i32, _>("lot_tran_no").unwrap_or(0),
+                source_table: row.get::<&str, _>("source_table").unwrap_or("").to_string(),
+                doc_no: row.get::<&str, _>("doc_no").unwrap_or("").to_string(),
+                doc_line_no: row.get::<i16, _>("doc_line_no"),
+                transaction_type: row.get::<u8, _>("transaction_type").unwrap_or(0),
+                qty: row.get::<BigDecimal, _>("qty").unwrap_or_default(),
+                bin_from: row.get::<&str, _>("bin_from").unwrap_or("").to_string(),
+                bin_to: row.get::<&str, _>("bin_to").unwrap_or("").to_string(),
+            })
+            .collect();
+
+        let reversal_query = r#"
+            SELECT original_document_no, reversal_document_no
+            FROM dbo.putaway_bt_reversals WITH (NOLOCK)
+            WHERE original_document_no = @P1 OR reversal_document_no = @P1
+        "#;
+        let reversal = document_rows(&mut conn, reversal_query, document_no, "reversal").await?.first().map(|row| {
+            (
+                row.get::<&str, _>("original_document_no").unwrap_or("").to_string(),
+                row.get::<&str, _>("reversal_document_no").unwrap_or("").to_string(),
+            )
+        });
+
+        Ok(BtDocumentRecords { header, lot_transactions, bin_transfers, lot_snapshots, commitment_moves, reversal })
+    }
+
+    async fn find_lots_in_bin(
+        &self,
+        location: &str,
+        bin_no: &str,
+    ) -> Result<Vec<LotMasterRecord>, PutawayError> {
+        let mut client = self
+            .db
+            .get_client()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        let query = format!(
+            "SELECT {LOT_MASTER_COLUMNS} FROM LotMaster WITH (NOLOCK)
+             WHERE LocationKey = @P1 AND BinNo = @P2 AND QtyOnHand > 0
+             ORDER BY DateExpiry ASC, LotNo ASC"
+        );
+
+        let rows = client
+            .query(query, &[&location, &bin_no])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.iter().map(lot_master_from_row).collect())
+    }
+
+    async fn find_bin_contents(&self, location: &str, bin_no: &str) -> Result<Vec<LotBinRecord>, PutawayError> {
+        let mut client = self
+            .db
+            .get_client()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        let query = format!(
+            "{LOT_BIN_SELECT} {BIN_COMMITMENT_APPLY} WHERE l.LocationKey = @P1 AND l.BinNo = @P2 AND l.QtyOnHand > 0
+             ORDER BY l.DateExpiry ASC, l.LotNo ASC"
+        );
+
+        let rows = client
+            .query(query, &[&location, &bin_no])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.iter().map(lot_bin_from_row).collect())
+    }
+
+    async fn find_bin(&self, location: &str, bin_no: &str) -> Result<Option<BinMasterRecord>, PutawayError> {
+        let mut client = self
+            .db
+            .get_client()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        let query = r#"
+            SELECT Location, BinNo, Description, aisle, row, rack, RecDate
+            FROM BINMaster WITH (NOLOCK)
+            WHERE Location = @P1 AND BinNo = @P2
+        "#;
+
+        let row = client
+            .query(query, &[&location, &bin_no])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .into_row()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(row.as_ref().map(bin_master_from_row))
+    }
+
+    async fn find_bins_by_number(&self, bin_no: &str) -> Result<Vec<BinMasterRecord>, PutawayError> {
+        let mut client = self
+            .db
+            .get_client()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        let query = r#"
+            SELECT Location, BinNo, Description, aisle, row, rack, RecDate
+            FROM BINMaster WITH (NOLOCK)
+            WHERE BinNo = @P1
+            ORDER BY Location
+        "#;
+
+        let rows = client
+            .query(query, &[&bin_no])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.iter().map(bin_master_from_row).collect())
+    }
 
-        // **Step 0: Query source lot details FIRST (before potential deletion)**
-        // CRITICAL: This must happen before Step 1 because full transfers delete the source record.
-        // If we query after deletion, we'll lose lot details and the destination INSERT will fail,
-        // causing lots to disappear from the database entirely.
-        let source_details_result = client.query(
-            "SELECT DateReceived, DateExpiry, VendorKey, VendorLotNo, QtyCommitSales, LotStatus FROM LotMaster WHERE LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3 AND BinNo = @P4",
-            &[&lot_no, &item_key, &location, &bin_from]
-        ).await.map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+    async fn get_pending_putaway_items(
+        &self,
+        filter: PendingPutawayFilter<'_>,
+        page: i32,
+        limit: i32,
+    ) -> Result<(Vec<(LotMasterRecord, ItemMasterRecord)>, i32), PutawayError> {
+        if filter.bins.is_empty() {
+            return Ok((Vec::new(), 0));
+        }
 
-        let source_details = source_details_result
-            .into_row()
-            .await
-            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
-            .ok_or_else(|| PutawayError::ValidationError("Source lot not found before transfer".to_string()))?;
-
-        // Extract and save all source details for later use (needed for destination INSERT)
-        let date_received: NaiveDateTime = source_details.get("DateReceived").unwrap_or(*now);
-        let date_expiry: NaiveDateTime = source_details.get("DateExpiry").unwrap_or(*now);
-        let vendor_key: String = source_details.get::<&str, _>("VendorKey").unwrap_or("").to_string();
-        let vendor_lot_no: String = source_details.get::<&str, _>("VendorLotNo").unwrap_or("").to_string();
-        let lot_status: Option<String> = source_details.get::<&str, _>("LotStatus").map(|s| s.to_string());
-
-        // Step 1: Update source bin - reduce QtyOnHand or delete if becomes 0
-        let source_update_result = client.query(
-            "SELECT QtyOnHand FROM LotMaster WHERE LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3 AND BinNo = @P4",
-            &[&lot_no, &item_key, &location, &bin_from]
-        ).await.map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
-
-        if let Some(row) = source_update_result
-            .into_row()
+        let mut client = self
+            .db
+            .get_client()
             .await
-            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
-        {
-            let current_qty: f64 = row.get("QtyOnHand").unwrap_or(0.0);
-            let remaining_qty = current_qty - transfer_qty;
-
-            if remaining_qty <= 0.0 {
-                // Delete source record if quantity becomes 0
-                client.execute(
-                    "DELETE FROM LotMaster WHERE LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3 AND BinNo = @P4",
-                    &[&lot_no, &item_key, &location, &bin_from]
-                ).await.map_err(|e| PutawayError::TransactionError(format!("Failed to delete source record: {e}")))?;
-            } else {
-                // Update source bin with reduced quantity
-                client.execute(
-                    "UPDATE LotMaster SET QtyOnHand = @P1, DocumentNo = @P2, TransactionType = 9, RecUserId = @P3, Recdate = @P4 WHERE LotNo = @P5 AND ItemKey = @P6 AND LocationKey = @P7 AND BinNo = @P8",
-                    &[&remaining_qty, &document_no, &user_id_truncated, now, &lot_no, &item_key, &location, &bin_from]
-                ).await.map_err(|e| PutawayError::TransactionError(format!("Failed to update source bin: {e}")))?;
-            }
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        let mut params: Vec<&dyn ToSql> = Vec::new();
+        let mut where_clause = format!("l.QtyOnHand > 0 AND l.BinNo IN ({})", push_params(&mut params, filter.bins));
+        if !filter.statuses.is_empty() {
+            where_clause.push_str(&format!(" AND l.LotStatus IN ({})", push_params(&mut params, filter.statuses)));
+        }
+        if let Some(location) = &filter.location {
+            params.push(location);
+            where_clause.push_str(&format!(" AND l.LocationKey = @P{}", params.len()));
         }
 
-        // Step 2: Handle destination bin - add to existing or create new record
-        let dest_check_result = client.query(
-            "SELECT QtyOnHand, QtyCommitSales, DateReceived, DateExpiry, VendorKey, VendorLotNo FROM LotMaster WHERE LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3 AND BinNo = @P4",
-            &[&lot_no, &item_key, &location, &bin_to]
-        ).await.map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+        let count_query = format!(
+            "SELECT COUNT(*) AS total_count
+             FROM LotMaster l WITH (NOLOCK)
+             JOIN INMAST i WITH (NOLOCK) ON l.ItemKey = i.Itemkey
+             WHERE {where_clause}"
+        );
 
-        if let Some(row) = dest_check_result
+        let total_count = client
+            .query(count_query, &params)
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to count pending putaway lots: {e}")))?
             .into_row()
             .await
             .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
-        {
-            // Destination bin already has this lot - add quantities (lot consolidation)
-            let current_qty: f64 = row.get("QtyOnHand").unwrap_or(0.0);
-            let new_qty = current_qty + transfer_qty;
-
-            client.execute(
-                "UPDATE LotMaster SET QtyOnHand = @P1, DocumentNo = @P2, TransactionType = 8, RecUserId = @P3, Recdate = @P4 WHERE LotNo = @P5 AND ItemKey = @P6 AND LocationKey = @P7 AND BinNo = @P8",
-                &[&new_qty, &document_no, &user_id_truncated, now, &lot_no, &item_key, &location, &bin_to]
-            ).await.map_err(|e| PutawayError::TransactionError(format!("Failed to update destination bin: {e}")))?;
-        } else {
-            // Destination bin doesn't have this lot - create new record
-            // Use source lot details saved in Step 0 (before potential deletion)
-            let insert_query = r#"
-                INSERT INTO LotMaster (
-                    LotNo, ItemKey, LocationKey, DateReceived, DateExpiry,
-                    QtyReceived, QtyIssued, QtyCommitSales, QtyOnHand,
-                    DocumentNo, DocumentLineNo, TransactionType, VendorKey, VendorLotNo,
-                    QtyOnOrder, RecUserId, Recdate, BinNo, LotStatus
-                ) VALUES (
-                    @P1, @P2, @P3, @P4, @P5, @P6, 0, 0, @P6, @P7, 1, 8, @P8, @P9,
-                    0, @P10, @P11, @P12, @P13
-                )
-            "#;
+            .and_then(|row| row.get::<i32, _>("total_count"))
+            .unwrap_or(0);
 
-            client
-                .execute(
-                    insert_query,
-                    &[
-                        &lot_no,
-                        &item_key,
-                        &location,
-                        &date_received,
-                        &date_expiry,
-                        &transfer_qty,
-                        &document_no,
-                        &vendor_key,
-                        &vendor_lot_no,
-                        &user_id_truncated,
-                        now,
-                        &bin_to,
-                        &lot_status,
-                    ],
-                )
-                .await
-                .map_err(|e| {
-                    PutawayError::TransactionError(format!(
-                        "Failed to create destination record: {e}"
-                    ))
-                })?;
-        }
+        let offset = (page - 1) * limit;
+        params.push(&offset);
+        params.push(&limit);
+        let page_query = format!(
+            "SELECT
+                l.LotNo, l.ItemKey, l.LocationKey, l.BinNo, l.DateReceived, l.DateExpiry,
+                CAST(l.QtyReceived AS DECIMAL(28,10)) AS QtyReceived, CAST(l.QtyOnHand AS DECIMAL(28,10)) AS QtyOnHand,
+                CAST(l.QtyIssued AS DECIMAL(28,10)) AS QtyIssued,
+                CAST(l.QtyCommitSales AS DECIMAL(28,10)) AS QtyCommitSales, l.VendorKey, l.VendorLotNo,
+                l.DocumentNo, l.DocumentLineNo, l.TransactionType, l.LotStatus, l.RecUserId, l.Recdate,
+                i.Desc1, i.Desc2, i.Stockuomcode, i.Purchaseuomcode, i.Salesuomcode
+             FROM LotMaster l WITH (NOLOCK)
+             JOIN INMAST i WITH (NOLOCK) ON l.ItemKey = i.Itemkey
+             WHERE {where_clause}
+             ORDER BY CASE WHEN l.DateExpiry IS NULL THEN 1 ELSE 0 END, l.DateExpiry, l.DateReceived, l.LotNo, l.BinNo
+             OFFSET @P{} ROWS FETCH NEXT @P{} ROWS ONLY",
+            params.len() - 1,
+            params.len()
+        );
 
-        Ok(())
+        let rows = client
+            .query(page_query, &params)
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to read pending putaway lots: {e}")))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        let lots = rows.iter().map(|row| (lot_master_from_row(row), item_master_from_row(row))).collect();
+        Ok((lots, total_count))
     }
 
-    /// Validate transfer request - checks specific bin quantities for lot consolidation
-    pub async fn validate_transfer_request(
+
+    async fn get_transfer_history(
         &self,
-        lot_no: &str,
-        item_key: &str,
-        location: &str,
-        bin_from: &str,
-        bin_to: &str,
-        transfer_qty: f64,
-    ) -> Result<(f64, bool), PutawayError> {
+        filter: TransferHistoryFilter<'_>,
+        page: i32,
+        limit: i32,
+    ) -> Result<(Vec<TransferHistoryLine>, i32), PutawayError> {
         let mut client = self
             .db
             .get_client()
             .await
             .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
 
-        // Check specific bin record (not general lot record)
-        let source_bin_query = r#"
-            SELECT QtyOnHand, QtyCommitSales, ItemKey, LocationKey
-            FROM LotMaster 
-            WHERE LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3 AND BinNo = @P4
+        // A BT line is its issue (type 9) joined to the receipt (type 8) on the document line
+        let mut params: Vec<&dyn ToSql> = Vec::new();
+        let mut where_clause = "i.TransactionType = 9 AND i.IssueDocNo LIKE 'BT-%'".to_string();
+        let column_filters = [
+            ("i.RecUserid", filter.user_id),
+            ("i.LotNo", filter.lot_no),
+            ("i.ItemKey", filter.item_key),
+            ("i.LocationKey", filter.location),
+        ];
+        for (column, value) in column_filters.iter() {
+            if let Some(value) = value {
+                params.push(value);
+                where_clause.push_str(&format!(" AND {column} = @P{}", params.len()));
+            }
+        }
+        if let Some(bin_no) = &filter.bin_no {
+            params.push(bin_no);
+            where_clause.push_str(&format!(" AND (i.BinNo = @P{0} OR r.BinNo = @P{0})", params.len()));
+        }
+        if let Some(date_from) = &filter.date_from {
+            params.push(date_from);
+            where_clause.push_str(&format!(" AND i.RecDate >= @P{}", params.len()));
+        }
+        if let Some(date_to) = &filter.date_to {
+            params.push(date_to);
+            where_clause.push_str(&format!(" AND i.RecDate < @P{}", params.len()));
+        }
+
+        let from_clause = r#"
+            FROM LotTransaction i WITH (NOLOCK)
+            JOIN LotTransaction r WITH (NOLOCK)
+              ON r.ReceiptDocNo = i.IssueDocNo
+             AND r.ReceiptDocLineNo = i.IssueDocLineNo
+             AND r.TransactionType = 8
+             AND r.LotNo = i.LotNo AND r.ItemKey = i.ItemKey AND r.LocationKey = i.LocationKey
         "#;
 
-        let result = client
-            .query(
-                source_bin_query,
-                &[&lot_no, &item_key, &location, &bin_from],
-            )
+        let count_query = format!("SELECT COUNT(*) AS total_count {from_clause} WHERE {where_clause}");
+        let total_count = client
+            .query(count_query, &params)
             .await
-            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
-
-        if let Some(row) = result
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to count transfer history: {e}")))?
             .into_row()
             .await
             .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
-        {
-            // Verify item key matches
-            let record_item_key: &str = row.get("ItemKey").unwrap_or("");
-            if record_item_key != item_key {
-                return Err(PutawayError::ValidationError(format!(
-                    "Item key mismatch: expected {record_item_key}, got {item_key}"
-                )));
-            }
+            .and_then(|row| row.get::<i32, _>("total_count"))
+            .unwrap_or(0);
+
+        // Sort column comes from the enum, never from the request
+        let sort_column = match filter.sort {
+            TransferHistorySort::Date => "i.RecDate",
+            TransferHistorySort::DocumentNo => "i.IssueDocNo",
+            TransferHistorySort::LotNo => "i.LotNo",
+            TransferHistorySort::ItemKey => "i.ItemKey",
+            TransferHistorySort::UserId => "i.RecUserid",
+        };
+        let direction = if filter.descending { "DESC" } else { "ASC" };
 
-            // Verify location matches
-            let record_location: &str = row.get("LocationKey").unwrap_or("");
-            if record_location != location {
-                return Err(PutawayError::ValidationError(format!(
-                    "Location mismatch: expected {record_location}, got {location}"
-                )));
-            }
+        let offset = (page - 1) * limit;
+        params.push(&offset);
+        params.push(&limit);
+        // BinTransfer carries no document number: rows link to the document's issue row through
+        // LotTranNo. Committed-transfer rows written before that link (LotTranNo 0, not yet
+        // backfilled) are matched on the lot, bins and the RecDate the whole document shares
+        let page_query = format!(
+            "SELECT
+                i.IssueDocNo, i.IssueDocLineNo, i.LotNo, i.ItemKey, i.LocationKey,
+                i.BinNo AS BinFrom, r.BinNo AS BinTo, CAST(i.QtyIssued AS DECIMAL(28,10)) AS Qty,
+                i.RecUserid, i.RecDate, m.Desc1, m.Stockuomcode, bt.User1, bt.User5
+             {from_clause}
+             LEFT JOIN INMAST m WITH (NOLOCK) ON m.Itemkey = i.ItemKey
+             OUTER APPLY (
+                SELECT TOP 1 b.User1, b.User5
+                FROM BinTransfer b WITH (NOLOCK)
+                WHERE b.LotNo = i.LotNo AND b.ItemKey = i.ItemKey AND b.Location = i.LocationKey
+                  AND b.BinNoFrom = i.BinNo AND b.BinNoTo = r.BinNo
+                  AND (b.LotTranNo = i.LotTranNo OR (b.LotTranNo = 0 AND b.RecDate = i.RecDate))
+                ORDER BY b.LotTranNo DESC
+             ) bt
+             WHERE {where_clause}
+             ORDER BY {sort_column} {direction}, i.IssueDocNo {direction}, i.IssueDocLineNo
+             OFFSET @P{} ROWS FETCH NEXT @P{} ROWS ONLY",
+            params.len() - 1,
+            params.len()
+        );
 
-            // Calculate available quantity in THIS SPECIFIC BIN (QtyOnHand - QtyCommitSales)
-            let qty_on_hand: f64 = row.get("QtyOnHand").unwrap_or(0.0);
-            let qty_commit_sales: f64 = row.get("QtyCommitSales").unwrap_or(0.0);
-            let available_qty = qty_on_hand - qty_commit_sales;
+        let rows = client
+            .query(page_query, &params)
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to read transfer history: {e}")))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
 
-            // Add tolerance for floating-point precision errors (0.001 = 1 milligram tolerance)
-            // This prevents false validation errors from JavaScript decimal precision issues
-            const QUANTITY_TOLERANCE: f64 = 0.001;
+        let lines = rows
+            .iter()
+            .map(|row| TransferHistoryLine {
+                document_no: row.get::<&str, _>("IssueDocNo").unwrap_or("").trim().to_string(),
+                line_no: row.get::<i16, _>("IssueDocLineNo").unwrap_or(1),
+                lot_no: row.get::<&str, _>("LotNo").unwrap_or("").to_string(),
+                item_key: row.get::<&str, _>("ItemKey").unwrap_or("").to_string(),
+                item_description: row.get::<&str, _>("Desc1").unwrap_or("").to_string(),
+                uom: row.get::<&str, _>("Stockuomcode").unwrap_or("").to_string(),
+                location: row.get::<&str, _>("LocationKey").unwrap_or("").to_string(),
+                bin_from: row.get::<&str, _>("BinFrom").unwrap_or("").to_string(),
+                bin_to: row.get::<&str, _>("BinTo").unwrap_or("").to_string(),
+                qty: row.get::<BigDecimal, _>("Qty").unwrap_or_default(),
+                user_id: row.get::<&str, _>("RecUserid").unwrap_or("").to_string(),
+                rec_date: row.get::<NaiveDateTime, _>("RecDate").unwrap_or_default(),
+                remarks: row.get::<&str, _>("User1").unwrap_or("").to_string(),
+                referenced: row.get::<&str, _>("User5").unwrap_or("").to_string(),
+            })
+            .collect();
 
-            if transfer_qty > (available_qty + QUANTITY_TOLERANCE) {
-                return Err(PutawayError::InsufficientQuantity {
-                    requested: transfer_qty,
-                    available: available_qty,
-                });
-            }
+        Ok((lines, total_count))
+    }
 
-            if transfer_qty <= 0.0 {
-                return Err(PutawayError::ValidationError(
-                    "Transfer quantity must be greater than 0".to_string(),
-                ));
-            }
 
-            // Detect full transfer: when requested quantity is within tolerance of available quantity
-            // This prevents microscopic residuals that block source record deletion
-            let is_full_transfer = (transfer_qty + QUANTITY_TOLERANCE) >= available_qty;
-            let actual_transfer_qty = if is_full_transfer {
-                // Use exact available quantity for full transfers to prevent floating-point residuals
-                available_qty
-            } else {
-                transfer_qty
-            };
+    /// Find which BT document numbers in a range left rows behind.
+    /// Returns (document numbers with LotTransaction rows, document numbers with a Mintxdh header).
+    /// BinTransfer has no DocNo column - its rows hang off LotTransaction via LotTranNo.
+    async fn find_bt_documents_in_range(
+        &self,
+        from_doc: &str,
+        to_doc: &str,
+    ) -> Result<(HashSet<String>, HashSet<String>), PutawayError> {
+        let mut client = self
+            .db
+            .get_client()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
 
-            // Validate destination bin exists
-            if !self.validate_bin_location(location, bin_to).await? {
-                return Err(PutawayError::InvalidBin {
-                    bin_no: bin_to.to_string(),
-                    location: location.to_string(),
-                });
-            }
+        let query = r#"
+            SELECT DISTINCT DocNo, Source FROM (
+                SELECT IssueDocNo AS DocNo, 'LT' AS Source
+                FROM LotTransaction WITH (NOLOCK)
+                WHERE IssueDocNo BETWEEN @P1 AND @P2
+                UNION ALL
+                SELECT ReceiptDocNo AS DocNo, 'LT' AS Source
+                FROM LotTransaction WITH (NOLOCK)
+                WHERE ReceiptDocNo BETWEEN @P1 AND @P2
+                UNION ALL
+                SELECT DocNo, 'MX' AS Source
+                FROM Mintxdh WITH (NOLOCK)
+                WHERE DocNo BETWEEN @P1 AND @P2
+            ) d
+        "#;
 
-            // Validate source and destination bins are different
-            if bin_from == bin_to {
-                return Err(PutawayError::ValidationError(
-                    "Source and destination bins cannot be the same".to_string(),
-                ));
-            }
+        let rows = client
+            .query(query, &[&from_doc, &to_doc])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
 
-            // Return actual transfer quantity and full transfer flag
-            Ok((actual_transfer_qty, is_full_transfer))
-        } else {
-            Err(PutawayError::ValidationError(format!(
-                "Lot {lot_no} not found in bin {bin_from} or insufficient quantity available"
-            )))
+        let mut with_lot_transactions = HashSet::new();
+        let mut with_mintxdh = HashSet::new();
+        for row in rows {
+            let doc_no = row.get::<&str, _>("DocNo").unwrap_or("").trim().to_string();
+            match row.get::<&str, _>("Source") {
+                Some("LT") => with_lot_transactions.insert(doc_no),
+                _ => with_mintxdh.insert(doc_no),
+            };
         }
-    }
 
+        Ok((with_lot_transactions, with_mintxdh))
+    }
 
     /// Search for lots with pagination (READ operation - uses TFCPILOT3)
-    pub async fn search_lots_paginated(
+    async fn search_lots_paginated(
         &self,
         query: Option<&str>,
         page: i32,
@@ -868,8 +1900,8 @@ impl PutawayDatabase {
         let sql_query = if let Some(_search_term) = query {
             r#"
                 SELECT
-                    l.LotNo, l.ItemKey, l.LocationKey, l.BinNo, l.QtyOnHand,
-                    l.QtyCommitSales, l.DateReceived, l.DateExpiry, l.LotStatus,
+                    l.LotNo, l.ItemKey, l.LocationKey, l.BinNo, CAST(l.QtyOnHand AS DECIMAL(28,10)) AS QtyOnHand,
+                    CAST(l.QtyCommitSales AS DECIMAL(28,10)) AS QtyCommitSales, l.DateReceived, l.DateExpiry, l.LotStatus,
                     i.Desc1, i.Stockuomcode
                 FROM LotMaster l WITH (NOLOCK)
                 JOIN INMAST i WITH (NOLOCK) ON l.ItemKey = i.Itemkey
@@ -881,8 +1913,8 @@ impl PutawayDatabase {
         } else {
             r#"
                 SELECT
-                    l.LotNo, l.ItemKey, l.LocationKey, l.BinNo, l.QtyOnHand,
-                    l.QtyCommitSales, l.DateReceived, l.DateExpiry, l.LotStatus,
+                    l.LotNo, l.ItemKey, l.LocationKey, l.BinNo, CAST(l.QtyOnHand AS DECIMAL(28,10)) AS QtyOnHand,
+                    CAST(l.QtyCommitSales AS DECIMAL(28,10)) AS QtyCommitSales, l.DateReceived, l.DateExpiry, l.LotStatus,
                     i.Desc1, i.Stockuomcode
                 FROM LotMaster l WITH (NOLOCK)
                 JOIN INMAST i WITH (NOLOCK) ON l.ItemKey = i.Itemkey
@@ -910,9 +1942,9 @@ impl PutawayDatabase {
                     .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
 
                 for row in rows {
-                    let qty_on_hand: f64 = row.get("QtyOnHand").unwrap_or(0.0);
-                    let qty_commit_sales: f64 = row.get("QtyCommitSales").unwrap_or(0.0);
-                    let qty_available = qty_on_hand - qty_commit_sales;
+                    let qty_on_hand: BigDecimal = row.get("QtyOnHand").unwrap_or_default();
+                    let qty_commit_sales: BigDecimal = row.get("QtyCommitSales").unwrap_or_default();
+                    let qty_available = &qty_on_hand - &qty_commit_sales;
 
                     let expiry_date = row
                         .get::<NaiveDateTime, _>("DateExpiry")
@@ -948,15 +1980,16 @@ impl PutawayDatabase {
     ///
     /// When lot_no, item_key, and location are provided, LEFT JOIN with LotMaster to show
     /// if the bin contains this lot and what status it has (helps users see consolidation targets)
-    pub async fn search_bins_paginated(
+    async fn search_bins_paginated(
         &self,
         query: Option<&str>,
         page: i32,
         limit: i32,
-        lot_no: Option<&str>,
-        item_key: Option<&str>,
-        location: Option<&str>,
+        lot: Option<LotKey<'_>>,
     ) -> Result<(Vec<BinSearchItem>, i32), PutawayError> {
+        let lot_no = lot.map(|lot| lot.lot_no);
+        let item_key = lot.map(|lot| lot.item_key);
+        let location = lot.map(|lot| lot.location);
         let mut client = self
             .db
             .get_client()
@@ -1017,57 +2050,55 @@ impl PutawayDatabase {
         let sql_query = match (has_lot_context, query.is_some()) {
             // Case 1: Has lot context AND search query
             (true, true) => {
-                r#"
+                format!(r#"
                     SELECT
                         b.Location, b.BinNo, b.Description, b.aisle, b.row, b.rack, b.RecDate,
-                        l.LotStatus
+                        l.LotStatus, k.bin_no AS BlockedBinNo, k.reason AS BlockReason, k.blocked_until AS BlockedUntil
                     FROM BINMaster b WITH (NOLOCK)
-                    LEFT JOIN LotMaster l WITH (NOLOCK) ON
-                        l.LotNo = @P1 AND
-                        l.ItemKey = @P2 AND
-                        l.LocationKey = @P3 AND
-                        l.BinNo = b.BinNo
+                    {BIN_LOT_CONTEXT_JOIN}
+                    {BIN_BLOCK_JOIN}
                     WHERE b.BinNo LIKE @P4 OR b.Location LIKE @P4 OR b.Description LIKE @P4
                     ORDER BY b.RecDate DESC
                     OFFSET @P5 ROWS FETCH NEXT @P6 ROWS ONLY
-                "#
+                "#)
             },
             // Case 2: Has lot context but NO search query
             (true, false) => {
-                r#"
+                format!(r#"
                     SELECT
                         b.Location, b.BinNo, b.Description, b.aisle, b.row, b.rack, b.RecDate,
-                        l.LotStatus
+                        l.LotStatus, k.bin_no AS BlockedBinNo, k.reason AS BlockReason, k.blocked_until AS BlockedUntil
                     FROM BINMaster b WITH (NOLOCK)
-                    LEFT JOIN LotMaster l WITH (NOLOCK) ON
-                        l.LotNo = @P1 AND
-                        l.ItemKey = @P2 AND
-                        l.LocationKey = @P3 AND
-                        l.BinNo = b.BinNo
+                    {BIN_LOT_CONTEXT_JOIN}
+                    {BIN_BLOCK_JOIN}
                     ORDER BY b.RecDate DESC
                     OFFSET @P4 ROWS FETCH NEXT @P5 ROWS ONLY
-                "#
+                "#)
             },
             // Case 3: No lot context but HAS search query
             (false, true) => {
-                r#"
+                format!(r#"
                     SELECT
-                        Location, BinNo, Description, aisle, row, rack, RecDate
-                    FROM BINMaster WITH (NOLOCK)
-                    WHERE BinNo LIKE @P1 OR Location LIKE @P1 OR Description LIKE @P1
-                    ORDER BY RecDate DESC
+                        b.Location, b.BinNo, b.Description, b.aisle, b.row, b.rack, b.RecDate,
+                        k.bin_no AS BlockedBinNo, k.reason AS BlockReason, k.blocked_until AS BlockedUntil
+                    FROM BINMaster b WITH (NOLOCK)
+                    {BIN_BLOCK_JOIN}
+                    WHERE b.BinNo LIKE @P1 OR b.Location LIKE @P1 OR b.Description LIKE @P1
+                    ORDER BY b.RecDate DESC
                     OFFSET @P2 ROWS FETCH NEXT @P3 ROWS ONLY
-                "#
+                "#)
             },
             // Case 4: No lot context and NO search query
             (false, false) => {
-                r#"
+                format!(r#"
                     SELECT
-                        Location, BinNo, Description, aisle, row, rack, RecDate
-                    FROM BINMaster WITH (NOLOCK)
-                    ORDER BY RecDate DESC
+                        b.Location, b.BinNo, b.Description, b.aisle, b.row, b.rack, b.RecDate,
+                        k.bin_no AS BlockedBinNo, k.reason AS BlockReason, k.blocked_until AS BlockedUntil
+                    FROM BINMaster b WITH (NOLOCK)
+                    {BIN_BLOCK_JOIN}
+                    ORDER BY b.RecDate DESC
                     OFFSET @P1 ROWS FETCH NEXT @P2 ROWS ONLY
-                "#
+                "#)
             },
         };
 
@@ -1113,6 +2144,7 @@ impl PutawayDatabase {
 
         match results {
             Ok(stream) => {
+                let now = bangkok_now().naive_local();
                 let mut bins = Vec::new();
                 let rows = stream
                     .into_first_result()
@@ -1127,6 +2159,12 @@ impl PutawayDatabase {
                         None
                     };
 
+                    // Expired blocks stay in putaway_bin_blocks until the bin is unblocked
+                    let block_reason = row
+                        .get::<&str, _>("BlockedBinNo")
+                        .filter(|_| bin_block_active(row.get::<NaiveDateTime, _>("BlockedUntil").as_ref(), &now))
+                        .map(|_| row.get::<&str, _>("BlockReason").unwrap_or("").to_string());
+
                     bins.push(BinSearchItem {
                         bin_no: row.get::<&str, _>("BinNo").unwrap_or("").to_string(),
                         location: row.get::<&str, _>("Location").unwrap_or("").to_string(),
@@ -1135,6 +2173,8 @@ impl PutawayDatabase {
                         row: row.get::<&str, _>("row").unwrap_or("").to_string(),
                         rack: row.get::<&str, _>("rack").unwrap_or("").to_string(),
                         lot_status,
+                        blocked: block_reason.is_some(),
+                        block_reason,
                     });
                 }
 
@@ -1144,8 +2184,192 @@ impl PutawayDatabase {
         }
     }
 
+    async fn bin_candidates(
+        &self,
+        lot: LotKey<'_>,
+        exclusions: BinCandidateExclusions<'_>,
+    ) -> Result<Vec<BinCandidateRecord>, PutawayError> {
+        let mut client = self
+            .db
+            .get_client()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        let mut params: Vec<&dyn ToSql> = vec![&lot.lot_no, &lot.item_key, &lot.location, &exclusions.now];
+        let (prefixes, bins): (Vec<String>, Vec<String>) = exclusions.bins.iter().cloned().partition(|bin| bin.ends_with('*'));
+        // Prefix patterns become LIKE 'prefix%' with the LIKE wildcards in the prefix escaped
+        let prefixes: Vec<String> = prefixes
+            .iter()
+            .map(|prefix| {
+                let prefix = prefix.trim_end_matches('*');
+                let escaped: String = prefix.chars().flat_map(|c| match c {
+                    '%' | '_' | '[' | '\\' => vec!['\\', c],
+                    c => vec![c],
+                }).collect();
+                format!("{escaped}%")
+            })
+            .collect();
+
+        let mut exclusion_clause = String::new();
+        if !bins.is_empty() {
+            exclusion_clause.push_str(&format!(" AND b.BinNo NOT IN ({})", push_params(&mut params, &bins)));
+        }
+        for pattern in &prefixes {
+            params.push(pattern);
+            exclusion_clause.push_str(&format!(" AND b.BinNo NOT LIKE @P{} ESCAPE '\\'", params.len()));
+        }
+        if !exclusions.aisles.is_empty() {
+            exclusion_clause.push_str(&format!(
+                " AND LTRIM(RTRIM(ISNULL(b.aisle, ''))) NOT IN ({})",
+                push_params(&mut params, exclusions.aisles)
+            ));
+        }
+
+        let query = format!(
+            r#"
+            SELECT
+                b.Location, b.BinNo, b.Description, b.aisle, b.row, b.rack, b.RecDate,
+                l.LotStatus, CAST(COALESCE(l.QtyOnHand, 0) AS DECIMAL(28,10)) AS LotQtyOnHand,
+                COALESCE(s.ItemLots, 0) AS ItemLots, COALESCE(s.StockedLots, 0) AS StockedLots
+            FROM BINMaster b WITH (NOLOCK)
+            {BIN_LOT_CONTEXT_JOIN}
+            OUTER APPLY (
+                SELECT COUNT(*) AS StockedLots,
+                       SUM(CASE WHEN o.ItemKey = @P2 AND o.LotNo <> @P1 THEN 1 ELSE 0 END) AS ItemLots
+                FROM LotMaster o WITH (NOLOCK)
+                WHERE o.LocationKey = b.Location AND o.BinNo = b.BinNo AND o.QtyOnHand > 0
+            ) AS s
+            WHERE b.Location = @P3
+              AND (COALESCE(l.QtyOnHand, 0) > 0 OR COALESCE(s.ItemLots, 0) > 0 OR COALESCE(s.StockedLots, 0) = 0)
+              AND NOT EXISTS (
+                  SELECT 1 FROM dbo.putaway_bin_blocks k WITH (NOLOCK)
+                  WHERE k.location = b.Location AND k.bin_no = b.BinNo AND (k.blocked_until IS NULL OR k.blocked_until > @P4)
+              ){exclusion_clause}
+            "#
+        );
+
+        let rows = client
+            .query(query, &params)
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| BinCandidateRecord {
+                bin: bin_master_from_row(row),
+                lot_status: row.get::<&str, _>("LotStatus").map(str::to_string),
+                lot_qty_on_hand: row.get::<BigDecimal, _>("LotQtyOnHand").unwrap_or_default(),
+                item_lots: row.get::<i32, _>("ItemLots").unwrap_or(0),
+                stocked_lots: row.get::<i32, _>("StockedLots").unwrap_or(0),
+            })
+            .collect())
+    }
+
+    async fn find_bin_blocks(&self, location: Option<&str>) -> Result<Vec<BinBlockRecord>, PutawayError> {
+        let mut client = self
+            .db
+            .get_client()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        let query = format!(
+            r#"
+            SELECT {BIN_BLOCK_COLUMNS}
+            FROM dbo.putaway_bin_blocks k WITH (NOLOCK)
+            WHERE @P1 IS NULL OR k.location = @P1
+            ORDER BY k.location, k.bin_no
+            "#
+        );
+
+        let rows = client
+            .query(query, &[&location])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to read blocked bins: {e}")))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(rows.iter().map(bin_block_from_row).collect())
+    }
+
+    async fn find_bin_block_history(&self, location: &str, bin_no: &str) -> Result<Vec<BinBlockEvent>, PutawayError> {
+        let mut client = self
+            .db
+            .get_client()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        let query = r#"
+            SELECT action, location, bin_no, reason, blocked_until, rec_user_id, rec_date
+            FROM dbo.putaway_bin_block_history WITH (NOLOCK)
+            WHERE location = @P1 AND bin_no = @P2
+            ORDER BY rec_date DESC, id DESC
+        "#;
+
+        let rows = client
+            .query(query, &[&location, &bin_no])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to read block history of bin {bin_no}: {e}")))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(rows
+            .iter()
+            .filter_map(|row| {
+                Some(BinBlockEvent {
+                    action: BinBlockAction::from_code(row.get::<&str, _>("action").unwrap_or(""))?,
+                    location: row.get::<&str, _>("location").unwrap_or("").to_string(),
+                    bin_no: row.get::<&str, _>("bin_no").unwrap_or("").to_string(),
+                    reason: row.get::<&str, _>("reason").unwrap_or("").to_string(),
+                    blocked_until: row.get::<NaiveDateTime, _>("blocked_until"),
+                    user_id: row.get::<&str, _>("rec_user_id").unwrap_or("").to_string(),
+                    event_date: row.get::<NaiveDateTime, _>("rec_date").unwrap_or_default(),
+                })
+            })
+            .collect())
+    }
+
+    async fn find_bin_capacities(
+        &self,
+        location: &str,
+    ) -> Result<Vec<(BinCapacityRecord, BinMasterRecord)>, PutawayError> {
+        let mut client = self
+            .db
+            .get_client()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        let query = format!(
+            r#"
+            SELECT {BIN_CAPACITY_COLUMNS},
+                   b.Location, b.BinNo, b.Description, b.aisle, b.row, b.rack, b.RecDate
+            FROM dbo.putaway_bin_capacity c WITH (NOLOCK)
+            JOIN BINMaster b WITH (NOLOCK) ON b.Location = c.location AND b.BinNo = c.bin_no
+            WHERE c.location = @P1
+            ORDER BY c.bin_no
+            "#
+        );
+
+        let rows = client
+            .query(query, &[&location])
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to read bin capacities: {e}")))?
+            .into_first_result()
+            .await
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+
+        Ok(rows
+            .iter()
+            .filter_map(|row| Some((bin_capacity_from_row(row)?, bin_master_from_row(row))))
+            .collect())
+    }
+
     /// Get all active putaway remarks for dropdown
-    pub async fn get_active_remarks(&self) -> Result<Vec<serde_json::Value>, PutawayError> {
+    async fn get_active_remarks(&self) -> Result<Vec<serde_json::Value>, PutawayError> {
         let mut client = self
             .db
             .get_client()
@@ -1182,7 +2406,7 @@ impl PutawayDatabase {
         Ok(remarks)
     }
     /// Search for transactions associated with a lot and bin
-    pub async fn find_transactions_by_lot_and_bin(
+    async fn find_transactions_by_lot_and_bin(
         &self,
         lot_no: &str,
         bin_no: &str,
@@ -1197,7 +2421,7 @@ impl PutawayDatabase {
         // Filters: Processed IN ('N','P'), TransactionType In (2,3,5,7,9,10,12,16,17,20,21)
         // Returns QtyIssued, IssueDocNo with human-readable transaction type names
         let query = r#"
-            SELECT LotNo, BinNo, IssueDocNo as DocNo, IssueDocLineNo, QtyIssued as Qty, LotTranNo, 
+            SELECT LotNo, BinNo, IssueDocNo as DocNo, IssueDocLineNo, CAST(QtyIssued AS DECIMAL(28,10)) as Qty, LotTranNo, 
                    TransactionType,
                    (CASE TransactionType  
                       When 1  Then 'Purchase Receipt' 
@@ -1220,13 +2444,13 @@ impl PutawayDatabase {
                       When 21 Then 'Sales Provisional'
                       Else 'Unknown' 
                    END) as TranTyp,
-                   RecDate, Processed
+                   RecDate, Processed, 'LotTransaction' as SourceTable
             FROM LotTransaction 
             WHERE Processed IN ('N','P') 
               AND TransactionType In (2,3,5,7,9,10,12,16,17,20,21) 
               AND LotNo = @P1 AND BinNo = @P2
             UNION ALL 
-            SELECT LotNo, BinNo, IssueDocNo as DocNo, IssueDocLineNo, QtyIssued as Qty, LotTranNo, 
+            SELECT LotNo, BinNo, IssueDocNo as DocNo, IssueDocLineNo, CAST(QtyIssued AS DECIMAL(28,10)) as Qty, LotTranNo, 
                    TransactionType,
                    (CASE TransactionType  
                       When 1  Then 'Purchase Receipt' 
@@ -1249,7 +2473,7 @@ impl PutawayDatabase {
                       When 21 Then 'Sales Provisional'
                       Else 'Unknown' 
                    END) as TranTyp,
-                   RecDate, Processed
+                   RecDate, Processed, 'QCLotTransaction' as SourceTable
             FROM QCLotTransaction 
             WHERE Processed IN ('N','P') 
               AND TransactionType In (2,3,5,7,9,10,12,16,17,20,21) 
@@ -1273,7 +2497,7 @@ impl PutawayDatabase {
                 bin_no: row.get::<&str, _>("BinNo").unwrap_or("").to_string(),
                 doc_no: row.get::<&str, _>("DocNo").unwrap_or("").to_string(),
                 issue_doc_line_no: row.get::<i16, _>("IssueDocLineNo"),
-                qty: row.get::<f64, _>("Qty").unwrap_or(0.0),
+                qty: row.get::<BigDecimal, _>("Qty").unwrap_or_default(),
                 transaction_type: row.get::<u8, _>("TransactionType").unwrap_or(0),
                 tran_typ: row.get::<&str, _>("TranTyp").unwrap_or("").to_string(),
                 transaction_date: row
@@ -1281,298 +2505,107 @@ impl PutawayDatabase {
                     .map(|d| d.to_string())
                     .unwrap_or_default(),
                 status: row.get::<&str, _>("Processed").unwrap_or("").to_string(),
+                source_table: row.get::<&str, _>("SourceTable").unwrap_or("").to_string(),
             });
         }
 
         Ok(transactions)
     }
 
-    /// Execute transfer of committed stock (BME official behavior)
-    /// 
-    /// This function moves physical inventory ALONG WITH its commitment status:
-    /// 1. Creates LotTransaction records: Type 9 (Issue) from source, Type 8 (Receipt) to dest
-    /// 2. Reduces source LotMaster: QtyOnHand -= transfer_qty, QtyCommitSales -= transfer_qty
-    /// 3. Deletes source LotMaster if QtyOnHand becomes 0
-    /// 4. Creates or updates destination LotMaster with transferred quantities
-    /// 5. Destination gets both QtyOnHand AND QtyCommitSales from the transfer (committed stock moves with commitment)
-    #[allow(clippy::too_many_arguments)]
-    pub async fn execute_committed_bin_transfer(
-        &self,
-        lot_no: &str,
-        item_key: &str,
-        location: &str,
-        target_bin: &str,
-        transfer_qty: f64,
-        source_bin: &str,
-        user_id: &str,
-        remarks: &str,
-        referenced: &str,
-    ) -> Result<String, PutawayError> {
+    async fn save_bin_capacity(&self, row: &BinCapacityRecord) -> Result<(), PutawayError> {
         let mut client = self
             .db
             .get_client()
             .await
             .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
 
-        // Start Transaction
+        let query = r#"
+            MERGE dbo.putaway_bin_capacity WITH (HOLDLOCK) AS c
+            USING (SELECT @P1 AS location, @P2 AS bin_no) AS s
+               ON c.location = s.location AND c.bin_no = s.bin_no
+            WHEN MATCHED THEN
+                UPDATE SET capacity_unit = @P3, capacity = @P4, rec_user_id = @P5, rec_date = @P6
+            WHEN NOT MATCHED THEN
+                INSERT (location, bin_no, capacity_unit, capacity, rec_user_id, rec_date)
+                VALUES (@P1, @P2, @P3, @P4, @P5, @P6);
+        "#;
+
         client
-            .simple_query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+            .execute(
+                query,
+                &[&row.location, &row.bin_no, &row.unit.code(), &row.capacity, &row.rec_user_id, &row.rec_date],
+            )
             .await
-            .map_err(|e| PutawayError::DatabaseError(format!("Failed to set isolation level: {e}")))?;
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to save capacity of bin {}: {e}", row.bin_no)))?;
 
-        client
-            .simple_query("BEGIN TRANSACTION")
+        Ok(())
+    }
+
+    async fn delete_bin_capacity(&self, location: &str, bin_no: &str) -> Result<bool, PutawayError> {
+        let mut client = self
+            .db
+            .get_client()
             .await
-            .map_err(|e| PutawayError::DatabaseError(format!("Failed to begin transaction: {e}")))?;
+            .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
 
-        let transaction_result: Result<String, PutawayError> = async {
-            let now = bangkok_now().naive_local();
-            let user_id_truncated = if user_id.len() > 8 { &user_id[0..8] } else { user_id };
-
-            // 1. Get next BT document number
-            let bt_number = self.get_next_bt_sequence().await?;
-            let document_no = format!("BT-{bt_number:08}");
-
-            // 2. Create Mintxdh record for audit trail (same as Transfer Avail Qty)
-            let inloc_record = self.get_inloc_record(item_key, location).await?;
-            let in_acct = map_inclasskey_to_inacct(&inloc_record.inclasskey);
-            let std_cost = inloc_record.stdcost;
-            let trn_desc = "Bin Transfer";
-
-            let mintxdh_query = r#"
-                INSERT INTO Mintxdh (
-                    ItemKey, Location, ToLocation, SysID, ProcessID, SysDocID, SysLinSq,
-                    TrnTyp, TrnSubTyp, DocNo, DocDate, AplDate, TrnDesc, TrnQty, TrnAmt,
-                    NLAcct, INAcct, CreatedSerlot, RecUserID, RecDate, Updated_FinTable,
-                    SortField, JrnlBtchNo, StdCost, Stdcostupdated, GLtrnAmt
-                ) VALUES (
-                    @P1, @P2, '', '7', 'M', @P3, 1, 'A', '', @P4, @P5, @P5, @P6, 0, 0.000000,
-                    '1100', @P7, 'Y', @P8, @P9, 0, '', '', @P10, 0, 0.000000
-                )
-            "#;
-            client.execute(mintxdh_query, &[
-                &item_key, &location, &document_no, &document_no, &now, &trn_desc,
-                &in_acct, &user_id_truncated, &now, &std_cost
-            ]).await.map_err(|e| PutawayError::TransactionError(format!("Failed to create Mintxdh record: {e}")))?;
-
-            // 3. Validate Target Bin Exists
-            let bin_exists_query = "SELECT COUNT(*) as count FROM BINMaster WHERE Location = @P1 AND BinNo = @P2";
-            let bin_check = client.query(bin_exists_query, &[&location, &target_bin]).await
-                .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
-                .into_row().await
-                .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
-            
-            if let Some(row) = bin_check {
-                let count: i32 = row.get("count").unwrap_or(0);
-                if count == 0 {
-                    return Err(PutawayError::InvalidBin { bin_no: target_bin.to_string(), location: location.to_string() });
-                }
-            } else {
-                return Err(PutawayError::InvalidBin { bin_no: target_bin.to_string(), location: location.to_string() });
-            }
+        let deleted = client
+            .execute(
+                "DELETE FROM dbo.putaway_bin_capacity WHERE location = @P1 AND bin_no = @P2",
+                &[&location, &bin_no],
+            )
+            .await
+            .map_err(|e| PutawayError::DatabaseError(format!("Failed to delete capacity of bin {bin_no}: {e}")))?
+            .total();
 
-            // 3. Get source LotMaster details (DateReceived, DateExpiry, VendorKey, VendorLotNo)
-            let source_query = r#"
-                SELECT DateReceived, DateExpiry, VendorKey, VendorLotNo
-                FROM LotMaster 
-                WHERE LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3 AND BinNo = @P4
-            "#;
-            let source_row = client.query(source_query, &[&lot_no, &item_key, &location, &source_bin]).await
-                .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
-                .into_row().await
-                .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
-                .ok_or(PutawayError::ValidationError("Source lot not found".to_string()))?;
-
-            let date_received: NaiveDateTime = source_row.get("DateReceived").unwrap_or(now);
-            let date_expiry: Option<NaiveDateTime> = source_row.get("DateExpiry");
-            let vendor_key: &str = source_row.get("VendorKey").unwrap_or("");
-            let vendor_lot_no: &str = source_row.get("VendorLotNo").unwrap_or("");
-            // Note: CustomerKey doesn't exist in LotMaster, use empty string for LotTransaction
-            let customer_key: &str = "";
-
-            // NOTE: We do NOT update LotMaster.QtyCommitSales here.
-            // BME calculates commitment from LotTransaction records (Type 9 with Processed='N')
-            // The Type 9 INSERT below already tracks the commitment via the standard query:
-            // SELECT SUM(QtyIssued) FROM LotTransaction WHERE Processed IN ('N','P') AND TransactionType IN (2,3,5,7,9,10,12,16,17,20,21)
-
-            // 5. INSERT LotTransaction - Type 9 (Inv Adj Negative / Issue) from source bin
-            let issue_insert = r#"
-                INSERT INTO LotTransaction (
-                    LotNo, ItemKey, LocationKey, DateReceived, DateExpiry,
-                    TransactionType, VendorlotNo, 
-                    IssueDocNo, IssueDocLineNo, IssueDate, QtyIssued,
-                    RecUserid, RecDate, Processed, BinNo
-                ) VALUES (
-                    @P1, @P2, @P3, @P4, @P5,
-                    9, @P6,
-                    @P7, 1, @P8, @P9,
-                    @P10, @P11, 'N', @P12
-                )
-            "#;
-            client.execute(issue_insert, &[
-                &lot_no, &item_key, &location, &date_received, &date_expiry,
-                &vendor_lot_no,
-                &document_no, &now, &transfer_qty,
-                &user_id_truncated, &now, &source_bin
-            ]).await.map_err(|e| PutawayError::TransactionError(format!("Failed to insert Issue LotTransaction: {e}")))?;
-
-            // 6. INSERT LotTransaction - Type 8 (Inv Adj Positive / Receipt) to dest bin
-            // BME includes DateQuarantine field (NULL for committed transfers)
-            let receipt_insert = r#"
-                INSERT INTO LotTransaction (
-                    LotNo, ItemKey, LocationKey, DateReceived, DateExpiry,
-                    TransactionType,
-                    ReceiptDocNo, ReceiptDocLineNo, QtyReceived,
-                    Vendorkey, VendorlotNo, CustomerKey,
-                    RecUserid, RecDate, Processed, BinNo, DateQuarantine
-                ) VALUES (
-                    @P1, @P2, @P3, @P4, @P5,
-                    8,
-                    @P6, 1, @P7,
-                    @P8, @P9, @P10,
-                    @P11, @P12, 'N', @P13, NULL
-                )
-            "#;
-            client.execute(receipt_insert, &[
-                &lot_no, &item_key, &location, &date_received, &date_expiry,
-                &document_no, &transfer_qty,
-                &vendor_key, &vendor_lot_no, &customer_key,
-                &user_id_truncated, &now, &target_bin
-            ]).await.map_err(|e| PutawayError::TransactionError(format!("Failed to insert Receipt LotTransaction: {e}")))?;
-
-            // 7. Handle LotMaster movement - Move BOTH QtyOnHand AND QtyCommitSales from source to destination
-            // BME "Transfer with Commit" moves physical inventory along with its commitment status
-            
-            // 7a. Get source LotMaster current quantities
-            let source_qty_query = r#"
-                SELECT QtyOnHand, QtyCommitSales, LotStatus, QtyReceived
-                FROM LotMaster 
-                WHERE LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3 AND BinNo = @P4
-            "#;
-            let source_qty_row = client.query(source_qty_query, &[&lot_no, &item_key, &location, &source_bin]).await
-                .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
-                .into_row().await
-                .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
-                .ok_or(PutawayError::ValidationError("Source lot not found for qty check".to_string()))?;
-
-            let source_qty_on_hand: f64 = source_qty_row.get("QtyOnHand").unwrap_or(0.0);
-            let source_qty_commit: f64 = source_qty_row.get("QtyCommitSales").unwrap_or(0.0);
-            let source_lot_status: String = source_qty_row.get::<&str, _>("LotStatus").unwrap_or("P").to_string();
-            let source_qty_received: f64 = source_qty_row.get("QtyReceived").unwrap_or(0.0);
-            
-            // Calculate new source quantities after transfer
-            let new_source_qty_on_hand = source_qty_on_hand - transfer_qty;
-            let new_source_qty_commit = if source_qty_commit >= transfer_qty {
-                source_qty_commit - transfer_qty
-            } else {
-                0.0 // Don't go negative
-            };
+        Ok(deleted > 0)
+    }
 
-            // 7b. Update or Delete source LotMaster
-            if new_source_qty_on_hand <= 0.0 {
-                // Delete source record if QtyOnHand becomes 0 or negative
-                let delete_source = r#"
-                    DELETE FROM LotMaster 
-                    WHERE LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3 AND BinNo = @P4
-                "#;
-                client.execute(delete_source, &[&lot_no, &item_key, &location, &source_bin]).await
-                    .map_err(|e| PutawayError::TransactionError(format!("Failed to delete source LotMaster: {e}")))?;
-            } else {
-                // Update source with reduced quantities
-                let update_source = r#"
-                    UPDATE LotMaster 
-                    SET QtyOnHand = @P1, QtyCommitSales = @P2, 
-                        DocumentNo = @P3, TransactionType = 9,
-                        RecUserId = @P4, Recdate = @P5
-                    WHERE LotNo = @P6 AND ItemKey = @P7 AND LocationKey = @P8 AND BinNo = @P9
-                "#;
-                client.execute(update_source, &[
-                    &new_source_qty_on_hand, &new_source_qty_commit,
-                    &document_no, &user_id_truncated, &now,
-                    &lot_no, &item_key, &location, &source_bin
-                ]).await.map_err(|e| PutawayError::TransactionError(format!("Failed to update source LotMaster: {e}")))?;
-            }
+    async fn reserve_idempotency_key(
+        &self,
+        scope: &str,
+        user_id: &str,
+        key: &str,
+        request_hash: &str,
+        ttl_hours: i64,
+        lease_seconds: i64,
+    ) -> Result<IdempotencyReservation, PutawayError> {
+        self.idempotency().reserve(scope, user_id, key, request_hash, ttl_hours, lease_seconds).await
+    }
 
-            // 7c. Check if destination LotMaster exists
-            let dest_check_query = r#"
-                SELECT QtyOnHand, QtyCommitSales 
-                FROM LotMaster 
-                WHERE LotNo = @P1 AND ItemKey = @P2 AND LocationKey = @P3 AND BinNo = @P4
-            "#;
-            let dest_exists = client.query(dest_check_query, &[&lot_no, &item_key, &location, &target_bin]).await
-                .map_err(|e| PutawayError::DatabaseError(e.to_string()))?
-                .into_row().await
-                .map_err(|e| PutawayError::DatabaseError(e.to_string()))?;
+    async fn complete_idempotency_key(
+        &self,
+        conn: &mut SqlConnection,
+        claim: &IdempotencyClaim,
+        document_no: &str,
+        response_body: Option<&str>,
+    ) -> Result<(), PutawayError> {
+        IdempotencyDatabase::complete(conn, claim, document_no, response_body).await
+    }
 
-            if let Some(dest_row) = dest_exists {
-                // 7d. Destination exists - Update with added quantities
-                let dest_qty_on_hand: f64 = dest_row.get("QtyOnHand").unwrap_or(0.0);
-                let dest_qty_commit: f64 = dest_row.get("QtyCommitSales").unwrap_or(0.0);
-                
-                let new_dest_qty_on_hand = dest_qty_on_hand + transfer_qty;
-                let new_dest_qty_commit = dest_qty_commit + transfer_qty; // Committed stock moves with commitment
-
-                let update_dest = r#"
-                    UPDATE LotMaster 
-                    SET QtyOnHand = @P1, QtyCommitSales = @P2,
-                        DocumentNo = @P3, TransactionType = 8,
-                        RecUserId = @P4, Recdate = @P5
-                    WHERE LotNo = @P6 AND ItemKey = @P7 AND LocationKey = @P8 AND BinNo = @P9
-                "#;
-                client.execute(update_dest, &[
-                    &new_dest_qty_on_hand, &new_dest_qty_commit,
-                    &document_no, &user_id_truncated, &now,
-                    &lot_no, &item_key, &location, &target_bin
-                ]).await.map_err(|e| PutawayError::TransactionError(format!("Failed to update destination LotMaster: {e}")))?;
-            } else {
-                // 7e. Destination doesn't exist - Create new record with committed quantities
-                let insert_dest = r#"
-                    INSERT INTO LotMaster (
-                        LotNo, ItemKey, LocationKey, DateReceived, DateExpiry,
-                        QtyReceived, QtyIssued, QtyCommitSales, QtyOnHand,
-                        DocumentNo, DocumentLineNo, TransactionType, VendorKey, VendorLotNo,
-                        QtyOnOrder, RecUserId, Recdate, BinNo, LotStatus
-                    ) VALUES (
-                        @P1, @P2, @P3, @P4, @P5,
-                        @P6, 0, @P7, @P7,
-                        @P8, 1, 8, @P9, @P10,
-                        0, @P11, @P12, @P13, @P14
-                    )
-                "#;
-                client.execute(insert_dest, &[
-                    &lot_no, &item_key, &location, &date_received, &date_expiry,
-                    &source_qty_received, &transfer_qty,
-                    &document_no, &vendor_key, &vendor_lot_no,
-                    &user_id_truncated, &now, &target_bin, &source_lot_status
-                ]).await.map_err(|e| PutawayError::TransactionError(format!("Failed to insert destination LotMaster: {e}")))?;
-            }
+    async fn release_idempotency_key(&self, claim: &IdempotencyClaim) -> Result<(), PutawayError> {
+        self.idempotency().release(claim).await
+    }
+}
 
-            // 8. Audit Trail (BinTransfer) - Include User1 (remarks) and User5 (referenced)
-            let bin_transfer_query = r#"
-                INSERT INTO BinTransfer (
-                    ItemKey, Location, LotNo, BinNoFrom, BinNoTo, 
-                    LotTranNo, QtyOnHand, TransferQty, InTransID, 
-                    RecUserID, RecDate, ContainerNo, User1, User5
-                ) VALUES (@P1, @P2, @P3, @P4, @P5, 0, @P6, @P7, 0, @P8, @P9, '0', @P10, @P11)
-            "#;
-            client.execute(bin_transfer_query, &[
-                &item_key, &location, &lot_no, &source_bin, &target_bin,
-                &source_qty_on_hand, &transfer_qty, &user_id_truncated, &now,
-                &remarks, &referenced
-            ]).await.map_err(|e| PutawayError::TransactionError(format!("Failed to log BinTransfer: {e}")))?;
-
-            Ok(document_no)
-        }.await;
-
-        match transaction_result {
-            Ok(doc_no) => {
-                client.simple_query("COMMIT").await.map_err(|e| PutawayError::DatabaseError(format!("Commit failed: {e}")))?;
-                Ok(doc_no)
-            },
-            Err(e) => {
-                client.simple_query("ROLLBACK").await.ok();
-                Err(e)
-            }
-        }
+/// Read a BME Y/N style flag that may be stored as char, bit or numeric depending on the table
+fn flag_column_is_set(row: &tiberius::Row, column: &str) -> bool {
+    if let Ok(Some(value)) = row.try_get::<&str, _>(column) {
+        return matches!(
+            value.trim().to_ascii_uppercase().as_str(),
+            "Y" | "YES" | "T" | "TRUE" | "1"
+        );
+    }
+    if let Ok(Some(value)) = row.try_get::<bool, _>(column) {
+        return value;
+    }
+    if let Ok(Some(value)) = row.try_get::<u8, _>(column) {
+        return value != 0;
+    }
+    if let Ok(Some(value)) = row.try_get::<i16, _>(column) {
+        return value != 0;
+    }
+    if let Ok(Some(value)) = row.try_get::<i32, _>(column) {
+        return value != 0;
     }
+    false
 }
@@ -0,0 +1,642 @@
+use crate::database::idempotency_db::{IdempotencyClaim, IdempotencyReservation};
+use crate::models::putaway_models::{
+    BinBlockEvent, BinBlockRecord, BinCapacityRecord, BinMasterRecord, BinSearchItem, BinTransferRecord, InlocRecord, ItemMasterRecord, LotMasterRecord,
+    LotSearchItem, LotSnapshotRecord, LotTransactionItem, LotTransactionRecord, MintxdhRecord, MovedCommitmentLine,
+    PutawayError, UomConversionRecord,
+};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use std::collections::HashSet;
+use std::future::Future;
+
+/// A lot of one item in one location. LotMaster holds one row per bin for it.
+#[derive(Debug, Clone, Copy)]
+pub struct LotKey<'a> {
+    pub lot_no: &'a str,
+    pub item_key: &'a str,
+    pub location: &'a str,
+}
+
+/// One line of a BT document: its issue (type 9) / receipt (type 8) LotTransaction pair
+#[derive(Debug, Clone)]
+pub struct BtDocumentLine {
+    pub line_no: i16,
+    pub lot_no: String,
+    pub item_key: String,
+    pub location: String,
+    pub bin_from: String,
+    pub bin_to: String,
+    pub qty: BigDecimal,
+    pub processed: String,
+    pub bin_transfer_count: i32,
+}
+
+/// BinTransfer columns the link backfill matches on. BinTransfer has no key of its own,
+/// so a row is found again by all of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinTransferLink {
+    pub lot_no: String,
+    pub item_key: String,
+    pub location: String,
+    pub bin_no_from: String,
+    pub bin_no_to: String,
+    pub rec_date: NaiveDateTime,
+    /// 0 until linked to the issue LotTransaction
+    pub lot_tran_no: i32,
+    /// 0 until linked to the Mintxdh header
+    pub in_trans_id: i32,
+}
+
+/// Issue (type 9) or receipt (type 8) LotTransaction row of a BT document
+#[derive(Debug, Clone)]
+pub struct BtLotTransaction {
+    pub lot_tran_no: i32,
+    pub transaction_type: u8,
+    /// IssueDocNo / ReceiptDocNo
+    pub document_no: String,
+    /// IssueDocLineNo / ReceiptDocLineNo
+    pub line_no: i16,
+    pub bin_no: String,
+    pub rec_date: NaiveDateTime,
+}
+
+/// One bin holding a lot, joined with its INMAST item
+#[derive(Debug, Clone)]
+pub struct LotBinRecord {
+    pub lot: LotMasterRecord,
+    pub item: ItemMasterRecord,
+    /// BME commitment of the bin: pending issues in LotTransaction and QCLotTransaction
+    pub committed: BigDecimal,
+}
+
+/// A BINMaster row of the lot's location as a putaway destination: the bin search's lot
+/// context plus what else the bin holds
+#[derive(Debug, Clone)]
+pub struct BinCandidateRecord {
+    pub bin: BinMasterRecord,
+    /// LotStatus of the lot in this bin (LEFT JOIN LotMaster, as in the bin search)
+    pub lot_status: Option<String>,
+    /// QtyOnHand of the lot in this bin
+    pub lot_qty_on_hand: BigDecimal,
+    /// Other lots of the same item with stock in the bin
+    pub item_lots: i32,
+    /// Lot rows with stock in the bin, any item
+    pub stocked_lots: i32,
+}
+
+/// Bins `bin_candidates` leaves out
+#[derive(Debug, Clone, Copy)]
+pub struct BinCandidateExclusions<'a> {
+    /// Bin numbers, or prefixes ending in `*`
+    pub bins: &'a [String],
+    /// Whole aisles (BINMaster.aisle); bins with a blank aisle are never left out by it
+    pub aisles: &'a [String],
+    /// Bins with a block in force at this time are left out too
+    pub now: NaiveDateTime,
+}
+
+/// Everything written under one BT document number
+#[derive(Debug, Clone, Default)]
+pub struct BtDocumentRecords {
+    pub header: Option<MintxdhRecord>,
+    /// Issue and receipt rows, by document line
+    pub lot_transactions: Vec<LotTransactionRecord>,
+    pub bin_transfers: Vec<BinTransferRecord>,
+    pub lot_snapshots: Vec<LotSnapshotRecord>,
+    pub commitment_moves: Vec<MovedCommitmentLine>,
+    /// (original, reversal) pair the document takes part in
+    pub reversal: Option<(String, String)>,
+}
+
+/// Which lots the pending putaway queue lists
+#[derive(Debug, Clone, Copy)]
+pub struct PendingPutawayFilter<'a> {
+    /// Receiving/dock bins the queue is built from
+    pub bins: &'a [String],
+    /// LotStatus values to keep; every status when empty
+    pub statuses: &'a [String],
+    pub location: Option<&'a str>,
+}
+
+/// Column the transfer history is ordered by (ties broken by document and line number)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransferHistorySort {
+    #[default]
+    Date,
+    DocumentNo,
+    LotNo,
+    ItemKey,
+    UserId,
+}
+
+/// Which BT document lines the transfer history lists. Every filter is optional.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransferHistoryFilter<'a> {
+    pub user_id: Option<&'a str>,
+    pub lot_no: Option<&'a str>,
+    pub item_key: Option<&'a str>,
+    /// Source or destination bin
+    pub bin_no: Option<&'a str>,
+    pub location: Option<&'a str>,
+    /// Inclusive lower bound on the issue RecDate
+    pub date_from: Option<NaiveDateTime>,
+    /// Exclusive upper bound on the issue RecDate
+    pub date_to: Option<NaiveDateTime>,
+    pub sort: TransferHistorySort,
+    pub descending: bool,
+}
+
+/// One moved lot of a BT document: the issue/receipt pair plus what BinTransfer recorded for it
+#[derive(Debug, Clone)]
+pub struct TransferHistoryLine {
+    pub document_no: String,
+    pub line_no: i16,
+    pub lot_no: String,
+    pub item_key: String,
+    pub item_description: String,
+    pub uom: String,
+    pub location: String,
+    pub bin_from: String,
+    pub bin_to: String,
+    pub qty: BigDecimal,
+    pub user_id: String,
+    pub rec_date: NaiveDateTime,
+    /// BinTransfer.User1
+    pub remarks: String,
+    /// BinTransfer.User5
+    pub referenced: String,
+}
+
+/// Storage behind the putaway service: the BME tables a bin transfer reads and writes
+/// (Seqnum, LotMaster, BINMaster, LotTransaction, BinTransfer, Mintxdh) plus the putaway
+/// extension tables. `PutawayDatabase` is the SQL Server implementation.
+///
+/// Operations taking a `Conn` run on that connection, so between `begin` and `commit` they
+/// share one REPEATABLE READ transaction. The transfer flows themselves live in the service.
+pub trait PutawayRepository: Clone + Send + Sync + 'static {
+    type Conn: Send;
+
+    /// Connection without an open transaction (single statements, autocommit)
+    fn connect(&self) -> impl Future<Output = Result<Self::Conn, PutawayError>> + Send;
+
+    /// Connection with an open REPEATABLE READ transaction
+    fn begin(&self) -> impl Future<Output = Result<Self::Conn, PutawayError>> + Send;
+
+    /// Commit the open transaction; the connection stays usable for reads
+    fn commit(&self, conn: &mut Self::Conn) -> impl Future<Output = Result<(), PutawayError>> + Send;
+
+    /// Roll back the open transaction (best effort - the caller is already failing)
+    fn rollback(&self, conn: &mut Self::Conn) -> impl Future<Output = ()> + Send;
+
+    // ----- Seqnum -----
+
+    /// Allocate the next BT number. Inside a transaction the Seqnum row stays locked until
+    /// COMMIT and a ROLLBACK returns the number, so no BT number is burned.
+    fn next_bt_sequence(&self, conn: &mut Self::Conn) -> impl Future<Output = Result<i32, PutawayError>> + Send;
+
+    /// Last allocated BT number, without incrementing it
+    fn current_bt_sequence(&self) -> impl Future<Output = Result<i32, PutawayError>> + Send;
+
+    // ----- BME lock flags and GL lookups -----
+
+    /// DistributionParameter.Freeze_Inventory
+    fn inventory_frozen(&self, conn: &mut Self::Conn) -> impl Future<Output = Result<bool, PutawayError>> + Send;
+
+    /// INLOC.Physinprogress for the item/location
+    fn physical_count_in_progress(
+        &self,
+        conn: &mut Self::Conn,
+        item_key: &str,
+        location: &str,
+    ) -> impl Future<Output = Result<bool, PutawayError>> + Send;
+
+    /// INLOC record for GL account mapping
+    fn inloc_record(
+        &self,
+        conn: &mut Self::Conn,
+        item_key: &str,
+        location: &str,
+    ) -> impl Future<Output = Result<InlocRecord, PutawayError>> + Send;
+
+    // ----- INMAST / INUOMD -----
+
+    /// INUOMD.UMQtyDP of the item's stock UOM: the decimals its quantities are kept to.
+    /// The largest when the UOM has several INUOMD rows; None when the item or its UOM has none.
+    fn item_qty_decimals(
+        &self,
+        conn: &mut Self::Conn,
+        item_key: &str,
+    ) -> impl Future<Output = Result<Option<i64>, PutawayError>> + Send;
+
+    fn item_master(
+        &self,
+        conn: &mut Self::Conn,
+        item_key: &str,
+    ) -> impl Future<Output = Result<Option<ItemMasterRecord>, PutawayError>> + Send;
+
+    /// INQTYCNV row converting `from_uom` into `to_uom` for the item, most specific scope first
+    /// (the item, then its INLOC item class, then every item)
+    fn uom_conversion(
+        &self,
+        conn: &mut Self::Conn,
+        item_key: &str,
+        from_uom: &str,
+        to_uom: &str,
+    ) -> impl Future<Output = Result<Option<UomConversionRecord>, PutawayError>> + Send;
+
+    // ----- BINMaster -----
+
+    fn bin_exists(
+        &self,
+        conn: &mut Self::Conn,
+        location: &str,
+        bin_no: &str,
+    ) -> impl Future<Output = Result<bool, PutawayError>> + Send;
+
+    /// putaway_bin_capacity row of the bin, None when its capacity is not tracked
+    fn bin_capacity(
+        &self,
+        conn: &mut Self::Conn,
+        location: &str,
+        bin_no: &str,
+    ) -> impl Future<Output = Result<Option<BinCapacityRecord>, PutawayError>> + Send;
+
+    /// putaway_bin_blocks row of the bin, expired or not (UPDLOCK inside a transaction)
+    fn bin_block(
+        &self,
+        conn: &mut Self::Conn,
+        location: &str,
+        bin_no: &str,
+    ) -> impl Future<Output = Result<Option<BinBlockRecord>, PutawayError>> + Send;
+
+    /// Insert or replace the bin's block
+    fn save_bin_block(
+        &self,
+        conn: &mut Self::Conn,
+        row: &BinBlockRecord,
+    ) -> impl Future<Output = Result<(), PutawayError>> + Send;
+
+    fn delete_bin_block(
+        &self,
+        conn: &mut Self::Conn,
+        location: &str,
+        bin_no: &str,
+    ) -> impl Future<Output = Result<(), PutawayError>> + Send;
+
+    /// History row in putaway_bin_block_history
+    fn insert_bin_block_event(
+        &self,
+        conn: &mut Self::Conn,
+        event: &BinBlockEvent,
+    ) -> impl Future<Output = Result<(), PutawayError>> + Send;
+
+    // ----- LotMaster -----
+
+    /// Lock the lot's rows in the given bins (UPDLOCK, ROWLOCK), always in BinNo order so two
+    /// transfers between the same bins cannot deadlock. Returns the rows that exist.
+    fn lock_lot_rows(
+        &self,
+        conn: &mut Self::Conn,
+        lot: LotKey<'_>,
+        bins: &[&str],
+    ) -> impl Future<Output = Result<Vec<LotMasterRecord>, PutawayError>> + Send;
+
+    /// Lock every LotMaster row in a bin, whatever the lot (UPDLOCK, HOLDLOCK - rows arriving in
+    /// the bin wait too), so a capacity check and the move it allows see the same bin contents
+    fn lock_bin_lot_rows(
+        &self,
+        conn: &mut Self::Conn,
+        location: &str,
+        bin_no: &str,
+    ) -> impl Future<Output = Result<Vec<LotMasterRecord>, PutawayError>> + Send;
+
+    fn lot_row(
+        &self,
+        conn: &mut Self::Conn,
+        lot: LotKey<'_>,
+        bin_no: &str,
+    ) -> impl Future<Output = Result<Option<LotMasterRecord>, PutawayError>> + Send;
+
+    fn insert_lot_row(
+        &self,
+        conn: &mut Self::Conn,
+        row: &LotMasterRecord,
+    ) -> impl Future<Output = Result<(), PutawayError>> + Send;
+
+    /// Write QtyOnHand, QtyCommitSales, DocumentNo, TransactionType, RecUserId and Recdate of an existing row
+    fn update_lot_row(
+        &self,
+        conn: &mut Self::Conn,
+        row: &LotMasterRecord,
+    ) -> impl Future<Output = Result<(), PutawayError>> + Send;
+
+    fn delete_lot_row(
+        &self,
+        conn: &mut Self::Conn,
+        lot: LotKey<'_>,
+        bin_no: &str,
+    ) -> impl Future<Output = Result<(), PutawayError>> + Send;
+
+    // ----- LotTransaction / QCLotTransaction -----
+
+    /// Insert a LotTransaction row and return its LotTranNo
+    fn insert_lot_transaction(
+        &self,
+        conn: &mut Self::Conn,
+        row: &LotTransactionRecord,
+    ) -> impl Future<Output = Result<i32, PutawayError>> + Send;
+
+    /// BME commitment for a lot in one bin (Phase 1.3 of the legacy trace):
+    /// sum of pending issue quantities from LotTransaction and QCLotTransaction, less the pending
+    /// type 9 issues of BT documents. Phase 1.3 counts those too, because in BME they are the
+    /// temporary reservation of a move its posting run has not applied yet (Appendix A). Here a
+    /// committed transfer moves LotMaster and the picked issue lines in its own transaction, so its
+    /// leftover issue row reserves nothing; counting it would commit the moved quantity twice.
+    /// The lot lookup and bin contents use the same rule.
+    fn bin_commitment(
+        &self,
+        conn: &mut Self::Conn,
+        lot: LotKey<'_>,
+        bin_no: &str,
+    ) -> impl Future<Output = Result<BigDecimal, PutawayError>> + Send;
+
+    /// Lock one pending issue line of the lot/bin (LotTransaction first, then QCLotTransaction).
+    /// None when the LotTranNo is not a pending commitment of that lot/bin.
+    fn lock_pending_commitment_line(
+        &self,
+        conn: &mut Self::Conn,
+        lot_tran_no: i32,
+        lot: LotKey<'_>,
+        source_bin: &str,
+        target_bin: &str,
+    ) -> impl Future<Output = Result<Option<MovedCommitmentLine>, PutawayError>> + Send;
+
+    /// Point a pending issue line at its new bin
+    fn repoint_commitment_line(
+        &self,
+        conn: &mut Self::Conn,
+        line: &MovedCommitmentLine,
+    ) -> impl Future<Output = Result<(), PutawayError>> + Send;
+
+    /// Audit row in putaway_commitment_moves
+    fn insert_commitment_move(
+        &self,
+        conn: &mut Self::Conn,
+        document_no: &str,
+        line: &MovedCommitmentLine,
+        lot: LotKey<'_>,
+        user_id: &str,
+        now: &NaiveDateTime,
+    ) -> impl Future<Output = Result<(), PutawayError>> + Send;
+
+    /// Issue/receipt pairs of a BT document, matched on the document line number
+    fn bt_document_lines(
+        &self,
+        conn: &mut Self::Conn,
+        document_no: &str,
+    ) -> impl Future<Output = Result<Vec<BtDocumentLine>, PutawayError>> + Send;
+
+    // ----- BinTransfer / Mintxdh -----
+
+    fn insert_bin_transfer(
+        &self,
+        conn: &mut Self::Conn,
+        row: &BinTransferRecord,
+    ) -> impl Future<Output = Result<(), PutawayError>> + Send;
+
+    /// Returns the new InTransID
+    fn insert_mintxdh(
+        &self,
+        conn: &mut Self::Conn,
+        row: &MintxdhRecord,
+    ) -> impl Future<Output = Result<i32, PutawayError>> + Send;
+
+    fn mintxdh_exists(
+        &self,
+        conn: &mut Self::Conn,
+        document_no: &str,
+    ) -> impl Future<Output = Result<bool, PutawayError>> + Send;
+
+    /// InTransID and RecDate of the document's Mintxdh headers
+    fn mintxdh_headers(
+        &self,
+        conn: &mut Self::Conn,
+        document_no: &str,
+    ) -> impl Future<Output = Result<Vec<(i32, NaiveDateTime)>, PutawayError>> + Send;
+
+    /// BinTransfer rows still missing their issue LotTranNo or their InTransID
+    fn unlinked_bin_transfers(
+        &self,
+        conn: &mut Self::Conn,
+    ) -> impl Future<Output = Result<Vec<BinTransferLink>, PutawayError>> + Send;
+
+    /// Every BinTransfer row of a lot
+    fn lot_bin_transfers(
+        &self,
+        conn: &mut Self::Conn,
+        lot: LotKey<'_>,
+    ) -> impl Future<Output = Result<Vec<BinTransferLink>, PutawayError>> + Send;
+
+    /// Issue and receipt LotTransaction rows BT documents wrote for a lot
+    fn bt_lot_transactions(
+        &self,
+        conn: &mut Self::Conn,
+        lot: LotKey<'_>,
+    ) -> impl Future<Output = Result<Vec<BtLotTransaction>, PutawayError>> + Send;
+
+    /// Set LotTranNo and InTransID on the BinTransfer rows matching `row`
+    fn link_bin_transfer(
+        &self,
+        conn: &mut Self::Conn,
+        row: &BinTransferLink,
+        lot_tran_no: i32,
+        in_trans_id: i32,
+    ) -> impl Future<Output = Result<(), PutawayError>> + Send;
+
+    /// Audit row in putaway_bt_lot_snapshots
+    fn insert_lot_snapshot(
+        &self,
+        conn: &mut Self::Conn,
+        row: &LotSnapshotRecord,
+    ) -> impl Future<Output = Result<(), PutawayError>> + Send;
+
+    // ----- putaway_bt_reversals -----
+
+    /// Lock and return the (original, reversal) pair the document takes part in, if any
+    fn find_bt_reversal(
+        &self,
+        conn: &mut Self::Conn,
+        document_no: &str,
+    ) -> impl Future<Output = Result<Option<(String, String)>, PutawayError>> + Send;
+
+    fn insert_bt_reversal(
+        &self,
+        conn: &mut Self::Conn,
+        original_document_no: &str,
+        reversal_document_no: &str,
+        reason: &str,
+        user_id: &str,
+        now: &NaiveDateTime,
+    ) -> impl Future<Output = Result<(), PutawayError>> + Send;
+
+    // ----- Lookups (own connection, NOLOCK reads) -----
+
+    /// Every LotMaster row of the lot with stock, by location and bin
+    fn find_lot_bins(
+        &self,
+        lot_no: &str,
+    ) -> impl Future<Output = Result<Vec<LotBinRecord>, PutawayError>> + Send;
+
+    /// Header, LotTransaction pairs, BinTransfer rows and putaway audit rows of a BT document.
+    /// BinTransfer carries no document number: its rows are found through the issue LotTranNo,
+    /// or for unlinked committed-transfer rows written before that (LotTranNo 0) the lot, bins and
+    /// RecDate of the issue.
+    fn bt_document_records(
+        &self,
+        document_no: &str,
+    ) -> impl Future<Output = Result<BtDocumentRecords, PutawayError>> + Send;
+
+    /// Every lot row with stock in a bin, oldest expiry first
+    fn find_lots_in_bin(
+        &self,
+        location: &str,
+        bin_no: &str,
+    ) -> impl Future<Output = Result<Vec<LotMasterRecord>, PutawayError>> + Send;
+
+    /// Every lot row with stock in a bin with its INMAST item and commitment, oldest expiry first
+    fn find_bin_contents(
+        &self,
+        location: &str,
+        bin_no: &str,
+    ) -> impl Future<Output = Result<Vec<LotBinRecord>, PutawayError>> + Send;
+
+    fn find_bin(
+        &self,
+        location: &str,
+        bin_no: &str,
+    ) -> impl Future<Output = Result<Option<BinMasterRecord>, PutawayError>> + Send;
+
+    /// BINMaster rows with this bin number, in any location
+    fn find_bins_by_number(&self, bin_no: &str) -> impl Future<Output = Result<Vec<BinMasterRecord>, PutawayError>> + Send;
+
+    /// Lots with stock in the filter's bins joined with their INMAST item, earliest expiry
+    /// first (no expiry last), one page of them plus the total count
+    fn get_pending_putaway_items(
+        &self,
+        filter: PendingPutawayFilter<'_>,
+        page: i32,
+        limit: i32,
+    ) -> impl Future<Output = Result<(Vec<(LotMasterRecord, ItemMasterRecord)>, i32), PutawayError>> + Send;
+
+    /// BT document lines (issue/receipt LotTransaction pairs) matching the filter, with the
+    /// remarks of their BinTransfer row, one page of them plus the total count
+    fn get_transfer_history(
+        &self,
+        filter: TransferHistoryFilter<'_>,
+        page: i32,
+        limit: i32,
+    ) -> impl Future<Output = Result<(Vec<TransferHistoryLine>, i32), PutawayError>> + Send;
+
+    fn search_lots_paginated(
+        &self,
+        query: Option<&str>,
+        page: i32,
+        limit: i32,
+    ) -> impl Future<Output = Result<(Vec<LotSearchItem>, i32), PutawayError>> + Send;
+
+    /// Bins matching the query, flagged when blocked for putaway. With lot context (lot, item and
+    /// location) each bin carries the LotStatus of that lot in it (LEFT JOIN LotMaster).
+    fn search_bins_paginated(
+        &self,
+        query: Option<&str>,
+        page: i32,
+        limit: i32,
+        lot: Option<LotKey<'_>>,
+    ) -> impl Future<Output = Result<(Vec<BinSearchItem>, i32), PutawayError>> + Send;
+
+    /// Bins of the lot's location a putaway could go to - holding the lot or another lot of its item,
+    /// or empty - with the lot context of `search_bins_paginated`, minus the excluded and blocked bins
+    fn bin_candidates(
+        &self,
+        lot: LotKey<'_>,
+        exclusions: BinCandidateExclusions<'_>,
+    ) -> impl Future<Output = Result<Vec<BinCandidateRecord>, PutawayError>> + Send;
+
+    fn get_active_remarks(&self) -> impl Future<Output = Result<Vec<serde_json::Value>, PutawayError>> + Send;
+
+    /// Pending lines of a lot in a bin (official BME query: LotTransaction UNION QCLotTransaction)
+    fn find_transactions_by_lot_and_bin(
+        &self,
+        lot_no: &str,
+        bin_no: &str,
+    ) -> impl Future<Output = Result<Vec<LotTransactionItem>, PutawayError>> + Send;
+
+    /// Which BT document numbers in a range left rows behind.
+    /// Returns (document numbers with LotTransaction rows, document numbers with a Mintxdh header).
+    fn find_bt_documents_in_range(
+        &self,
+        from_doc: &str,
+        to_doc: &str,
+    ) -> impl Future<Output = Result<(HashSet<String>, HashSet<String>), PutawayError>> + Send;
+
+    /// Capacity-tracked bins of a location with their BINMaster row, by bin number
+    fn find_bin_capacities(
+        &self,
+        location: &str,
+    ) -> impl Future<Output = Result<Vec<(BinCapacityRecord, BinMasterRecord)>, PutawayError>> + Send;
+
+    /// putaway_bin_blocks rows, expired or not, of one location or all of them, by location and bin
+    fn find_bin_blocks(
+        &self,
+        location: Option<&str>,
+    ) -> impl Future<Output = Result<Vec<BinBlockRecord>, PutawayError>> + Send;
+
+    /// Block and unblock events of a bin, newest first
+    fn find_bin_block_history(
+        &self,
+        location: &str,
+        bin_no: &str,
+    ) -> impl Future<Output = Result<Vec<BinBlockEvent>, PutawayError>> + Send;
+
+    // ----- putaway_bin_capacity -----
+
+    /// Insert or replace the bin's capacity
+    fn save_bin_capacity(&self, row: &BinCapacityRecord) -> impl Future<Output = Result<(), PutawayError>> + Send;
+
+    /// Stop tracking the bin's capacity. False when it was not tracked.
+    fn delete_bin_capacity(
+        &self,
+        location: &str,
+        bin_no: &str,
+    ) -> impl Future<Output = Result<bool, PutawayError>> + Send;
+
+    // ----- putaway_idempotency_keys -----
+
+    /// Claim a user's key for a request, or report what an earlier attempt with the same key left
+    /// behind. A pending claim older than its lease is taken over by the same request under a new
+    /// attempt id, unless a BT document was already written for it.
+    fn reserve_idempotency_key(
+        &self,
+        scope: &str,
+        user_id: &str,
+        key: &str,
+        request_hash: &str,
+        ttl_hours: i64,
+        lease_seconds: i64,
+    ) -> impl Future<Output = Result<IdempotencyReservation, PutawayError>> + Send;
+
+    /// Store the BT document and the successful response for replay, on the transfer's transaction.
+    /// A key stored without a response stays pending and is refused as already applied.
+    /// `RequestInProgress` when the claim is no longer this attempt's - the caller rolls back.
+    fn complete_idempotency_key(
+        &self,
+        conn: &mut Self::Conn,
+        claim: &IdempotencyClaim,
+        document_no: &str,
+        response_body: Option<&str>,
+    ) -> impl Future<Output = Result<(), PutawayError>> + Send;
+
+    /// Drop a claim whose request did not move any stock, if the attempt still holds it
+    fn release_idempotency_key(&self, claim: &IdempotencyClaim) -> impl Future<Output = Result<(), PutawayError>> + Send;
+
+}
@@ -0,0 +1,198 @@
+use super::Database;
+use anyhow::{Context, Result};
+use tracing::info;
+
+/// Putaway-owned tables that are not part of the BME schema.
+/// Each script is idempotent so it can run on every startup.
+const PUTAWAY_TABLES: &[(&str, &str)] = &[
+    (
+        "putaway_commitment_moves",
+        r#"
+            IF OBJECT_ID(N'dbo.putaway_commitment_moves', N'U') IS NULL
+            BEGIN
+                CREATE TABLE dbo.putaway_commitment_moves (
+                    id INT IDENTITY(1,1) NOT NULL PRIMARY KEY,
+                    bt_document_no VARCHAR(20) NOT NULL,
+                    source_table VARCHAR(20) NOT NULL,
+                    lot_tran_no INT NOT NULL,
+                    doc_no VARCHAR(30) NULL,
+                    doc_line_no SMALLINT NULL,
+                    transaction_type TINYINT NOT NULL,
+                    lot_no VARCHAR(50) NOT NULL,
+                    item_key VARCHAR(50) NOT NULL,
+                    location VARCHAR(20) NOT NULL,
+                    bin_from VARCHAR(50) NOT NULL,
+                    bin_to VARCHAR(50) NOT NULL,
+                    qty DECIMAL(28,10) NOT NULL,
+                    rec_user_id VARCHAR(20) NOT NULL,
+                    rec_date DATETIME NOT NULL
+                );
+                CREATE INDEX ix_putaway_commitment_moves_doc ON dbo.putaway_commitment_moves (bt_document_no);
+            END
+        "#,
+    ),
+    (
+        "putaway_bt_reversals",
+        r#"
+            IF OBJECT_ID(N'dbo.putaway_bt_reversals', N'U') IS NULL
+            BEGIN
+                CREATE TABLE dbo.putaway_bt_reversals (
+                    id INT IDENTITY(1,1) NOT NULL PRIMARY KEY,
+                    original_document_no VARCHAR(20) NOT NULL,
+                    reversal_document_no VARCHAR(20) NOT NULL,
+                    reason VARCHAR(255) NOT NULL,
+                    rec_user_id VARCHAR(20) NOT NULL,
+                    rec_date DATETIME NOT NULL,
+                    CONSTRAINT uq_putaway_bt_reversals_original UNIQUE (original_document_no)
+                );
+                CREATE INDEX ix_putaway_bt_reversals_reversal ON dbo.putaway_bt_reversals (reversal_document_no);
+            END
+        "#,
+    ),
+    (
+        "putaway_bt_lot_snapshots",
+        r#"
+            IF OBJECT_ID(N'dbo.putaway_bt_lot_snapshots', N'U') IS NULL
+            BEGIN
+                CREATE TABLE dbo.putaway_bt_lot_snapshots (
+                    id INT IDENTITY(1,1) NOT NULL PRIMARY KEY,
+                    bt_document_no VARCHAR(20) NOT NULL,
+                    line_no SMALLINT NOT NULL,
+                    lot_no VARCHAR(50) NOT NULL,
+                    item_key VARCHAR(50) NOT NULL,
+                    location VARCHAR(20) NOT NULL,
+                    bin_no VARCHAR(50) NOT NULL,
+                    side VARCHAR(4) NOT NULL,
+                    qty_on_hand_before DECIMAL(28,10) NOT NULL,
+                    qty_on_hand_after DECIMAL(28,10) NOT NULL,
+                    qty_commit_before DECIMAL(28,10) NOT NULL,
+                    qty_commit_after DECIMAL(28,10) NOT NULL,
+                    lot_tran_no INT NULL,
+                    rec_user_id VARCHAR(20) NOT NULL,
+                    rec_date DATETIME NOT NULL
+                );
+                CREATE INDEX ix_putaway_bt_lot_snapshots_doc ON dbo.putaway_bt_lot_snapshots (bt_document_no);
+            END
+        "#,
+    ),
+    (
+        "putaway_idempotency_keys",
+        r#"
+            IF OBJECT_ID(N'dbo.putaway_idempotency_keys', N'U') IS NULL
+            BEGIN
+                CREATE TABLE dbo.putaway_idempotency_keys (
+                    scope VARCHAR(30) NOT NULL,
+                    user_id NVARCHAR(100) NOT NULL,
+                    idempotency_key VARCHAR(100) NOT NULL,
+                    request_hash VARCHAR(32) NOT NULL,
+                    status VARCHAR(10) NOT NULL,
+                    response_body NVARCHAR(MAX) NULL,
+                    created_at DATETIME NOT NULL,
+                    expires_at DATETIME NOT NULL,
+                    lease_expires_at DATETIME NOT NULL,
+                    attempt_id VARCHAR(36) NULL,
+                    document_no VARCHAR(20) NULL,
+                    CONSTRAINT pk_putaway_idempotency_keys PRIMARY KEY (scope, user_id, idempotency_key)
+                );
+                CREATE INDEX ix_putaway_idempotency_keys_expires ON dbo.putaway_idempotency_keys (expires_at);
+            END
+        "#,
+    ),
+    (
+        "putaway_bin_capacity",
+        r#"
+            IF OBJECT_ID(N'dbo.putaway_bin_capacity', N'U') IS NULL
+            BEGIN
+                CREATE TABLE dbo.putaway_bin_capacity (
+                    location VARCHAR(20) NOT NULL,
+                    bin_no VARCHAR(50) NOT NULL,
+                    capacity_unit VARCHAR(10) NOT NULL,
+                    capacity DECIMAL(19,4) NOT NULL,
+                    rec_user_id VARCHAR(20) NOT NULL,
+                    rec_date DATETIME NOT NULL,
+                    CONSTRAINT pk_putaway_bin_capacity PRIMARY KEY (location, bin_no)
+                );
+            END
+        "#,
+    ),
+    (
+        "putaway_bin_blocks",
+        r#"
+            IF OBJECT_ID(N'dbo.putaway_bin_blocks', N'U') IS NULL
+            BEGIN
+                CREATE TABLE dbo.putaway_bin_blocks (
+                    location VARCHAR(20) NOT NULL,
+                    bin_no VARCHAR(50) NOT NULL,
+                    reason VARCHAR(255) NOT NULL,
+                    blocked_until DATETIME NULL,
+                    rec_user_id VARCHAR(20) NOT NULL,
+                    rec_date DATETIME NOT NULL,
+                    CONSTRAINT pk_putaway_bin_blocks PRIMARY KEY (location, bin_no)
+                );
+            END
+        "#,
+    ),
+    (
+        "putaway_bin_block_history",
+        r#"
+            IF OBJECT_ID(N'dbo.putaway_bin_block_history', N'U') IS NULL
+            BEGIN
+                CREATE TABLE dbo.putaway_bin_block_history (
+                    id INT IDENTITY(1,1) NOT NULL PRIMARY KEY,
+                    location VARCHAR(20) NOT NULL,
+                    bin_no VARCHAR(50) NOT NULL,
+                    action VARCHAR(10) NOT NULL,
+                    reason VARCHAR(255) NOT NULL,
+                    blocked_until DATETIME NULL,
+                    rec_user_id VARCHAR(20) NOT NULL,
+                    rec_date DATETIME NOT NULL
+                );
+                CREATE INDEX ix_putaway_bin_block_history_bin ON dbo.putaway_bin_block_history (location, bin_no);
+            END
+        "#,
+    ),
+    (
+        "putaway_refresh_tokens",
+        r#"
+            IF OBJECT_ID(N'dbo.putaway_refresh_tokens', N'U') IS NULL
+            BEGIN
+                CREATE TABLE dbo.putaway_refresh_tokens (
+                    token_hash CHAR(64) NOT NULL PRIMARY KEY,
+                    session_id VARCHAR(36) NOT NULL,
+                    user_id VARCHAR(50) NOT NULL,
+                    username VARCHAR(50) NOT NULL,
+                    email VARCHAR(100) NOT NULL,
+                    display_name NVARCHAR(100) NOT NULL,
+                    roles VARCHAR(255) NOT NULL,
+                    auth_source VARCHAR(10) NULL,
+                    issued_at DATETIME NOT NULL,
+                    expires_at DATETIME NOT NULL,
+                    replaced_by CHAR(64) NULL,
+                    revoked_at DATETIME NULL
+                );
+                CREATE INDEX ix_putaway_refresh_tokens_session ON dbo.putaway_refresh_tokens (session_id);
+                CREATE INDEX ix_putaway_refresh_tokens_user ON dbo.putaway_refresh_tokens (user_id);
+            END
+        "#,
+    ),
+];
+
+impl Database {
+    /// Create missing putaway extension tables
+    pub async fn ensure_putaway_tables(&self) -> Result<()> {
+        let mut client = self.get_client().await?;
+
+        for (name, script) in PUTAWAY_TABLES {
+            client
+                .simple_query(*script)
+                .await
+                .with_context(|| format!("Failed to create table {name}"))?
+                .into_results()
+                .await
+                .with_context(|| format!("Failed to create table {name}"))?;
+            info!("✅ Putaway table '{}' ready", name);
+        }
+
+        Ok(())
+    }
+}
@@ -1,44 +1,70 @@
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::Json,
-    routing::{get, post},
-    Router,
+    routing::{delete, get, post, put},
+    Extension, Router,
 };
 use std::collections::HashMap;
 use serde_json::json;
 
-use crate::database::Database;
+use crate::database::repository::{LotKey, PutawayRepository};
+use crate::middleware::auth::AuthUser;
+use crate::models::putaway::{ScanRequest, ScanResponse};
+use crate::services::putaway_service::{ReceivingBins, SuggestionRules};
 use crate::services::PutawayService;
+use crate::utils::authorization::{GrantedPermissions, Permission};
+use crate::utils::quantity::ExactQty;
 use crate::models::putaway_models::{
     LotSearchResult, BinValidationResult, BinTransferRequest,
     TransferResult, PutawayHealthResponse,
-    PutawayError, LotTransactionItem, CommittedTransferRequest, CommittedTransferResult
+    PutawayError, LotTransactionItem, CommittedTransferRequest, CommittedTransferResult,
+    InventoryLockReason, BtSequenceGapReport, BatchTransferRequest, BatchTransferResult,
+    BinEvacuationRequest, BinEvacuationResult, TransferReversalRequest, TransferReversalResult,
+    TransferHistoryQuery, BtDocumentAudit, BinContents, BinSuggestionResponse, BinCapacityRequest, BinSpace,
+    BinBlockRequest, BinUnblockRequest, BinBlockRecord, BinBlockEvent,
 };
 
 /// Create putaway routes
-pub fn create_putaway_routes() -> Router<Database> {
+pub fn create_putaway_routes<R: PutawayRepository>() -> Router<R> {
     Router::new()
-        .route("/lot/{lot_no}", get(search_lot))
-        .route("/lots/search", get(search_lots))
-        .route("/bins/search", get(search_bins))
-        .route("/bin/{location}/{bin_no}", get(validate_bin))
-        .route("/transfer", post(execute_transfer))
-        .route("/health", get(get_health))
-        .route("/remarks", get(get_remarks))
-        .route("/transactions/{lot_no}/{bin_no}", get(search_transactions))
-        .route("/transfer/committed", post(transfer_committed))
+        .route("/lot/{lot_no}", get(search_lot::<R>))
+        .route("/lots/search", get(search_lots::<R>))
+        .route("/pending", get(get_pending_putaway::<R>))
+        .route("/history", get(get_transfer_history::<R>))
+        .route("/scan", post(scan_barcode::<R>))
+        .route("/bins/search", get(search_bins::<R>))
+        .route("/bins/suggest", get(suggest_bins::<R>))
+        .route("/bins/available", get(get_available_bins::<R>))
+        .route("/bin/{location}/{bin_no}", get(validate_bin::<R>))
+        .route("/bin/{location}/{bin_no}/contents", get(get_bin_contents::<R>))
+        .route("/transfer", post(execute_transfer::<R>))
+        .route("/transfer/batch", post(execute_batch_transfer::<R>))
+        .route("/bin/evacuate", post(evacuate_bin::<R>))
+        .route("/transfer/{document_no}/reverse", post(reverse_transfer::<R>))
+        .route("/health", get(get_health::<R>))
+        .route("/remarks", get(get_remarks::<R>))
+        .route("/transactions/{lot_no}/{bin_no}", get(search_transactions::<R>))
+        .route("/transfer/committed", post(transfer_committed::<R>))
+        .route("/reports/bt-gaps", get(get_bt_sequence_gaps::<R>))
+        .route("/audit/{document_no}", get(get_bt_document_audit::<R>))
+        .route("/admin/bins/capacity", put(set_bin_capacity::<R>))
+        .route("/admin/bins/capacity/{location}/{bin_no}", delete(clear_bin_capacity::<R>))
+        .route("/admin/bins/block", post(block_bin::<R>))
+        .route("/admin/bins/unblock", post(unblock_bin::<R>))
+        .route("/reports/blocked-bins", get(get_blocked_bins::<R>))
+        .route("/reports/bin-blocks/{location}/{bin_no}/history", get(get_bin_block_history::<R>))
 }
 
 // ... existing code ...
 
 /// Search for transactions
 /// GET /api/putaway/transactions/{lot_no}/{bin_no}
-async fn search_transactions(
-    State(database): State<Database>,
+async fn search_transactions<R: PutawayRepository>(
+    State(repository): State<R>,
     Path((lot_no, bin_no)): Path<(String, String)>,
 ) -> Result<Json<Vec<LotTransactionItem>>, (StatusCode, Json<serde_json::Value>)> {
-    let service = PutawayService::new(database);
+    let service = PutawayService::with_repository(repository);
     match service.search_lot_transactions(&lot_no, &bin_no).await {
          Ok(result) => Ok(Json(result)),
          Err(e) => handle_putaway_error(e)
@@ -47,17 +73,177 @@ async fn search_transactions(
 
 /// Execute committed transfer
 /// POST /api/putaway/transfer/committed
-async fn transfer_committed(
-    State(database): State<Database>,
-    Json(request): Json<CommittedTransferRequest>,
+async fn transfer_committed<R: PutawayRepository>(
+    State(repository): State<R>,
+    auth: AuthUser,
+    granted: Option<Extension<GrantedPermissions>>,
+    headers: HeaderMap,
+    Json(mut request): Json<CommittedTransferRequest>,
 ) -> Result<Json<CommittedTransferResult>, (StatusCode, Json<serde_json::Value>)> {
-    let service = PutawayService::new(database);
-    match service.execute_committed_transfer(request).await {
+    let idempotency_key = match idempotency_key(&headers) {
+        Ok(key) => key,
+        Err(e) => return handle_putaway_error(e),
+    };
+    if let Err(e) = bind_acting_user(&mut request.user_id, &auth)
+        .and_then(|_| authorize_capacity_override(request.override_capacity, &auth, granted.as_deref()))
+    {
+        return handle_putaway_error(e);
+    }
+
+    let service = PutawayService::with_repository(repository);
+    match service.execute_committed_transfer_idempotent(request, idempotency_key.as_deref()).await {
+        Ok(result) => Ok(Json(result)),
+        Err(e) => handle_putaway_error(e)
+    }
+}
+
+/// Execute a multi-line transfer under one BT document (all lines or none)
+/// POST /api/putaway/transfer/batch
+async fn execute_batch_transfer<R: PutawayRepository>(
+    State(repository): State<R>,
+    auth: AuthUser,
+    granted: Option<Extension<GrantedPermissions>>,
+    Json(mut request): Json<BatchTransferRequest>,
+) -> Result<Json<BatchTransferResult>, (StatusCode, Json<serde_json::Value>)> {
+    for line in request.lines.iter_mut() {
+        if let Err(e) = bind_acting_user(&mut line.user_id, &auth)
+            .and_then(|_| authorize_capacity_override(line.override_capacity, &auth, granted.as_deref()))
+        {
+            return handle_putaway_error(e);
+        }
+    }
+
+    let service = PutawayService::with_repository(repository);
+    match service.execute_batch_transfer(request).await {
+        Ok(result) => Ok(Json(result)),
+        Err(e) => handle_putaway_error(e)
+    }
+}
+
+/// Move every lot in a bin to another bin (committed stock stays and is reported)
+/// POST /api/putaway/bin/evacuate
+async fn evacuate_bin<R: PutawayRepository>(
+    State(repository): State<R>,
+    auth: AuthUser,
+    granted: Option<Extension<GrantedPermissions>>,
+    Json(mut request): Json<BinEvacuationRequest>,
+) -> Result<Json<BinEvacuationResult>, (StatusCode, Json<serde_json::Value>)> {
+    if let Err(e) = bind_acting_user(&mut request.user_id, &auth)
+        .and_then(|_| authorize_capacity_override(request.override_capacity, &auth, granted.as_deref()))
+    {
+        return handle_putaway_error(e);
+    }
+
+    let service = PutawayService::with_repository(repository);
+    match service.evacuate_bin(request).await {
+        Ok(result) => Ok(Json(result)),
+        Err(e) => handle_putaway_error(e)
+    }
+}
+
+/// Reverse a completed bin transfer document
+/// POST /api/putaway/transfer/{document_no}/reverse
+async fn reverse_transfer<R: PutawayRepository>(
+    State(repository): State<R>,
+    auth: AuthUser,
+    Path(document_no): Path<String>,
+    Json(mut request): Json<TransferReversalRequest>,
+) -> Result<Json<TransferReversalResult>, (StatusCode, Json<serde_json::Value>)> {
+    if let Err(e) = bind_acting_user(&mut request.user_id, &auth) {
+        return handle_putaway_error(e);
+    }
+
+    let service = PutawayService::with_repository(repository);
+    match service.reverse_transfer(&document_no, request).await {
         Ok(result) => Ok(Json(result)),
         Err(e) => handle_putaway_error(e)
     }
 }
 
+/// Report BT document numbers that were allocated but never written
+/// GET /api/putaway/reports/bt-gaps?from={seq}&to={seq}
+async fn get_bt_sequence_gaps<R: PutawayRepository>(
+    State(repository): State<R>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<BtSequenceGapReport>, (StatusCode, Json<serde_json::Value>)> {
+    let service = PutawayService::with_repository(repository);
+
+    let from = params.get("from").and_then(|s| s.parse::<i32>().ok());
+    let to = params.get("to").and_then(|s| s.parse::<i32>().ok());
+
+    match service.get_bt_sequence_gaps(from, to).await {
+        Ok(report) => Ok(Json(report)),
+        Err(e) => handle_putaway_error(e)
+    }
+}
+
+/// Everything a BT document wrote, for resolving disputes
+/// GET /api/putaway/audit/{document_no}
+async fn get_bt_document_audit<R: PutawayRepository>(
+    State(repository): State<R>,
+    Path(document_no): Path<String>,
+) -> Result<Json<BtDocumentAudit>, (StatusCode, Json<serde_json::Value>)> {
+    let service = PutawayService::with_repository(repository);
+    match service.get_bt_document_audit(&document_no).await {
+        Ok(audit) => Ok(Json(audit)),
+        Err(e) => handle_putaway_error(e)
+    }
+}
+
+/// Record transfers under the signed-in user: an omitted body user_id is filled in from the
+/// JWT, a different one is refused so nobody can post under someone else's ID
+fn bind_acting_user(user_id: &mut String, auth: &AuthUser) -> Result<(), PutawayError> {
+    let requested = user_id.trim();
+    if !requested.is_empty() && !requested.eq_ignore_ascii_case(auth.user_id()) {
+        tracing::warn!(
+            requested,
+            authenticated = auth.user_id(),
+            "Rejected transfer posted under another user's ID"
+        );
+        return Err(PutawayError::UserMismatch {
+            requested: requested.to_string(),
+            authenticated: auth.user_id().to_string(),
+        });
+    }
+
+    *user_id = auth.user_id().to_string();
+    Ok(())
+}
+
+/// Only holders of capacity_override may move stock past a destination bin's capacity
+fn authorize_capacity_override(
+    requested: bool,
+    auth: &AuthUser,
+    granted: Option<&GrantedPermissions>,
+) -> Result<(), PutawayError> {
+    let permission = Permission::CapacityOverride;
+    if requested && !granted.is_some_and(|granted| granted.contains(permission)) {
+        tracing::warn!(user = auth.user_id(), "Rejected capacity override without '{}'", permission.name());
+        return Err(PutawayError::PermissionDenied { permission: permission.name() });
+    }
+    Ok(())
+}
+
+/// Read the optional Idempotency-Key header sent by retrying scanners
+fn idempotency_key(headers: &HeaderMap) -> Result<Option<String>, PutawayError> {
+    let Some(value) = headers.get("Idempotency-Key") else {
+        return Ok(None);
+    };
+
+    let key = value
+        .to_str()
+        .map_err(|_| PutawayError::ValidationError("Idempotency-Key must be plain text".to_string()))?
+        .trim();
+
+    if key.is_empty() || key.len() > 100 {
+        return Err(PutawayError::ValidationError(
+            "Idempotency-Key must be between 1 and 100 characters".to_string(),
+        ));
+    }
+
+    Ok(Some(key.to_string()))
+}
+
 fn handle_putaway_error<T>(error: PutawayError) -> Result<T, (StatusCode, Json<serde_json::Value>)> {
     match error {
         PutawayError::LotNotFound { lot_no } => Err((
@@ -81,6 +267,94 @@ fn handle_putaway_error<T>(error: PutawayError) -> Result<T, (StatusCode, Json<s
                  "message": format!("Bin '{}' is not valid in location '{}'", bin_no, location)
              }))
          )),
+        PutawayError::InsufficientQuantity { requested, available } => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": "Insufficient quantity",
+                "message": format!("Requested {} but only {} available", requested, available),
+                "requested": ExactQty(&requested),
+                "available": ExactQty(&available)
+            }))
+        )),
+        PutawayError::InventoryLocked { reason } => Err((
+            StatusCode::LOCKED,
+            Json(json!({
+                "error": match reason {
+                    InventoryLockReason::InventoryFrozen => "Inventory frozen",
+                    InventoryLockReason::PhysicalCountInProgress { .. } => "Physical count in progress",
+                },
+                "code": reason.code(),
+                "message": format!("Transfer not allowed: {}", reason)
+            }))
+        )),
+        PutawayError::DocumentNotFound { document_no } => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "error": "Document not found",
+                "message": format!("Document '{}' not found", document_no)
+            }))
+        )),
+        PutawayError::RequestInProgress { key } => Err((
+            StatusCode::CONFLICT,
+            Json(json!({
+                "error": "Request in progress",
+                "message": format!("A request with Idempotency-Key '{}' is still being processed - retry shortly", key)
+            }))
+        )),
+        PutawayError::RequestAlreadyApplied { key, document_no } => Err((
+            StatusCode::CONFLICT,
+            Json(json!({
+                "error": "Request already applied",
+                "message": format!("The request with Idempotency-Key '{}' was already applied as {} - check the transfer history", key, document_no),
+                "document_no": document_no
+            }))
+        )),
+        PutawayError::IdempotencyKeyReused { key } => Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({
+                "error": "Idempotency key reused",
+                "message": format!("Idempotency-Key '{}' was already used for a different request", key)
+            }))
+        )),
+        PutawayError::UserMismatch { requested, authenticated } => Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "error": "User mismatch",
+                "message": format!("Request user '{}' does not match signed-in user '{}'", requested, authenticated)
+            }))
+        )),
+        PutawayError::PermissionDenied { permission } => Err((
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "error": "Forbidden",
+                "code": "PERMISSION_DENIED",
+                "message": format!("You do not have permission to perform this action ({})", permission)
+            }))
+        )),
+        PutawayError::BinCapacityExceeded(excess) => Err((
+            StatusCode::CONFLICT,
+            Json(json!({
+                "error": "Bin capacity exceeded",
+                "code": "BIN_CAPACITY_EXCEEDED",
+                "message": format!(
+                    "Bin '{}' in location '{}' holds {} of {} {} - {} more would overfill it",
+                    excess.bin_no, excess.location, excess.occupied, excess.capacity, excess.unit.label(), excess.incoming
+                ),
+                "unit": excess.unit,
+                "capacity": ExactQty(&excess.capacity),
+                "occupied": ExactQty(&excess.occupied),
+                "incoming": ExactQty(&excess.incoming)
+            }))
+        )),
+        PutawayError::BinBlocked { bin_no, location, reason } => Err((
+            StatusCode::CONFLICT,
+            Json(json!({
+                "error": "Bin blocked",
+                "code": "BIN_BLOCKED",
+                "message": format!("Bin '{}' in location '{}' is blocked for putaway: {}", bin_no, location, reason),
+                "reason": reason
+            }))
+        )),
         PutawayError::DatabaseError(msg) => {
             tracing::error!("Database error: {msg}");
             Err((
@@ -101,27 +375,17 @@ fn handle_putaway_error<T>(error: PutawayError) -> Result<T, (StatusCode, Json<s
                  }))
              ))
         },
-        e => {
-             tracing::error!("Unexpected error: {e}");
-             Err((
-                 StatusCode::INTERNAL_SERVER_ERROR,
-                 Json(json!({
-                     "error": "Internal server error",
-                     "message": "An unexpected error occurred"
-                 }))
-             ))
-        }
     }
 }
 
 
 /// Search for lot details
 /// GET /api/putaway/lot/{lot_no}
-async fn search_lot(
-    State(database): State<Database>,
+async fn search_lot<R: PutawayRepository>(
+    State(repository): State<R>,
     Path(lot_no): Path<String>,
 ) -> Result<Json<LotSearchResult>, (StatusCode, Json<serde_json::Value>)> {
-    let service = PutawayService::new(database);
+    let service = PutawayService::with_repository(repository);
 
     match service.search_lot(&lot_no).await {
         Ok(result) => Ok(Json(result)),
@@ -169,11 +433,11 @@ async fn search_lot(
 
 /// Search for lots with optional query filter and pagination
 /// GET /api/putaway/lots/search?query={search_term}&page={page}&limit={limit}
-async fn search_lots(
-    State(database): State<Database>,
+async fn search_lots<R: PutawayRepository>(
+    State(repository): State<R>,
     Query(params): Query<HashMap<String, String>>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
-    let service = PutawayService::new(database);
+    let service = PutawayService::with_repository(repository);
 
     // Extract query parameters
     let query = params.get("query").map(|s| s.as_str());
@@ -218,16 +482,101 @@ async fn search_lots(
     }
 }
 
+/// Lots waiting in the receiving/dock bins, earliest expiry first
+/// GET /api/putaway/pending?status={P,Q}&location={loc}&page={page}&limit={limit}
+async fn get_pending_putaway<R: PutawayRepository>(
+    State(repository): State<R>,
+    Extension(ReceivingBins(receiving_bins)): Extension<ReceivingBins>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    let service = PutawayService::with_repository(repository);
+
+    // LotStatus codes, comma-separated
+    let statuses: Vec<String> = params
+        .get("status")
+        .map(|s| s.split(',').map(|status| status.trim().to_string()).filter(|status| !status.is_empty()).collect())
+        .unwrap_or_default();
+    let location = params.get("location").map(|s| s.as_str()).filter(|s| !s.trim().is_empty());
+    let page = params.get("page")
+        .and_then(|s| s.parse::<i32>().ok())
+        .unwrap_or(1)
+        .max(1);
+    let limit = params.get("limit")
+        .and_then(|s| s.parse::<i32>().ok())
+        .filter(|limit| (1..=100).contains(limit))
+        .unwrap_or(20);
+
+    match service.get_pending_putaway_items(&receiving_bins, &statuses, location, page, limit).await {
+        Ok((items, total)) => {
+            let total_pages = ((total as f64) / (limit as f64)).ceil() as i32;
+            Ok(Json(json!({
+                "items": items,
+                "total": total,
+                "page": page,
+                "pages": total_pages,
+                "limit": limit
+            })))
+        }
+        Err(e) => handle_putaway_error(e),
+    }
+}
+
+/// Bin transfers by BT document line, newest first
+/// GET /api/putaway/history?user_id=&lot_no=&item_key=&bin_no=&location=&date_from={YYYY-MM-DD}&date_to={YYYY-MM-DD}&sort={date|document_no|lot_no|item_key|user_id}&order={asc|desc}&page={page}&limit={limit}
+async fn get_transfer_history<R: PutawayRepository>(
+    State(repository): State<R>,
+    Query(filters): Query<TransferHistoryQuery>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    let service = PutawayService::with_repository(repository);
+
+    let page = params.get("page")
+        .and_then(|s| s.parse::<i32>().ok())
+        .unwrap_or(1)
+        .max(1);
+    let limit = params.get("limit")
+        .and_then(|s| s.parse::<i32>().ok())
+        .filter(|limit| (1..=100).contains(limit))
+        .unwrap_or(20);
+
+    match service.get_transfer_history(&filters, page, limit).await {
+        Ok((items, total)) => {
+            let total_pages = ((total as f64) / (limit as f64)).ceil() as i32;
+            Ok(Json(json!({
+                "items": items,
+                "total": total,
+                "page": page,
+                "pages": total_pages,
+                "limit": limit
+            })))
+        }
+        Err(e) => handle_putaway_error(e),
+    }
+}
+
+/// Resolve a raw scanner string (GS1-128 label, lot, bin or item barcode)
+/// POST /api/putaway/scan
+async fn scan_barcode<R: PutawayRepository>(
+    State(repository): State<R>,
+    Json(request): Json<ScanRequest>,
+) -> Result<Json<ScanResponse>, (StatusCode, Json<serde_json::Value>)> {
+    let service = PutawayService::with_repository(repository);
+    match service.scan(request).await {
+        Ok(result) => Ok(Json(result)),
+        Err(e) => handle_putaway_error(e),
+    }
+}
+
 /// Search for bins with optional query filter and pagination
 /// GET /api/putaway/bins/search?query={search_term}&page={page}&limit={limit}&lot_no={lot}&item_key={item}&location={loc}
 ///
 /// When lot_no, item_key, and location are provided, the search will LEFT JOIN with LotMaster
 /// to show if the bin contains this lot and what status it has (helps users see consolidation targets)
-async fn search_bins(
-    State(database): State<Database>,
+async fn search_bins<R: PutawayRepository>(
+    State(repository): State<R>,
     Query(params): Query<HashMap<String, String>>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
-    let service = PutawayService::new(database);
+    let service = PutawayService::with_repository(repository);
 
     // Extract query parameters
     let query = params.get("query").map(|s| s.as_str());
@@ -279,11 +628,11 @@ async fn search_bins(
 
 /// Validate destination bin
 /// GET /api/putaway/bin/{location}/{bin_no}
-async fn validate_bin(
-    State(database): State<Database>,
+async fn validate_bin<R: PutawayRepository>(
+    State(repository): State<R>,
     Path((location, bin_no)): Path<(String, String)>,
 ) -> Result<Json<BinValidationResult>, (StatusCode, Json<serde_json::Value>)> {
-    let service = PutawayService::new(database);
+    let service = PutawayService::with_repository(repository);
 
     match service.validate_bin(&location, &bin_no).await {
         Ok(result) => Ok(Json(result)),
@@ -310,15 +659,186 @@ async fn validate_bin(
     }
 }
 
+/// Ranked destination bins for a lot, each with the reasons behind its score
+/// GET /api/putaway/bins/suggest?lot_no=&item_key=&location=&bin_from=&limit=
+async fn suggest_bins<R: PutawayRepository>(
+    State(repository): State<R>,
+    Extension(ReceivingBins(receiving_bins)): Extension<ReceivingBins>,
+    Extension(rules): Extension<SuggestionRules>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<BinSuggestionResponse>, (StatusCode, Json<serde_json::Value>)> {
+    let service = PutawayService::with_repository(repository);
+    let param = |name: &str| params.get(name).map(String::as_str).unwrap_or("");
+    let lot = LotKey { lot_no: param("lot_no"), item_key: param("item_key"), location: param("location") };
+    let bin_from = params.get("bin_from").map(String::as_str);
+    let limit = params.get("limit").and_then(|s| s.parse::<usize>().ok());
+
+    match service.suggest_destination_bins(lot, bin_from, limit, &receiving_bins, &rules).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => handle_putaway_error(e)
+    }
+}
+
+/// Lots in a bin with their commitments, plus the bin's BINMaster attributes
+/// GET /api/putaway/bin/{location}/{bin_no}/contents
+async fn get_bin_contents<R: PutawayRepository>(
+    State(repository): State<R>,
+    Path((location, bin_no)): Path<(String, String)>,
+) -> Result<Json<BinContents>, (StatusCode, Json<serde_json::Value>)> {
+    let service = PutawayService::with_repository(repository);
+    match service.get_bin_contents(&location, &bin_no).await {
+        Ok(contents) => Ok(Json(contents)),
+        Err(e) => handle_putaway_error(e)
+    }
+}
+
+/// Capacity-tracked bins of a location with the space left in them
+/// GET /api/putaway/bins/available?location=
+async fn get_available_bins<R: PutawayRepository>(
+    State(repository): State<R>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    let service = PutawayService::with_repository(repository);
+    let location = params.get("location").map(String::as_str).unwrap_or("");
+
+    match service.get_available_bins(location).await {
+        Ok(bins) => Ok(Json(json!({
+            "success": true,
+            "message": format!("{} bin(s) with space in {}", bins.len(), location.trim()),
+            "data": bins
+        }))),
+        Err(e) => handle_putaway_error(e)
+    }
+}
+
+/// Set how much a bin holds, in pallets, weight (KG) or stock quantity
+/// PUT /api/putaway/admin/bins/capacity
+async fn set_bin_capacity<R: PutawayRepository>(
+    State(repository): State<R>,
+    auth: AuthUser,
+    Json(mut request): Json<BinCapacityRequest>,
+) -> Result<Json<BinSpace>, (StatusCode, Json<serde_json::Value>)> {
+    if let Err(e) = bind_acting_user(&mut request.user_id, &auth) {
+        return handle_putaway_error(e);
+    }
+
+    let service = PutawayService::with_repository(repository);
+    match service.set_bin_capacity(request).await {
+        Ok(space) => Ok(Json(space)),
+        Err(e) => handle_putaway_error(e)
+    }
+}
+
+/// Stop tracking a bin's capacity
+/// DELETE /api/putaway/admin/bins/capacity/{location}/{bin_no}
+async fn clear_bin_capacity<R: PutawayRepository>(
+    State(repository): State<R>,
+    Path((location, bin_no)): Path<(String, String)>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    let service = PutawayService::with_repository(repository);
+    match service.clear_bin_capacity(&location, &bin_no).await {
+        Ok(()) => Ok(Json(json!({
+            "success": true,
+            "message": format!("Capacity of bin {} in {} cleared", bin_no.trim(), location.trim())
+        }))),
+        Err(e) => handle_putaway_error(e)
+    }
+}
+
+/// Block a bin for putaway, with a reason and an optional expiry
+/// POST /api/putaway/admin/bins/block
+async fn block_bin<R: PutawayRepository>(
+    State(repository): State<R>,
+    auth: AuthUser,
+    Json(mut request): Json<BinBlockRequest>,
+) -> Result<Json<BinBlockRecord>, (StatusCode, Json<serde_json::Value>)> {
+    if let Err(e) = bind_acting_user(&mut request.user_id, &auth) {
+        return handle_putaway_error(e);
+    }
+
+    let service = PutawayService::with_repository(repository);
+    match service.block_bin(request).await {
+        Ok(block) => Ok(Json(block)),
+        Err(e) => handle_putaway_error(e)
+    }
+}
+
+/// Lift a bin's putaway block
+/// POST /api/putaway/admin/bins/unblock
+async fn unblock_bin<R: PutawayRepository>(
+    State(repository): State<R>,
+    auth: AuthUser,
+    Json(mut request): Json<BinUnblockRequest>,
+) -> Result<Json<BinBlockEvent>, (StatusCode, Json<serde_json::Value>)> {
+    if let Err(e) = bind_acting_user(&mut request.user_id, &auth) {
+        return handle_putaway_error(e);
+    }
+
+    let service = PutawayService::with_repository(repository);
+    match service.unblock_bin(request).await {
+        Ok(event) => Ok(Json(event)),
+        Err(e) => handle_putaway_error(e)
+    }
+}
+
+/// Bins currently blocked for putaway, optionally for one location
+/// GET /api/putaway/reports/blocked-bins?location=
+async fn get_blocked_bins<R: PutawayRepository>(
+    State(repository): State<R>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    let service = PutawayService::with_repository(repository);
+    let location = params.get("location").map(String::as_str);
+
+    match service.get_blocked_bins(location).await {
+        Ok(blocks) => Ok(Json(json!({
+            "success": true,
+            "message": format!("{} blocked bin(s)", blocks.len()),
+            "data": blocks
+        }))),
+        Err(e) => handle_putaway_error(e)
+    }
+}
+
+/// Who blocked and unblocked a bin, newest first
+/// GET /api/putaway/reports/bin-blocks/{location}/{bin_no}/history
+async fn get_bin_block_history<R: PutawayRepository>(
+    State(repository): State<R>,
+    Path((location, bin_no)): Path<(String, String)>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    let service = PutawayService::with_repository(repository);
+    match service.get_bin_block_history(&location, &bin_no).await {
+        Ok(events) => Ok(Json(json!({
+            "success": true,
+            "message": format!("{} block event(s) for bin {} in {}", events.len(), bin_no.trim(), location.trim()),
+            "data": events
+        }))),
+        Err(e) => handle_putaway_error(e)
+    }
+}
+
 /// Execute bin transfer
 /// POST /api/putaway/transfer
-async fn execute_transfer(
-    State(database): State<Database>,
-    Json(request): Json<BinTransferRequest>,
+async fn execute_transfer<R: PutawayRepository>(
+    State(repository): State<R>,
+    auth: AuthUser,
+    granted: Option<Extension<GrantedPermissions>>,
+    headers: HeaderMap,
+    Json(mut request): Json<BinTransferRequest>,
 ) -> Result<Json<TransferResult>, (StatusCode, Json<serde_json::Value>)> {
-    let service = PutawayService::new(database);
+    let idempotency_key = match idempotency_key(&headers) {
+        Ok(key) => key,
+        Err(e) => return handle_putaway_error(e),
+    };
+    if let Err(e) = bind_acting_user(&mut request.user_id, &auth)
+        .and_then(|_| authorize_capacity_override(request.override_capacity, &auth, granted.as_deref()))
+    {
+        return handle_putaway_error(e);
+    }
 
-    match service.execute_transfer(request).await {
+    let service = PutawayService::with_repository(repository);
+
+    match service.execute_transfer_idempotent(request, idempotency_key.as_deref()).await {
         Ok(result) => {
             if result.success {
                 Ok(Json(result))
@@ -357,8 +877,8 @@ async fn execute_transfer(
                 Json(json!({
                     "error": "Insufficient quantity",
                     "message": format!("Requested {} but only {} available", requested, available),
-                    "requested": requested,
-                    "available": available
+                    "requested": ExactQty(&requested),
+                    "available": ExactQty(&available)
                 }))
             ))
         }
@@ -371,6 +891,15 @@ async fn execute_transfer(
                 }))
             ))
         }
+        Err(e @ (PutawayError::InventoryLocked { .. }
+            | PutawayError::DocumentNotFound { .. }
+            | PutawayError::RequestInProgress { .. }
+            | PutawayError::RequestAlreadyApplied { .. }
+            | PutawayError::IdempotencyKeyReused { .. }
+            | PutawayError::UserMismatch { .. }
+            | PutawayError::PermissionDenied { .. }
+            | PutawayError::BinCapacityExceeded(_)
+            | PutawayError::BinBlocked { .. })) => handle_putaway_error(e),
         Err(PutawayError::TransactionError(msg)) => {
             tracing::error!("Transaction error in execute_transfer: {msg}");
             Err((
@@ -396,19 +925,19 @@ async fn execute_transfer(
 
 /// Get service health status
 /// GET /api/putaway/health
-async fn get_health(
-    State(database): State<Database>,
+async fn get_health<R: PutawayRepository>(
+    State(repository): State<R>,
 ) -> Json<PutawayHealthResponse> {
-    let service = PutawayService::new(database);
+    let service = PutawayService::with_repository(repository);
     Json(service.get_health().await)
 }
 
 /// Get all active putaway remarks for dropdown
 /// GET /api/putaway/remarks
-async fn get_remarks(
-    State(database): State<Database>,
+async fn get_remarks<R: PutawayRepository>(
+    State(repository): State<R>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
-    let service = PutawayService::new(database);
+    let service = PutawayService::with_repository(repository);
 
     match service.get_active_remarks().await {
         Ok(remarks) => Ok(Json(json!({
@@ -439,3 +968,150 @@ async fn get_remarks(
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::memory_db::{qty, transfer, warehouse, MemoryPutawayDatabase, LOT};
+    use crate::models::putaway_models::{BinCapacityRecord, CapacityUnit};
+    use crate::utils::auth::Claims;
+    use crate::utils::authorization::AuthorizationConfig;
+    use axum::body::{to_bytes, Body};
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    fn app(db: MemoryPutawayDatabase) -> Router {
+        let claims = Claims {
+            sub: "deachawat".to_string(),
+            username: "deachawat".to_string(),
+            email: String::new(),
+            display_name: "Deachawat".to_string(),
+            exp: i64::MAX,
+            iat: 0,
+            iss: "NWFTH-Putaway".to_string(),
+            roles: Vec::new(),
+            sid: "test-session".to_string(),
+        };
+
+        create_putaway_routes()
+            .layer(axum::Extension(claims))
+            .layer(axum::Extension(ReceivingBins::default()))
+            .layer(axum::Extension(SuggestionRules::default()))
+            .with_state(db)
+    }
+
+    async fn send(app: Router, request: Request<Body>) -> (StatusCode, serde_json::Value) {
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_lot_lookup() {
+        let request = Request::get("/lot/2600107-1").body(Body::empty()).unwrap();
+        let (status, body) = send(app(MemoryPutawayDatabase::new(warehouse("K0802-4B"))), request).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["item_key"], "INBC1403");
+        assert_eq!(body["current_bin"], "K0802-4B");
+        // Quantities go out as JSON numbers, not decimal strings
+        assert_eq!(body["qty_available"], 1000.0);
+
+        let request = Request::get("/lot/9999999-9").body(Body::empty()).unwrap();
+        let (status, _) = send(app(MemoryPutawayDatabase::new(warehouse("K0802-4B"))), request).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_records_jwt_user() {
+        let db = MemoryPutawayDatabase::new(warehouse("K0802-4B"));
+        let request = Request::post("/transfer")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({
+                    "lot_no": "2600107-1",
+                    "item_key": "INBC1403",
+                    "location": "TFC1",
+                    "bin_from": "K0802-4B",
+                    "bin_to": "WHKON1",
+                    "transfer_qty": 250.0
+                })
+                .to_string(),
+            ))
+            .unwrap();
+
+        let (status, body) = send(app(db.clone()), request).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["document_no"], "BT-26112174");
+
+        let tables = db.tables().await;
+        assert_eq!(tables.bin_transfers.len(), 1);
+        assert_eq!(tables.bin_transfers[0].rec_user_id, "deachawa");
+        assert_eq!(tables.bt_sequence, 26112174);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_under_another_user_is_forbidden() {
+        let db = MemoryPutawayDatabase::new(warehouse("K0802-4B"));
+        let body = BinTransferRequest { user_id: "somchai".to_string(), ..transfer(LOT, "K0802-4B", "WHKON1", "250") };
+        let request = Request::post("/transfer")
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::to_string(&body).unwrap()))
+            .unwrap();
+
+        let (status, body) = send(app(db.clone()), request).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{body}");
+        assert_eq!(body["error"], "User mismatch");
+
+        let tables = db.tables().await;
+        assert!(tables.bin_transfers.is_empty());
+        assert_eq!(tables.bt_sequence, 26112173);
+    }
+
+    #[tokio::test]
+    async fn test_capacity_override_needs_permission() {
+        let mut tables = warehouse("K0802-4B");
+        tables.bin_capacities.push(BinCapacityRecord {
+            location: "TFC1".to_string(),
+            bin_no: "WHKON1".to_string(),
+            unit: CapacityUnit::Quantity,
+            capacity: qty("100"),
+            rec_user_id: "supervisor".to_string(),
+            rec_date: chrono::NaiveDateTime::default(),
+        });
+        let db = MemoryPutawayDatabase::new(tables);
+        let transfer = |override_capacity: bool| {
+            Request::post("/transfer")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    json!({
+                        "lot_no": "2600107-1",
+                        "item_key": "INBC1403",
+                        "location": "TFC1",
+                        "bin_from": "K0802-4B",
+                        "bin_to": "WHKON1",
+                        "transfer_qty": 250.0,
+                        "override_capacity": override_capacity
+                    })
+                    .to_string(),
+                ))
+                .unwrap()
+        };
+
+        let (status, body) = send(app(db.clone()), transfer(false)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], "BIN_CAPACITY_EXCEEDED");
+        assert_eq!(body["occupied"], 0.0);
+
+        let (status, body) = send(app(db.clone()), transfer(true)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["code"], "PERMISSION_DENIED");
+
+        let config = AuthorizationConfig::parse("", "", "capacity_override=supervisor");
+        let supervisor = app(db.clone()).layer(Extension(config.granted(&["supervisor".to_string()])));
+        let (status, body) = send(supervisor, transfer(true)).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(db.tables().await.bin_transfers.len(), 1);
+    }
+}
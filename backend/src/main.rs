@@ -33,13 +33,23 @@ mod utils;
 use handlers::putaway;
 use middleware::auth::jwt_auth_middleware;
 use types::{ApiResponse, LoginResponse, User};
+use database::auth_session_db::AuthSessionDatabase;
+use database::putaway_db::PutawayDatabase;
+use services::session_service::RoleDirectory;
+use services::putaway_service::{ReceivingBins, SuggestionRules};
+use services::SessionService;
 use utils::AuthService;
+use utils::auth::AuthSource;
+use utils::authorization::{AuthorizationConfig, Permission};
+use middleware::authorization::putaway_authorization_middleware;
 
 #[derive(Clone)]
 pub struct AppState {
     pub database: database::Database,
     pub ldap_config: LdapConfig,
     pub auth_service: AuthService,
+    pub sessions: SessionService<AuthSessionDatabase>,
+    pub authorization: Arc<AuthorizationConfig>,
     pub static_assets_path: String,
 }
 
@@ -53,13 +63,15 @@ impl std::fmt::Debug for AppState {
 }
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct LdapConfig {
     pub url: String,
     pub base_dn: String,
     pub enabled: bool,
     pub use_ssl: bool,
     pub skip_verify: bool,
+    /// (LDAP_BIND_DN, LDAP_BIND_PASSWORD): account that looks users up again on token refresh
+    pub service_account: Option<(String, String)>,
 }
 
 #[derive(Deserialize)]
@@ -68,6 +80,22 @@ pub struct LoginRequest {
     pub password: String,
 }
 
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct LogoutRequest {
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RevokeSessionsRequest {
+    pub user_id: String,
+}
+
 #[derive(Serialize)]
 pub struct LoginData {
     pub access_token: String,
@@ -221,14 +249,13 @@ async fn login(
     for user_format in user_formats {
         info!("🔍 Attempting LDAP authentication for: {}", user_format);
 
-        match authenticate_ldap(&state.ldap_config, &user_format, &request.password).await {
+        match authenticate_ldap(&state.ldap_config, &state.authorization, &user_format, &request.password).await {
             Ok(user) => {
                 info!("✅ LDAP authentication successful for: {}", user_format);
 
-                // Generate proper JWT token
-                match state.auth_service.generate_token(&user) {
-                    Ok(token) => {
-                        let login_response = LoginResponse { token, user };
+                // Generate proper JWT token and refresh token
+                match state.sessions.start_session(user, AuthSource::Ldap).await {
+                    Ok(login_response) => {
                         return Ok(Json(ApiResponse::success(login_response, "Authentication successful")));
                     }
                     Err(e) => {
@@ -250,10 +277,9 @@ async fn login(
         Ok(user) => {
             info!("✅ SQL authentication successful for: {}", request.username);
 
-            // Generate proper JWT token
-            match state.auth_service.generate_token(&user) {
-                Ok(token) => {
-                    let login_response = LoginResponse { token, user };
+            // Generate proper JWT token and refresh token
+            match state.sessions.start_session(user, AuthSource::Sql).await {
+                Ok(login_response) => {
                     Ok(Json(ApiResponse::success(login_response, "Authentication successful")))
                 }
                 Err(e) => {
@@ -278,8 +304,131 @@ async fn login(
     }
 }
 
+/// Exchange a refresh token for a new access token and a new refresh token (rotation).
+/// Presenting an already exchanged token revokes the whole session - it was copied.
+#[instrument(skip(state, request))]
+async fn refresh(
+    State(state): State<AppState>,
+    Json(request): Json<RefreshRequest>,
+) -> Result<Json<ApiResponse<LoginResponse>>, StatusCode> {
+    let directory = UserDirectory { state: &state };
+    match state.sessions.refresh(&request.refresh_token, &directory).await {
+        Ok(Some(login_response)) => Ok(Json(ApiResponse::success(login_response, "Token refreshed"))),
+        Ok(None) => Err(StatusCode::UNAUTHORIZED),
+        Err(e) => {
+            error!("❌ Refresh failed: {:#}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Revoke the caller's session (from the access token and/or the refresh token)
+async fn logout(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    request: Option<Json<LogoutRequest>>,
+) -> Json<ApiResponse<bool>> {
+    let access_token = headers
+        .get("authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| AuthService::extract_token_from_header(Some(h)));
+    let refresh_token = request.and_then(|Json(body)| body.refresh_token);
+
+    if state.sessions.logout(access_token, refresh_token.as_deref()).await {
+        Json(ApiResponse::success(true, "Logged out"))
+    } else {
+        Json(ApiResponse::success(false, "No active session found"))
+    }
+}
+
+/// Cut off every session of a user (lost or stolen handheld) - admin permission required
+async fn revoke_user_sessions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<RevokeSessionsRequest>,
+) -> Result<Json<ApiResponse<usize>>, StatusCode> {
+    let claims = headers
+        .get("authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| AuthService::extract_token_from_header(Some(h)))
+        .and_then(|token| state.auth_service.verify_token(token).ok())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if !state.authorization.allows(&claims.roles, Permission::Admin) {
+        warn!("🚫 {} tried to revoke sessions of {} without admin permission", claims.sub, request.user_id);
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let session_ids = state.sessions.revoke_user_sessions(&request.user_id).await.map_err(|e| {
+        error!("❌ Failed to revoke sessions of {}: {:#}", request.user_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    info!("🔒 {} revoked {} session(s) of {}", claims.sub, session_ids.len(), request.user_id);
+    Ok(Json(ApiResponse::success(session_ids.len(), "Sessions revoked")))
+}
+
+/// Roles of a signed-in user as the directory has them now, for token refreshes
+struct UserDirectory<'a> {
+    state: &'a AppState,
+}
+
+impl RoleDirectory for UserDirectory<'_> {
+    async fn current_roles(&self, user: &User, source: AuthSource) -> anyhow::Result<Option<Vec<String>>> {
+        match source {
+            AuthSource::Ldap => {
+                let groups = ldap_member_of(&self.state.ldap_config, &user.user_id).await?;
+                Ok(groups.map(|groups| self.state.authorization.roles_for_groups(&groups)))
+            }
+            AuthSource::Sql => {
+                let mut client = self.state.database.get_client().await?;
+                let row = client
+                    .query("SELECT department FROM tbl_user WHERE uname = @P1 AND ad_enabled = 1", &[&user.user_id.as_str()])
+                    .await?
+                    .into_row()
+                    .await?;
+                Ok(row.map(|row| self.state.authorization.roles_for_department(row.get::<&str, _>("department"))))
+            }
+        }
+    }
+}
+
+/// memberOf of an enabled AD account, read with the LDAP service account (LDAP_BIND_DN).
+/// None if the account is gone or disabled, or no service account is configured.
+async fn ldap_member_of(config: &LdapConfig, user_id: &str) -> anyhow::Result<Option<Vec<String>>> {
+    let Some((bind_dn, bind_password)) = &config.service_account else {
+        warn!("⚠️ LDAP_BIND_DN is not set - {} has to sign in again instead of refreshing", user_id);
+        return Ok(None);
+    };
+
+    let settings = LdapConnSettings::new()
+        .set_conn_timeout(Duration::from_secs(5))
+        .set_no_tls_verify(config.skip_verify);
+    let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &config.url).await?;
+    ldap3::drive!(conn);
+
+    ldap.simple_bind(bind_dn, bind_password).await?.success()?;
+
+    // Disabled accounts (userAccountControl ACCOUNTDISABLE) are left out
+    let filter = format!(
+        "(&(sAMAccountName={})(!(userAccountControl:1.2.840.113556.1.4.803:=2)))",
+        ldap3::ldap_escape(user_id)
+    );
+    let (results, _res) = ldap
+        .search(&config.base_dn, Scope::Subtree, &filter, vec!["memberOf"])
+        .await?
+        .success()?;
+    ldap.unbind().await?;
+
+    Ok(results
+        .into_iter()
+        .next()
+        .map(|entry| SearchEntry::construct(entry).attrs.remove("memberOf").unwrap_or_default()))
+}
+
 async fn authenticate_ldap(
     config: &LdapConfig,
+    authorization: &AuthorizationConfig,
     username: &str,
     password: &str,
 ) -> Result<User, Box<dyn std::error::Error + Send + Sync>> {
@@ -318,7 +467,7 @@ async fn authenticate_ldap(
         .search(&config.base_dn, Scope::Subtree, &search_filter, vec![
             "cn", "department", "displayName", "givenName", "sAMAccountName",
             "company", "title", "organizationalUnit", "ou", "description",
-            "physicalDeliveryOfficeName", "division", "departmentNumber"
+            "physicalDeliveryOfficeName", "division", "departmentNumber", "memberOf"
         ])
         .await?
         .success()?;
@@ -343,7 +492,7 @@ async fn authenticate_ldap(
             .search(&config.base_dn, Scope::Subtree, &alt_filter, vec![
                 "cn", "department", "displayName", "givenName", "sAMAccountName",
                 "company", "title", "organizationalUnit", "ou", "description",
-                "physicalDeliveryOfficeName", "division", "departmentNumber"
+                "physicalDeliveryOfficeName", "division", "departmentNumber", "memberOf"
             ])
             .await?
             .success()?;
@@ -432,12 +581,17 @@ async fn authenticate_ldap(
             info!("🎯 Department successfully extracted: '{:?}'", department);
         }
 
+        let member_of = search_entry.attrs.get("memberOf").cloned().unwrap_or_default();
+        let roles = authorization.roles_for_groups(&member_of);
+        info!("🛡️ Roles for {}: {:?} (from {} group(s))", clean_username, roles, member_of.len());
+
         User {
             user_id: clean_username.clone(),
             username: clean_username.clone(),
             email: format!("{clean_username}@nwfth.com"),
             display_name,
             is_active: true,
+            roles,
         }
     } else {
         // Extract clean username for fallback case too
@@ -462,6 +616,7 @@ async fn authenticate_ldap(
             email: format!("{clean_username}@nwfth.com"),
             display_name,
             is_active: true,
+            roles: Vec::new(),
         }
     };
 
@@ -519,7 +674,8 @@ async fn authenticate_sql(
                 (None, Some(l)) => l.to_string(),
                 (None, None) => username.to_string(), // Fallback to username
             };
-            let _department: Option<&str> = row.get("department");
+            let department: Option<&str> = row.get("department");
+            let roles = state.authorization.roles_for_department(department);
 
             Ok(User {
                 user_id: username.to_string(),
@@ -527,6 +683,7 @@ async fn authenticate_sql(
                 email: format!("{username}@nwfth.com"),
                 display_name,
                 is_active: true,
+                roles,
             })
         } else {
             // SEC-008 FIX: Generic error message to prevent user enumeration
@@ -696,12 +853,19 @@ async fn main() {
             .unwrap_or(true),
         use_ssl,
         skip_verify,
+        service_account: std::env::var("LDAP_BIND_DN")
+            .ok()
+            .filter(|dn| !dn.trim().is_empty())
+            .map(|dn| (dn, std::env::var("LDAP_BIND_PASSWORD").unwrap_or_default())),
     };
 
     info!(
         "LDAP configured: {} with base DN: {}",
         ldap_config.url, ldap_config.base_dn
     );
+    if ldap_config.enabled && ldap_config.service_account.is_none() {
+        warn!("⚠️  LDAP_BIND_DN not set - LDAP users cannot refresh their tokens and sign in again when they expire");
+    }
 
     // Initialize database connection with pooling
     let database = database::Database::new().await.expect("Failed to initialize database with connection pool");
@@ -725,9 +889,45 @@ async fn main() {
         }
     }
 
+    // Create putaway extension tables (audit/extension data not in the BME schema)
+    if let Err(e) = database.ensure_putaway_tables().await {
+        warn!("⚠️  Failed to prepare putaway extension tables: {:#}", e);
+        warn!("    Features that record putaway audit data may fail until the tables exist");
+    }
+
+    // One-off maintenance: `putaway-backend backfill-bin-transfer-links [--apply]`
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("backfill-bin-transfer-links") {
+        let apply = args.iter().any(|arg| arg == "--apply");
+        let service = services::PutawayService::with_repository(PutawayDatabase::new(database.clone()));
+        match service.backfill_bin_transfer_links(apply).await {
+            Ok(backfill) => {
+                info!(
+                    "BinTransfer backfill ({}): {} LotTranNo, {} InTransID",
+                    if apply { "applied" } else { "dry run" },
+                    backfill.lot_tran_nos,
+                    backfill.in_trans_ids
+                );
+                std::process::exit(0);
+            }
+            Err(e) => {
+                error!("❌ BinTransfer backfill failed: {:#}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
     // Initialize authentication service
     let auth_service = AuthService::new().expect("Failed to initialize JWT authentication service");
 
+    let sessions = SessionService::new(auth_service.clone(), AuthSessionDatabase::new(database.clone()));
+
+    // Reload sessions revoked while their access tokens may still be valid
+    match sessions.reload_revocations().await {
+        Ok(count) => info!("🔒 Loaded {} revoked session(s)", count),
+        Err(e) => warn!("⚠️  Failed to load revoked sessions: {:#}", e),
+    }
+
     // Determine static assets path at startup for better performance
     let static_assets_path = {
         let possible_paths = vec![
@@ -749,10 +949,25 @@ async fn main() {
         selected_path
     };
 
+    // Receiving/dock bins of the pending putaway queue, fixed for the life of the process
+    let receiving_bins = ReceivingBins::from_env();
+    info!("📥 Receiving bins: {:?}", receiving_bins.0);
+    // Bins and aisles destination suggestions leave out, likewise read once
+    let suggestion_rules = SuggestionRules::from_env();
+    info!(
+        "🧭 Suggestions leave out bins {:?} and aisles {:?}",
+        suggestion_rules.excluded_bins, suggestion_rules.excluded_aisles
+    );
+
+    // Role-to-permission mapping for putaway operations
+    let authorization = Arc::new(AuthorizationConfig::from_env());
+
     let state = AppState {
         database,
         ldap_config,
         auth_service,
+        sessions,
+        authorization,
         static_assets_path,
     };
 
@@ -766,7 +981,7 @@ async fn main() {
         CorsLayer::new()
             .allow_origin(Any)
             .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-            .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION, header::HeaderName::from_static("x-user-id")])
+            .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION, header::HeaderName::from_static("x-user-id"), header::HeaderName::from_static("idempotency-key")])
     } else {
         // SEC-004 FIX: Parse and validate specific origins
         info!("🔒 SEC-004: CORS configured for specific origins: {}", cors_origins);
@@ -782,13 +997,13 @@ async fn main() {
             CorsLayer::new()
                 .allow_origin("http://localhost:4400".parse::<axum::http::HeaderValue>().unwrap())
                 .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-                .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION, header::HeaderName::from_static("x-user-id")])
+                .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION, header::HeaderName::from_static("x-user-id"), header::HeaderName::from_static("idempotency-key")])
                 .allow_credentials(true)
         } else {
             CorsLayer::new()
                 .allow_origin(origins)
                 .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
-                .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION, header::HeaderName::from_static("x-user-id")])
+                .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION, header::HeaderName::from_static("x-user-id"), header::HeaderName::from_static("idempotency-key")])
                 .allow_credentials(true)
         }
     };
@@ -807,7 +1022,7 @@ async fn main() {
         header::HeaderValue::from_static("1; mode=block"),
     );
 
-    // SEC-005: Rate limiting for authentication endpoint (5 requests per minute per IP)
+    // SEC-005: Rate limiting for authentication endpoints - login and refresh share one limiter (5 requests per minute per IP)
     let auth_rate_limiter = Arc::new(
         RateLimiter::keyed(Quota::per_minute(NonZeroU32::new(5).unwrap()))
     );
@@ -819,17 +1034,23 @@ async fn main() {
         .route("/api/database/status", get(database_status))
         .route("/api/auth/health", get(auth_health))
         .route("/api/auth/login", post(login))
+        .route("/api/auth/refresh", post(refresh))
         .route_layer(axum::middleware::from_fn_with_state(
             auth_rate_limiter.clone(),
             rate_limit_middleware,
         ))
         .route("/api/auth/status", get(auth_status))
-        // Add putaway routes with Database state and JWT protection
+        .route("/api/auth/logout", post(logout))
+        .route("/api/auth/sessions/revoke", post(revoke_user_sessions))
+        // Add putaway routes with the BME repository as state and JWT protection
         .nest(
             "/api/putaway",
             putaway::create_putaway_routes()
+                .layer(from_fn_with_state(state.clone(), putaway_authorization_middleware))
                 .layer(from_fn_with_state(state.clone(), jwt_auth_middleware))
-                .with_state(state.database.clone()),
+                .layer(axum::Extension(receiving_bins))
+                .layer(axum::Extension(suggestion_rules))
+                .with_state(PutawayDatabase::new(state.database.clone())),
         )
         // Serve static files from Angular dist (using detected path)
         .nest_service("/assets", ServeDir::new(format!("{}/assets", state.static_assets_path)))
@@ -1,13 +1,14 @@
 use axum::{
-    extract::{Request, State},
-    http::StatusCode,
+    extract::{FromRequestParts, Request, State},
+    http::{request::Parts, StatusCode},
     middleware::Next,
-    response::Response,
+    response::{Json, Response},
 };
 use axum::http::HeaderMap;
+use serde_json::json;
 use tracing::{debug, warn};
 
-use crate::{AppState, utils::AuthService};
+use crate::{AppState, utils::{AuthService, auth::Claims}};
 
 /// JWT authentication middleware
 /// Validates JWT tokens and extracts user information for protected routes
@@ -45,6 +46,9 @@ pub async fn jwt_auth_middleware(
                 claims.username.parse().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
             );
             
+            // Verified claims for handlers that record the acting user (see AuthUser)
+            request.extensions_mut().insert(claims);
+
             Ok(next.run(request).await)
         }
         Err(e) => {
@@ -54,3 +58,35 @@ pub async fn jwt_auth_middleware(
     }
 }
 
+/// Authenticated user of a request behind `jwt_auth_middleware`
+#[derive(Clone, Debug)]
+pub struct AuthUser(pub Claims);
+
+impl AuthUser {
+    /// User ID recorded in RecUserid/RecUserID audit columns
+    pub fn user_id(&self) -> &str {
+        &self.0.sub
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for AuthUser {
+    type Rejection = (StatusCode, Json<serde_json::Value>);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Claims>()
+            .cloned()
+            .map(AuthUser)
+            .ok_or_else(|| {
+                warn!("🚫 JWT Auth: route reached without verified claims");
+                (
+                    StatusCode::UNAUTHORIZED,
+                    Json(json!({
+                        "error": "Unauthorized",
+                        "message": "Authentication required"
+                    })),
+                )
+            })
+    }
+}
@@ -0,0 +1,55 @@
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::json;
+use tracing::warn;
+
+use crate::{AppState, utils::{auth::Claims, authorization::required_permission}};
+
+/// Role check for the putaway router - runs after `jwt_auth_middleware` has verified the token.
+/// Routes without a required permission (lookups, standard transfers) pass straight through.
+/// Every request carries the user's `GrantedPermissions` for checks that depend on the body.
+pub async fn putaway_authorization_middleware(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if let Some(claims) = request.extensions().get::<Claims>() {
+        let granted = state.authorization.granted(&claims.roles);
+        request.extensions_mut().insert(granted);
+    }
+
+    let Some(permission) = required_permission(request.method().as_str(), request.uri().path()) else {
+        return next.run(request).await;
+    };
+
+    let Some(claims) = request.extensions().get::<Claims>() else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
+    if state.authorization.allows(&claims.roles, permission) {
+        return next.run(request).await;
+    }
+
+    warn!(
+        "🚫 Authorization: {} (roles {:?}) denied {} {} - requires '{}'",
+        claims.sub,
+        claims.roles,
+        request.method(),
+        request.uri().path(),
+        permission.name()
+    );
+
+    (
+        StatusCode::FORBIDDEN,
+        Json(json!({
+            "error": "Forbidden",
+            "code": "PERMISSION_DENIED",
+            "message": format!("You do not have permission to perform this action ({})", permission.name())
+        })),
+    )
+        .into_response()
+}
@@ -1,67 +1,48 @@
-use chrono::{DateTime, Utc};
+use crate::models::putaway_models::LotSearchResult;
+use crate::utils::gs1::Gs1Data;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct PutawayItem {
-    pub lot_no: String,
-    pub item_key: String,
-    pub item_description: Option<String>,
-    pub location_key: String,
-    pub bin_no: Option<String>,
-    pub qty_received: f64,
-    pub qty_on_hand: f64,
-    pub date_received: DateTime<Utc>,
-    pub date_expiry: DateTime<Utc>,
-    pub vendor_key: String,
-    pub vendor_lot_no: String,
-    pub document_no: String,
-    pub lot_status: String,
-    pub rec_user_id: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
+/// What a scanned barcode is (or is expected to be)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum ScanType {
     Item,
-    Location,
+    Bin,
     Lot,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Raw scanner input. Without `scan_type` the barcode is tried as a GS1-128 label,
+/// then as a lot, a bin and an item number.
+#[derive(Debug, Deserialize)]
+pub struct ScanRequest {
+    pub barcode: String,
+    #[serde(default)]
+    pub scan_type: Option<ScanType>,
+}
+
+#[derive(Debug, Serialize)]
 pub struct ScanResponse {
     pub valid: bool,
-    pub scan_type: ScanType,
+    /// What the barcode resolved to, None when nothing matched
+    pub scan_type: Option<ScanType>,
     pub data: Option<ScanData>,
+    /// Decoded application identifiers when the barcode is a GS1-128 label
+    pub gs1: Option<Gs1Data>,
     pub message: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize)]
 pub enum ScanData {
     Item {
         item_key: String,
         description: String,
-        unit: String,
+        uom: String,
     },
-    Location {
-        location_key: String,
+    Bin {
+        bin_no: String,
         description: String,
-        location_type: String,
+        /// Locations holding a bin with this number
+        locations: Vec<String>,
     },
-    Lot {
-        lot_no: String,
-        item_key: String,
-        qty_on_hand: f64,
-    },
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct PutawayHistory {
-    pub transaction_id: i32,
-    pub lot_no: String,
-    pub item_key: String,
-    pub from_location: String,
-    pub to_location: String,
-    pub bin_no: String,
-    pub qty_moved: f64,
-    pub transaction_date: DateTime<Utc>,
-    pub user_id: String,
+    /// First bin of the lot with stock, as returned by the lot lookup
+    Lot(Box<LotSearchResult>),
 }
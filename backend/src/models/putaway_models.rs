@@ -1,5 +1,7 @@
+use crate::utils::quantity::{deserialize_qty, round_qty, serialize_opt_qty, serialize_qty};
+use bigdecimal::BigDecimal;
 use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Utc};
+use chrono::NaiveDateTime;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LotSearchResult {
@@ -7,13 +9,79 @@ pub struct LotSearchResult {
     pub item_key: String,
     pub location: String,
     pub current_bin: String,
-    pub qty_on_hand: f64,
-    pub qty_commit_sales: f64,
-    pub qty_available: f64,
+    #[serde(serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub qty_on_hand: BigDecimal,
+    #[serde(serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub qty_commit_sales: BigDecimal,
+    #[serde(serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub qty_available: BigDecimal,
     pub expiry_date: Option<String>,
     pub item_description: String,
     pub uom: String,
     pub lot_status: String,
+    /// Every bin holding the lot, by location and bin (the fields above describe the first one)
+    pub bins: Vec<LotBinStock>,
+    /// Per-location totals over `bins`
+    pub locations: Vec<LotLocationTotal>,
+}
+
+/// Stock of a lot in one bin. Committed follows BME: pending LotTransaction and QCLotTransaction issues,
+/// less the issue rows of BT documents (see `PutawayRepository::bin_commitment`).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LotBinStock {
+    pub location: String,
+    pub bin_no: String,
+    #[serde(serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub qty_on_hand: BigDecimal,
+    #[serde(serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub qty_committed: BigDecimal,
+    #[serde(serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub qty_available: BigDecimal,
+    pub lot_status: String,
+    pub expiry_date: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LotLocationTotal {
+    pub location: String,
+    pub bin_count: usize,
+    #[serde(serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub qty_on_hand: BigDecimal,
+    #[serde(serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub qty_committed: BigDecimal,
+    #[serde(serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub qty_available: BigDecimal,
+}
+
+/// What sits in one bin: its BINMaster attributes and every lot with stock
+#[derive(Debug, Serialize)]
+pub struct BinContents {
+    pub location: String,
+    pub bin_no: String,
+    pub description: String,
+    pub aisle: String,
+    pub row: String,
+    pub rack: String,
+    /// Oldest expiry first
+    pub lots: Vec<BinContentLot>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BinContentLot {
+    pub lot_no: String,
+    pub item_key: String,
+    pub item_description: String,
+    pub uom: String,
+    #[serde(serialize_with = "serialize_qty")]
+    pub qty_on_hand: BigDecimal,
+    /// Pending issues (LotTransaction + QCLotTransaction) against the lot in this bin, except BT document issues
+    #[serde(serialize_with = "serialize_qty")]
+    pub qty_committed: BigDecimal,
+    #[serde(serialize_with = "serialize_qty")]
+    pub qty_available: BigDecimal,
+    pub date_received: Option<String>,
+    pub expiry_date: Option<String>,
+    pub lot_status: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,10 +99,19 @@ pub struct BinTransferRequest {
     pub location: String,
     pub bin_from: String,
     pub bin_to: String,
-    pub transfer_qty: f64,
+    #[serde(serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub transfer_qty: BigDecimal,
+    /// UOM `transfer_qty` is counted in (bags, cartons); the item's stock UOM when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uom: Option<String>,
+    /// Acting user - filled from the JWT when omitted, rejected when it differs
+    #[serde(default)]
     pub user_id: String,
     pub remarks: Option<String>,
     pub referenced: Option<String>,
+    /// Move even if the destination bin ends up over its capacity (capacity_override permission)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub override_capacity: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,6 +122,122 @@ pub struct TransferResult {
     pub timestamp: String,
     pub source_lot_status: Option<String>,
     pub destination_lot_status: Option<String>,
+    /// Quantity and UOM as entered
+    #[serde(default, serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub entered_qty: BigDecimal,
+    #[serde(default)]
+    pub entered_uom: String,
+    /// Quantity moved, in the item's stock UOM
+    #[serde(default, serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub transfer_qty: BigDecimal,
+    #[serde(default)]
+    pub stock_uom: String,
+}
+
+/// Several lot movements written under one BT document number
+#[derive(Debug, Deserialize)]
+pub struct BatchTransferRequest {
+    pub lines: Vec<BinTransferRequest>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchTransferResult {
+    pub success: bool,
+    pub document_no: String,
+    pub message: String,
+    pub timestamp: String,
+    pub lines: Vec<BatchTransferLineResult>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchTransferLineResult {
+    /// IssueDocLineNo/ReceiptDocLineNo of the line's LotTransaction pair
+    pub line_no: i16,
+    pub lot_no: String,
+    pub item_key: String,
+    pub bin_from: String,
+    pub bin_to: String,
+    #[serde(serialize_with = "serialize_qty")]
+    pub transfer_qty: BigDecimal,
+    pub full_transfer: bool,
+    pub source_lot_status: Option<String>,
+    pub destination_lot_status: Option<String>,
+}
+
+/// Move every lot in a bin to another bin under one BT document
+#[derive(Debug, Deserialize)]
+pub struct BinEvacuationRequest {
+    pub location: String,
+    pub bin_from: String,
+    pub bin_to: String,
+    /// Acting user - filled from the JWT when omitted, rejected when it differs
+    #[serde(default)]
+    pub user_id: String,
+    pub remarks: Option<String>,
+    pub referenced: Option<String>,
+    /// Move even if the destination bin ends up over its capacity (capacity_override permission)
+    #[serde(default)]
+    pub override_capacity: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BinEvacuationResult {
+    pub success: bool,
+    pub document_no: String,
+    pub message: String,
+    pub timestamp: String,
+    pub moved: Vec<BatchTransferLineResult>,
+    /// Lots (or the committed part of them) left in the source bin
+    pub skipped: Vec<EvacuationSkippedLot>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EvacuationSkippedLot {
+    pub lot_no: String,
+    pub item_key: String,
+    #[serde(serialize_with = "serialize_qty")]
+    pub qty_on_hand: BigDecimal,
+    #[serde(serialize_with = "serialize_qty")]
+    pub qty_commit_sales: BigDecimal,
+    /// Quantity that stays in the source bin
+    #[serde(serialize_with = "serialize_qty")]
+    pub qty_left: BigDecimal,
+    pub reason: String,
+}
+
+/// Undo a completed BT document with an inverse document
+#[derive(Debug, Deserialize)]
+pub struct TransferReversalRequest {
+    /// Acting user - filled from the JWT when omitted, rejected when it differs
+    #[serde(default)]
+    pub user_id: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TransferReversalResult {
+    pub success: bool,
+    pub original_document_no: String,
+    /// New BT document holding the inverse movements (BinTransfer.User5 points back to the original)
+    pub document_no: String,
+    pub message: String,
+    pub timestamp: String,
+    pub lines: Vec<ReversedTransferLine>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReversedTransferLine {
+    pub line_no: i16,
+    pub lot_no: String,
+    pub item_key: String,
+    /// Destination bin of the original line
+    pub bin_from: String,
+    /// Source bin of the original line
+    pub bin_to: String,
+    #[serde(serialize_with = "serialize_qty")]
+    pub qty: BigDecimal,
+    /// The source LotMaster row had been deleted by a full transfer and was recreated
+    pub source_row_recreated: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -62,15 +255,83 @@ pub struct LotSearchItem {
     pub item_description: String,
     pub location: String,
     pub current_bin: String,
-    pub qty_on_hand: f64,
-    pub qty_commit_sales: f64,
-    pub qty_available: f64,
+    #[serde(serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub qty_on_hand: BigDecimal,
+    #[serde(serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub qty_commit_sales: BigDecimal,
+    #[serde(serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub qty_available: BigDecimal,
     pub date_received: Option<String>,
     pub expiry_date: Option<String>,
     pub uom: String,
     pub lot_status: String,
 }
 
+/// Lot waiting in a receiving/dock bin to be put away
+#[derive(Debug, Serialize)]
+pub struct PendingPutawayItem {
+    pub lot_no: String,
+    pub item_key: String,
+    pub item_description: String,
+    pub location_key: String,
+    pub bin_no: String,
+    #[serde(serialize_with = "serialize_qty")]
+    pub qty_received: BigDecimal,
+    #[serde(serialize_with = "serialize_qty")]
+    pub qty_on_hand: BigDecimal,
+    #[serde(serialize_with = "serialize_qty")]
+    pub qty_available: BigDecimal,
+    pub uom: String,
+    pub date_received: Option<String>,
+    pub date_expiry: Option<String>,
+    /// Whole days since DateReceived
+    pub age_days: Option<i64>,
+    pub vendor_key: String,
+    pub vendor_lot_no: String,
+    pub document_no: String,
+    pub lot_status: String,
+    pub rec_user_id: String,
+}
+
+/// Filters of the transfer history, as sent by the client. Blank values are ignored.
+#[derive(Debug, Default, Deserialize)]
+pub struct TransferHistoryQuery {
+    pub user_id: Option<String>,
+    pub lot_no: Option<String>,
+    pub item_key: Option<String>,
+    /// Source or destination bin
+    pub bin_no: Option<String>,
+    pub location: Option<String>,
+    /// First day included (YYYY-MM-DD)
+    pub date_from: Option<String>,
+    /// Last day included (YYYY-MM-DD)
+    pub date_to: Option<String>,
+    /// date (default), document_no, lot_no, item_key or user_id
+    pub sort: Option<String>,
+    /// asc or desc (default)
+    pub order: Option<String>,
+}
+
+/// One moved lot of a BT document
+#[derive(Debug, Serialize)]
+pub struct TransferHistoryItem {
+    pub document_no: String,
+    pub line_no: i16,
+    pub lot_no: String,
+    pub item_key: String,
+    pub item_description: String,
+    pub location: String,
+    pub bin_from: String,
+    pub bin_to: String,
+    #[serde(serialize_with = "serialize_qty")]
+    pub qty: BigDecimal,
+    pub uom: String,
+    pub user_id: String,
+    pub transaction_date: NaiveDateTime,
+    pub remarks: String,
+    pub referenced: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BinSearchItem {
     pub bin_no: String,
@@ -82,6 +343,208 @@ pub struct BinSearchItem {
     /// Lot status if this bin contains the specified lot (optional - only populated when lot context is provided)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub lot_status: Option<String>,
+    /// Blocked for putaway (putaway_bin_blocks, not expired)
+    #[serde(default)]
+    pub blocked: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_reason: Option<String>,
+}
+
+/// Ranked destination bins for a lot
+#[derive(Debug, Serialize)]
+pub struct BinSuggestionResponse {
+    pub lot_no: String,
+    pub item_key: String,
+    pub location: String,
+    /// Bin the lot is moving out of, whose zone empty bins are scored against
+    pub bin_from: Option<String>,
+    /// Highest score first
+    pub suggestions: Vec<BinSuggestion>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BinSuggestion {
+    pub bin_no: String,
+    pub location: String,
+    pub description: String,
+    pub aisle: String,
+    pub row: String,
+    pub rack: String,
+    /// LotStatus of the lot already in the bin
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lot_status: Option<String>,
+    pub score: i32,
+    /// What the score is made of
+    pub reasons: Vec<BinSuggestionReason>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BinSuggestionReason {
+    pub points: i32,
+    pub reason: String,
+}
+
+/// Unit a bin's capacity is kept in (putaway_bin_capacity.capacity_unit)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CapacityUnit {
+    /// Lot rows with stock - one pallet each
+    Pallets,
+    /// Stock converted to KG through INQTYCNV
+    Weight,
+    /// QtyOnHand in the items' stock UOMs
+    Quantity,
+}
+
+impl CapacityUnit {
+    /// Value stored in putaway_bin_capacity.capacity_unit
+    pub fn code(&self) -> &'static str {
+        match self {
+            CapacityUnit::Pallets => "PALLETS",
+            CapacityUnit::Weight => "WEIGHT",
+            CapacityUnit::Quantity => "QUANTITY",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        [CapacityUnit::Pallets, CapacityUnit::Weight, CapacityUnit::Quantity]
+            .into_iter()
+            .find(|unit| unit.code().eq_ignore_ascii_case(code.trim()))
+    }
+
+    /// Unit label in messages
+    pub fn label(&self) -> &'static str {
+        match self {
+            CapacityUnit::Pallets => "pallet(s)",
+            CapacityUnit::Weight => "KG",
+            CapacityUnit::Quantity => "unit(s)",
+        }
+    }
+}
+
+/// putaway_bin_capacity row: how much a BINMaster bin holds
+#[derive(Debug, Clone)]
+pub struct BinCapacityRecord {
+    pub location: String,
+    pub bin_no: String,
+    pub unit: CapacityUnit,
+    pub capacity: BigDecimal,
+    pub rec_user_id: String,
+    pub rec_date: NaiveDateTime,
+}
+
+/// Set the capacity of a bin
+#[derive(Debug, Deserialize)]
+pub struct BinCapacityRequest {
+    pub location: String,
+    pub bin_no: String,
+    pub unit: CapacityUnit,
+    #[serde(deserialize_with = "deserialize_qty")]
+    pub capacity: BigDecimal,
+    /// Acting user - filled from the JWT when omitted, rejected when it differs
+    #[serde(default)]
+    pub user_id: String,
+}
+
+/// A bin's capacity against what LotMaster holds in it
+#[derive(Debug, Serialize)]
+pub struct BinSpace {
+    pub bin_no: String,
+    pub location: String,
+    pub description: String,
+    pub unit: CapacityUnit,
+    #[serde(serialize_with = "serialize_qty")]
+    pub capacity: BigDecimal,
+    #[serde(serialize_with = "serialize_qty")]
+    pub occupied: BigDecimal,
+    /// Capacity left, never below zero
+    #[serde(serialize_with = "serialize_qty")]
+    pub available_space: BigDecimal,
+    /// Lot rows with stock in the bin
+    pub lot_count: usize,
+}
+
+/// putaway_bin_blocks row: a bin taken out of putaway (damaged rack, cleaning)
+#[derive(Debug, Clone, Serialize)]
+pub struct BinBlockRecord {
+    pub location: String,
+    pub bin_no: String,
+    pub reason: String,
+    /// The block lapses at this time; None blocks until the bin is unblocked
+    pub blocked_until: Option<NaiveDateTime>,
+    /// Who blocked the bin, and when
+    pub rec_user_id: String,
+    pub rec_date: NaiveDateTime,
+}
+
+impl BinBlockRecord {
+    pub fn is_active(&self, now: &NaiveDateTime) -> bool {
+        bin_block_active(self.blocked_until.as_ref(), now)
+    }
+}
+
+/// Whether a block with this expiry still holds at `now`
+pub fn bin_block_active(blocked_until: Option<&NaiveDateTime>, now: &NaiveDateTime) -> bool {
+    blocked_until.is_none_or(|until| until > now)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum BinBlockAction {
+    Block,
+    Unblock,
+}
+
+impl BinBlockAction {
+    /// Value stored in putaway_bin_block_history.action
+    pub fn code(&self) -> &'static str {
+        match self {
+            BinBlockAction::Block => "BLOCK",
+            BinBlockAction::Unblock => "UNBLOCK",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        [BinBlockAction::Block, BinBlockAction::Unblock]
+            .into_iter()
+            .find(|action| action.code().eq_ignore_ascii_case(code.trim()))
+    }
+}
+
+/// putaway_bin_block_history row: who blocked or unblocked a bin, and why
+#[derive(Debug, Clone, Serialize)]
+pub struct BinBlockEvent {
+    pub action: BinBlockAction,
+    pub location: String,
+    pub bin_no: String,
+    pub reason: String,
+    pub blocked_until: Option<NaiveDateTime>,
+    pub user_id: String,
+    pub event_date: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BinBlockRequest {
+    pub location: String,
+    pub bin_no: String,
+    pub reason: String,
+    /// Lift the block automatically at this time (Bangkok time)
+    #[serde(default)]
+    pub blocked_until: Option<NaiveDateTime>,
+    /// Acting user - filled from the JWT when omitted, rejected when it differs
+    #[serde(default)]
+    pub user_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BinUnblockRequest {
+    pub location: String,
+    pub bin_no: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Acting user - filled from the JWT when omitted, rejected when it differs
+    #[serde(default)]
+    pub user_id: String,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -93,7 +556,7 @@ pub enum PutawayError {
     InvalidBin { bin_no: String, location: String },
 
     #[error("Insufficient quantity: requested {requested}, available {available}")]
-    InsufficientQuantity { requested: f64, available: f64 },
+    InsufficientQuantity { requested: BigDecimal, available: BigDecimal },
 
     #[error("Database error: {0}")]
     DatabaseError(String),
@@ -103,6 +566,88 @@ pub enum PutawayError {
 
     #[error("Validation error: {0}")]
     ValidationError(String),
+
+    #[error("Inventory locked: {reason}")]
+    InventoryLocked { reason: InventoryLockReason },
+
+    #[error("Document not found: {document_no}")]
+    DocumentNotFound { document_no: String },
+
+    #[error("A request with Idempotency-Key '{key}' is still in progress")]
+    RequestInProgress { key: String },
+
+    #[error("The request with Idempotency-Key '{key}' was already applied as {document_no}")]
+    RequestAlreadyApplied { key: String, document_no: String },
+
+    #[error("Idempotency-Key '{key}' was already used for a different request")]
+    IdempotencyKeyReused { key: String },
+
+    #[error("Request user '{requested}' does not match signed-in user '{authenticated}'")]
+    UserMismatch { requested: String, authenticated: String },
+
+    #[error("Missing permission: {permission}")]
+    PermissionDenied { permission: &'static str },
+
+    #[error("{0}")]
+    BinCapacityExceeded(Box<CapacityExcess>),
+
+    #[error("Bin {bin_no} in location {location} is blocked for putaway: {reason}")]
+    BinBlocked { bin_no: String, location: String, reason: String },
+}
+
+/// A transfer that would take its destination bin past its capacity
+#[derive(Debug)]
+pub struct CapacityExcess {
+    pub bin_no: String,
+    pub location: String,
+    pub unit: CapacityUnit,
+    pub capacity: BigDecimal,
+    /// What LotMaster already holds in the bin
+    pub occupied: BigDecimal,
+    /// What the transfer (with the earlier lines of its document) adds
+    pub incoming: BigDecimal,
+}
+
+impl std::fmt::Display for CapacityExcess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "bin {} in location {} holds {} of {} {}, adding {} would overfill it",
+            self.bin_no, self.location, self.occupied, self.capacity, self.unit.label(), self.incoming
+        )
+    }
+}
+
+/// Why BME refuses stock movements (Phase 2 checks of the legacy trace)
+#[derive(Debug, Clone, PartialEq)]
+pub enum InventoryLockReason {
+    /// DistributionParameter.Freeze_Inventory is set
+    InventoryFrozen,
+    /// INLOC.Physinprogress is flagged for the item/location
+    PhysicalCountInProgress { item_key: String, location: String },
+}
+
+impl InventoryLockReason {
+    /// Stable code the frontend switches on
+    pub fn code(&self) -> &'static str {
+        match self {
+            InventoryLockReason::InventoryFrozen => "INVENTORY_FROZEN",
+            InventoryLockReason::PhysicalCountInProgress { .. } => "COUNT_IN_PROGRESS",
+        }
+    }
+}
+
+impl std::fmt::Display for InventoryLockReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InventoryLockReason::InventoryFrozen => {
+                write!(f, "inventory is frozen (Freeze_Inventory)")
+            }
+            InventoryLockReason::PhysicalCountInProgress { item_key, location } => {
+                write!(f, "physical count in progress for item {item_key} in location {location}")
+            }
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -112,55 +657,210 @@ pub struct LotTransactionItem {
     pub bin_no: String,
     pub doc_no: String,
     pub issue_doc_line_no: Option<i16>,
-    pub qty: f64,
+    #[serde(serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub qty: BigDecimal,
     pub transaction_type: u8,
     pub tran_typ: String,  // Human readable transaction type name
     pub transaction_date: String,
     pub status: String,
+    /// Table the line lives in (LotTransaction or QCLotTransaction) - LotTranNo is only unique per table
+    pub source_table: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct CommittedTransferRequest {
     pub lot_no: String,
     pub item_key: String,
     pub location: String,
     pub source_bin: String,
     pub to_bin: String,
-    pub transfer_qty: f64,
+    #[serde(serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub transfer_qty: BigDecimal,
+    /// Acting user - filled from the JWT when omitted, rejected when it differs
+    #[serde(default)]
     pub user_id: String,
     pub remarks: Option<String>,
     pub referenced: Option<String>,
+    /// Pending LotTransaction/QCLotTransaction lines (from /transactions/{lot_no}/{bin_no}) whose
+    /// BinNo follows the stock to the destination bin. At least one is required; their quantities
+    /// must add up to transfer_qty.
+    #[serde(default)]
+    pub lot_tran_nos: Vec<i32>,
+    /// Move even if the destination bin ends up over its capacity (capacity_override permission)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub override_capacity: bool,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct CommittedTransferResult {
     pub success: bool,
     pub message: String,
     pub document_no: String,
     pub timestamp: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub moved_lines: Vec<MovedCommitmentLine>,
+}
+
+/// A pending issue line re-pointed to the destination bin by a committed transfer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MovedCommitmentLine {
+    pub lot_tran_no: i32,
+    pub source_table: String,
+    pub doc_no: String,
+    pub doc_line_no: Option<i16>,
+    pub transaction_type: u8,
+    #[serde(serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+    pub qty: BigDecimal,
+    pub bin_from: String,
+    pub bin_to: String,
+}
+
+/// Everything written under one BT document number, for supervisors resolving disputes
+#[derive(Debug, Serialize)]
+pub struct BtDocumentAudit {
+    pub document_no: String,
+    pub header: Option<BtAuditHeader>,
+    pub lot_transactions: Vec<BtAuditLotTransaction>,
+    pub bin_transfers: Vec<BtAuditBinTransfer>,
+    /// LotMaster quantities around each line, source (FROM) and destination (TO) bin
+    pub lot_master: Vec<BtAuditLotSnapshot>,
+    /// Pending issue lines re-pointed by a committed transfer
+    pub commitment_moves: Vec<MovedCommitmentLine>,
+    /// Document this one reverses
+    pub reversal_of: Option<String>,
+    /// Document that reversed this one
+    pub reversed_by: Option<String>,
+}
+
+/// Mintxdh header of a BT document
+#[derive(Debug, Serialize)]
+pub struct BtAuditHeader {
+    pub in_trans_id: Option<i32>,
+    pub item_key: String,
+    pub location: String,
+    pub doc_date: NaiveDateTime,
+    pub description: String,
+    pub in_acct: String,
+    pub user_id: String,
+    pub rec_date: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BtAuditLotTransaction {
+    pub lot_tran_no: Option<i32>,
+    /// 9 = issue from the source bin, 8 = receipt into the destination bin
+    pub transaction_type: u8,
+    pub line_no: Option<i16>,
+    pub lot_no: String,
+    pub item_key: String,
+    pub location: String,
+    pub bin_no: String,
+    #[serde(serialize_with = "serialize_qty")]
+    pub qty: BigDecimal,
+    /// Y = applied to LotMaster, N/P = pending
+    pub processed: String,
+    pub user_id: String,
+    pub rec_date: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BtAuditBinTransfer {
+    pub lot_tran_no: i32,
+    pub in_trans_id: i32,
+    pub lot_no: String,
+    pub item_key: String,
+    pub location: String,
+    pub bin_from: String,
+    pub bin_to: String,
+    /// Source bin QtyOnHand before the move
+    #[serde(serialize_with = "serialize_qty")]
+    pub qty_on_hand: BigDecimal,
+    #[serde(serialize_with = "serialize_qty")]
+    pub transfer_qty: BigDecimal,
+    /// User1
+    pub remarks: String,
+    /// User5
+    pub referenced: String,
+    pub user_id: String,
+    pub rec_date: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BtAuditLotSnapshot {
+    pub line_no: i16,
+    /// Issue (FROM) or receipt (TO) LotTransaction row behind the change
+    pub lot_tran_no: Option<i32>,
+    pub lot_no: String,
+    pub bin_no: String,
+    /// FROM or TO
+    pub side: String,
+    #[serde(serialize_with = "serialize_qty")]
+    pub qty_on_hand_before: BigDecimal,
+    #[serde(serialize_with = "serialize_qty")]
+    pub qty_on_hand_after: BigDecimal,
+    /// None when the document predates the snapshots and the values are derived from BinTransfer
+    #[serde(serialize_with = "serialize_opt_qty")]
+    pub qty_commit_before: Option<BigDecimal>,
+    #[serde(serialize_with = "serialize_opt_qty")]
+    pub qty_commit_after: Option<BigDecimal>,
+}
+
+/// BT document numbers allocated from Seqnum that never produced a transfer
+#[derive(Debug, Serialize)]
+pub struct BtSequenceGapReport {
+    pub current_sequence: i32,
+    pub range_from: i32,
+    pub range_to: i32,
+    pub checked_count: i32,
+    pub gap_count: usize,
+    pub gaps: Vec<BtSequenceGap>,
+    pub timestamp: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BtSequenceGap {
+    pub document_no: String,
+    /// A Mintxdh header exists without any LotTransaction/BinTransfer rows (half-written document)
+    pub has_mintxdh_header: bool,
 }
 
 // Internal database models
-#[derive(Debug)]
+/// One LotMaster row (a lot in one bin) - read by the lookups, written by the transfer flows
+#[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct LotMasterRecord {
     pub lot_no: String,
     pub item_key: String,
     pub location_key: String,
     pub bin_no: String,
-    pub qty_on_hand: f64,
-    pub qty_issued: f64,
-    pub qty_commit_sales: f64,
-    pub date_expiry: DateTime<Utc>,
+    pub date_received: Option<NaiveDateTime>,
+    pub date_expiry: Option<NaiveDateTime>,
+    pub qty_received: BigDecimal,
+    pub qty_on_hand: BigDecimal,
+    pub qty_issued: BigDecimal,
+    pub qty_commit_sales: BigDecimal,
     pub vendor_key: String,
     pub vendor_lot_no: String,
     pub document_no: String,
     pub document_line_no: i16,
     pub transaction_type: u8,
-    pub lot_status: String,
+    pub lot_status: Option<String>,
+    pub rec_user_id: String,
+    pub rec_date: Option<NaiveDateTime>,
 }
 
-#[derive(Debug)]
+impl LotMasterRecord {
+    /// Quantities rounded to the item's UOM decimals, dropping float residue left in LotMaster
+    pub fn at_qty_scale(mut self, decimals: i64) -> Self {
+        self.qty_received = round_qty(&self.qty_received, decimals);
+        self.qty_on_hand = round_qty(&self.qty_on_hand, decimals);
+        self.qty_issued = round_qty(&self.qty_issued, decimals);
+        self.qty_commit_sales = round_qty(&self.qty_commit_sales, decimals);
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct ItemMasterRecord {
     pub item_key: String,
@@ -171,44 +871,129 @@ pub struct ItemMasterRecord {
     pub sales_uom_code: String,
 }
 
-#[derive(Debug)]
+/// INQTYCNV row: `qty in ToKey = qty in FromKey (operation) ConvFact`
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct UomConversionRecord {
+    /// 0 = every item, 1 = one item class, 2 = one item
+    pub scope: i32,
+    pub item_class_key: String,
+    pub item_key: String,
+    pub from_uom: String,
+    pub to_uom: String,
+    pub conv_factor: BigDecimal,
+    /// '/' (or 'D') divides by the factor, anything else multiplies
+    pub operation: String,
+}
+
+impl UomConversionRecord {
+    fn divides(&self) -> bool {
+        matches!(self.operation.trim(), "/" | "D" | "d")
+    }
+
+    /// Quantity in `to_uom` for a quantity in `from_uom`
+    pub fn convert(&self, qty: &BigDecimal) -> BigDecimal {
+        if self.divides() { qty / &self.conv_factor } else { qty * &self.conv_factor }
+    }
+
+    /// Quantity in `from_uom` for a quantity in `to_uom`
+    pub fn convert_back(&self, qty: &BigDecimal) -> BigDecimal {
+        if self.divides() { qty * &self.conv_factor } else { qty / &self.conv_factor }
+    }
+}
+
+/// BINMaster row
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct BinMasterRecord {
+    pub location: String,
+    pub bin_no: String,
+    pub description: String,
+    pub aisle: String,
+    pub row: String,
+    pub rack: String,
+    pub rec_date: Option<NaiveDateTime>,
+}
+
+/// BinTransfer audit row (one per moved lot line)
+#[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct BinTransferRecord {
+    /// Identity - None until inserted
     pub bin_tran_id: Option<i32>,
     pub item_key: String,
     pub location: String,
     pub lot_no: String,
     pub bin_no_from: String,
     pub bin_no_to: String,
+    /// Issue LotTranNo of the line (0 on committed transfers)
+    pub lot_tran_no: i32,
+    /// Source bin QtyOnHand before the move
+    pub qty_on_hand: BigDecimal,
+    pub transfer_qty: BigDecimal,
+    pub in_trans_id: i32,
+    pub rec_user_id: String,
+    pub rec_date: NaiveDateTime,
+    pub container_no: String,
+    /// Remarks
+    pub user1: String,
+    /// Referenced document
+    pub user5: String,
+}
+
+/// LotMaster quantities of one bin before and after a BT line (putaway_bt_lot_snapshots)
+#[derive(Debug, Clone)]
+pub struct LotSnapshotRecord {
+    pub document_no: String,
+    pub line_no: i16,
+    pub lot_no: String,
+    pub item_key: String,
+    pub location: String,
+    pub bin_no: String,
+    /// FROM (source bin) or TO (destination bin)
+    pub side: String,
+    pub qty_on_hand_before: BigDecimal,
+    pub qty_on_hand_after: BigDecimal,
+    pub qty_commit_before: BigDecimal,
+    pub qty_commit_after: BigDecimal,
+    /// LotTransaction row that moved the bin: the issue on FROM, the receipt on TO
     pub lot_tran_no: Option<i32>,
-    pub qty_on_hand: f64,
-    pub transfer_qty: f64,
     pub rec_user_id: String,
-    pub rec_date: DateTime<Utc>,
+    pub rec_date: NaiveDateTime,
 }
 
-#[derive(Debug)]
+/// LotTransaction (or QCLotTransaction) row.
+/// Optional columns left as None are not part of the INSERT, like the BME statements.
+#[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct LotTransactionRecord {
+    /// Identity - None until inserted
     pub lot_tran_no: Option<i32>,
     pub lot_no: String,
     pub item_key: String,
     pub location_key: String,
-    pub date_received: DateTime<Utc>,
-    pub date_expiry: DateTime<Utc>,
-    pub transaction_type: i16, // 8 = Receipt, 9 = Issue
+    pub date_received: NaiveDateTime,
+    pub date_expiry: Option<NaiveDateTime>,
+    pub transaction_type: u8, // 8 = Receipt, 9 = Issue
     pub issue_doc_no: Option<String>,
     pub issue_doc_line_no: Option<i16>,
-    pub issue_date: Option<DateTime<Utc>>,
-    pub qty_issued: Option<f64>,
+    pub issue_date: Option<NaiveDateTime>,
+    pub qty_issued: Option<BigDecimal>,
     pub receipt_doc_no: Option<String>,
     pub receipt_doc_line_no: Option<i16>,
-    pub qty_received: Option<f64>,
-    pub vendor_key: String,
+    pub qty_received: Option<BigDecimal>,
+    pub vendor_key: Option<String>,
+    pub vendor_lot_no: String,
+    pub customer_key: Option<String>,
     pub bin_no: String,
     pub rec_userid: String,
-    pub rec_date: DateTime<Utc>,
+    pub rec_date: NaiveDateTime,
+    /// 'Y' = applied to LotMaster, 'N'/'P' = pending (counts as commitment when it is an issue)
     pub processed: String,
+    pub date_quarantine: Option<NaiveDateTime>,
+    pub temp_qty: Option<BigDecimal>,
+    pub qty_for_lot_assignment: Option<BigDecimal>,
+    pub qty_used: Option<BigDecimal>,
 }
 
 #[derive(Debug)]
@@ -218,40 +1003,25 @@ pub struct SequenceRecord {
     pub seq_num: i32,
 }
 
-#[derive(Debug)]
+/// Mintxdh header of a BT document. The remaining columns are constant for bin transfers
+/// (SysID '7', ProcessID 'M', TrnTyp 'A', NLAcct '1100', ...) and written by the insert itself.
+#[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct MintxdhRecord {
+    /// Identity - None until inserted
     pub in_trans_id: Option<i32>,
     pub item_key: String,
     pub location: String,
-    pub to_location: String,
-    pub sys_id: String,
-    pub process_id: String,
-    pub sys_doc_id: String,
-    pub sys_lin_sq: i16,
-    pub trn_typ: String,
-    pub trn_sub_typ: String,
     pub doc_no: String,
-    pub doc_date: DateTime<Utc>,
-    pub apl_date: DateTime<Utc>,
+    pub doc_date: NaiveDateTime,
     pub trn_desc: String,
-    pub trn_qty: f64,
-    pub trn_amt: f64,
-    pub nl_acct: String,
     pub in_acct: String,
-    pub created_serlot: String,
     pub rec_user_id: String,
-    pub rec_date: DateTime<Utc>,
-    pub rec_time: DateTime<Utc>,
-    pub updated_fin_table: Option<bool>,
-    pub sort_field: String,
-    pub jrnl_btch_no: String,
+    pub rec_date: NaiveDateTime,
     pub std_cost: f64,
-    pub stdcostupdated: bool,
-    pub gl_trn_amt: f64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct InlocRecord {
     pub item_key: String,
@@ -262,6 +1032,18 @@ pub struct InlocRecord {
     pub stdcost: f64,
 }
 
+/// Rows the BinTransfer link backfill updated (or would update on a dry run)
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct BinTransferBackfill {
+    pub lot_tran_nos: u64,
+    pub in_trans_ids: u64,
+}
+
+/// Format a Seqnum 'BT' value as a bin transfer document number (BT-26112174)
+pub fn bt_document_no(seq_num: i32) -> String {
+    format!("BT-{seq_num:08}")
+}
+
 // GL Account mapping helper
 pub fn map_inclasskey_to_inacct(inclasskey: &str) -> &str {
     match inclasskey {
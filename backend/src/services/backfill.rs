@@ -0,0 +1,183 @@
+use crate::database::repository::{BinTransferLink, BtLotTransaction, LotKey, PutawayRepository};
+use crate::models::putaway_models::{BinTransferBackfill, PutawayError};
+use crate::services::putaway_service::PutawayService;
+use std::collections::{BTreeSet, HashSet};
+
+/// BinTransfer rows written before they were linked to their document: the committed flow stored
+/// LotTranNo 0 and every path stored InTransID 0. Each row is matched to the issue LotTransaction
+/// of the same lot, item, location, bins and timestamp, and then to the Mintxdh header of the
+/// issue's BT document with the same timestamp - each only when that match is unambiguous.
+impl<R: PutawayRepository> PutawayService<R> {
+    /// Link historical BinTransfer rows to their issue LotTransaction and Mintxdh header
+    /// (`putaway-backend backfill-bin-transfer-links [--apply]`). Runs in one transaction that is
+    /// only committed when `apply` is set; a dry run counts what it would link.
+    pub async fn backfill_bin_transfer_links(&self, apply: bool) -> Result<BinTransferBackfill, PutawayError> {
+        let mut conn = self.db.begin().await?;
+
+        let result: Result<BinTransferBackfill, PutawayError> = async {
+            let lots: BTreeSet<(String, String, String)> = self
+                .db
+                .unlinked_bin_transfers(&mut conn)
+                .await?
+                .into_iter()
+                .map(|row| (row.lot_no, row.item_key, row.location))
+                .collect();
+
+            let mut backfill = BinTransferBackfill::default();
+            for (lot_no, item_key, location) in &lots {
+                let lot = LotKey { lot_no, item_key, location };
+                let rows = self.db.lot_bin_transfers(&mut conn, lot).await?;
+                let transactions = self.db.bt_lot_transactions(&mut conn, lot).await?;
+
+                // (LotTranNo, InTransID) of each row, LotTranNo first so rows linked now get their header too
+                let mut links: Vec<(i32, i32)> = rows.iter().map(|row| (row.lot_tran_no, row.in_trans_id)).collect();
+                for (index, lot_tran_no) in issue_links(&rows, &transactions) {
+                    links[index].0 = lot_tran_no;
+                    backfill.lot_tran_nos += 1;
+                }
+
+                for (row, (lot_tran_no, in_trans_id)) in rows.iter().zip(links.iter_mut()) {
+                    if *lot_tran_no == 0 || *in_trans_id != 0 {
+                        continue;
+                    }
+                    let Some(issue) = transactions.iter().find(|issue| issue.transaction_type == 9 && issue.lot_tran_no == *lot_tran_no)
+                    else {
+                        continue;
+                    };
+                    let headers = self.db.mintxdh_headers(&mut conn, &issue.document_no).await?;
+                    if let [header] = headers.iter().filter(|(_, rec_date)| *rec_date == row.rec_date).collect::<Vec<_>>()[..] {
+                        *in_trans_id = header.0;
+                        backfill.in_trans_ids += 1;
+                    }
+                }
+
+                for (row, (lot_tran_no, in_trans_id)) in rows.iter().zip(links) {
+                    if (lot_tran_no, in_trans_id) != (row.lot_tran_no, row.in_trans_id) {
+                        self.db.link_bin_transfer(&mut conn, row, lot_tran_no, in_trans_id).await?;
+                    }
+                }
+            }
+            Ok(backfill)
+        }
+        .await;
+
+        match result {
+            Ok(backfill) if apply => {
+                self.db.commit(&mut conn).await?;
+                Ok(backfill)
+            }
+            Ok(backfill) => {
+                // Dry run: report what would change
+                self.db.rollback(&mut conn).await;
+                Ok(backfill)
+            }
+            Err(e) => {
+                self.db.rollback(&mut conn).await;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// (row index, issue LotTranNo) for the lot's BinTransfer rows without a LotTranNo. A candidate issue
+/// leaves the row's bin at the row's timestamp, has its receipt in the row's destination bin and is
+/// not linked to a row yet; a pair is kept only when neither the row (with any identical row) nor
+/// the issue has another candidate.
+fn issue_links(rows: &[BinTransferLink], transactions: &[BtLotTransaction]) -> Vec<(usize, i32)> {
+    let linked: HashSet<i32> = rows.iter().map(|row| row.lot_tran_no).collect();
+    let has_receipt = |issue: &BtLotTransaction, bin_no: &str| {
+        transactions.iter().any(|receipt| {
+            receipt.transaction_type == 8
+                && receipt.document_no == issue.document_no
+                && receipt.line_no == issue.line_no
+                && receipt.bin_no == bin_no
+        })
+    };
+
+    let mut candidates = Vec::new();
+    for (index, row) in rows.iter().enumerate().filter(|(_, row)| row.lot_tran_no == 0) {
+        for issue in transactions.iter().filter(|issue| {
+            issue.transaction_type == 9
+                && issue.bin_no == row.bin_no_from
+                && issue.rec_date == row.rec_date
+                && !linked.contains(&issue.lot_tran_no)
+                && has_receipt(issue, &row.bin_no_to)
+        }) {
+            candidates.push((index, issue.lot_tran_no));
+        }
+    }
+
+    let same_transfer = |a: &BinTransferLink, b: &BinTransferLink| {
+        (&a.bin_no_from, &a.bin_no_to, a.rec_date) == (&b.bin_no_from, &b.bin_no_to, b.rec_date)
+    };
+    candidates
+        .iter()
+        .filter(|(index, lot_tran_no)| {
+            let issue_matches = candidates.iter().filter(|(other, _)| same_transfer(&rows[*other], &rows[*index])).count();
+            let transfer_matches = candidates.iter().filter(|(_, other)| other == lot_tran_no).count();
+            issue_matches == 1 && transfer_matches == 1
+        })
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::memory_db::{transfer, warehouse, MemoryPutawayDatabase, MemoryTables, LOT};
+    use crate::models::putaway_models::MintxdhRecord;
+
+    #[tokio::test]
+    async fn test_bin_transfer_link_backfill_only_links_unambiguous_rows() {
+        let db = MemoryPutawayDatabase::new(warehouse("K0802-4B"));
+        let service = PutawayService::with_repository(db.clone());
+        let linked = service.execute_transfer(transfer(LOT, "K0802-4B", "K0803-1A", "100")).await.unwrap().document_no;
+        let two_headers = service.execute_transfer(transfer(LOT, "K0802-4B", "WHKON1", "50")).await.unwrap().document_no;
+        let two_rows = service.execute_transfer(transfer(LOT, "K0803-1A", "WHKON1", "20")).await.unwrap().document_no;
+
+        // Rewind to before the links were written: committed-flow rows without LotTranNo, no InTransID
+        // anywhere, a document with a duplicated Mintxdh header and one with a duplicated BinTransfer row
+        let mut tables = db.tables().await;
+        for row in tables.bin_transfers.iter_mut() {
+            row.in_trans_id = 0;
+        }
+        let bt_row = |tables: &MemoryTables, doc_no: &str| {
+            let issue = tables
+                .lot_transactions
+                .iter()
+                .find(|row| row.transaction_type == 9 && row.issue_doc_no.as_deref() == Some(doc_no))
+                .and_then(|row| row.lot_tran_no)
+                .unwrap();
+            (issue, tables.bin_transfers.iter().position(|row| row.lot_tran_no == issue).unwrap())
+        };
+        let (linked_issue, linked_row) = bt_row(&tables, &linked);
+        let (_, two_rows_row) = bt_row(&tables, &two_rows);
+        tables.bin_transfers[linked_row].lot_tran_no = 0;
+        tables.bin_transfers[two_rows_row].lot_tran_no = 0;
+        let copied = tables.bin_transfers[two_rows_row].clone();
+        tables.bin_transfers.push(copied);
+        let header = tables.mintxdh.iter().find(|header| header.doc_no == two_headers).unwrap().clone();
+        tables.mintxdh.push(MintxdhRecord { in_trans_id: Some(9001), ..header });
+        let db = MemoryPutawayDatabase::new(tables.clone());
+        let service = PutawayService::with_repository(db.clone());
+
+        let expected = BinTransferBackfill { lot_tran_nos: 1, in_trans_ids: 1 };
+        assert_eq!(service.backfill_bin_transfer_links(false).await.unwrap(), expected);
+        let links = |tables: &MemoryTables| -> Vec<(i32, i32)> {
+            tables.bin_transfers.iter().map(|row| (row.lot_tran_no, row.in_trans_id)).collect()
+        };
+        assert_eq!(links(&db.tables().await), links(&tables), "a dry run writes nothing");
+
+        assert_eq!(service.backfill_bin_transfer_links(true).await.unwrap(), expected);
+        let after = db.tables().await;
+        let linked_header = after.mintxdh.iter().find(|header| header.doc_no == linked).and_then(|header| header.in_trans_id).unwrap();
+        assert_eq!((after.bin_transfers[linked_row].lot_tran_no, after.bin_transfers[linked_row].in_trans_id), (linked_issue, linked_header));
+        let (_, two_headers_row) = bt_row(&after, &two_headers);
+        assert_eq!(after.bin_transfers[two_headers_row].in_trans_id, 0);
+        assert_eq!(links(&after)[two_rows_row], (0, 0));
+        assert_eq!(links(&after).last(), Some(&(0, 0)));
+
+        // Nothing left that can be linked
+        assert_eq!(service.backfill_bin_transfer_links(true).await.unwrap(), BinTransferBackfill::default());
+    }
+}
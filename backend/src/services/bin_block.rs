@@ -0,0 +1,308 @@
+use crate::database::repository::PutawayRepository;
+use crate::models::putaway_models::{
+    BinBlockAction, BinBlockEvent, BinBlockRecord, BinBlockRequest, BinUnblockRequest, PutawayError,
+};
+use crate::services::putaway_service::PutawayService;
+use crate::utils::bangkok_now;
+
+/// Bin blocks: bins taken out of putaway (damaged, being cleaned) in putaway_bin_blocks,
+/// with every block and unblock kept in putaway_bin_block_history
+impl<R: PutawayRepository> PutawayService<R> {
+    /// Block a bin as a transfer destination until it is unblocked or `blocked_until` passes.
+    /// Blocking an already blocked bin replaces its reason and expiry.
+    pub async fn block_bin(&self, request: BinBlockRequest) -> Result<BinBlockRecord, PutawayError> {
+        let (location, bin_no, reason) = (request.location.trim(), request.bin_no.trim(), request.reason.trim());
+        if location.is_empty() || bin_no.is_empty() {
+            return Err(PutawayError::ValidationError("location and bin_no are required".to_string()));
+        }
+        if reason.is_empty() {
+            return Err(PutawayError::ValidationError("A reason is required to block a bin".to_string()));
+        }
+
+        let now = bangkok_now().naive_local();
+        if request.blocked_until.is_some_and(|until| until <= now) {
+            return Err(PutawayError::ValidationError("blocked_until must be in the future".to_string()));
+        }
+
+        let bin = self.db.find_bin(location, bin_no).await?.ok_or_else(|| PutawayError::InvalidBin {
+            bin_no: bin_no.to_string(),
+            location: location.to_string(),
+        })?;
+
+        let block = BinBlockRecord {
+            location: bin.location,
+            bin_no: bin.bin_no,
+            reason: reason.to_string(),
+            blocked_until: request.blocked_until,
+            rec_user_id: request.user_id,
+            rec_date: now,
+        };
+        let event = BinBlockEvent {
+            action: BinBlockAction::Block,
+            location: block.location.clone(),
+            bin_no: block.bin_no.clone(),
+            reason: block.reason.clone(),
+            blocked_until: block.blocked_until,
+            user_id: block.rec_user_id.clone(),
+            event_date: now,
+        };
+
+        let mut conn = self.db.begin().await?;
+        let transaction_result: Result<(), PutawayError> = async {
+            self.db.save_bin_block(&mut conn, &block).await?;
+            self.db.insert_bin_block_event(&mut conn, &event).await
+        }
+        .await;
+
+        match transaction_result {
+            Ok(()) => {
+                self.db.commit(&mut conn).await?;
+                tracing::info!(bin = %block.bin_no, location = %block.location, user = %block.rec_user_id, "Bin blocked: {}", block.reason);
+                Ok(block)
+            }
+            Err(e) => {
+                self.db.rollback(&mut conn).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Lift a bin's block. An expired block is lifted the same way, so the history shows who cleared it.
+    pub async fn unblock_bin(&self, request: BinUnblockRequest) -> Result<BinBlockEvent, PutawayError> {
+        let (location, bin_no) = (request.location.trim(), request.bin_no.trim());
+        if location.is_empty() || bin_no.is_empty() {
+            return Err(PutawayError::ValidationError("location and bin_no are required".to_string()));
+        }
+
+        let mut conn = self.db.begin().await?;
+        let transaction_result: Result<BinBlockEvent, PutawayError> = async {
+            let Some(block) = self.db.bin_block(&mut conn, location, bin_no).await? else {
+                return Err(PutawayError::ValidationError(format!(
+                    "Bin {bin_no} in location {location} is not blocked"
+                )));
+            };
+
+            let event = BinBlockEvent {
+                action: BinBlockAction::Unblock,
+                reason: request.reason.as_deref().map(str::trim).filter(|r| !r.is_empty()).unwrap_or(&block.reason).to_string(),
+                location: block.location,
+                bin_no: block.bin_no,
+                blocked_until: block.blocked_until,
+                user_id: request.user_id.clone(),
+                event_date: bangkok_now().naive_local(),
+            };
+            self.db.delete_bin_block(&mut conn, location, bin_no).await?;
+            self.db.insert_bin_block_event(&mut conn, &event).await?;
+            Ok(event)
+        }
+        .await;
+
+        match transaction_result {
+            Ok(event) => {
+                self.db.commit(&mut conn).await?;
+                tracing::info!(bin = %event.bin_no, location = %event.location, user = %event.user_id, "Bin unblocked");
+                Ok(event)
+            }
+            Err(e) => {
+                self.db.rollback(&mut conn).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Blocks in force now, by location and bin; expired blocks are left out
+    pub async fn get_blocked_bins(&self, location: Option<&str>) -> Result<Vec<BinBlockRecord>, PutawayError> {
+        let location = location.map(str::trim).filter(|location| !location.is_empty());
+        let now = bangkok_now().naive_local();
+        let mut blocks = self.db.find_bin_blocks(location).await?;
+        blocks.retain(|block| block.is_active(&now));
+        Ok(blocks)
+    }
+
+    /// Every block and unblock of a bin, newest first
+    pub async fn get_bin_block_history(&self, location: &str, bin_no: &str) -> Result<Vec<BinBlockEvent>, PutawayError> {
+        self.db.find_bin_block_history(location.trim(), bin_no.trim()).await
+    }
+
+    /// Refuse a blocked bin as a transfer destination
+    pub(super) async fn check_bin_not_blocked(
+        &self,
+        conn: &mut R::Conn,
+        location: &str,
+        bin_no: &str,
+    ) -> Result<(), PutawayError> {
+        match self.db.bin_block(conn, location, bin_no).await? {
+            Some(block) if block.is_active(&bangkok_now().naive_local()) => Err(PutawayError::BinBlocked {
+                bin_no: block.bin_no,
+                location: block.location,
+                reason: block.reason,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::memory_db::{pending_issue, qty, transfer, warehouse, MemoryPutawayDatabase, LOT};
+    use crate::models::putaway_models::{CommittedTransferRequest, TransferReversalRequest};
+    use chrono::Duration;
+    use std::convert::identity;
+
+    fn block(bin_no: &str, blocked_until: Option<chrono::NaiveDateTime>) -> BinBlockRequest {
+        BinBlockRequest {
+            location: "TFC1".to_string(),
+            bin_no: bin_no.to_string(),
+            reason: "Damaged racking".to_string(),
+            blocked_until,
+            user_id: "supervisor".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_blocked_bins_are_refused_as_destinations_until_unblocked() {
+        let service = PutawayService::with_repository(MemoryPutawayDatabase::new(warehouse("WHKON1")));
+        service.block_bin(block("K0802-4B", None)).await.unwrap();
+
+        let refused = service.execute_transfer(transfer(LOT, "WHKON1", "K0802-4B", "10")).await;
+        assert!(matches!(refused, Err(PutawayError::BinBlocked { ref reason, .. }) if reason == "Damaged racking"), "{refused:?}");
+        assert!(!service.validate_bin("TFC1", "K0802-4B").await.unwrap().is_valid);
+
+        let (bins, _) = service.search_bins_paginated(Some("K08"), 1, 20, None, None, None).await.unwrap();
+        let flagged: Vec<_> = bins.iter().filter(|bin| bin.blocked).map(|bin| bin.bin_no.as_str()).collect();
+        assert_eq!(flagged, ["K0802-4B"]);
+        assert_eq!(service.get_blocked_bins(Some("TFC1")).await.unwrap().len(), 1);
+
+        let unblock = BinUnblockRequest {
+            location: "TFC1".to_string(),
+            bin_no: "K0802-4B".to_string(),
+            reason: Some("Racking repaired".to_string()),
+            user_id: "supervisor2".to_string(),
+        };
+        service.unblock_bin(unblock).await.unwrap();
+        assert!(service.execute_transfer(transfer(LOT, "WHKON1", "K0802-4B", "10")).await.unwrap().success);
+        assert!(service.get_blocked_bins(None).await.unwrap().is_empty());
+
+        let history: Vec<_> = service
+            .get_bin_block_history("TFC1", "K0802-4B")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|event| (event.action, event.user_id, event.reason))
+            .collect();
+        assert_eq!(
+            history,
+            [
+                (BinBlockAction::Unblock, "supervisor2".to_string(), "Racking repaired".to_string()),
+                (BinBlockAction::Block, "supervisor".to_string(), "Damaged racking".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expired_blocks_no_longer_apply() {
+        let now = bangkok_now().naive_local();
+        // Cleaning of K0802-4B finished earlier in the day
+        let mut tables = warehouse("WHKON1");
+        tables.bin_blocks.push(BinBlockRecord {
+            location: "TFC1".to_string(),
+            bin_no: "K0802-4B".to_string(),
+            reason: "Cleaning".to_string(),
+            blocked_until: Some(now - Duration::minutes(1)),
+            rec_user_id: "supervisor".to_string(),
+            rec_date: now - Duration::hours(4),
+        });
+        let service = PutawayService::with_repository(MemoryPutawayDatabase::new(tables));
+
+        let past = service.block_bin(block("K0803-1A", Some(now - Duration::hours(1)))).await;
+        assert!(matches!(past, Err(PutawayError::ValidationError(_))));
+        let missing_reason = service.block_bin(BinBlockRequest { reason: "  ".to_string(), ..block("K0803-1A", None) }).await;
+        assert!(matches!(missing_reason, Err(PutawayError::ValidationError(_))));
+
+        service.block_bin(block("K0803-1A", Some(now + Duration::hours(8)))).await.unwrap();
+        assert!(matches!(service.execute_transfer(transfer(LOT, "WHKON1", "K0803-1A", "10")).await, Err(PutawayError::BinBlocked { .. })));
+
+        let blocked: Vec<_> = service.get_blocked_bins(Some("TFC1")).await.unwrap().into_iter().map(|block| block.bin_no).collect();
+        assert_eq!(blocked, ["K0803-1A"]);
+        assert!(service.validate_bin("TFC1", "K0802-4B").await.unwrap().is_valid);
+        assert!(service.execute_transfer(transfer(LOT, "WHKON1", "K0802-4B", "10")).await.unwrap().success);
+    }
+
+    #[tokio::test]
+    async fn test_blocks_are_checked_under_the_transfer_transaction() {
+        let db = MemoryPutawayDatabase::new(warehouse("WHKON1"));
+        let service = PutawayService::with_repository(db.clone());
+
+        // A bin blocked after the transfer was validated is refused when the line is written
+        service.block_bin(block("K0802-4B", None)).await.unwrap();
+        let single = service
+            .execute_bin_transfer_transaction(LOT.lot_no, LOT.item_key, LOT.location, "WHKON1", "K0802-4B", &qty("10"), "deachawat", "", "", false, None, identity)
+            .await;
+        assert!(matches!(single, Err(PutawayError::BinBlocked { .. })), "{single:?}");
+        let batch = service
+            .execute_batch_bin_transfer(&[transfer(LOT, "WHKON1", "K0803-1A", "10"), transfer(LOT, "WHKON1", "K0802-4B", "10")])
+            .await;
+        assert!(matches!(batch, Err(PutawayError::BinBlocked { .. })), "{batch:?}");
+        let tables = db.tables().await;
+        assert_eq!(tables.lot(LOT, "WHKON1").unwrap().qty_on_hand, qty("1000"));
+        assert!(tables.lot(LOT, "K0803-1A").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reversals_into_a_blocked_bin_are_refused() {
+        let db = MemoryPutawayDatabase::new(warehouse("WHKON1"));
+        let service = PutawayService::with_repository(db.clone());
+        let moved = service.execute_transfer(transfer(LOT, "WHKON1", "K0802-4B", "10")).await.unwrap();
+
+        // The stock would go back to WHKON1, blocked since the transfer
+        service.block_bin(block("WHKON1", None)).await.unwrap();
+        let reversal = || TransferReversalRequest { user_id: "supervisor".to_string(), reason: "Wrong bin".to_string() };
+        let refused = service.reverse_transfer(&moved.document_no, reversal()).await;
+        assert!(matches!(refused, Err(PutawayError::BinBlocked { ref bin_no, .. }) if bin_no == "WHKON1"), "{refused:?}");
+        assert_eq!(db.tables().await.lot(LOT, "K0802-4B").unwrap().qty_on_hand, qty("10"));
+
+        let unblock = BinUnblockRequest { location: "TFC1".to_string(), bin_no: "WHKON1".to_string(), reason: None, user_id: "supervisor".to_string() };
+        service.unblock_bin(unblock).await.unwrap();
+        assert!(service.reverse_transfer(&moved.document_no, reversal()).await.unwrap().success);
+        assert_eq!(db.tables().await.lot(LOT, "WHKON1").unwrap().qty_on_hand, qty("1000"));
+    }
+
+    #[tokio::test]
+    async fn test_committed_transfers_into_a_blocked_bin_are_refused() {
+        let mut tables = warehouse("WHKON1");
+        tables.lot_transactions.push(pending_issue(41, LOT, "WHKON1", "30"));
+        tables.lot_master[0].qty_commit_sales = qty("30");
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+
+        service.block_bin(block("K0802-4B", None)).await.unwrap();
+        let committed = || CommittedTransferRequest {
+            lot_no: LOT.lot_no.to_string(),
+            item_key: LOT.item_key.to_string(),
+            location: LOT.location.to_string(),
+            source_bin: "WHKON1".to_string(),
+            to_bin: "K0802-4B".to_string(),
+            transfer_qty: qty("30"),
+            user_id: "supervisor".to_string(),
+            remarks: None,
+            referenced: None,
+            lot_tran_nos: vec![41],
+            override_capacity: false,
+        };
+        let refused = service.execute_committed_transfer(committed()).await;
+        assert!(matches!(refused, Err(PutawayError::BinBlocked { ref bin_no, .. }) if bin_no == "K0802-4B"), "{refused:?}");
+
+        // Refused before a BT number is taken; the commitment stays in WHKON1
+        let tables = db.tables().await;
+        assert_eq!(tables.bt_sequence, 26112173);
+        assert_eq!(tables.lot_transactions.len(), 1);
+        assert_eq!(tables.lot_transactions[0].bin_no, "WHKON1");
+        assert_eq!(tables.lot(LOT, "WHKON1").unwrap().qty_commit_sales, qty("30"));
+        assert!(tables.lot(LOT, "K0802-4B").is_none());
+
+        let unblock = BinUnblockRequest { location: "TFC1".to_string(), bin_no: "K0802-4B".to_string(), reason: None, user_id: "supervisor".to_string() };
+        service.unblock_bin(unblock).await.unwrap();
+        assert!(service.execute_committed_transfer(committed()).await.unwrap().success);
+    }
+}
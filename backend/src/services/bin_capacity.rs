@@ -0,0 +1,421 @@
+use crate::constants::CAPACITY_WEIGHT_UOM;
+use crate::database::repository::{LotKey, PutawayRepository};
+use crate::models::putaway_models::{
+    BinCapacityRecord, BinCapacityRequest, BinMasterRecord, BinSpace, BinTransferRequest, CapacityExcess, CapacityUnit,
+    LotMasterRecord, PutawayError,
+};
+use crate::services::putaway_service::PutawayService;
+use crate::utils::bangkok_now;
+use crate::utils::quantity::round_qty;
+use bigdecimal::{BigDecimal, Signed, Zero};
+
+/// Decimals capacities and occupancy are reported with (weights are converted, so not at a UOM scale)
+const CAPACITY_DECIMALS: i64 = 4;
+
+/// Bin capacity: what a bin may hold (putaway_bin_capacity) against what LotMaster puts in it
+impl<R: PutawayRepository> PutawayService<R> {
+    /// Capacity-tracked bins of a location with space left, by bin number
+    pub async fn get_available_bins(&self, location: &str) -> Result<Vec<BinSpace>, PutawayError> {
+        let location = location.trim();
+        if location.is_empty() {
+            return Err(PutawayError::ValidationError("location is required".to_string()));
+        }
+
+        let now = bangkok_now().naive_local();
+        let blocks = self.db.find_bin_blocks(Some(location)).await?;
+        let blocked = |bin_no: &str| blocks.iter().any(|block| block.bin_no == bin_no && block.is_active(&now));
+
+        let mut conn = self.db.connect().await?;
+        let mut bins = Vec::new();
+        for (capacity, bin) in self.db.find_bin_capacities(location).await? {
+            if blocked(&bin.bin_no) {
+                continue;
+            }
+            let space = self.bin_space(&mut conn, capacity, bin).await?;
+            if space.available_space.is_positive() {
+                bins.push(space);
+            }
+        }
+        Ok(bins)
+    }
+
+    /// Start or change tracking a bin's capacity; returns the bin's space under the new capacity
+    pub async fn set_bin_capacity(&self, request: BinCapacityRequest) -> Result<BinSpace, PutawayError> {
+        let (location, bin_no) = (request.location.trim(), request.bin_no.trim());
+        if location.is_empty() || bin_no.is_empty() {
+            return Err(PutawayError::ValidationError("location and bin_no are required".to_string()));
+        }
+        if !request.capacity.is_positive() {
+            return Err(PutawayError::ValidationError("Capacity must be greater than 0".to_string()));
+        }
+        if request.unit == CapacityUnit::Pallets && !request.capacity.is_integer() {
+            return Err(PutawayError::ValidationError("A pallet capacity must be a whole number".to_string()));
+        }
+
+        let bin = self.db.find_bin(location, bin_no).await?.ok_or_else(|| PutawayError::InvalidBin {
+            bin_no: bin_no.to_string(),
+            location: location.to_string(),
+        })?;
+
+        let record = BinCapacityRecord {
+            location: bin.location.clone(),
+            bin_no: bin.bin_no.clone(),
+            unit: request.unit,
+            capacity: round_qty(&request.capacity, CAPACITY_DECIMALS),
+            rec_user_id: request.user_id,
+            rec_date: bangkok_now().naive_local(),
+        };
+        self.db.save_bin_capacity(&record).await?;
+
+        let mut conn = self.db.connect().await?;
+        self.bin_space(&mut conn, record, bin).await
+    }
+
+    /// Stop tracking a bin's capacity: it takes any amount again
+    pub async fn clear_bin_capacity(&self, location: &str, bin_no: &str) -> Result<(), PutawayError> {
+        let (location, bin_no) = (location.trim(), bin_no.trim());
+        if !self.db.delete_bin_capacity(location, bin_no).await? {
+            return Err(PutawayError::ValidationError(format!(
+                "Bin {bin_no} in location {location} has no capacity set"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Refuse a transfer that would take its destination bin past its capacity, unless the
+    /// request overrides it. `earlier` are the lines of the same document validated before this
+    /// one, already in stock UOM: their stock is not in LotMaster yet but lands with this line.
+    /// Checked again under the transaction by `recheck_bin_capacity`, which logs overrides.
+    pub(super) async fn check_bin_capacity(
+        &self,
+        conn: &mut R::Conn,
+        request: &BinTransferRequest,
+        transfer_qty: &BigDecimal,
+        earlier: &[BinTransferRequest],
+    ) -> Result<(), PutawayError> {
+        if request.override_capacity {
+            return Ok(());
+        }
+        let Some(capacity) = self.db.bin_capacity(conn, &request.location, &request.bin_to).await? else {
+            return Ok(());
+        };
+        let contents: Vec<LotMasterRecord> = self
+            .db
+            .find_bin_contents(&request.location, &request.bin_to)
+            .await?
+            .into_iter()
+            .map(|row| row.lot)
+            .collect();
+
+        let arriving: Vec<_> = earlier
+            .iter()
+            .filter(|line| line.bin_to == request.bin_to)
+            .map(|line| (line, &line.transfer_qty))
+            .chain([(request, transfer_qty)])
+            .map(|(line, qty)| (LotKey { lot_no: &line.lot_no, item_key: &line.item_key, location: &line.location }, qty))
+            .collect();
+        self.enforce_bin_capacity(conn, &capacity, &contents, &arriving, false, &request.user_id).await
+    }
+
+    /// Capacity check of one BT line under the writing transaction, after its LotMaster rows
+    /// are locked: the destination bin's rows are locked too, so two transfers into the same bin
+    /// cannot both pass against the same contents. Earlier lines of the document are already in
+    /// LotMaster by now.
+    pub(super) async fn recheck_bin_capacity(
+        &self,
+        conn: &mut R::Conn,
+        lot: LotKey<'_>,
+        bin_to: &str,
+        transfer_qty: &BigDecimal,
+        override_capacity: bool,
+        user_id: &str,
+    ) -> Result<(), PutawayError> {
+        let Some(capacity) = self.db.bin_capacity(conn, lot.location, bin_to).await? else {
+            return Ok(());
+        };
+        let contents = self.db.lock_bin_lot_rows(conn, lot.location, bin_to).await?;
+        self.enforce_bin_capacity(conn, &capacity, &contents, &[(lot, transfer_qty)], override_capacity, user_id)
+            .await
+    }
+
+    /// Compare a bin's `contents` plus the `arriving` lots against its capacity. Under the
+    /// pallet unit a lot already in the bin, or arriving twice, merges into one row.
+    async fn enforce_bin_capacity(
+        &self,
+        conn: &mut R::Conn,
+        capacity: &BinCapacityRecord,
+        contents: &[LotMasterRecord],
+        arriving: &[(LotKey<'_>, &BigDecimal)],
+        override_capacity: bool,
+        user_id: &str,
+    ) -> Result<(), PutawayError> {
+        let occupied = self.bin_load(conn, capacity.unit, contents).await?;
+
+        let mut incoming = BigDecimal::zero();
+        let mut new_lots: Vec<(&str, &str)> = Vec::new();
+        for (lot, qty) in arriving {
+            if capacity.unit == CapacityUnit::Pallets {
+                let key = (lot.lot_no, lot.item_key);
+                let merges = new_lots.contains(&key)
+                    || contents.iter().any(|row| row.lot_no == lot.lot_no && row.item_key == lot.item_key);
+                if merges {
+                    continue;
+                }
+                new_lots.push(key);
+            }
+            incoming += self.unit_load(conn, capacity.unit, lot.item_key, qty).await?;
+        }
+
+        if &occupied + &incoming <= capacity.capacity {
+            return Ok(());
+        }
+        if override_capacity {
+            tracing::warn!(
+                bin = %capacity.bin_no,
+                location = %capacity.location,
+                user = %user_id,
+                "Bin capacity overridden: {occupied} + {incoming} of {} {}",
+                capacity.capacity,
+                capacity.unit.label()
+            );
+            return Ok(());
+        }
+
+        Err(PutawayError::BinCapacityExceeded(Box::new(CapacityExcess {
+            bin_no: capacity.bin_no.clone(),
+            location: capacity.location.clone(),
+            unit: capacity.unit,
+            capacity: capacity.capacity.clone(),
+            occupied: round_qty(&occupied, CAPACITY_DECIMALS),
+            incoming: round_qty(&incoming, CAPACITY_DECIMALS),
+        })))
+    }
+
+    async fn bin_space(
+        &self,
+        conn: &mut R::Conn,
+        capacity: BinCapacityRecord,
+        bin: BinMasterRecord,
+    ) -> Result<BinSpace, PutawayError> {
+        let contents: Vec<LotMasterRecord> = self
+            .db
+            .find_bin_contents(&capacity.location, &capacity.bin_no)
+            .await?
+            .into_iter()
+            .map(|row| row.lot)
+            .collect();
+        let occupied = round_qty(&self.bin_load(conn, capacity.unit, &contents).await?, CAPACITY_DECIMALS);
+        let available_space = (&capacity.capacity - &occupied).max(BigDecimal::zero());
+
+        Ok(BinSpace {
+            bin_no: bin.bin_no,
+            location: bin.location,
+            description: bin.description,
+            unit: capacity.unit,
+            capacity: capacity.capacity,
+            occupied,
+            available_space,
+            lot_count: contents.len(),
+        })
+    }
+
+    /// What the lot rows in a bin take up, in the capacity unit
+    async fn bin_load(
+        &self,
+        conn: &mut R::Conn,
+        unit: CapacityUnit,
+        contents: &[LotMasterRecord],
+    ) -> Result<BigDecimal, PutawayError> {
+        let mut load = BigDecimal::zero();
+        for row in contents {
+            load += self.unit_load(conn, unit, &row.item_key, &row.qty_on_hand).await?;
+        }
+        Ok(load)
+    }
+
+    /// What one lot row of a stock quantity takes up: a pallet, its weight or the quantity itself
+    async fn unit_load(
+        &self,
+        conn: &mut R::Conn,
+        unit: CapacityUnit,
+        item_key: &str,
+        qty: &BigDecimal,
+    ) -> Result<BigDecimal, PutawayError> {
+        match unit {
+            CapacityUnit::Pallets => Ok(BigDecimal::from(1)),
+            CapacityUnit::Quantity => Ok(qty.clone()),
+            CapacityUnit::Weight => self.weight_kg(conn, item_key, qty).await,
+        }
+    }
+
+    /// Stock quantity of an item in KG, through INQTYCNV unless the item is stocked in KG
+    async fn weight_kg(&self, conn: &mut R::Conn, item_key: &str, qty: &BigDecimal) -> Result<BigDecimal, PutawayError> {
+        let item = self.db.item_master(conn, item_key).await?.ok_or_else(|| {
+            PutawayError::ValidationError(format!("Item {item_key} not found in INMAST"))
+        })?;
+        let stock_uom = item.stock_uom_code.trim();
+        if stock_uom.eq_ignore_ascii_case(CAPACITY_WEIGHT_UOM) {
+            return Ok(qty.clone());
+        }
+
+        if let Some(conversion) = self.db.uom_conversion(conn, item_key, stock_uom, CAPACITY_WEIGHT_UOM).await? {
+            if conversion.conv_factor.is_positive() {
+                return Ok(conversion.convert(qty));
+            }
+        }
+        if let Some(conversion) = self.db.uom_conversion(conn, item_key, CAPACITY_WEIGHT_UOM, stock_uom).await? {
+            if conversion.conv_factor.is_positive() {
+                return Ok(conversion.convert_back(qty));
+            }
+        }
+        Err(PutawayError::ValidationError(format!(
+            "No conversion from {stock_uom} to {CAPACITY_WEIGHT_UOM} for item {item_key}: its weight in the bin is unknown"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::memory_db::{pending_issue, qty, transfer, warehouse, MemoryPutawayDatabase, LOT};
+    use crate::models::putaway_models::{BatchTransferRequest, CommittedTransferRequest};
+    use std::convert::identity;
+
+    fn capacity(bin_no: &str, unit: CapacityUnit, capacity: &str) -> BinCapacityRecord {
+        BinCapacityRecord {
+            location: "TFC1".to_string(),
+            bin_no: bin_no.to_string(),
+            unit,
+            capacity: qty(capacity),
+            rec_user_id: "supervisor".to_string(),
+            rec_date: bangkok_now().naive_local(),
+        }
+    }
+
+    const SALT: LotKey<'static> = LotKey { lot_no: "2600233-4", item_key: "INSA0102", location: "TFC1" };
+
+    #[tokio::test]
+    async fn test_available_bins_report_remaining_space() {
+        // Salt Fine comes in 25 KG bags; K0802-4B holds 300 KG and K0803-1A one pallet
+        let mut tables = warehouse("WHKON1");
+        tables.add_item(SALT.item_key, "Salt Fine", "BAG", SALT.location);
+        tables.add_uom_conversion("BAG", "KG", qty("25"));
+        tables.add_bin("TFC1", "K0901-1A");
+        tables.add_lot(SALT.lot_no, SALT.item_key, SALT.location, "WHKON1", qty("20"));
+        tables.add_lot("2600188-2", "INBC1403", "TFC1", "K0802-4B", qty("300"));
+        tables.add_lot("2600301-1", "INSA0102", "TFC1", "K0803-1A", qty("8"));
+        tables.bin_capacities = vec![
+            capacity("K0802-4B", CapacityUnit::Weight, "500"),
+            capacity("K0803-1A", CapacityUnit::Pallets, "2"),
+            capacity("K0901-1A", CapacityUnit::Quantity, "100"),
+        ];
+        let service = PutawayService::with_repository(MemoryPutawayDatabase::new(tables));
+
+        let bins = service.get_available_bins("TFC1").await.unwrap();
+        let space: Vec<_> = bins
+            .iter()
+            .map(|bin| (bin.bin_no.as_str(), bin.unit, bin.occupied.clone(), bin.available_space.clone()))
+            .collect();
+        assert_eq!(
+            space,
+            [
+                ("K0802-4B", CapacityUnit::Weight, qty("300"), qty("200")),
+                ("K0803-1A", CapacityUnit::Pallets, qty("1"), qty("1")),
+                ("K0901-1A", CapacityUnit::Quantity, qty("0"), qty("100")),
+            ]
+        );
+
+        // 8 bags of 25 KG fill the rest of the weight capacity
+        service.execute_transfer(transfer(SALT, "WHKON1", "K0802-4B", "8")).await.unwrap();
+        let bins = service.get_available_bins("TFC1").await.unwrap();
+        assert!(bins.iter().all(|bin| bin.bin_no != "K0802-4B"), "{bins:?}");
+    }
+
+    #[tokio::test]
+    async fn test_transfers_that_overfill_a_bin_are_refused_unless_overridden() {
+        let mut tables = warehouse("WHKON1");
+        tables.add_item(SALT.item_key, "Salt Fine", "BAG", SALT.location);
+        tables.add_uom_conversion("BAG", "KG", qty("25"));
+        tables.add_bin("TFC1", "K0901-1A");
+        tables.add_lot(SALT.lot_no, SALT.item_key, SALT.location, "WHKON1", qty("20"));
+        tables.add_lot("2600188-2", "INBC1403", "TFC1", "K0802-4B", qty("300"));
+        tables.add_lot("2600301-1", "INSA0102", "TFC1", "K0803-1A", qty("8"));
+        tables.bin_capacities = vec![
+            capacity("K0802-4B", CapacityUnit::Weight, "500"),
+            capacity("K0803-1A", CapacityUnit::Pallets, "2"),
+            capacity("K0901-1A", CapacityUnit::Quantity, "100"),
+        ];
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+
+        // 9 bags = 225 KG on top of 300 KG
+        let overfill = service.execute_transfer(transfer(SALT, "WHKON1", "K0802-4B", "9")).await;
+        match overfill {
+            Err(PutawayError::BinCapacityExceeded(excess)) => {
+                assert_eq!((excess.unit, excess.occupied, excess.incoming), (CapacityUnit::Weight, qty("300"), qty("225")));
+            }
+            other => panic!("expected the weight capacity to refuse the transfer, got {other:?}"),
+        }
+        // Checked again when the line is written, not only at validation
+        let unvalidated = service
+            .execute_bin_transfer_transaction("2600233-4", "INSA0102", "TFC1", "WHKON1", "K0802-4B", &qty("9"), "deachawat", "", "", false, None, identity)
+            .await;
+        assert!(matches!(unvalidated, Err(PutawayError::BinCapacityExceeded(_))), "{unvalidated:?}");
+
+        // A second lot fits the pallet bin, a third would not; a lot already there merges
+        let batch = |lines| BatchTransferRequest { lines };
+        let refused = service
+            .execute_batch_transfer(batch(vec![
+                transfer(LOT, "WHKON1", "K0803-1A", "10"),
+                transfer(SALT, "WHKON1", "K0803-1A", "5"),
+            ]))
+            .await;
+        match refused {
+            Err(PutawayError::BinCapacityExceeded(excess)) => {
+                assert_eq!((excess.unit, excess.occupied, excess.incoming), (CapacityUnit::Pallets, qty("1"), qty("2")));
+            }
+            other => panic!("expected the pallet capacity to refuse the batch, got {other:?}"),
+        }
+        service.execute_transfer(transfer(LOT, "WHKON1", "K0803-1A", "10")).await.unwrap();
+        service.execute_transfer(transfer(LOT, "WHKON1", "K0803-1A", "5")).await.unwrap();
+
+        let quantity = service.execute_transfer(transfer(LOT, "WHKON1", "K0901-1A", "101")).await;
+        assert!(matches!(quantity, Err(PutawayError::BinCapacityExceeded(_))));
+        let overridden = service
+            .execute_transfer(BinTransferRequest { override_capacity: true, ..transfer(LOT, "WHKON1", "K0901-1A", "101") })
+            .await
+            .unwrap();
+        assert!(overridden.success);
+        assert_eq!(db.tables().await.lot(LOT, "K0901-1A").unwrap().qty_on_hand, qty("101"));
+
+        // Committed stock moves under the same capacity: 9 committed bags of salt into K0802-4B
+        let mut tables = warehouse("K0802-4B");
+        tables.lot_master[0].qty_on_hand = qty("300");
+        tables.add_item(SALT.item_key, "Salt Fine", "BAG", SALT.location);
+        tables.add_uom_conversion("BAG", "KG", qty("25"));
+        tables.add_lot(SALT.lot_no, SALT.item_key, SALT.location, "WHKON1", qty("20"));
+        tables.lot_master[1].qty_commit_sales = qty("9");
+        tables.lot_transactions.push(pending_issue(41, SALT, "WHKON1", "9"));
+        tables.bin_capacities = vec![capacity("K0802-4B", CapacityUnit::Weight, "500")];
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+        let committed = |override_capacity| CommittedTransferRequest {
+            lot_no: SALT.lot_no.to_string(),
+            item_key: SALT.item_key.to_string(),
+            location: SALT.location.to_string(),
+            source_bin: "WHKON1".to_string(),
+            to_bin: "K0802-4B".to_string(),
+            transfer_qty: qty("9"),
+            user_id: "deachawat".to_string(),
+            remarks: None,
+            referenced: None,
+            lot_tran_nos: vec![41],
+            override_capacity,
+        };
+        let refused = service.execute_committed_transfer(committed(false)).await;
+        assert!(matches!(refused, Err(PutawayError::BinCapacityExceeded(_))), "{refused:?}");
+        assert!(db.tables().await.lot(SALT, "K0802-4B").is_none());
+        assert!(service.execute_committed_transfer(committed(true)).await.unwrap().success);
+        assert_eq!(db.tables().await.lot(SALT, "K0802-4B").unwrap().qty_commit_sales, qty("9"));
+    }
+}
@@ -0,0 +1,214 @@
+use crate::database::repository::{BinCandidateExclusions, BinCandidateRecord, LotKey, PutawayRepository};
+use crate::models::putaway_models::{BinMasterRecord, BinSuggestion, BinSuggestionReason, BinSuggestionResponse, PutawayError};
+use crate::services::putaway_service::{PutawayService, SuggestionRules};
+use crate::utils::bangkok_now;
+use bigdecimal::Signed;
+
+/// Default and largest number of suggestions returned
+const DEFAULT_SUGGESTION_LIMIT: usize = 10;
+const MAX_SUGGESTION_LIMIT: usize = 50;
+
+/// The lot already sits in the bin: moving there consolidates it
+const SAME_LOT_POINTS: i32 = 100;
+/// Other lots of the item sit in the bin
+const SAME_ITEM_POINTS: i32 = 60;
+/// Nothing in the bin yet
+const EMPTY_BIN_POINTS: i32 = 20;
+/// Empty bins near the source bin: same aisle, then same row and rack within it
+const SAME_AISLE_POINTS: i32 = 15;
+const SAME_ROW_POINTS: i32 = 10;
+const SAME_RACK_POINTS: i32 = 5;
+
+/// Destination bin suggestions: candidates from the lot's location scored for consolidation
+/// and for empty space near the source bin
+impl<R: PutawayRepository> PutawayService<R> {
+    pub async fn suggest_destination_bins(
+        &self,
+        lot: LotKey<'_>,
+        bin_from: Option<&str>,
+        limit: Option<usize>,
+        receiving_bins: &[String],
+        rules: &SuggestionRules,
+    ) -> Result<BinSuggestionResponse, PutawayError> {
+        let lot = LotKey { lot_no: lot.lot_no.trim(), item_key: lot.item_key.trim(), location: lot.location.trim() };
+        if lot.lot_no.is_empty() || lot.item_key.is_empty() || lot.location.is_empty() {
+            return Err(PutawayError::ValidationError("lot_no, item_key and location are required".to_string()));
+        }
+        let bin_from = bin_from.map(str::trim).filter(|bin| !bin.is_empty());
+        let limit = limit.unwrap_or(DEFAULT_SUGGESTION_LIMIT).clamp(1, MAX_SUGGESTION_LIMIT);
+
+        let source = match bin_from {
+            Some(bin_no) => Some(self.db.find_bin(lot.location, bin_no).await?.ok_or_else(|| {
+                PutawayError::InvalidBin { bin_no: bin_no.to_string(), location: lot.location.to_string() }
+            })?),
+            None => None,
+        };
+        // Receiving bins and the source bin are never destinations either
+        let mut excluded_bins = rules.excluded_bins.clone();
+        excluded_bins.extend(receiving_bins.iter().cloned());
+        excluded_bins.extend(bin_from.map(str::to_string));
+        let exclusions = BinCandidateExclusions {
+            bins: &excluded_bins,
+            aisles: &rules.excluded_aisles,
+            now: bangkok_now().naive_local(),
+        };
+        let candidates = self.db.bin_candidates(lot, exclusions).await?;
+
+        Ok(BinSuggestionResponse {
+            lot_no: lot.lot_no.to_string(),
+            item_key: lot.item_key.to_string(),
+            location: lot.location.to_string(),
+            bin_from: bin_from.map(str::to_string),
+            suggestions: rank_candidates(candidates, lot, source.as_ref(), limit),
+        })
+    }
+}
+
+/// Score every candidate and keep the best `limit`
+fn rank_candidates(
+    candidates: Vec<BinCandidateRecord>,
+    lot: LotKey<'_>,
+    source: Option<&BinMasterRecord>,
+    limit: usize,
+) -> Vec<BinSuggestion> {
+    let mut suggestions: Vec<BinSuggestion> = candidates
+        .into_iter()
+        .map(|candidate| {
+            let reasons = score(&candidate, lot, source);
+            BinSuggestion {
+                bin_no: candidate.bin.bin_no,
+                location: candidate.bin.location,
+                description: candidate.bin.description,
+                aisle: candidate.bin.aisle,
+                row: candidate.bin.row,
+                rack: candidate.bin.rack,
+                lot_status: candidate.lot_status,
+                score: reasons.iter().map(|reason| reason.points).sum(),
+                reasons,
+            }
+        })
+        .collect();
+
+    suggestions.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.bin_no.cmp(&b.bin_no)));
+    suggestions.truncate(limit);
+    suggestions
+}
+
+fn score(candidate: &BinCandidateRecord, lot: LotKey<'_>, source: Option<&BinMasterRecord>) -> Vec<BinSuggestionReason> {
+    let mut reasons = Vec::new();
+    let mut add = |points: i32, reason: String| reasons.push(BinSuggestionReason { points, reason });
+
+    if candidate.lot_qty_on_hand.is_positive() {
+        add(SAME_LOT_POINTS, format!("Already holds lot {} (consolidation)", lot.lot_no));
+    }
+    if candidate.item_lots > 0 {
+        add(SAME_ITEM_POINTS, format!("Holds {} other lot(s) of item {}", candidate.item_lots, lot.item_key));
+    }
+
+    if candidate.stocked_lots == 0 {
+        add(EMPTY_BIN_POINTS, "Empty bin".to_string());
+
+        // Zone fields are free text in BINMaster; blank ones never match
+        let same = |a: &str, b: &str| !a.trim().is_empty() && a.trim() == b.trim();
+        if let Some(source) = source {
+            let bin = &candidate.bin;
+            if same(&bin.aisle, &source.aisle) {
+                add(SAME_AISLE_POINTS, format!("Same aisle as {} ({})", source.bin_no, source.aisle.trim()));
+                if same(&bin.row, &source.row) {
+                    add(SAME_ROW_POINTS, format!("Same row ({})", source.row.trim()));
+                    if same(&bin.rack, &source.rack) {
+                        add(SAME_RACK_POINTS, format!("Same rack ({})", source.rack.trim()));
+                    }
+                }
+            }
+        }
+    }
+
+    reasons
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::memory_db::{qty, warehouse, MemoryPutawayDatabase, MemoryTables, LOT};
+    use crate::models::putaway_models::BinBlockRecord;
+
+    /// Gives a bin its aisle, row and rack, adding it when the shared warehouse has none
+    fn zone(tables: &mut MemoryTables, bin_no: &str, aisle: &str, row: &str, rack: &str) {
+        if !tables.bins.iter().any(|bin| bin.bin_no == bin_no) {
+            tables.add_bin("TFC1", bin_no);
+        }
+        let bin = tables.bins.iter_mut().find(|bin| bin.bin_no == bin_no).unwrap();
+        (bin.aisle, bin.row, bin.rack) = (aisle.to_string(), row.to_string(), rack.to_string());
+    }
+
+    /// Lays bins out in aisles K08 and K09
+    fn zones(tables: &mut MemoryTables) {
+        zone(tables, "K0802-4B", "K08", "02", "4");
+        zone(tables, "K0802-4C", "K08", "02", "4");
+        zone(tables, "K0803-1A", "K08", "03", "1");
+        zone(tables, "K0901-1A", "K09", "01", "1");
+        zone(tables, "K0902-1A", "K09", "02", "1");
+        zone(tables, "K0903-1A", "K09", "03", "1");
+    }
+
+    #[tokio::test]
+    async fn test_suggestions_rank_consolidation_then_nearby_empty_bins() {
+        // The lot is in K0802-4B and K0901-1A, another lot of the item in K0902-1A, only Salt Fine in K0903-1A
+        let mut tables = warehouse("K0802-4B");
+        tables.add_item("INSA0102", "Salt Fine", "BAG", "TFC1");
+        zones(&mut tables);
+        tables.add_lot("2600107-1", "INBC1403", "TFC1", "K0901-1A", qty("40"));
+        tables.add_lot("2600188-2", "INBC1403", "TFC1", "K0902-1A", qty("75"));
+        tables.add_lot("2600233-4", "INSA0102", "TFC1", "K0903-1A", qty("12"));
+        let service = PutawayService::with_repository(MemoryPutawayDatabase::new(tables));
+        let receiving_bins = ["WHKON1".to_string()];
+
+        let response = service.suggest_destination_bins(LOT, Some("K0802-4B"), None, &receiving_bins, &SuggestionRules::default()).await.unwrap();
+        let ranked: Vec<_> = response.suggestions.iter().map(|bin| (bin.bin_no.as_str(), bin.score)).collect();
+        // Source and receiving bins and the bin holding only another item are never suggested
+        assert_eq!(ranked, [("K0901-1A", 100), ("K0902-1A", 60), ("K0802-4C", 50), ("K0803-1A", 35)]);
+        let reasons: Vec<_> = response.suggestions[2].reasons.iter().map(|reason| reason.points).collect();
+        assert_eq!(reasons, [EMPTY_BIN_POINTS, SAME_AISLE_POINTS, SAME_ROW_POINTS, SAME_RACK_POINTS]);
+        assert_eq!(response.suggestions[0].lot_status.as_deref(), Some("P"));
+
+        let top = service.suggest_destination_bins(LOT, None, Some(1), &receiving_bins, &SuggestionRules::default()).await.unwrap();
+        assert_eq!(top.suggestions.len(), 1);
+        let unknown_source = service.suggest_destination_bins(LOT, Some("NOPE"), None, &receiving_bins, &SuggestionRules::default()).await;
+        assert!(matches!(unknown_source, Err(PutawayError::InvalidBin { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_suggestion_rules_exclude_bins_and_aisles() {
+        let mut tables = warehouse("K0802-4B");
+        tables.add_item("INSA0102", "Salt Fine", "BAG", "TFC1");
+        zones(&mut tables);
+        tables.add_lot("2600107-1", "INBC1403", "TFC1", "K0901-1A", qty("40"));
+        tables.add_lot("2600233-4", "INSA0102", "TFC1", "K0903-1A", qty("12"));
+        let service = PutawayService::with_repository(MemoryPutawayDatabase::new(tables.clone()));
+        let rules = SuggestionRules { excluded_bins: vec!["K0901*".to_string()], excluded_aisles: vec!["K08".to_string()] };
+        let receiving_bins = ["K0902-1A".to_string(), "WHKON1".to_string()];
+
+        let response = service.suggest_destination_bins(LOT, None, None, &receiving_bins, &rules).await.unwrap();
+        assert!(response.suggestions.is_empty(), "{:?}", response.suggestions);
+
+        // The repository leaves them out, with bins blocked now and bins nothing speaks for
+        // (K0903-1A holds only another item); K0803-1A's block has lapsed
+        let now = bangkok_now().naive_local();
+        for (bin_no, blocked_until) in [("K0802-4C", None), ("K0803-1A", Some(now - chrono::Duration::minutes(1)))] {
+            tables.bin_blocks.push(BinBlockRecord {
+                location: "TFC1".to_string(),
+                bin_no: bin_no.to_string(),
+                reason: "Damaged racking".to_string(),
+                blocked_until,
+                rec_user_id: "supervisor".to_string(),
+                rec_date: now,
+            });
+        }
+        let db = MemoryPutawayDatabase::new(tables);
+        let exclusions = BinCandidateExclusions { bins: &rules.excluded_bins, aisles: &[], now };
+        let candidates = db.bin_candidates(LOT, exclusions).await.unwrap();
+        let bins: Vec<_> = candidates.iter().map(|candidate| candidate.bin.bin_no.as_str()).collect();
+        assert_eq!(bins, ["K0802-4B", "K0803-1A", "WHKON1", "K0902-1A"]);
+    }
+}
@@ -0,0 +1,1401 @@
+use crate::constants::DEFAULT_QTY_DECIMALS;
+use crate::database::idempotency_db::IdempotencyClaim;
+use crate::database::repository::{LotKey, PutawayRepository};
+use crate::models::putaway_models::{
+    bt_document_no, map_inclasskey_to_inacct, BinTransferRecord, BinTransferRequest, InventoryLockReason,
+    LotMasterRecord, LotSnapshotRecord, LotTransactionRecord, MintxdhRecord, MovedCommitmentLine, PutawayError,
+    ReversedTransferLine,
+};
+use crate::services::putaway_service::PutawayService;
+use crate::utils::{bangkok_now, truncate_user_id};
+use crate::utils::quantity::round_qty;
+use bigdecimal::{BigDecimal, Signed, Zero};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Largest UMQtyDP honoured - quantities are read from LotMaster as DECIMAL(28,10)
+const MAX_QTY_DECIMALS: i64 = 10;
+
+/// (QtyOnHand, QtyCommitSales) of a bin's LotMaster row before and after a BT line
+struct BinQtyChange {
+    /// LotTransaction row that moved the bin: the issue on FROM, the receipt on TO
+    lot_tran_no: i32,
+    before: (BigDecimal, BigDecimal),
+    after: (BigDecimal, BigDecimal),
+}
+
+/// One movement of a BT document, written by `write_bin_transfer_line`
+struct TransferLine<'a> {
+    lot: LotKey<'a>,
+    line_no: i16,
+    bin_from: &'a str,
+    bin_to: &'a str,
+    transfer_qty: &'a BigDecimal,
+    /// BinTransfer.User1
+    remarks: &'a str,
+    /// BinTransfer.User5
+    referenced: &'a str,
+    /// Move even if it takes `bin_to` past its capacity (logged)
+    override_capacity: bool,
+}
+
+/// Bin transfer flows (BME "Transfer Avail Qty", "Transfer with Commit" and reversals).
+/// Every row is built here and written through the repository, so the SQL Server and
+/// in-memory implementations receive exactly the same LotTransaction/LotMaster/BinTransfer rows.
+impl<R: PutawayRepository> PutawayService<R> {
+    /// Execute complete bin transfer transaction with lot consolidation.
+    /// `respond` turns the document number and the (source, destination) LotStatus into the result;
+    /// with a `claim` it is stored against the Idempotency-Key before the transaction commits.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) async fn execute_bin_transfer_transaction<T: Serialize>(
+        &self,
+        lot_no: &str,
+        item_key: &str,
+        location: &str,
+        bin_from: &str,
+        bin_to: &str,
+        transfer_qty: &BigDecimal,
+        user_id: &str,
+        remarks: &str,
+        referenced: &str,
+        override_capacity: bool,
+        claim: Option<&IdempotencyClaim>,
+        respond: impl FnOnce((String, Option<String>, Option<String>)) -> T,
+    ) -> Result<T, PutawayError> {
+        let lot = LotKey { lot_no, item_key, location };
+
+        // **🔒 BEGIN TRANSACTION** - Ensure atomic 6-step putaway operation (REPEATABLE READ)
+        let mut conn = self.db.begin().await?;
+
+        let transaction_result: Result<T, PutawayError> = async {
+            // 0. Re-check the freeze/count flags inside the transaction: one set since validation wins
+            self.check_inventory_locks(&mut conn, item_key, location).await?;
+
+            // 1. Get next BT document number
+            let bt_number = self.db.next_bt_sequence(&mut conn).await?;
+            let document_no = bt_document_no(bt_number);
+            let now = bangkok_now().naive_local();
+
+            // 2. Create Mintxdh record for audit trail
+            let in_trans_id = self
+                .insert_bt_mintxdh_header(&mut conn, &document_no, item_key, location, truncate_user_id(user_id), &now)
+                .await?;
+
+            // 3-6. Issue/receipt LotTransaction, BinTransfer and LotMaster consolidation as line 1
+            let line = TransferLine { lot, line_no: 1, bin_from, bin_to, transfer_qty, remarks, referenced, override_capacity };
+            self.write_bin_transfer_line(&mut conn, &document_no, in_trans_id, &line, user_id, &now).await?;
+
+            // Source row may have been deleted by a full transfer
+            let source_status = self.lot_status(&mut conn, lot, bin_from).await;
+            let dest_status = self.lot_status(&mut conn, lot, bin_to).await;
+
+            let response = respond((document_no.clone(), source_status, dest_status));
+            self.complete_idempotency_claim(&mut conn, claim, &document_no, &response).await?;
+
+            Ok(response)
+        }
+        .await;
+
+        // **🔒 COMMIT or ROLLBACK** - Atomic transaction handling
+        match transaction_result {
+            Ok(response) => {
+                self.db.commit(&mut conn).await?;
+                Ok(response)
+            }
+            Err(e) => {
+                self.db.rollback(&mut conn).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Execute a multi-line bin transfer: one BT number, one Mintxdh header and one
+    /// LotTransaction pair per line (IssueDocLineNo/ReceiptDocLineNo 1..N). All or nothing.
+    /// Quantities must already be validated/corrected per line.
+    /// Returns the document number and (source, destination) LotStatus per line.
+    pub(super) async fn execute_batch_bin_transfer(
+        &self,
+        lines: &[BinTransferRequest],
+    ) -> Result<(String, Vec<(Option<String>, Option<String>)>), PutawayError> {
+        let first = lines
+            .first()
+            .ok_or_else(|| PutawayError::ValidationError("Batch transfer has no lines".to_string()))?;
+
+        let mut conn = self.db.begin().await?;
+
+        let transaction_result: Result<String, PutawayError> = async {
+            // Re-check the freeze/count flags inside the transaction for every item on the document
+            let mut checked_items: HashSet<&str> = HashSet::new();
+            for line in lines {
+                if checked_items.insert(line.item_key.as_str()) {
+                    self.check_inventory_locks(&mut conn, &line.item_key, &line.location).await?;
+                }
+            }
+
+            let bt_number = self.db.next_bt_sequence(&mut conn).await?;
+            let document_no = bt_document_no(bt_number);
+            let now = bangkok_now().naive_local();
+
+            // One header per document, keyed on the first line (BME does the same for multi-lot BTs)
+            let in_trans_id = self
+                .insert_bt_mintxdh_header(
+                    &mut conn, &document_no, &first.item_key, &first.location, truncate_user_id(&first.user_id), &now,
+                )
+                .await?;
+
+            for (index, line) in lines.iter().enumerate() {
+                let line_no = i16::try_from(index + 1)
+                    .map_err(|_| PutawayError::ValidationError("Too many lines on one BT document".to_string()))?;
+
+                let transfer_line = TransferLine {
+                    lot: LotKey { lot_no: &line.lot_no, item_key: &line.item_key, location: &line.location },
+                    line_no,
+                    bin_from: &line.bin_from,
+                    bin_to: &line.bin_to,
+                    transfer_qty: &line.transfer_qty,
+                    remarks: line.remarks.as_deref().unwrap_or(""),
+                    referenced: line.referenced.as_deref().unwrap_or(""),
+                    override_capacity: line.override_capacity,
+                };
+                self.write_bin_transfer_line(&mut conn, &document_no, in_trans_id, &transfer_line, &line.user_id, &now)
+                    .await
+                    .map_err(|e| match e {
+                        PutawayError::ValidationError(msg) => {
+                            PutawayError::ValidationError(format!("Line {line_no} (lot {}): {msg}", line.lot_no))
+                        }
+                        other => other,
+                    })?;
+            }
+
+            Ok(document_no)
+        }
+        .await;
+
+        match transaction_result {
+            Ok(doc_no) => {
+                self.db.commit(&mut conn).await?;
+
+                let mut statuses = Vec::with_capacity(lines.len());
+                for line in lines {
+                    let lot = LotKey { lot_no: &line.lot_no, item_key: &line.item_key, location: &line.location };
+                    let source_status = self.lot_status(&mut conn, lot, &line.bin_from).await;
+                    let dest_status = self.lot_status(&mut conn, lot, &line.bin_to).await;
+                    statuses.push((source_status, dest_status));
+                }
+
+                Ok((doc_no, statuses))
+            }
+            Err(e) => {
+                self.db.rollback(&mut conn).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Reverse a standard BT document: post the inverse of every line under a new BT document
+    /// (User1 = reason, User5 = original document) and record the pair in putaway_bt_reversals.
+    /// Refuses committed-transfer documents, documents already reversed or themselves reversals,
+    /// lines whose destination stock has since been consumed and lines whose original source bin is blocked.
+    pub(super) async fn execute_bt_reversal(
+        &self,
+        original_document_no: &str,
+        user_id: &str,
+        reason: &str,
+    ) -> Result<(String, Vec<ReversedTransferLine>), PutawayError> {
+        let mut conn = self.db.begin().await?;
+
+        let transaction_result: Result<(String, Vec<ReversedTransferLine>), PutawayError> = async {
+            // 1. One reversal per document, and no reversing a reversal
+            if let Some((original, reversal)) = self.db.find_bt_reversal(&mut conn, original_document_no).await? {
+                return Err(PutawayError::ValidationError(if original == original_document_no {
+                    format!("{original_document_no} was already reversed by {reversal}")
+                } else {
+                    format!("{original_document_no} is the reversal of {original} and cannot be reversed")
+                }));
+            }
+
+            // 2. The Mintxdh header must exist
+            if !self.db.mintxdh_exists(&mut conn, original_document_no).await? {
+                return Err(PutawayError::DocumentNotFound { document_no: original_document_no.to_string() });
+            }
+
+            // 3. Issue (9) / receipt (8) pairs, matched on the document line number
+            let document_lines = self.db.bt_document_lines(&mut conn, original_document_no).await?;
+            if document_lines.is_empty() {
+                return Err(PutawayError::DocumentNotFound { document_no: original_document_no.to_string() });
+            }
+
+            let mut lines = Vec::with_capacity(document_lines.len());
+            for line in &document_lines {
+                if line.processed != "Y" {
+                    return Err(PutawayError::ValidationError(format!(
+                        "{original_document_no} is a committed transfer and cannot be reversed"
+                    )));
+                }
+                if line.bin_transfer_count == 0 {
+                    return Err(PutawayError::ValidationError(format!(
+                        "{original_document_no} line {} has no BinTransfer record",
+                        line.line_no
+                    )));
+                }
+                lines.push(ReversedTransferLine {
+                    line_no: line.line_no,
+                    lot_no: line.lot_no.clone(),
+                    item_key: line.item_key.clone(),
+                    bin_from: line.bin_to.clone(),
+                    bin_to: line.bin_from.clone(),
+                    qty: line.qty.clone(),
+                    source_row_recreated: false,
+                });
+            }
+            let location = document_lines[0].location.clone();
+
+            // 4. Same freeze/count rules as a forward transfer
+            let mut checked_items: HashSet<&str> = HashSet::new();
+            for line in &lines {
+                if checked_items.insert(line.item_key.as_str()) {
+                    self.check_inventory_locks(&mut conn, &line.item_key, &location).await?;
+                }
+            }
+
+            // 5. The moved stock must still be sitting (uncommitted) in the destination bin
+            for line in lines.iter_mut() {
+                let lot = LotKey { lot_no: &line.lot_no, item_key: &line.item_key, location: &location };
+                let decimals = self.qty_decimals(&mut conn, &line.item_key).await?;
+                line.qty = round_qty(&line.qty, decimals);
+
+                let available = self
+                    .db
+                    .lock_lot_rows(&mut conn, lot, &[line.bin_from.as_str()])
+                    .await?
+                    .into_iter()
+                    .next()
+                    .map(|row| {
+                        let row = row.at_qty_scale(decimals);
+                        row.qty_on_hand - row.qty_commit_sales
+                    })
+                    .unwrap_or_default();
+                if available < line.qty {
+                    return Err(PutawayError::ValidationError(format!(
+                        "Lot {} in bin {} has since been consumed or committed (available {available}, needed {})",
+                        line.lot_no, line.bin_from, line.qty
+                    )));
+                }
+
+                let source_rows = self.db.lock_lot_rows(&mut conn, lot, &[line.bin_to.as_str()]).await?;
+                line.source_row_recreated = source_rows.is_empty();
+
+                // The original source bin receives the stock back: a bin blocked since takes nothing
+                self.check_bin_not_blocked(&mut conn, &location, &line.bin_to).await?;
+            }
+
+            // 6. Inverse movements under a new BT document
+            let bt_number = self.db.next_bt_sequence(&mut conn).await?;
+            let document_no = bt_document_no(bt_number);
+            let now = bangkok_now().naive_local();
+            let user_id_truncated = truncate_user_id(user_id);
+
+            let in_trans_id = self
+                .insert_bt_mintxdh_header(&mut conn, &document_no, &lines[0].item_key, &location, user_id_truncated, &now)
+                .await?;
+
+            for line in &lines {
+                // handle_lot_consolidation recreates a deleted source row from the destination row's details
+                let transfer_line = TransferLine {
+                    lot: LotKey { lot_no: &line.lot_no, item_key: &line.item_key, location: &location },
+                    line_no: line.line_no,
+                    bin_from: &line.bin_from,
+                    bin_to: &line.bin_to,
+                    transfer_qty: &line.qty,
+                    remarks: reason,
+                    referenced: original_document_no,
+                    // Stock goes back where it came from: a bin filled since is logged, not a reason to refuse
+                    override_capacity: true,
+                };
+                self.write_bin_transfer_line(&mut conn, &document_no, in_trans_id, &transfer_line, user_id, &now)
+                    .await?;
+            }
+
+            // 7. Link the two documents
+            self.db
+                .insert_bt_reversal(&mut conn, original_document_no, &document_no, reason, user_id_truncated, &now)
+                .await?;
+
+            tracing::info!(
+                original_document_no,
+                reversal_document_no = %document_no,
+                lines = lines.len(),
+                "Reversed bin transfer document"
+            );
+
+            Ok((document_no, lines))
+        }
+        .await;
+
+        match transaction_result {
+            Ok(result) => {
+                self.db.commit(&mut conn).await?;
+                Ok(result)
+            }
+            Err(e) => {
+                self.db.rollback(&mut conn).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Execute transfer of committed stock (BME official behavior)
+    ///
+    /// This function moves physical inventory ALONG WITH its commitment status:
+    /// 1. Creates LotTransaction records: Type 9 (Issue) from source, Type 8 (Receipt) to dest
+    /// 2. Reduces source LotMaster: QtyOnHand -= transfer_qty, QtyCommitSales -= transfer_qty
+    /// 3. Deletes source LotMaster if QtyOnHand becomes 0
+    /// 4. Creates or updates destination LotMaster with transferred quantities
+    /// 5. Destination gets both QtyOnHand AND QtyCommitSales from the transfer (committed stock moves with commitment)
+    /// 6. Re-points the selected pending issue lines (lot_tran_nos) to the destination bin and audits each one
+    ///
+    /// `respond` turns the document number, the quantity moved (rounded to the UOM decimals) and the
+    /// re-pointed lines into the result; with a `claim` it is stored against the Idempotency-Key
+    /// before the transaction commits.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) async fn execute_committed_bin_transfer<T: Serialize>(
+        &self,
+        lot_no: &str,
+        item_key: &str,
+        location: &str,
+        target_bin: &str,
+        transfer_qty: &BigDecimal,
+        source_bin: &str,
+        user_id: &str,
+        remarks: &str,
+        referenced: &str,
+        lot_tran_nos: &[i32],
+        override_capacity: bool,
+        claim: Option<&IdempotencyClaim>,
+        respond: impl FnOnce((String, BigDecimal, Vec<MovedCommitmentLine>)) -> T,
+    ) -> Result<T, PutawayError> {
+        let lot = LotKey { lot_no, item_key, location };
+        let mut conn = self.db.begin().await?;
+
+        let transaction_result: Result<T, PutawayError> = async {
+            let now = bangkok_now().naive_local();
+            let user_id_truncated = truncate_user_id(user_id);
+
+            // 0. Refuse transfers during an inventory freeze or physical count
+            self.check_inventory_locks(&mut conn, item_key, location).await?;
+
+            // 0b. Committed stock must exist: lock the source row, then compare against the
+            // BME commitment (Phase 1.3) and the physical quantity in the bin, all at the UOM scale
+            let decimals = self.qty_decimals(&mut conn, item_key).await?;
+            let transfer_qty = round_qty(transfer_qty, decimals);
+            if !transfer_qty.is_positive() {
+                return Err(PutawayError::ValidationError("Transfer quantity must be greater than 0".to_string()));
+            }
+
+            let source = self
+                .db
+                .lock_lot_rows(&mut conn, lot, &[source_bin])
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| PutawayError::ValidationError(format!("Lot {lot_no} not found in bin {source_bin}")))?
+                .at_qty_scale(decimals);
+
+            let committed_qty = round_qty(&self.db.bin_commitment(&mut conn, lot, source_bin).await?, decimals);
+            if transfer_qty > committed_qty {
+                return Err(PutawayError::InsufficientQuantity { requested: transfer_qty, available: committed_qty });
+            }
+            if transfer_qty > source.qty_on_hand {
+                return Err(PutawayError::InsufficientQuantity { requested: transfer_qty, available: source.qty_on_hand });
+            }
+
+            // 0c. Lock the specific pending lines the operator picked - they must account for the whole quantity
+            let moved_lines = self
+                .lock_pending_commitment_lines(&mut conn, lot_tran_nos, lot, source_bin, target_bin)
+                .await?;
+            let lines_qty = round_qty(&moved_lines.iter().map(|line| &line.qty).sum(), decimals);
+            if lines_qty != transfer_qty {
+                return Err(PutawayError::ValidationError(format!(
+                    "Selected lines total {lines_qty} but transfer quantity is {transfer_qty}"
+                )));
+            }
+
+            // 0d. Target bin must exist, take putaway and have room - all before a BT number is used
+            if !self.db.bin_exists(&mut conn, location, target_bin).await? {
+                return Err(PutawayError::InvalidBin { bin_no: target_bin.to_string(), location: location.to_string() });
+            }
+            self.check_bin_not_blocked(&mut conn, location, target_bin).await?;
+            self.recheck_bin_capacity(&mut conn, lot, target_bin, &transfer_qty, override_capacity, user_id).await?;
+
+            // 1. Get next BT document number
+            let bt_number = self.db.next_bt_sequence(&mut conn).await?;
+            let document_no = bt_document_no(bt_number);
+
+            // 2. Create Mintxdh record for audit trail (same as Transfer Avail Qty)
+            let in_trans_id = self
+                .insert_bt_mintxdh_header(&mut conn, &document_no, item_key, location, user_id_truncated, &now)
+                .await?;
+
+            let date_received = source.date_received.unwrap_or(now);
+
+            // NOTE: The Type 9 row below is left pending like BME's, but it is not a commitment:
+            // QtyCommitSales and the picked issue lines move in this transaction, so `bin_commitment`
+            // and the lot/bin lookups leave pending BT issues out of the Phase 1.3 sum.
+
+            // 3. LotTransaction - Type 9 (Inv Adj Negative / Issue) from source bin.
+            // The legacy trace writes no Vendorkey/CustomerKey on the issue row.
+            let issue_lot_tran_no = self
+                .db
+                .insert_lot_transaction(&mut conn, &LotTransactionRecord {
+                    lot_tran_no: None,
+                    lot_no: lot_no.to_string(),
+                    item_key: item_key.to_string(),
+                    location_key: location.to_string(),
+                    date_received,
+                    date_expiry: source.date_expiry,
+                    transaction_type: 9,
+                    issue_doc_no: Some(document_no.clone()),
+                    issue_doc_line_no: Some(1),
+                    issue_date: Some(now),
+                    qty_issued: Some(transfer_qty.clone()),
+                    receipt_doc_no: None,
+                    receipt_doc_line_no: None,
+                    qty_received: None,
+                    vendor_key: None,
+                    vendor_lot_no: source.vendor_lot_no.clone(),
+                    customer_key: None,
+                    bin_no: source_bin.to_string(),
+                    rec_userid: user_id_truncated.to_string(),
+                    rec_date: now,
+                    processed: "N".to_string(),
+                    date_quarantine: None,
+                    temp_qty: None,
+                    qty_for_lot_assignment: None,
+                    qty_used: None,
+                })
+                .await?;
+
+            // 4. LotTransaction - Type 8 (Inv Adj Positive / Receipt) to dest bin (DateQuarantine NULL)
+            let receipt_lot_tran_no = self
+                .db
+                .insert_lot_transaction(&mut conn, &LotTransactionRecord {
+                    lot_tran_no: None,
+                    lot_no: lot_no.to_string(),
+                    item_key: item_key.to_string(),
+                    location_key: location.to_string(),
+                    date_received,
+                    date_expiry: source.date_expiry,
+                    transaction_type: 8,
+                    issue_doc_no: None,
+                    issue_doc_line_no: None,
+                    issue_date: None,
+                    qty_issued: None,
+                    receipt_doc_no: Some(document_no.clone()),
+                    receipt_doc_line_no: Some(1),
+                    qty_received: Some(transfer_qty.clone()),
+                    vendor_key: Some(source.vendor_key.clone()),
+                    vendor_lot_no: source.vendor_lot_no.clone(),
+                    customer_key: Some(String::new()),
+                    bin_no: target_bin.to_string(),
+                    rec_userid: user_id_truncated.to_string(),
+                    rec_date: now,
+                    processed: "N".to_string(),
+                    date_quarantine: None,
+                    temp_qty: None,
+                    qty_for_lot_assignment: None,
+                    qty_used: None,
+                })
+                .await?;
+
+            // 5. Move BOTH QtyOnHand AND QtyCommitSales from source to destination.
+            // BME "Transfer with Commit" moves physical inventory along with its commitment status
+            let new_source_qty_on_hand = &source.qty_on_hand - &transfer_qty;
+            let source_change = BinQtyChange {
+                lot_tran_no: issue_lot_tran_no,
+                before: (source.qty_on_hand.clone(), source.qty_commit_sales.clone()),
+                after: if new_source_qty_on_hand.is_positive() {
+                    (new_source_qty_on_hand.clone(), (&source.qty_commit_sales - &transfer_qty).max(BigDecimal::zero()))
+                } else {
+                    (BigDecimal::zero(), BigDecimal::zero())
+                },
+            };
+            if !new_source_qty_on_hand.is_positive() {
+                self.db.delete_lot_row(&mut conn, lot, source_bin).await?;
+            } else {
+                self.db
+                    .update_lot_row(&mut conn, &LotMasterRecord {
+                        qty_on_hand: new_source_qty_on_hand,
+                        // Don't go negative
+                        qty_commit_sales: (&source.qty_commit_sales - &transfer_qty).max(BigDecimal::zero()),
+                        document_no: document_no.clone(),
+                        transaction_type: 9,
+                        // BME leaves the source row's RecUserId/RecDate alone (Appendix A)
+                        ..source.clone()
+                    })
+                    .await?;
+            }
+
+            let destination = self.db.lot_row(&mut conn, lot, target_bin).await?.map(|row| row.at_qty_scale(decimals));
+            let destination_before = destination
+                .as_ref()
+                .map(|dest| (dest.qty_on_hand.clone(), dest.qty_commit_sales.clone()))
+                .unwrap_or_default();
+            let destination_change = BinQtyChange {
+                lot_tran_no: receipt_lot_tran_no,
+                after: (&destination_before.0 + &transfer_qty, &destination_before.1 + &transfer_qty),
+                before: destination_before,
+            };
+            match destination {
+                Some(dest) => {
+                    // Committed stock moves with its commitment
+                    self.db
+                        .update_lot_row(&mut conn, &LotMasterRecord {
+                            qty_on_hand: &dest.qty_on_hand + &transfer_qty,
+                            qty_commit_sales: &dest.qty_commit_sales + &transfer_qty,
+                            document_no: document_no.clone(),
+                            transaction_type: 8,
+                            rec_user_id: user_id_truncated.to_string(),
+                            rec_date: Some(now),
+                            ..dest
+                        })
+                        .await?;
+                }
+                None => {
+                    self.db
+                        .insert_lot_row(&mut conn, &LotMasterRecord {
+                            lot_no: lot_no.to_string(),
+                            item_key: item_key.to_string(),
+                            location_key: location.to_string(),
+                            bin_no: target_bin.to_string(),
+                            date_received: Some(date_received),
+                            date_expiry: source.date_expiry,
+                            qty_received: source.qty_received.clone(),
+                            qty_on_hand: transfer_qty.clone(),
+                            qty_issued: BigDecimal::zero(),
+                            qty_commit_sales: transfer_qty.clone(),
+                            vendor_key: source.vendor_key.clone(),
+                            vendor_lot_no: source.vendor_lot_no.clone(),
+                            document_no: document_no.clone(),
+                            document_line_no: 1,
+                            transaction_type: 8,
+                            lot_status: Some(source.lot_status.clone().unwrap_or_else(|| "P".to_string())),
+                            rec_user_id: user_id_truncated.to_string(),
+                            rec_date: Some(now),
+                        })
+                        .await?;
+                }
+            }
+
+            // 6. Audit Trail (BinTransfer) - linked to the issue row and the Mintxdh header,
+            // with User1 (remarks) and User5 (referenced)
+            self.db
+                .insert_bin_transfer(&mut conn, &BinTransferRecord {
+                    bin_tran_id: None,
+                    item_key: item_key.to_string(),
+                    location: location.to_string(),
+                    lot_no: lot_no.to_string(),
+                    bin_no_from: source_bin.to_string(),
+                    bin_no_to: target_bin.to_string(),
+                    lot_tran_no: issue_lot_tran_no,
+                    qty_on_hand: source.qty_on_hand.clone(),
+                    transfer_qty: transfer_qty.clone(),
+                    in_trans_id,
+                    rec_user_id: user_id_truncated.to_string(),
+                    rec_date: now,
+                    container_no: "0".to_string(),
+                    user1: remarks.to_string(),
+                    user5: referenced.to_string(),
+                })
+                .await?;
+
+            let line = TransferLine {
+                lot,
+                line_no: 1,
+                bin_from: source_bin,
+                bin_to: target_bin,
+                transfer_qty: &transfer_qty,
+                remarks,
+                referenced,
+                override_capacity,
+            };
+            self.insert_lot_snapshots(
+                &mut conn,
+                &document_no,
+                &line,
+                [source_change, destination_change],
+                user_id_truncated,
+                now,
+            )
+            .await?;
+
+            // 7. Sales/mfg issues follow the stock: re-point the selected lines and record each move
+            for line in &moved_lines {
+                self.db.repoint_commitment_line(&mut conn, line).await?;
+                self.db
+                    .insert_commitment_move(&mut conn, &document_no, line, lot, user_id_truncated, &now)
+                    .await?;
+
+                tracing::info!(
+                    document_no,
+                    lot_tran_no = line.lot_tran_no,
+                    source_table = %line.source_table,
+                    bin_from = %line.bin_from,
+                    bin_to = %line.bin_to,
+                    qty = %line.qty,
+                    "Re-pointed pending commitment line"
+                );
+            }
+
+            let response = respond((document_no.clone(), transfer_qty, moved_lines));
+            self.complete_idempotency_claim(&mut conn, claim, &document_no, &response).await?;
+
+            Ok(response)
+        }
+        .await;
+
+        match transaction_result {
+            Ok(result) => {
+                self.db.commit(&mut conn).await?;
+                Ok(result)
+            }
+            Err(e) => {
+                self.db.rollback(&mut conn).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Validate transfer request - checks specific bin quantities for lot consolidation.
+    /// Returns the quantity to move, converted to the item's stock UOM and rounded to its
+    /// decimals, the stock UOM, and whether it takes the whole available quantity of the bin.
+    /// `earlier` are the lines of the same document already validated (see `check_bin_capacity`).
+    pub(super) async fn validate_transfer_request(
+        &self,
+        request: &BinTransferRequest,
+        earlier: &[BinTransferRequest],
+    ) -> Result<(BigDecimal, String, bool), PutawayError> {
+        let BinTransferRequest { lot_no, item_key, location, bin_from, bin_to, .. } = request;
+        let lot = LotKey { lot_no, item_key, location };
+        let mut conn = self.db.connect().await?;
+
+        // Refuse transfers during an inventory freeze or physical count
+        self.check_inventory_locks(&mut conn, item_key, location).await?;
+
+        let (stock_qty, stock_uom) = self
+            .stock_qty(&mut conn, item_key, &request.transfer_qty, request.uom.as_deref())
+            .await?;
+        let decimals = self.qty_decimals(&mut conn, item_key).await?;
+        let transfer_qty = round_qty(&stock_qty, decimals);
+
+        // Check specific bin record (not general lot record)
+        let Some(source) = self.db.lot_row(&mut conn, lot, bin_from).await? else {
+            return Err(PutawayError::ValidationError(format!(
+                "Lot {lot_no} not found in bin {bin_from} or insufficient quantity available"
+            )));
+        };
+        let source = source.at_qty_scale(decimals);
+
+        // Calculate available quantity in THIS SPECIFIC BIN (QtyOnHand - QtyCommitSales)
+        let available_qty = &source.qty_on_hand - &source.qty_commit_sales;
+
+        if transfer_qty > available_qty {
+            return Err(PutawayError::InsufficientQuantity {
+                requested: transfer_qty,
+                available: available_qty,
+            });
+        }
+
+        if !transfer_qty.is_positive() {
+            return Err(PutawayError::ValidationError(
+                "Transfer quantity must be greater than 0".to_string(),
+            ));
+        }
+
+        // Exact at the UOM scale: taking everything available leaves no residual row behind
+        let is_full_transfer = transfer_qty == available_qty;
+
+        // Validate destination bin exists
+        if !self.db.bin_exists(&mut conn, location, bin_to).await? {
+            return Err(PutawayError::InvalidBin {
+                bin_no: bin_to.to_string(),
+                location: location.to_string(),
+            });
+        }
+
+        // Damaged or blocked bins take no putaway
+        self.check_bin_not_blocked(&mut conn, location, bin_to).await?;
+
+        // Validate source and destination bins are different
+        if bin_from == bin_to {
+            return Err(PutawayError::ValidationError(
+                "Source and destination bins cannot be the same".to_string(),
+            ));
+        }
+
+        // Destination capacity, counting the document's earlier lines into the same bin
+        self.check_bin_capacity(&mut conn, request, &transfer_qty, earlier).await?;
+
+        Ok((transfer_qty, stock_uom, is_full_transfer))
+    }
+
+    /// Write one line of a BT document: issue/receipt LotTransaction pair numbered `line_no`,
+    /// the BinTransfer audit row (linked to the issue and to the `in_trans_id` Mintxdh header) and the
+    /// LotMaster consolidation. Runs on the caller's transaction.
+    /// Returns the issue LotTranNo.
+    async fn write_bin_transfer_line(
+        &self,
+        conn: &mut R::Conn,
+        document_no: &str,
+        in_trans_id: i32,
+        line: &TransferLine<'_>,
+        user_id: &str,
+        now: &NaiveDateTime,
+    ) -> Result<i32, PutawayError> {
+        let user_id_truncated = truncate_user_id(user_id);
+        let now = *now;
+        let decimals = self.qty_decimals(conn, line.lot.item_key).await?;
+
+        // **🔒 LOCK LOTMASTER FIRST** - Lock BOTH source and destination bins in BinNo order
+        // to prevent circular waits
+        let locked_lots = self.db.lock_lot_rows(conn, line.lot, &[line.bin_from, line.bin_to]).await?;
+
+        // Available quantity again under the lock: stock picked or committed since validation
+        // must fail the line, not be deleted with the source row or created in the destination
+        let source = locked_lots.iter().find(|row| row.bin_no == line.bin_from).map(|row| row.clone().at_qty_scale(decimals));
+        let available_qty = source
+            .as_ref()
+            .map(|row| &row.qty_on_hand - &row.qty_commit_sales)
+            .unwrap_or_default();
+        if line.transfer_qty > &available_qty {
+            return Err(PutawayError::InsufficientQuantity {
+                requested: line.transfer_qty.clone(),
+                available: available_qty,
+            });
+        }
+        // Source details are captured before the consolidation, which deletes the row on a full transfer
+        let Some(source) = source else {
+            return Err(PutawayError::ValidationError("Source bin record not found".to_string()));
+        };
+
+        // Block and capacity again under the lock: either may have changed since validation
+        self.check_bin_not_blocked(conn, line.lot.location, line.bin_to).await?;
+        self.recheck_bin_capacity(conn, line.lot, line.bin_to, line.transfer_qty, line.override_capacity, user_id)
+            .await?;
+
+        let destination = locked_lots
+            .into_iter()
+            .find(|row| row.bin_no == line.bin_to)
+            .map(|row| row.at_qty_scale(decimals));
+
+        let date_received = source.date_received.unwrap_or(now);
+        let date_expiry = source.date_expiry.unwrap_or(now);
+
+        // Issue (type 9) and receipt (type 8) rows as in the legacy trace (Vendorkey/CustomerKey only
+        // on the receipt), except Processed = 'Y': this path applies the movement to LotMaster itself,
+        // so BME's posting run must not apply it again (README, Differences from BME).
+        let issue_row = LotTransactionRecord {
+            lot_tran_no: None,
+            lot_no: line.lot.lot_no.to_string(),
+            item_key: line.lot.item_key.to_string(),
+            location_key: line.lot.location.to_string(),
+            date_received,
+            date_expiry: Some(date_expiry),
+            transaction_type: 9,
+            issue_doc_no: Some(document_no.to_string()),
+            issue_doc_line_no: Some(line.line_no),
+            issue_date: Some(now),
+            qty_issued: Some(line.transfer_qty.clone()),
+            receipt_doc_no: None,
+            receipt_doc_line_no: None,
+            qty_received: None,
+            vendor_key: None,
+            vendor_lot_no: source.vendor_lot_no.clone(),
+            customer_key: None,
+            bin_no: line.bin_from.to_string(),
+            rec_userid: user_id_truncated.to_string(),
+            rec_date: now,
+            processed: "Y".to_string(),
+            date_quarantine: None,
+            temp_qty: Some(BigDecimal::zero()),
+            qty_for_lot_assignment: Some(BigDecimal::zero()),
+            qty_used: Some(BigDecimal::zero()),
+        };
+        let issue_lot_tran_no = self.db.insert_lot_transaction(conn, &issue_row).await?;
+
+        let receipt_row = LotTransactionRecord {
+            transaction_type: 8,
+            issue_doc_no: None,
+            issue_doc_line_no: None,
+            issue_date: None,
+            qty_issued: None,
+            receipt_doc_no: Some(document_no.to_string()),
+            receipt_doc_line_no: Some(line.line_no),
+            qty_received: Some(line.transfer_qty.clone()),
+            vendor_key: Some(source.vendor_key.clone()),
+            customer_key: Some(String::new()),
+            bin_no: line.bin_to.to_string(),
+            ..issue_row
+        };
+        let receipt_lot_tran_no = self.db.insert_lot_transaction(conn, &receipt_row).await?;
+
+        // BinTransfer record (with issue LotTranNo reference)
+        self.db
+            .insert_bin_transfer(conn, &BinTransferRecord {
+                bin_tran_id: None,
+                item_key: line.lot.item_key.to_string(),
+                location: line.lot.location.to_string(),
+                lot_no: line.lot.lot_no.to_string(),
+                bin_no_from: line.bin_from.to_string(),
+                bin_no_to: line.bin_to.to_string(),
+                lot_tran_no: issue_lot_tran_no,
+                qty_on_hand: source.qty_on_hand.clone(),
+                transfer_qty: line.transfer_qty.clone(),
+                in_trans_id,
+                rec_user_id: user_id_truncated.to_string(),
+                rec_date: now,
+                container_no: "0".to_string(),
+                user1: line.remarks.to_string(),
+                user5: line.referenced.to_string(),
+            })
+            .await?;
+
+        // LotMaster lot consolidation
+        let lot_tran_nos = [issue_lot_tran_no, receipt_lot_tran_no];
+        self.handle_lot_consolidation(conn, line, document_no, lot_tran_nos, source, destination, user_id_truncated, now)
+            .await?;
+
+        Ok(issue_lot_tran_no)
+    }
+
+    /// Handle lot consolidation logic for LotMaster records
+    #[allow(clippy::too_many_arguments)]
+    async fn handle_lot_consolidation(
+        &self,
+        conn: &mut R::Conn,
+        line: &TransferLine<'_>,
+        document_no: &str,
+        [issue_lot_tran_no, receipt_lot_tran_no]: [i32; 2],
+        source: LotMasterRecord,
+        destination: Option<LotMasterRecord>,
+        user_id_truncated: &str,
+        now: NaiveDateTime,
+    ) -> Result<(), PutawayError> {
+        // Quantities around the line for putaway_bt_lot_snapshots (QtyCommitSales is not moved here)
+        let remaining_qty = &source.qty_on_hand - line.transfer_qty;
+        let source_change = BinQtyChange {
+            lot_tran_no: issue_lot_tran_no,
+            before: (source.qty_on_hand.clone(), source.qty_commit_sales.clone()),
+            after: if remaining_qty.is_positive() {
+                (remaining_qty.clone(), source.qty_commit_sales.clone())
+            } else {
+                (BigDecimal::zero(), BigDecimal::zero())
+            },
+        };
+        let destination_before = destination
+            .as_ref()
+            .map(|dest| (dest.qty_on_hand.clone(), dest.qty_commit_sales.clone()))
+            .unwrap_or_default();
+        let destination_change = BinQtyChange {
+            lot_tran_no: receipt_lot_tran_no,
+            after: (&destination_before.0 + line.transfer_qty, destination_before.1.clone()),
+            before: destination_before,
+        };
+
+        // Step 1: Update source bin - reduce QtyOnHand or delete if becomes 0
+        if !remaining_qty.is_positive() {
+            self.db.delete_lot_row(conn, line.lot, line.bin_from).await?;
+        } else {
+            self.db
+                .update_lot_row(conn, &LotMasterRecord {
+                    qty_on_hand: remaining_qty,
+                    document_no: document_no.to_string(),
+                    transaction_type: 9,
+                    // BME leaves the source row's RecUserId/RecDate alone (Appendix A)
+                    ..source.clone()
+                })
+                .await?;
+        }
+
+        // Step 2: Handle destination bin - add to existing or create new record
+        match destination {
+            Some(dest) => {
+                // Destination bin already has this lot - add quantities (lot consolidation)
+                self.db
+                    .update_lot_row(conn, &LotMasterRecord {
+                        qty_on_hand: &dest.qty_on_hand + line.transfer_qty,
+                        document_no: document_no.to_string(),
+                        transaction_type: 8,
+                        rec_user_id: user_id_truncated.to_string(),
+                        rec_date: Some(now),
+                        ..dest
+                    })
+                    .await?;
+            }
+            None => {
+                // Destination bin doesn't have this lot - create new record from the source details
+                self.db
+                    .insert_lot_row(conn, &LotMasterRecord {
+                        bin_no: line.bin_to.to_string(),
+                        date_received: Some(source.date_received.unwrap_or(now)),
+                        date_expiry: Some(source.date_expiry.unwrap_or(now)),
+                        qty_received: line.transfer_qty.clone(),
+                        qty_on_hand: line.transfer_qty.clone(),
+                        qty_issued: BigDecimal::zero(),
+                        qty_commit_sales: BigDecimal::zero(),
+                        document_no: document_no.to_string(),
+                        document_line_no: 1,
+                        transaction_type: 8,
+                        rec_user_id: user_id_truncated.to_string(),
+                        rec_date: Some(now),
+                        ..source
+                    })
+                    .await?;
+            }
+        }
+
+        self.insert_lot_snapshots(
+            conn,
+            document_no,
+            line,
+            [source_change, destination_change],
+            user_id_truncated,
+            now,
+        )
+        .await
+    }
+
+    /// Record the source (FROM) and destination (TO) LotMaster QtyOnHand/QtyCommitSales
+    /// before and after one BT line, so the document audit can show what the move did
+    async fn insert_lot_snapshots(
+        &self,
+        conn: &mut R::Conn,
+        document_no: &str,
+        line: &TransferLine<'_>,
+        [from, to]: [BinQtyChange; 2],
+        user_id_truncated: &str,
+        now: NaiveDateTime,
+    ) -> Result<(), PutawayError> {
+        for (side, bin_no, change) in [("FROM", line.bin_from, from), ("TO", line.bin_to, to)] {
+            self.db
+                .insert_lot_snapshot(conn, &LotSnapshotRecord {
+                    document_no: document_no.to_string(),
+                    line_no: line.line_no,
+                    lot_no: line.lot.lot_no.to_string(),
+                    item_key: line.lot.item_key.to_string(),
+                    location: line.lot.location.to_string(),
+                    bin_no: bin_no.to_string(),
+                    side: side.to_string(),
+                    qty_on_hand_before: change.before.0,
+                    qty_on_hand_after: change.after.0,
+                    qty_commit_before: change.before.1,
+                    qty_commit_after: change.after.1,
+                    lot_tran_no: Some(change.lot_tran_no),
+                    rec_user_id: user_id_truncated.to_string(),
+                    rec_date: now,
+                })
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Insert the Mintxdh header of a BT document (one per document, whatever the line count).
+    /// Returns its InTransID for the document's BinTransfer rows.
+    async fn insert_bt_mintxdh_header(
+        &self,
+        conn: &mut R::Conn,
+        document_no: &str,
+        item_key: &str,
+        location: &str,
+        user_id_truncated: &str,
+        now: &NaiveDateTime,
+    ) -> Result<i32, PutawayError> {
+        let inloc_record = self.db.inloc_record(conn, item_key, location).await?;
+
+        self.db
+            .insert_mintxdh(conn, &MintxdhRecord {
+                in_trans_id: None,
+                item_key: item_key.to_string(),
+                location: location.to_string(),
+                doc_no: document_no.to_string(),
+                doc_date: *now,
+                trn_desc: "Bin Transfer".to_string(),
+                in_acct: map_inclasskey_to_inacct(&inloc_record.inclasskey).to_string(),
+                rec_user_id: user_id_truncated.to_string(),
+                rec_date: *now,
+                std_cost: inloc_record.stdcost,
+            })
+            .await
+    }
+
+    /// Store a transfer's document and response against its claimed Idempotency-Key on the transfer's
+    /// transaction, so a committed move always leaves its document on the key. A response that cannot
+    /// be stored must not undo the move - the key then keeps only the document.
+    async fn complete_idempotency_claim<T: Serialize>(
+        &self,
+        conn: &mut R::Conn,
+        claim: Option<&IdempotencyClaim>,
+        document_no: &str,
+        response: &T,
+    ) -> Result<(), PutawayError> {
+        let Some(claim) = claim else {
+            return Ok(());
+        };
+        let body = serde_json::to_string(response)
+            .inspect_err(|e| tracing::error!(idempotency_key = %claim.key, "Failed to serialize transfer result: {e}"))
+            .ok();
+
+        self.db.complete_idempotency_key(conn, claim, document_no, body.as_deref()).await
+    }
+
+    /// Lock the selected pending issue lines of a lot/bin
+    async fn lock_pending_commitment_lines(
+        &self,
+        conn: &mut R::Conn,
+        lot_tran_nos: &[i32],
+        lot: LotKey<'_>,
+        source_bin: &str,
+        target_bin: &str,
+    ) -> Result<Vec<MovedCommitmentLine>, PutawayError> {
+        let mut lines: Vec<MovedCommitmentLine> = Vec::with_capacity(lot_tran_nos.len());
+
+        for &lot_tran_no in lot_tran_nos {
+            if lines.iter().any(|line| line.lot_tran_no == lot_tran_no) {
+                return Err(PutawayError::ValidationError(format!(
+                    "LotTranNo {lot_tran_no} selected more than once"
+                )));
+            }
+
+            match self.db.lock_pending_commitment_line(conn, lot_tran_no, lot, source_bin, target_bin).await? {
+                Some(line) => lines.push(line),
+                None => {
+                    return Err(PutawayError::ValidationError(format!(
+                        "LotTranNo {lot_tran_no} is not a pending commitment of lot {} in bin {source_bin}",
+                        lot.lot_no
+                    )))
+                }
+            }
+        }
+
+        Ok(lines)
+    }
+
+    /// BME Phase 2 guards: refuse any movement while inventory is frozen or a
+    /// physical count is running for the item/location.
+    /// Runs on the caller's connection so it sees the same snapshot as the transfer.
+    async fn check_inventory_locks(&self, conn: &mut R::Conn, item_key: &str, location: &str) -> Result<(), PutawayError> {
+        if self.db.inventory_frozen(conn).await? {
+            return Err(PutawayError::InventoryLocked {
+                reason: InventoryLockReason::InventoryFrozen,
+            });
+        }
+
+        if self.db.physical_count_in_progress(conn, item_key, location).await? {
+            return Err(PutawayError::InventoryLocked {
+                reason: InventoryLockReason::PhysicalCountInProgress {
+                    item_key: item_key.to_string(),
+                    location: location.to_string(),
+                },
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Decimals the item's quantities are kept to (INUOMD.UMQtyDP of its stock UOM). BME's
+    /// `roundingdflt` is not read: the trace records no rule from it (see README, Differences from BME).
+    pub(super) async fn qty_decimals(&self, conn: &mut R::Conn, item_key: &str) -> Result<i64, PutawayError> {
+        Ok(self
+            .db
+            .item_qty_decimals(conn, item_key)
+            .await?
+            .unwrap_or(DEFAULT_QTY_DECIMALS)
+            .clamp(0, MAX_QTY_DECIMALS))
+    }
+
+    /// Quantity entered in `uom` expressed in the item's stock UOM, with the stock UOM code.
+    /// Uses the INQTYCNV row from `uom` to the stock UOM, or the inverse of the row from the
+    /// stock UOM to `uom`. The result is not rounded - the caller rounds it once to UMQtyDP.
+    pub(super) async fn stock_qty(
+        &self,
+        conn: &mut R::Conn,
+        item_key: &str,
+        qty: &BigDecimal,
+        uom: Option<&str>,
+    ) -> Result<(BigDecimal, String), PutawayError> {
+        let item = self.db.item_master(conn, item_key).await?.ok_or_else(|| {
+            PutawayError::ValidationError(format!("Item {item_key} not found in INMAST"))
+        })?;
+        let stock_uom = item.stock_uom_code.trim().to_string();
+
+        // Counting in the stock UOM needs no conversion
+        let uom = match uom.map(str::trim) {
+            Some(uom) if !uom.is_empty() && !uom.eq_ignore_ascii_case(&stock_uom) => uom,
+            _ => return Ok((qty.clone(), stock_uom)),
+        };
+
+        let (conversion, inverse) = match self.db.uom_conversion(conn, item_key, uom, &stock_uom).await? {
+            Some(conversion) => (conversion, false),
+            None => match self.db.uom_conversion(conn, item_key, &stock_uom, uom).await? {
+                Some(conversion) => (conversion, true),
+                None => {
+                    return Err(PutawayError::ValidationError(format!(
+                        "No conversion from {uom} to stock UOM {stock_uom} for item {item_key}"
+                    )))
+                }
+            },
+        };
+
+        if !conversion.conv_factor.is_positive() {
+            return Err(PutawayError::ValidationError(format!(
+                "Conversion factor between {uom} and {stock_uom} for item {item_key} is not positive"
+            )));
+        }
+
+        let stock_qty = if inverse { conversion.convert_back(qty) } else { conversion.convert(qty) };
+        Ok((stock_qty, stock_uom))
+    }
+
+    /// LotStatus of the lot in a bin, None if the row is gone or cannot be read
+    async fn lot_status(&self, conn: &mut R::Conn, lot: LotKey<'_>, bin_no: &str) -> Option<String> {
+        self.db.lot_row(conn, lot, bin_no).await.ok().flatten().and_then(|row| row.lot_status)
+    }
+}
+
+/// BME parity suite: BT-26112174 from Docs/Putaway-Bme-Transaction.md, replayed through both
+/// transfer flows. The expected values are the rows the legacy system wrote in that trace, so a
+/// change to either flow that drifts from BME fails here.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::memory_db::{qty, MemoryPutawayDatabase, MemoryTables, LOT};
+    use chrono::NaiveDate;
+    use std::convert::identity;
+
+    const SOURCE_BIN: &str = "K0802-4B";
+    const TARGET_BIN: &str = "WHKON1";
+    /// Pending Mfg. Issue behind the 50 committed in the source bin
+    const MFG_ISSUE_LOT_TRAN_NO: i32 = 18318699;
+
+    fn date_received() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 8, 7).unwrap().and_hms_opt(8, 36, 2).unwrap()
+    }
+
+    fn date_expiry() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2027, 5, 7).unwrap().and_hms_opt(0, 0, 0).unwrap()
+    }
+
+    /// Pre-transfer state of the trace: 975 on hand / 50 committed in K0802-4B, 3350 in WHKON1
+    fn pre_transfer_state() -> MemoryTables {
+        let mut tables = MemoryTables { bt_sequence: 26112173, ..Default::default() };
+        tables.add_item(LOT.item_key, "Batter Mix", "KG", LOT.location);
+        tables.add_bin(LOT.location, SOURCE_BIN);
+        tables.add_bin(LOT.location, TARGET_BIN);
+        tables.add_lot(LOT.lot_no, LOT.item_key, LOT.location, SOURCE_BIN, qty("975"));
+        tables.add_lot(LOT.lot_no, LOT.item_key, LOT.location, TARGET_BIN, qty("3350"));
+        for row in &mut tables.lot_master {
+            row.date_received = Some(date_received());
+            row.date_expiry = Some(date_expiry());
+            row.vendor_key = "NZSUS".to_string();
+            row.vendor_lot_no = "07-05-25".to_string();
+            row.rec_user_id = "WUTICHAI".to_string();
+            if row.bin_no == SOURCE_BIN {
+                row.qty_commit_sales = qty("50");
+                row.lot_status = Some("C".to_string());
+            } else {
+                row.lot_status = Some("B".to_string());
+            }
+        }
+
+        tables.lot_transactions.push(LotTransactionRecord {
+            lot_tran_no: Some(MFG_ISSUE_LOT_TRAN_NO),
+            lot_no: LOT.lot_no.to_string(),
+            item_key: LOT.item_key.to_string(),
+            location_key: LOT.location.to_string(),
+            date_received: date_received(),
+            date_expiry: Some(date_expiry()),
+            transaction_type: 5,
+            issue_doc_no: Some("B2600412".to_string()),
+            issue_doc_line_no: Some(1),
+            issue_date: Some(date_received()),
+            qty_issued: Some(qty("50")),
+            receipt_doc_no: None,
+            receipt_doc_line_no: None,
+            qty_received: None,
+            vendor_key: None,
+            vendor_lot_no: "07-05-25".to_string(),
+            customer_key: None,
+            bin_no: SOURCE_BIN.to_string(),
+            rec_userid: "WUTICHAI".to_string(),
+            rec_date: date_received(),
+            processed: "N".to_string(),
+            date_quarantine: None,
+            temp_qty: None,
+            qty_for_lot_assignment: None,
+            qty_used: None,
+        });
+        tables
+    }
+
+    /// The issue/receipt pair the transfer wrote, after the seeded Mfg. Issue
+    fn bt_pair(tables: &MemoryTables) -> (&LotTransactionRecord, &LotTransactionRecord) {
+        let [_, issue, receipt] = &tables.lot_transactions[..] else {
+            panic!("expected one issue/receipt pair, got {:#?}", tables.lot_transactions)
+        };
+        (issue, receipt)
+    }
+
+    /// Step 5.1 / 5.2 of the trace, field by field. Processed is checked per flow: only the
+    /// committed transfer leaves the pair pending like BME (README, Differences from BME).
+    fn assert_bme_rows(issue: &LotTransactionRecord, receipt: &LotTransactionRecord, transfer_qty: &str) {
+        for row in [issue, receipt] {
+            assert_eq!(row.lot_no, "2600107-1");
+            assert_eq!(row.item_key, "INBC1403");
+            assert_eq!(row.location_key, "TFC1");
+            assert_eq!(row.date_received, date_received());
+            assert_eq!(row.date_expiry, Some(date_expiry()));
+            assert_eq!(row.vendor_lot_no, "07-05-25");
+            assert_eq!(row.rec_userid, "DECHAWAT");
+            assert_eq!(row.date_quarantine, None);
+        }
+
+        assert_eq!(issue.lot_tran_no, Some(18318700));
+        assert_eq!(issue.transaction_type, 9);
+        assert_eq!(issue.issue_doc_no.as_deref(), Some("BT-26112174"));
+        assert_eq!(issue.issue_doc_line_no, Some(1));
+        assert_eq!(issue.issue_date, Some(issue.rec_date));
+        assert_eq!(issue.qty_issued, Some(qty(transfer_qty)));
+        assert_eq!((issue.receipt_doc_no.as_deref(), issue.receipt_doc_line_no, issue.qty_received.as_ref()), (None, None, None));
+        assert_eq!(issue.vendor_key, None);
+        assert_eq!(issue.customer_key, None);
+        assert_eq!(issue.bin_no, SOURCE_BIN);
+
+        assert_eq!(receipt.lot_tran_no, Some(18318701));
+        assert_eq!(receipt.transaction_type, 8);
+        assert_eq!(receipt.receipt_doc_no.as_deref(), Some("BT-26112174"));
+        assert_eq!(receipt.receipt_doc_line_no, Some(1));
+        assert_eq!(receipt.qty_received, Some(qty(transfer_qty)));
+        assert_eq!((receipt.issue_doc_no.as_deref(), receipt.issue_doc_line_no, receipt.qty_issued.as_ref()), (None, None, None));
+        assert_eq!(receipt.vendor_key.as_deref(), Some("NZSUS"));
+        assert_eq!(receipt.customer_key.as_deref(), Some(""));
+        assert_eq!(receipt.bin_no, TARGET_BIN);
+        assert_eq!(receipt.rec_date, issue.rec_date);
+    }
+
+    fn assert_lot_row(tables: &MemoryTables, bin_no: &str, qty_on_hand: &str, qty_commit_sales: &str, transaction_type: u8) {
+        let row = tables.lot(LOT, bin_no).unwrap_or_else(|| panic!("no LotMaster row in {bin_no}"));
+        assert_eq!((&row.qty_on_hand, &row.qty_commit_sales), (&qty(qty_on_hand), &qty(qty_commit_sales)), "{bin_no}");
+        assert_eq!((row.document_no.as_str(), row.transaction_type), ("BT-26112174", transaction_type), "{bin_no}");
+        // Appendix A: the source keeps its RecUserId and status, the destination is stamped by the transfer
+        let (lot_status, rec_user_id) = if bin_no == SOURCE_BIN { ("C", "WUTICHAI") } else { ("B", "DECHAWAT") };
+        assert_eq!((row.lot_status.as_deref(), row.rec_user_id.as_str()), (Some(lot_status), rec_user_id), "{bin_no}");
+        assert_eq!((row.vendor_key.as_str(), row.vendor_lot_no.as_str()), ("NZSUS", "07-05-25"), "{bin_no}");
+        assert_eq!((row.date_received, row.date_expiry), (Some(date_received()), Some(date_expiry())), "{bin_no}");
+    }
+
+    #[tokio::test]
+    async fn test_bt_26112174_transfer_avail_qty() {
+        let db = MemoryPutawayDatabase::new(pre_transfer_state());
+        let service = PutawayService::with_repository(db.clone());
+
+        let (document_no, _, _) = service
+            .execute_bin_transfer_transaction(
+                LOT.lot_no, LOT.item_key, LOT.location, SOURCE_BIN, TARGET_BIN, &qty("500"), "DECHAWAT", "", "", false, None,
+                identity,
+            )
+            .await
+            .unwrap();
+        assert_eq!(document_no, "BT-26112174");
+
+        let tables = db.tables().await;
+        let (issue, receipt) = bt_pair(&tables);
+        assert_bme_rows(issue, receipt, "500");
+        // LotMaster already moved, so the pair is written as the trace shows it after posting
+        assert_eq!((issue.processed.as_str(), receipt.processed.as_str()), ("Y", "Y"));
+
+        // Appendix A: 975 -> 475 with the Mfg. Issue still committed, 3350 -> 3850
+        assert_lot_row(&tables, SOURCE_BIN, "475", "50", 9);
+        assert_lot_row(&tables, TARGET_BIN, "3850", "0", 8);
+
+        let [bin_transfer] = &tables.bin_transfers[..] else { panic!("expected one BinTransfer row") };
+        assert_eq!(Some(bin_transfer.lot_tran_no), issue.lot_tran_no);
+        assert_eq!((&bin_transfer.qty_on_hand, &bin_transfer.transfer_qty), (&qty("975"), &qty("500")));
+        assert_eq!((bin_transfer.bin_no_from.as_str(), bin_transfer.bin_no_to.as_str()), (SOURCE_BIN, TARGET_BIN));
+
+        let [header] = &tables.mintxdh[..] else { panic!("expected one Mintxdh header") };
+        assert_eq!(header.doc_no, "BT-26112174");
+        assert_eq!(tables.bt_sequence, 26112174);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_rechecks_the_available_quantity_under_the_lock() {
+        let db = MemoryPutawayDatabase::new(pre_transfer_state());
+        let service = PutawayService::with_repository(db.clone());
+
+        // Validated against 975 on hand, but 50 of it is committed by the time the line is written
+        let result = service
+            .execute_bin_transfer_transaction(
+                LOT.lot_no, LOT.item_key, LOT.location, SOURCE_BIN, TARGET_BIN, &qty("975"), "DECHAWAT", "", "", false, None,
+                identity,
+            )
+            .await;
+        match result {
+            Err(PutawayError::InsufficientQuantity { requested, available }) => {
+                assert_eq!((requested, available), (qty("975"), qty("925")));
+            }
+            other => panic!("expected InsufficientQuantity, got {other:?}"),
+        }
+
+        // Nothing deleted from the source, nothing created in the destination, no BT number used
+        let tables = db.tables().await;
+        assert_eq!(tables.lot(LOT, SOURCE_BIN).map(|row| &row.qty_on_hand), Some(&qty("975")));
+        assert_eq!(tables.lot(LOT, TARGET_BIN).map(|row| &row.qty_on_hand), Some(&qty("3350")));
+        assert_eq!(tables.lot_transactions.len(), 1);
+        assert!(tables.bin_transfers.is_empty() && tables.mintxdh.is_empty());
+        assert_eq!(tables.bt_sequence, 26112173);
+    }
+
+    #[tokio::test]
+    async fn test_bt_26112174_transfer_with_commit() {
+        let db = MemoryPutawayDatabase::new(pre_transfer_state());
+        let service = PutawayService::with_repository(db.clone());
+
+        let (document_no, _, moved) = service
+            .execute_committed_bin_transfer(
+                LOT.lot_no,
+                LOT.item_key,
+                LOT.location,
+                TARGET_BIN,
+                &qty("50"),
+                SOURCE_BIN,
+                "DECHAWAT",
+                "",
+                "",
+                &[MFG_ISSUE_LOT_TRAN_NO],
+                false,
+                None,
+                identity,
+            )
+            .await
+            .unwrap();
+        assert_eq!(document_no, "BT-26112174");
+        assert_eq!(moved.len(), 1);
+
+        let tables = db.tables().await;
+        let (issue, receipt) = bt_pair(&tables);
+        // Left pending for the BME posting run, as in the trace
+        assert_bme_rows(issue, receipt, "50");
+        assert_eq!((issue.processed.as_str(), receipt.processed.as_str()), ("N", "N"));
+
+        // The commitment moves with the stock: the Mfg. Issue now picks from WHKON1
+        let mfg_issue = &tables.lot_transactions[0];
+        assert_eq!((mfg_issue.bin_no.as_str(), mfg_issue.processed.as_str()), (TARGET_BIN, "N"));
+        assert_lot_row(&tables, SOURCE_BIN, "925", "0", 9);
+        assert_lot_row(&tables, TARGET_BIN, "3400", "50", 8);
+
+        let [header] = &tables.mintxdh[..] else { panic!("expected one Mintxdh header") };
+        assert_eq!(header.doc_no, "BT-26112174");
+        assert_eq!(tables.bt_sequence, 26112174);
+    }
+}
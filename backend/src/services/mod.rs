@@ -1,4 +1,12 @@
+mod backfill;
+mod bin_block;
+mod bin_capacity;
+mod bin_suggestion;
+mod bin_transfer;
 pub mod putaway_service;
+mod scan;
+pub mod session_service;
 
 // Re-exports for putaway service and types (match public API used by handlers)
 pub use putaway_service::PutawayService;
+pub use session_service::SessionService;
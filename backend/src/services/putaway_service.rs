@@ -1,19 +1,44 @@
-use crate::utils::bangkok_now_rfc3339;
-use crate::database::{Database, putaway_db::PutawayDatabase};
+use crate::constants;
+use crate::utils::{bangkok_now, bangkok_now_rfc3339};
+use crate::database::{
+    idempotency_db::{IdempotencyClaim, IdempotencyReservation},
+    repository::{LotKey, PendingPutawayFilter, PutawayRepository, TransferHistoryFilter, TransferHistorySort},
+};
 use crate::models::putaway_models::{
-    LotSearchResult, BinValidationResult, BinTransferRequest, 
-    TransferResult, PutawayHealthResponse, LotSearchItem, BinSearchItem, PutawayError
+    bt_document_no, LotSearchResult, LotBinStock, LotLocationTotal, BinValidationResult, BinContents, BinContentLot, BinTransferRequest,
+    TransferResult, PutawayHealthResponse, LotSearchItem, BinSearchItem, PutawayError,
+    BtSequenceGap, BtSequenceGapReport, BatchTransferRequest, BatchTransferResult,
+    BatchTransferLineResult, BinEvacuationRequest, BinEvacuationResult, EvacuationSkippedLot,
+    TransferReversalRequest, TransferReversalResult, CommittedTransferRequest, CommittedTransferResult,
+    PendingPutawayItem, TransferHistoryItem, TransferHistoryQuery, BtDocumentAudit, BtAuditHeader,
+    BtAuditLotTransaction, BtAuditBinTransfer, BtAuditLotSnapshot,
 };
+use crate::utils::quantity::round_qty;
+use bigdecimal::{BigDecimal, Signed};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+
+/// Default number of BT numbers (ending at the current sequence) checked by the gap report
+const DEFAULT_BT_GAP_WINDOW: i32 = 1000;
+/// Upper bound on the gap report range to keep the scan cheap
+const MAX_BT_GAP_WINDOW: i32 = 10000;
+/// Upper bound on the number of lines written under one BT document
+const MAX_BATCH_TRANSFER_LINES: usize = 100;
+
+/// Idempotency scopes - the same key may be used once per endpoint
+const IDEMPOTENCY_SCOPE_TRANSFER: &str = "transfer";
+const IDEMPOTENCY_SCOPE_COMMITTED: &str = "transfer_committed";
 
-pub struct PutawayService {
-    db: PutawayDatabase,
+/// Putaway business rules over a `PutawayRepository` (`PutawayDatabase` in production)
+#[derive(Clone)]
+pub struct PutawayService<R: PutawayRepository> {
+    pub(super) db: R,
 }
 
-impl PutawayService {
-    pub fn new(database: Database) -> Self {
-        Self {
-            db: PutawayDatabase::new(database),
-        }
+impl<R: PutawayRepository> PutawayService<R> {
+    pub fn with_repository(db: R) -> Self {
+        Self { db }
     }
 
     /// Search for lot details by lot number
@@ -23,31 +48,83 @@ impl PutawayService {
             return Err(PutawayError::ValidationError("Lot number cannot be empty".to_string()));
         }
 
-        // Search in database
-        match self.db.find_lot_by_number(lot_no).await? {
-            Some((lot_record, item_record)) => {
-                // Calculate available quantity (QtyOnHand - QtyCommitSales)
-                let qty_available = lot_record.qty_on_hand - lot_record.qty_commit_sales;
-
-                // Format expiry date
-                let expiry_date = lot_record.date_expiry.format("%Y-%m-%d").to_string();
-
-                Ok(LotSearchResult {
-                    lot_no: lot_record.lot_no,
-                    item_key: lot_record.item_key,
-                    location: lot_record.location_key,
-                    current_bin: lot_record.bin_no,
-                    qty_on_hand: lot_record.qty_on_hand,
-                    qty_commit_sales: lot_record.qty_commit_sales,
-                    qty_available,
-                    expiry_date: Some(expiry_date),
-                    item_description: item_record.desc1,
-                    uom: item_record.stock_uom_code,
-                    lot_status: lot_record.lot_status,
-                })
+        let records = self.db.find_lot_bins(lot_no).await?;
+        let Some(first) = records.first() else {
+            return Err(PutawayError::LotNotFound { lot_no: lot_no.to_string() });
+        };
+
+        // Quantities at each item's UOM decimals
+        let mut conn = self.db.connect().await?;
+        let mut item_decimals = HashMap::new();
+        for record in &records {
+            if !item_decimals.contains_key(&record.lot.item_key) {
+                let decimals = self.qty_decimals(&mut conn, &record.lot.item_key).await?;
+                item_decimals.insert(record.lot.item_key.clone(), decimals);
+            }
+        }
+        let decimals_of = |item_key: &str| item_decimals.get(item_key).copied().unwrap_or(constants::DEFAULT_QTY_DECIMALS);
+
+        let bins: Vec<LotBinStock> = records
+            .iter()
+            .map(|record| {
+                let decimals = decimals_of(&record.lot.item_key);
+                let qty_on_hand = round_qty(&record.lot.qty_on_hand, decimals);
+                let qty_committed = round_qty(&record.committed, decimals);
+                LotBinStock {
+                    location: record.lot.location_key.clone(),
+                    bin_no: record.lot.bin_no.clone(),
+                    qty_available: &qty_on_hand - &qty_committed,
+                    qty_on_hand,
+                    qty_committed,
+                    lot_status: record.lot.lot_status.clone().unwrap_or_default(),
+                    expiry_date: record.lot.date_expiry.map(|date| date.format("%Y-%m-%d").to_string()),
+                }
+            })
+            .collect();
+
+        // Bins come ordered by location
+        let mut locations: Vec<LotLocationTotal> = Vec::new();
+        for bin in &bins {
+            match locations.last_mut() {
+                Some(total) if total.location == bin.location => {
+                    total.bin_count += 1;
+                    total.qty_on_hand += &bin.qty_on_hand;
+                    total.qty_committed += &bin.qty_committed;
+                    total.qty_available += &bin.qty_available;
+                }
+                _ => locations.push(LotLocationTotal {
+                    location: bin.location.clone(),
+                    bin_count: 1,
+                    qty_on_hand: bin.qty_on_hand.clone(),
+                    qty_committed: bin.qty_committed.clone(),
+                    qty_available: bin.qty_available.clone(),
+                }),
             }
-            None => Err(PutawayError::LotNotFound { lot_no: lot_no.to_string() }),
         }
+
+        let lot_record = first.lot.clone().at_qty_scale(decimals_of(&first.lot.item_key));
+
+        // Calculate available quantity (QtyOnHand - QtyCommitSales), the figure transfers are checked against
+        let qty_available = &lot_record.qty_on_hand - &lot_record.qty_commit_sales;
+
+        // Format expiry date
+        let expiry_date = lot_record.date_expiry.map(|date| date.format("%Y-%m-%d").to_string());
+
+        Ok(LotSearchResult {
+            lot_no: lot_record.lot_no,
+            item_key: lot_record.item_key,
+            location: lot_record.location_key,
+            current_bin: lot_record.bin_no,
+            qty_on_hand: lot_record.qty_on_hand,
+            qty_commit_sales: lot_record.qty_commit_sales,
+            qty_available,
+            expiry_date,
+            item_description: first.item.desc1.clone(),
+            uom: first.item.stock_uom_code.clone(),
+            lot_status: lot_record.lot_status.unwrap_or_default(),
+            bins,
+            locations,
+        })
     }
 
     /// Validate destination bin
@@ -63,9 +140,20 @@ impl PutawayService {
         }
 
         // Check if bin is valid
-        match self.db.validate_bin_location(location, bin_no).await {
-            Ok(is_valid) => {
-                let message = if is_valid { "Bin is valid and available".to_string() } else { format!("Bin '{bin_no}' not found in location '{location}'") };
+        match self.db.find_bin(location, bin_no).await {
+            Ok(bin) => {
+                let mut is_valid = bin.is_some();
+                let mut message = if is_valid { "Bin is valid and available".to_string() } else { format!("Bin '{bin_no}' not found in location '{location}'") };
+
+                // A blocked bin exists but takes no putaway
+                if is_valid {
+                    let mut conn = self.db.connect().await?;
+                    let now = bangkok_now().naive_local();
+                    if let Some(block) = self.db.bin_block(&mut conn, location, bin_no).await?.filter(|block| block.is_active(&now)) {
+                        is_valid = false;
+                        message = format!("Bin '{bin_no}' is blocked for putaway: {}", block.reason);
+                    }
+                }
 
                 Ok(BinValidationResult {
                     bin_no: bin_no.to_string(),
@@ -78,66 +166,437 @@ impl PutawayService {
         }
     }
 
-    /// Execute bin transfer using validated/corrected quantity
+    /// Everything in one bin, for operators checking a rack before putting more stock there
+    pub async fn get_bin_contents(&self, location: &str, bin_no: &str) -> Result<BinContents, PutawayError> {
+        let (location, bin_no) = (location.trim(), bin_no.trim());
+        if location.is_empty() || bin_no.is_empty() {
+            return Err(PutawayError::ValidationError("Location and bin number cannot be empty".to_string()));
+        }
+
+        let bin = self.db.find_bin(location, bin_no).await?.ok_or_else(|| PutawayError::InvalidBin {
+            bin_no: bin_no.to_string(),
+            location: location.to_string(),
+        })?;
+        let records = self.db.find_bin_contents(location, bin_no).await?;
+
+        let mut conn = self.db.connect().await?;
+        let mut item_decimals = HashMap::new();
+        let mut lots = Vec::with_capacity(records.len());
+        for record in records {
+            let decimals = match item_decimals.get(&record.lot.item_key) {
+                Some(&decimals) => decimals,
+                None => {
+                    let decimals = self.qty_decimals(&mut conn, &record.lot.item_key).await?;
+                    item_decimals.insert(record.lot.item_key.clone(), decimals);
+                    decimals
+                }
+            };
+            let qty_on_hand = round_qty(&record.lot.qty_on_hand, decimals);
+            let qty_committed = round_qty(&record.committed, decimals);
+            lots.push(BinContentLot {
+                lot_no: record.lot.lot_no,
+                item_key: record.lot.item_key,
+                item_description: record.item.desc1,
+                uom: record.item.stock_uom_code,
+                qty_available: &qty_on_hand - &qty_committed,
+                qty_on_hand,
+                qty_committed,
+                date_received: record.lot.date_received.map(|date| date.format("%Y-%m-%d").to_string()),
+                expiry_date: record.lot.date_expiry.map(|date| date.format("%Y-%m-%d").to_string()),
+                lot_status: record.lot.lot_status.unwrap_or_default(),
+            });
+        }
+
+        Ok(BinContents {
+            location: bin.location,
+            bin_no: bin.bin_no,
+            description: bin.description,
+            aisle: bin.aisle,
+            row: bin.row,
+            rack: bin.rack,
+            lots,
+        })
+    }
+
+    /// Execute bin transfer using validated/corrected quantity, without an Idempotency-Key
+    #[cfg(test)]
     pub async fn execute_transfer(&self, request: BinTransferRequest) -> Result<TransferResult, PutawayError> {
+        self.execute_claimed_transfer(request, None).await
+    }
+
+    /// Execute bin transfer using validated/corrected quantity, completing the idempotency `claim`
+    /// in the transfer's transaction
+    async fn execute_claimed_transfer(
+        &self,
+        request: BinTransferRequest,
+        claim: Option<&IdempotencyClaim>,
+    ) -> Result<TransferResult, PutawayError> {
         // Validate request
-        self.validate_transfer_request(&request)?;
+        self.validate_transfer_fields(&request)?;
 
-        // Validate in database and get corrected transfer quantity for full transfers
-        let (actual_transfer_qty, is_full_transfer) = self.db.validate_transfer_request(
-            &request.lot_no,
-            &request.item_key,
-            &request.location,
-            &request.bin_from,
-            &request.bin_to,
-            request.transfer_qty,
-        ).await?;
+        // Validate in database and get the stock UOM quantity, corrected for full transfers
+        let (actual_transfer_qty, stock_uom, is_full_transfer) = self.validate_transfer_request(&request, &[]).await?;
+        let entered_uom = request
+            .uom
+            .as_deref()
+            .map(str::trim)
+            .filter(|uom| !uom.is_empty())
+            .unwrap_or(&stock_uom)
+            .to_string();
+
+        let message = if is_full_transfer {
+            format!(
+                "Successfully transferred {} units (FULL TRANSFER) of lot {} from {} to {} - Source bin cleared",
+                actual_transfer_qty, request.lot_no, request.bin_from, request.bin_to
+            )
+        } else {
+            format!(
+                "Successfully transferred {} units of lot {} from {} to {}",
+                actual_transfer_qty, request.lot_no, request.bin_from, request.bin_to
+            )
+        };
 
-        // Execute transfer with the corrected quantity (exact available qty for full transfers)
-        match self.db.execute_bin_transfer_transaction(
+        // Execute transfer with the quantity rounded to the item's UOM decimals. Errors raised under the
+        // transaction (a freeze set since validation, a blocked or full bin) reach the client as their own status.
+        self.execute_bin_transfer_transaction(
             &request.lot_no,
             &request.item_key,
             &request.location,
             &request.bin_from,
             &request.bin_to,
-            actual_transfer_qty,
+            &actual_transfer_qty,
             &request.user_id,
             request.remarks.as_deref().unwrap_or(""),
             request.referenced.as_deref().unwrap_or(""),
-        ).await {
-            Ok((document_no, source_lot_status, destination_lot_status)) => {
-                Ok(TransferResult {
-                    success: true,
-                    document_no,
-                    message: if is_full_transfer {
-                        format!(
-                            "Successfully transferred {} units (FULL TRANSFER) of lot {} from {} to {} - Source bin cleared",
-                            actual_transfer_qty, request.lot_no, request.bin_from, request.bin_to
-                        )
+            request.override_capacity,
+            claim,
+            |(document_no, source_lot_status, destination_lot_status)| TransferResult {
+                success: true,
+                document_no,
+                message,
+                timestamp: bangkok_now_rfc3339(),
+                source_lot_status,
+                destination_lot_status,
+                entered_qty: request.transfer_qty.clone(),
+                entered_uom,
+                transfer_qty: actual_transfer_qty.clone(),
+                stock_uom,
+            },
+        ).await
+    }
+
+
+    /// Execute a bin transfer at most once per Idempotency-Key
+    pub async fn execute_transfer_idempotent(
+        &self,
+        request: BinTransferRequest,
+        idempotency_key: Option<&str>,
+    ) -> Result<TransferResult, PutawayError> {
+        let request_hash = request_fingerprint(&request);
+        let user_id = request.user_id.clone();
+
+        self.run_idempotent(
+            IDEMPOTENCY_SCOPE_TRANSFER,
+            idempotency_key,
+            &request_hash,
+            &user_id,
+            |service, claim| async move { service.execute_claimed_transfer(request, claim.as_ref()).await },
+        ).await
+    }
+
+    /// Execute a committed transfer at most once per Idempotency-Key
+    pub async fn execute_committed_transfer_idempotent(
+        &self,
+        request: CommittedTransferRequest,
+        idempotency_key: Option<&str>,
+    ) -> Result<CommittedTransferResult, PutawayError> {
+        let request_hash = request_fingerprint(&request);
+        let user_id = request.user_id.clone();
+
+        self.run_idempotent(
+            IDEMPOTENCY_SCOPE_COMMITTED,
+            idempotency_key,
+            &request_hash,
+            &user_id,
+            |service, claim| async move { service.execute_claimed_committed_transfer(request, claim.as_ref()).await },
+        ).await
+    }
+
+    /// Claim the user's key and run the transfer, which stores its result for replay in the same
+    /// transaction that moves the stock. Failed attempts release the key - nothing moved, so a retry
+    /// may run again. An attempt whose expired lease a retry took over can no longer complete the
+    /// key, so its transaction rolls back and the stock moves once.
+    /// The claimed attempt runs as its own task: a client that disconnects mid-transfer only stops
+    /// waiting for it, and the key is still completed or released when the transfer finishes.
+    async fn run_idempotent<T, F, Fut>(
+        &self,
+        scope: &'static str,
+        idempotency_key: Option<&str>,
+        request_hash: &str,
+        user_id: &str,
+        transfer: F,
+    ) -> Result<T, PutawayError>
+    where
+        T: DeserializeOwned + Send + 'static,
+        F: FnOnce(Self, Option<IdempotencyClaim>) -> Fut,
+        Fut: Future<Output = Result<T, PutawayError>> + Send + 'static,
+    {
+        let Some(key) = idempotency_key else {
+            return transfer(self.clone(), None).await;
+        };
+
+        let reservation = self
+            .db
+            .reserve_idempotency_key(scope, user_id, key, request_hash, idempotency_ttl_hours(), constants::IDEMPOTENCY_KEY_LEASE_SECONDS)
+            .await?;
+        let claim = match reservation {
+            IdempotencyReservation::New { attempt_id } => IdempotencyClaim {
+                scope,
+                user_id: user_id.to_string(),
+                key: key.to_string(),
+                attempt_id,
+            },
+            IdempotencyReservation::Completed(body) => {
+                tracing::info!(scope, idempotency_key = key, "Replaying stored transfer result");
+                return serde_json::from_str(&body)
+                    .map_err(|e| PutawayError::DatabaseError(format!("Stored idempotent response is unreadable: {e}")));
+            }
+            // A failed completion left the key pending, but its document shows the stock moved
+            IdempotencyReservation::Applied(document_no) => {
+                return Err(PutawayError::RequestAlreadyApplied { key: key.to_string(), document_no });
+            }
+            IdempotencyReservation::InProgress => {
+                return Err(PutawayError::RequestInProgress { key: key.to_string() });
+            }
+            IdempotencyReservation::Mismatch => {
+                return Err(PutawayError::IdempotencyKeyReused { key: key.to_string() });
+            }
+        };
+
+        let attempt = transfer(self.clone(), Some(claim.clone()));
+        let db = self.db.clone();
+        let task = tokio::spawn(async move {
+            let result = attempt.await;
+
+            // A successful attempt completed the key in its own transaction; a failed one moved nothing
+            if result.is_err() {
+                if let Err(e) = db.release_idempotency_key(&claim).await {
+                    tracing::warn!(scope, idempotency_key = %claim.key, "Failed to release idempotency key: {e}");
+                }
+            }
+
+            result
+        });
+
+        task.await
+            .map_err(|e| PutawayError::TransactionError(format!("Transfer task did not finish: {e}")))?
+    }
+
+    /// Execute a multi-line transfer under a single BT document
+    pub async fn execute_batch_transfer(&self, request: BatchTransferRequest) -> Result<BatchTransferResult, PutawayError> {
+        let mut lines = request.lines;
+
+        if lines.is_empty() {
+            return Err(PutawayError::ValidationError("At least one transfer line is required".to_string()));
+        }
+        if lines.len() > MAX_BATCH_TRANSFER_LINES {
+            return Err(PutawayError::ValidationError(format!(
+                "A BT document can hold at most {MAX_BATCH_TRANSFER_LINES} lines"
+            )));
+        }
+
+        for (index, line) in lines.iter().enumerate() {
+            self.validate_transfer_fields(line)
+                .map_err(|e| line_error(index, line, e))?;
+
+            if line.location != lines[0].location {
+                return Err(PutawayError::ValidationError("All lines of a BT document must be in the same location".to_string()));
+            }
+            if line.user_id != lines[0].user_id {
+                return Err(PutawayError::ValidationError("All lines of a BT document must have the same user".to_string()));
+            }
+
+            // Each line is validated against current stock, so a lot/bin may only be touched once per document
+            let touches_same_stock = lines[..index].iter().any(|earlier| {
+                earlier.lot_no == line.lot_no
+                    && earlier.item_key == line.item_key
+                    && (earlier.bin_from == line.bin_from || earlier.bin_to == line.bin_from || earlier.bin_from == line.bin_to)
+            });
+            if touches_same_stock {
+                return Err(line_error(index, line, PutawayError::ValidationError(
+                    "The same lot/bin appears on more than one line".to_string()
+                )));
+            }
+        }
+
+        // Validate each line against the database and round it to the item's UOM decimals
+        let mut full_transfers = Vec::with_capacity(lines.len());
+        for index in 0..lines.len() {
+            let (earlier, rest) = lines.split_at_mut(index);
+            let line = &mut rest[0];
+            let (actual_transfer_qty, _, is_full_transfer) = self
+                .validate_transfer_request(line, earlier)
+                .await
+                .map_err(|e| line_error(index, line, e))?;
+
+            // The line is written in stock UOM from here on
+            line.transfer_qty = actual_transfer_qty;
+            line.uom = None;
+            full_transfers.push(is_full_transfer);
+        }
+
+        let (document_no, statuses) = self.execute_batch_bin_transfer(&lines).await?;
+
+        let total_qty: BigDecimal = lines.iter().map(|line| &line.transfer_qty).sum();
+        let line_results = lines
+            .into_iter()
+            .zip(full_transfers)
+            .zip(statuses)
+            .enumerate()
+            .map(|(index, ((line, full_transfer), (source_lot_status, destination_lot_status)))| BatchTransferLineResult {
+                line_no: (index + 1) as i16,
+                lot_no: line.lot_no,
+                item_key: line.item_key,
+                bin_from: line.bin_from,
+                bin_to: line.bin_to,
+                transfer_qty: line.transfer_qty,
+                full_transfer,
+                source_lot_status,
+                destination_lot_status,
+            })
+            .collect::<Vec<_>>();
+
+        Ok(BatchTransferResult {
+            success: true,
+            message: format!(
+                "Successfully transferred {} line(s), {} units in total (Doc: {})",
+                line_results.len(), total_qty, document_no
+            ),
+            document_no,
+            timestamp: bangkok_now_rfc3339(),
+            lines: line_results,
+        })
+    }
+
+    /// Empty a bin: move the available quantity of every lot to one destination bin.
+    /// Committed quantity (QtyCommitSales) stays behind and is reported as skipped.
+    pub async fn evacuate_bin(&self, request: BinEvacuationRequest) -> Result<BinEvacuationResult, PutawayError> {
+        if request.location.trim().is_empty() || request.bin_from.trim().is_empty() || request.bin_to.trim().is_empty() {
+            return Err(PutawayError::ValidationError("Location, source bin and destination bin are required".to_string()));
+        }
+
+        let lots = self.db.find_lots_in_bin(&request.location, &request.bin_from).await?;
+        if lots.is_empty() {
+            return Err(PutawayError::ValidationError(format!(
+                "Bin {} in location {} holds no stock", request.bin_from, request.location
+            )));
+        }
+
+        let mut conn = self.db.connect().await?;
+        let mut lines = Vec::with_capacity(lots.len());
+        let mut skipped = Vec::new();
+        for lot in lots {
+            let decimals = self.qty_decimals(&mut conn, &lot.item_key).await?;
+            let lot = lot.at_qty_scale(decimals);
+            let available = &lot.qty_on_hand - &lot.qty_commit_sales;
+
+            if lot.qty_commit_sales.is_positive() {
+                let fully_committed = !available.is_positive();
+                skipped.push(EvacuationSkippedLot {
+                    qty_left: if fully_committed { lot.qty_on_hand.clone() } else { lot.qty_commit_sales.clone() },
+                    reason: if fully_committed {
+                        "Fully committed - use a committed transfer".to_string()
                     } else {
-                        format!(
-                            "Successfully transferred {} units of lot {} from {} to {}",
-                            actual_transfer_qty, request.lot_no, request.bin_from, request.bin_to
-                        )
+                        "Committed quantity left in bin".to_string()
                     },
-                    timestamp: bangkok_now_rfc3339(),
-                    source_lot_status,
-                    destination_lot_status,
-                })
+                    lot_no: lot.lot_no.clone(),
+                    item_key: lot.item_key.clone(),
+                    qty_on_hand: lot.qty_on_hand.clone(),
+                    qty_commit_sales: lot.qty_commit_sales.clone(),
+                });
             }
-            Err(e) => {
-                Ok(TransferResult {
-                    success: false,
-                    document_no: String::new(),
-                    message: format!("Transfer failed: {e}"),
-                    timestamp: bangkok_now_rfc3339(),
-                    source_lot_status: None,
-                    destination_lot_status: None,
-                })
+
+            if available.is_positive() {
+                lines.push(BinTransferRequest {
+                    lot_no: lot.lot_no,
+                    item_key: lot.item_key,
+                    location: lot.location_key,
+                    bin_from: lot.bin_no,
+                    bin_to: request.bin_to.clone(),
+                    transfer_qty: available,
+                    uom: None,
+                    user_id: request.user_id.clone(),
+                    remarks: request.remarks.clone(),
+                    referenced: request.referenced.clone(),
+                    override_capacity: request.override_capacity,
+                });
             }
         }
+
+        if lines.is_empty() {
+            return Err(PutawayError::ValidationError(format!(
+                "Nothing to move: all {} lot(s) in bin {} are committed", skipped.len(), request.bin_from
+            )));
+        }
+        // One document holds every moved lot, so a bin with more lots cannot be emptied in one go
+        if lines.len() > MAX_BATCH_TRANSFER_LINES {
+            return Err(PutawayError::ValidationError(format!(
+                "Bin {} holds {} lots to move but one evacuation moves at most {MAX_BATCH_TRANSFER_LINES} - transfer some of them first",
+                request.bin_from, lines.len()
+            )));
+        }
+
+        // Same validation and single-document write as a batch transfer
+        let batch = self.execute_batch_transfer(BatchTransferRequest { lines }).await?;
+
+        Ok(BinEvacuationResult {
+            success: true,
+            message: if skipped.is_empty() {
+                format!("Bin {} emptied into {} (Doc: {})", request.bin_from, request.bin_to, batch.document_no)
+            } else {
+                format!(
+                    "Moved {} lot(s) from {} to {} (Doc: {}) - {} lot(s) kept back by commitments",
+                    batch.lines.len(), request.bin_from, request.bin_to, batch.document_no, skipped.len()
+                )
+            },
+            document_no: batch.document_no,
+            timestamp: bangkok_now_rfc3339(),
+            moved: batch.lines,
+            skipped,
+        })
     }
 
+    /// Reverse a completed BT document under a new document that references it
+    pub async fn reverse_transfer(&self, document_no: &str, request: TransferReversalRequest) -> Result<TransferReversalResult, PutawayError> {
+        let document_no = document_no.trim().to_uppercase();
+        if !document_no.starts_with("BT-") {
+            return Err(PutawayError::ValidationError(format!("'{document_no}' is not a BT document number")));
+        }
+        if request.user_id.trim().is_empty() {
+            return Err(PutawayError::ValidationError("User ID is required".to_string()));
+        }
+        if request.reason.trim().is_empty() {
+            return Err(PutawayError::ValidationError("A reason is required to reverse a transfer".to_string()));
+        }
+
+        let (reversal_document_no, lines) = self.execute_bt_reversal(
+            &document_no,
+            &request.user_id,
+            request.reason.trim(),
+        ).await?;
+
+        Ok(TransferReversalResult {
+            success: true,
+            message: format!(
+                "Reversed {} line(s) of {} (Doc: {})",
+                lines.len(), document_no, reversal_document_no
+            ),
+            original_document_no: document_no,
+            document_no: reversal_document_no,
+            timestamp: bangkok_now_rfc3339(),
+            lines,
+        })
+    }
 
     /// Search for lots with pagination
     pub async fn search_lots_paginated(&self, query: Option<&str>, page: i32, limit: i32) -> Result<(Vec<LotSearchItem>, i32), PutawayError> {
@@ -146,7 +605,172 @@ impl PutawayService {
         let safe_limit = if limit > 100 { 100 } else if limit < 1 { 20 } else { limit };
         
         // Search in database with pagination
-        self.db.search_lots_paginated(query, safe_page, safe_limit).await
+        let (mut items, total) = self.db.search_lots_paginated(query, safe_page, safe_limit).await?;
+
+        // Show each lot at its item's UOM decimals
+        let mut conn = self.db.connect().await?;
+        let mut item_decimals = HashMap::new();
+        for item in items.iter_mut() {
+            let decimals = match item_decimals.get(&item.item_key) {
+                Some(&decimals) => decimals,
+                None => {
+                    let decimals = self.qty_decimals(&mut conn, &item.item_key).await?;
+                    item_decimals.insert(item.item_key.clone(), decimals);
+                    decimals
+                }
+            };
+            item.qty_on_hand = round_qty(&item.qty_on_hand, decimals);
+            item.qty_commit_sales = round_qty(&item.qty_commit_sales, decimals);
+            item.qty_available = &item.qty_on_hand - &item.qty_commit_sales;
+        }
+
+        Ok((items, total))
+    }
+
+    /// Receiving-to-putaway queue: lots with stock in the receiving/dock bins, earliest
+    /// expiry first. `statuses` filters on LotStatus.
+    pub async fn get_pending_putaway_items(
+        &self,
+        receiving_bins: &[String],
+        statuses: &[String],
+        location: Option<&str>,
+        page: i32,
+        limit: i32,
+    ) -> Result<(Vec<PendingPutawayItem>, i32), PutawayError> {
+        if receiving_bins.is_empty() {
+            return Err(PutawayError::ValidationError(
+                "No receiving bins configured (PUTAWAY_RECEIVING_BINS)".to_string(),
+            ));
+        }
+
+        let safe_page = page.max(1);
+        let safe_limit = if limit > 100 { 100 } else if limit < 1 { 20 } else { limit };
+        let filter = PendingPutawayFilter { bins: receiving_bins, statuses, location };
+        let (rows, total) = self.db.get_pending_putaway_items(filter, safe_page, safe_limit).await?;
+
+        let today = bangkok_now().date_naive();
+        let mut conn = self.db.connect().await?;
+        let mut item_decimals = HashMap::new();
+        let mut items = Vec::with_capacity(rows.len());
+        for (lot, item) in rows {
+            let decimals = match item_decimals.get(&lot.item_key) {
+                Some(&decimals) => decimals,
+                None => {
+                    let decimals = self.qty_decimals(&mut conn, &lot.item_key).await?;
+                    item_decimals.insert(lot.item_key.clone(), decimals);
+                    decimals
+                }
+            };
+            let lot = lot.at_qty_scale(decimals);
+
+            items.push(PendingPutawayItem {
+                qty_available: &lot.qty_on_hand - &lot.qty_commit_sales,
+                age_days: lot.date_received.map(|date| (today - date.date()).num_days()),
+                date_received: lot.date_received.map(|date| date.format("%Y-%m-%d").to_string()),
+                date_expiry: lot.date_expiry.map(|date| date.format("%Y-%m-%d").to_string()),
+                lot_no: lot.lot_no,
+                item_key: lot.item_key,
+                item_description: item.desc1,
+                location_key: lot.location_key,
+                bin_no: lot.bin_no,
+                qty_received: lot.qty_received,
+                qty_on_hand: lot.qty_on_hand,
+                uom: item.stock_uom_code,
+                vendor_key: lot.vendor_key,
+                vendor_lot_no: lot.vendor_lot_no,
+                document_no: lot.document_no,
+                lot_status: lot.lot_status.unwrap_or_default(),
+                rec_user_id: lot.rec_user_id,
+            });
+        }
+
+        Ok((items, total))
+    }
+
+    /// BT documents written by the transfer flows, newest first unless `query` sorts otherwise
+    pub async fn get_transfer_history(
+        &self,
+        query: &TransferHistoryQuery,
+        page: i32,
+        limit: i32,
+    ) -> Result<(Vec<TransferHistoryItem>, i32), PutawayError> {
+        let date_from = history_date(&query.date_from, "date_from")?;
+        let date_to = history_date(&query.date_to, "date_to")?;
+        if let (Some(from), Some(to)) = (date_from, date_to) {
+            if from > to {
+                return Err(PutawayError::ValidationError(format!(
+                    "Invalid date range: date_from {from} is after date_to {to}"
+                )));
+            }
+        }
+
+        let sort = match query.sort.as_deref().map(str::trim).unwrap_or("") {
+            "" | "date" => TransferHistorySort::Date,
+            "document_no" => TransferHistorySort::DocumentNo,
+            "lot_no" => TransferHistorySort::LotNo,
+            "item_key" => TransferHistorySort::ItemKey,
+            "user_id" => TransferHistorySort::UserId,
+            other => {
+                return Err(PutawayError::ValidationError(format!(
+                    "Unknown sort '{other}' (date, document_no, lot_no, item_key or user_id)"
+                )))
+            }
+        };
+        let descending = match query.order.as_deref().map(str::trim).unwrap_or("") {
+            "" | "desc" => true,
+            "asc" => false,
+            other => return Err(PutawayError::ValidationError(format!("Unknown order '{other}' (asc or desc)"))),
+        };
+
+        let filter = TransferHistoryFilter {
+            user_id: non_blank(&query.user_id),
+            lot_no: non_blank(&query.lot_no),
+            item_key: non_blank(&query.item_key),
+            bin_no: non_blank(&query.bin_no),
+            location: non_blank(&query.location),
+            date_from: date_from.and_then(|date| date.and_hms_opt(0, 0, 0)),
+            // Whole last day: up to midnight of the next one
+            date_to: date_to.and_then(|date| date.succ_opt()).and_then(|date| date.and_hms_opt(0, 0, 0)),
+            sort,
+            descending,
+        };
+
+        let safe_page = page.max(1);
+        let safe_limit = if limit > 100 { 100 } else if limit < 1 { 20 } else { limit };
+        let (lines, total) = self.db.get_transfer_history(filter, safe_page, safe_limit).await?;
+
+        let mut conn = self.db.connect().await?;
+        let mut item_decimals = HashMap::new();
+        let mut items = Vec::with_capacity(lines.len());
+        for line in lines {
+            let decimals = match item_decimals.get(&line.item_key) {
+                Some(&decimals) => decimals,
+                None => {
+                    let decimals = self.qty_decimals(&mut conn, &line.item_key).await?;
+                    item_decimals.insert(line.item_key.clone(), decimals);
+                    decimals
+                }
+            };
+
+            items.push(TransferHistoryItem {
+                qty: round_qty(&line.qty, decimals),
+                document_no: line.document_no,
+                line_no: line.line_no,
+                lot_no: line.lot_no,
+                item_key: line.item_key,
+                item_description: line.item_description,
+                location: line.location,
+                bin_from: line.bin_from,
+                bin_to: line.bin_to,
+                uom: line.uom,
+                user_id: line.user_id,
+                transaction_date: line.rec_date,
+                remarks: line.remarks,
+                referenced: line.referenced,
+            });
+        }
+
+        Ok((items, total))
     }
 
     /// Get service health status
@@ -160,7 +784,7 @@ impl PutawayService {
     }
 
     /// Validate transfer request fields
-    fn validate_transfer_request(&self, request: &BinTransferRequest) -> Result<(), PutawayError> {
+    fn validate_transfer_fields(&self, request: &BinTransferRequest) -> Result<(), PutawayError> {
         if request.lot_no.trim().is_empty() {
             return Err(PutawayError::ValidationError("Lot number is required".to_string()));
         }
@@ -185,7 +809,7 @@ impl PutawayService {
             return Err(PutawayError::ValidationError("Source and destination bins cannot be the same".to_string()));
         }
 
-        if request.transfer_qty <= 0.0 {
+        if !request.transfer_qty.is_positive() {
             return Err(PutawayError::ValidationError("Transfer quantity must be greater than zero".to_string()));
         }
 
@@ -218,8 +842,13 @@ impl PutawayService {
         let safe_page = if page < 1 { 1 } else { page };
         let safe_limit = if limit > 100 { 100 } else if limit < 1 { 20 } else { limit };
 
+        let lot = match (lot_no, item_key, location) {
+            (Some(lot_no), Some(item_key), Some(location)) => Some(LotKey { lot_no, item_key, location }),
+            _ => None,
+        };
+
         // Search in database with pagination and lot context
-        self.db.search_bins_paginated(query, safe_page, safe_limit, lot_no, item_key, location).await
+        self.db.search_bins_paginated(query, safe_page, safe_limit, lot).await
     }
     /// Search for transactions associated with a lot and bin
     pub async fn search_lot_transactions(&self, lot_no: &str, bin_no: &str) -> Result<Vec<crate::models::putaway_models::LotTransactionItem>, PutawayError> {
@@ -232,8 +861,19 @@ impl PutawayService {
         self.db.find_transactions_by_lot_and_bin(lot_no, bin_no).await
     }
 
-    /// Execute transfer of committed items (BME style)
+    /// Execute transfer of committed items (BME style), without an Idempotency-Key
+    #[cfg(test)]
     pub async fn execute_committed_transfer(&self, request: crate::models::putaway_models::CommittedTransferRequest) -> Result<crate::models::putaway_models::CommittedTransferResult, PutawayError> {
+        self.execute_claimed_committed_transfer(request, None).await
+    }
+
+    /// Execute transfer of committed items (BME style), completing the idempotency `claim` in the
+    /// transfer's transaction
+    async fn execute_claimed_committed_transfer(
+        &self,
+        request: CommittedTransferRequest,
+        claim: Option<&IdempotencyClaim>,
+    ) -> Result<CommittedTransferResult, PutawayError> {
         // Validate request
         if request.to_bin.trim().is_empty() {
              return Err(PutawayError::ValidationError("Target bin is required".to_string()));
@@ -244,35 +884,1243 @@ impl PutawayService {
         if request.source_bin.trim().is_empty() {
              return Err(PutawayError::ValidationError("Source bin is required".to_string()));
         }
-        if request.transfer_qty <= 0.0 {
+        if !request.transfer_qty.is_positive() {
              return Err(PutawayError::ValidationError("Transfer quantity must be greater than 0".to_string()));
         }
+        if request.source_bin.trim() == request.to_bin.trim() {
+             return Err(PutawayError::ValidationError("Source and destination bins cannot be the same".to_string()));
+        }
+        // The commitment moves with its lines: QtyCommitSales must never leave the issues behind
+        if request.lot_tran_nos.is_empty() {
+             return Err(PutawayError::ValidationError(
+                 "Select the pending lines (lot_tran_nos) that move with the stock".to_string(),
+             ));
+        }
 
-        // Execute transfer transaction (BME style: creates 2 LotTransaction records)
-        match self.db.execute_committed_bin_transfer(
+        // Execute transfer transaction (BME style: creates 2 LotTransaction records). Every error reaches
+        // the client as its own 4xx/5xx status, like the avail-qty path.
+        self.execute_committed_bin_transfer(
             &request.lot_no,
             &request.item_key,
             &request.location,
             &request.to_bin,
-            request.transfer_qty,
+            &request.transfer_qty,
             &request.source_bin,
             &request.user_id,
             request.remarks.as_deref().unwrap_or(""),
             request.referenced.as_deref().unwrap_or(""),
-        ).await {
-            Ok(doc_no) => Ok(crate::models::putaway_models::CommittedTransferResult {
+            &request.lot_tran_nos,
+            request.override_capacity,
+            claim,
+            |(doc_no, transfer_qty, moved_lines)| CommittedTransferResult {
                 success: true,
-                message: format!("Successfully transferred {} to {} (Doc: {})", request.transfer_qty, request.to_bin, doc_no),
+                message: format!(
+                    "Successfully transferred {} to {} (Doc: {}) - {} pending line(s) moved with the stock",
+                    transfer_qty, request.to_bin, doc_no, moved_lines.len()
+                ),
                 document_no: doc_no,
                 timestamp: bangkok_now_rfc3339(),
+                moved_lines,
+            },
+        ).await
+    }
+
+    /// Everything a BT document wrote: Mintxdh header, LotTransaction pairs, BinTransfer rows and
+    /// the LotMaster quantities around each line. Documents written before the LotMaster snapshots
+    /// were recorded get the source bin's quantities derived from BinTransfer instead.
+    pub async fn get_bt_document_audit(&self, document_no: &str) -> Result<BtDocumentAudit, PutawayError> {
+        let document_no = document_no.trim();
+        let records = self.db.bt_document_records(document_no).await?;
+        if records.header.is_none() && records.lot_transactions.is_empty() {
+            return Err(PutawayError::DocumentNotFound { document_no: document_no.to_string() });
+        }
+
+        let mut conn = self.db.connect().await?;
+        let mut item_decimals = HashMap::new();
+        for item_key in records
+            .lot_transactions
+            .iter()
+            .map(|row| &row.item_key)
+            .chain(records.bin_transfers.iter().map(|row| &row.item_key))
+            .chain(records.lot_snapshots.iter().map(|row| &row.item_key))
+        {
+            if !item_decimals.contains_key(item_key) {
+                let decimals = self.qty_decimals(&mut conn, item_key).await?;
+                item_decimals.insert(item_key.clone(), decimals);
+            }
+        }
+        let rounded = |qty: &BigDecimal, item_key: &str| {
+            round_qty(qty, item_decimals.get(item_key).copied().unwrap_or(constants::DEFAULT_QTY_DECIMALS))
+        };
+
+        let lot_master = if records.lot_snapshots.is_empty() {
+            records
+                .bin_transfers
+                .iter()
+                .map(|bt| {
+                    // Issue this BinTransfer row belongs to
+                    let issue = records.lot_transactions.iter().find(|issue| {
+                        issue.transaction_type == 9
+                            && issue.lot_no == bt.lot_no
+                            && issue.bin_no == bt.bin_no_from
+                            && (issue.lot_tran_no == Some(bt.lot_tran_no) || bt.lot_tran_no == 0)
+                    });
+                    BtAuditLotSnapshot {
+                        line_no: issue.and_then(|issue| issue.issue_doc_line_no).unwrap_or(1),
+                        lot_tran_no: issue.and_then(|issue| issue.lot_tran_no),
+                        lot_no: bt.lot_no.clone(),
+                        bin_no: bt.bin_no_from.clone(),
+                        side: "FROM".to_string(),
+                        qty_on_hand_before: rounded(&bt.qty_on_hand, &bt.item_key),
+                        qty_on_hand_after: rounded(&(&bt.qty_on_hand - &bt.transfer_qty), &bt.item_key),
+                        qty_commit_before: None,
+                        qty_commit_after: None,
+                    }
+                })
+                .collect()
+        } else {
+            records
+                .lot_snapshots
+                .iter()
+                .map(|snapshot| BtAuditLotSnapshot {
+                    line_no: snapshot.line_no,
+                    lot_tran_no: snapshot.lot_tran_no,
+                    lot_no: snapshot.lot_no.clone(),
+                    bin_no: snapshot.bin_no.clone(),
+                    side: snapshot.side.clone(),
+                    qty_on_hand_before: rounded(&snapshot.qty_on_hand_before, &snapshot.item_key),
+                    qty_on_hand_after: rounded(&snapshot.qty_on_hand_after, &snapshot.item_key),
+                    qty_commit_before: Some(rounded(&snapshot.qty_commit_before, &snapshot.item_key)),
+                    qty_commit_after: Some(rounded(&snapshot.qty_commit_after, &snapshot.item_key)),
+                })
+                .collect()
+        };
+
+        let lot_transactions = records
+            .lot_transactions
+            .iter()
+            .map(|row| BtAuditLotTransaction {
+                lot_tran_no: row.lot_tran_no,
+                transaction_type: row.transaction_type,
+                line_no: row.issue_doc_line_no.or(row.receipt_doc_line_no),
+                lot_no: row.lot_no.clone(),
+                item_key: row.item_key.clone(),
+                location: row.location_key.clone(),
+                bin_no: row.bin_no.clone(),
+                qty: rounded(&row.qty_issued.clone().or(row.qty_received.clone()).unwrap_or_default(), &row.item_key),
+                processed: row.processed.clone(),
+                user_id: row.rec_userid.clone(),
+                rec_date: row.rec_date,
+            })
+            .collect();
+
+        let bin_transfers = records
+            .bin_transfers
+            .iter()
+            .map(|row| BtAuditBinTransfer {
+                lot_tran_no: row.lot_tran_no,
+                in_trans_id: row.in_trans_id,
+                lot_no: row.lot_no.clone(),
+                item_key: row.item_key.clone(),
+                location: row.location.clone(),
+                bin_from: row.bin_no_from.clone(),
+                bin_to: row.bin_no_to.clone(),
+                qty_on_hand: rounded(&row.qty_on_hand, &row.item_key),
+                transfer_qty: rounded(&row.transfer_qty, &row.item_key),
+                remarks: row.user1.clone(),
+                referenced: row.user5.clone(),
+                user_id: row.rec_user_id.clone(),
+                rec_date: row.rec_date,
+            })
+            .collect();
+
+        let (reversal_of, reversed_by) = match records.reversal {
+            Some((original, reversal)) if reversal == document_no => (Some(original), None),
+            Some((_, reversal)) => (None, Some(reversal)),
+            None => (None, None),
+        };
+
+        Ok(BtDocumentAudit {
+            document_no: document_no.to_string(),
+            header: records.header.map(|header| BtAuditHeader {
+                in_trans_id: header.in_trans_id,
+                item_key: header.item_key,
+                location: header.location,
+                doc_date: header.doc_date,
+                description: header.trn_desc,
+                in_acct: header.in_acct,
+                user_id: header.rec_user_id,
+                rec_date: header.rec_date,
             }),
-            Err(e) => Ok(crate::models::putaway_models::CommittedTransferResult {
-                success: false,
-                message: format!("Transfer failed: {e}"),
-                document_no: String::new(),
-                timestamp: bangkok_now_rfc3339(),
+            lot_transactions,
+            bin_transfers,
+            lot_master,
+            commitment_moves: records.commitment_moves,
+            reversal_of,
+            reversed_by,
+        })
+    }
+
+    /// Report BT numbers consumed from Seqnum that have no LotTransaction/BinTransfer rows
+    pub async fn get_bt_sequence_gaps(&self, from: Option<i32>, to: Option<i32>) -> Result<BtSequenceGapReport, PutawayError> {
+        let current_sequence = self.db.current_bt_sequence().await?;
+
+        let range_to = to.unwrap_or(current_sequence).min(current_sequence);
+        let range_from = from.unwrap_or(range_to - DEFAULT_BT_GAP_WINDOW + 1).max(1);
+
+        if range_from > range_to {
+            return Err(PutawayError::ValidationError(format!(
+                "Invalid BT range: from {range_from} is after to {range_to}"
+            )));
+        }
+        if range_to - range_from + 1 > MAX_BT_GAP_WINDOW {
+            return Err(PutawayError::ValidationError(format!(
+                "BT range too large: at most {MAX_BT_GAP_WINDOW} numbers per report"
+            )));
+        }
+
+        let (with_lot_transactions, with_mintxdh) = self
+            .db
+            .find_bt_documents_in_range(&bt_document_no(range_from), &bt_document_no(range_to))
+            .await?;
+
+        let gaps: Vec<BtSequenceGap> = (range_from..=range_to)
+            .map(bt_document_no)
+            .filter(|doc_no| !with_lot_transactions.contains(doc_no))
+            .map(|doc_no| BtSequenceGap {
+                has_mintxdh_header: with_mintxdh.contains(&doc_no),
+                document_no: doc_no,
             })
+            .collect();
+
+        Ok(BtSequenceGapReport {
+            current_sequence,
+            range_from,
+            range_to,
+            checked_count: range_to - range_from + 1,
+            gap_count: gaps.len(),
+            gaps,
+            timestamp: bangkok_now_rfc3339(),
+        })
+    }
+}
+
+/// Prefix a batch line's validation error with its line number so the operator can find it
+fn line_error(index: usize, line: &BinTransferRequest, error: PutawayError) -> PutawayError {
+    match error {
+        PutawayError::ValidationError(msg) => {
+            PutawayError::ValidationError(format!("Line {} (lot {}): {msg}", index + 1, line.lot_no))
+        }
+        other => other,
+    }
+}
+
+/// Configured Idempotency-Key lifetime (IDEMPOTENCY_KEY_TTL_HOURS)
+fn idempotency_ttl_hours() -> i64 {
+    std::env::var("IDEMPOTENCY_KEY_TTL_HOURS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|hours| *hours > 0)
+        .unwrap_or(constants::DEFAULT_IDEMPOTENCY_KEY_TTL_HOURS)
+}
+
+/// Receiving/dock bins the pending putaway queue is built from and destination suggestions
+/// leave out. Read once at startup and handed to the putaway routes as a request extension.
+#[derive(Debug, Clone, Default)]
+pub struct ReceivingBins(pub Vec<String>);
+
+impl ReceivingBins {
+    /// PUTAWAY_RECEIVING_BINS, comma-separated
+    pub fn from_env() -> Self {
+        Self(env_list("PUTAWAY_RECEIVING_BINS"))
+    }
+}
+
+/// Bins and aisles never suggested as putaway destinations, on top of the receiving bins and the
+/// source bin. Applied in the candidate query, so excluded bins never reach the scoring.
+#[derive(Debug, Clone, Default)]
+pub struct SuggestionRules {
+    /// PUTAWAY_SUGGEST_EXCLUDED_BINS: bin numbers, or prefixes ending in `*`
+    pub excluded_bins: Vec<String>,
+    /// PUTAWAY_SUGGEST_EXCLUDED_AISLES: whole aisles kept out of putaway
+    pub excluded_aisles: Vec<String>,
+}
+
+impl SuggestionRules {
+    pub fn from_env() -> Self {
+        Self {
+            excluded_bins: env_list("PUTAWAY_SUGGEST_EXCLUDED_BINS"),
+            excluded_aisles: env_list("PUTAWAY_SUGGEST_EXCLUDED_AISLES"),
         }
     }
 }
 
+/// Comma-separated environment list; blank entries dropped
+fn env_list(name: &str) -> Vec<String> {
+    std::env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// Trimmed filter value; blank means no filter
+fn non_blank(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|value| !value.is_empty())
+}
+
+/// Optional YYYY-MM-DD filter date; blank means no bound
+fn history_date(value: &Option<String>, field: &str) -> Result<Option<chrono::NaiveDate>, PutawayError> {
+    match non_blank(value) {
+        Some(value) => chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map(Some)
+            .map_err(|_| PutawayError::ValidationError(format!("{field} must be YYYY-MM-DD, got '{value}'"))),
+        None => Ok(None),
+    }
+}
+
+/// Stable fingerprint of a request body (FNV-1a over its JSON) to detect key reuse
+fn request_fingerprint<T: Serialize>(request: &T) -> String {
+    let json = serde_json::to_string(request).unwrap_or_default();
+    let hash = json.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::memory_db::{pending_issue, qty, transfer, warehouse, MemoryIdempotencyKey, MemoryPutawayDatabase, MemoryTables, LOT};
+    use crate::models::putaway_models::{InventoryLockReason, LotTransactionRecord, MintxdhRecord};
+    use std::convert::identity;
+
+    #[tokio::test]
+    async fn test_partial_transfer_writes_bt_document() {
+        let db = MemoryPutawayDatabase::new(warehouse("K0802-4B"));
+        let service = PutawayService::with_repository(db.clone());
+
+        let result = service.execute_transfer(transfer(LOT, "K0802-4B", "K0803-1A", "400")).await.unwrap();
+        assert!(result.success, "{}", result.message);
+        assert_eq!(result.document_no, "BT-26112174");
+        assert_eq!(result.destination_lot_status.as_deref(), Some("P"));
+
+        let tables = db.tables().await;
+        assert_eq!(tables.lot(LOT, "K0802-4B").unwrap().qty_on_hand, qty("600"));
+        let destination = tables.lot(LOT, "K0803-1A").unwrap();
+        assert_eq!((&destination.qty_on_hand, destination.transaction_type), (&qty("400"), 8));
+
+        let [issue, receipt] = &tables.lot_transactions[..] else { panic!("expected an issue/receipt pair") };
+        assert_eq!((issue.transaction_type, issue.bin_no.as_str()), (9, "K0802-4B"));
+        assert_eq!((receipt.transaction_type, receipt.bin_no.as_str()), (8, "K0803-1A"));
+        assert_eq!(issue.rec_userid, "deachawa");
+
+        assert_eq!(tables.bin_transfers.len(), 1);
+        assert_eq!(Some(tables.bin_transfers[0].lot_tran_no), issue.lot_tran_no);
+        assert_eq!(tables.bin_transfers[0].qty_on_hand, qty("1000"));
+        assert_eq!(tables.mintxdh.len(), 1);
+        assert_eq!(Some(tables.bin_transfers[0].in_trans_id), tables.mintxdh[0].in_trans_id);
+        let snapshot_links: Vec<_> = tables.lot_snapshots.iter().map(|row| row.lot_tran_no).collect();
+        assert_eq!(snapshot_links, [issue.lot_tran_no, receipt.lot_tran_no]);
+    }
+
+    #[tokio::test]
+    async fn test_lot_lookup_lists_every_bin_with_location_totals() {
+        let mut tables = warehouse("K0802-4B");
+        tables.add_lot("2600107-1", "INBC1403", "TFC1", "K0803-1A", qty("50"));
+        tables.add_lot("2600107-1", "INBC1403", "WHTAR", "A0101", qty("200"));
+        // QtyCommitSales that no pending line backs
+        tables.lot_master[0].qty_commit_sales = qty("300");
+        // A pending mfg issue in QCLotTransaction commits part of K0803-1A
+        tables.qc_lot_transactions.push(LotTransactionRecord {
+            lot_tran_no: Some(41),
+            lot_no: LOT.lot_no.to_string(),
+            item_key: LOT.item_key.to_string(),
+            location_key: LOT.location.to_string(),
+            date_received: bangkok_now_naive(),
+            date_expiry: None,
+            transaction_type: 5,
+            issue_doc_no: Some("BP-0002".to_string()),
+            issue_doc_line_no: Some(1),
+            issue_date: None,
+            qty_issued: Some(qty("20")),
+            receipt_doc_no: None,
+            receipt_doc_line_no: None,
+            qty_received: None,
+            vendor_key: None,
+            vendor_lot_no: String::new(),
+            customer_key: None,
+            bin_no: "K0803-1A".to_string(),
+            rec_userid: "WUTICHAI".to_string(),
+            rec_date: bangkok_now_naive(),
+            processed: "P".to_string(),
+            date_quarantine: None,
+            temp_qty: None,
+            qty_for_lot_assignment: None,
+            qty_used: None,
+        });
+        let service = PutawayService::with_repository(MemoryPutawayDatabase::new(tables));
+
+        let lot = service.search_lot(LOT.lot_no).await.unwrap();
+        let bins: Vec<_> = lot
+            .bins
+            .iter()
+            .map(|bin| (bin.location.as_str(), bin.bin_no.as_str(), bin.qty_committed.clone(), bin.qty_available.clone()))
+            .collect();
+        assert_eq!(bins, [
+            ("TFC1", "K0802-4B", qty("0"), qty("1000")),
+            ("TFC1", "K0803-1A", qty("20"), qty("30")),
+            ("WHTAR", "A0101", qty("0"), qty("200")),
+        ]);
+        let locations: Vec<_> =
+            lot.locations.iter().map(|total| (total.location.as_str(), total.bin_count, total.qty_available.clone())).collect();
+        assert_eq!(locations, [("TFC1", 2, qty("1030")), ("WHTAR", 1, qty("200"))]);
+        assert_eq!(lot.current_bin, "K0802-4B");
+        // The top-level figures are the first bin's LotMaster columns, which the transfer is checked against
+        assert_eq!((&lot.qty_commit_sales, &lot.qty_available), (&qty("300"), &qty("700")));
+        let over = service.execute_transfer(transfer(LOT, "K0802-4B", "K0803-1A", "701")).await;
+        assert!(matches!(over, Err(PutawayError::InsufficientQuantity { ref available, .. }) if *available == qty("700")), "{over:?}");
+    }
+
+    #[tokio::test]
+    async fn test_bin_contents_lists_every_lot_in_the_bin() {
+        let mut tables = warehouse("K0802-4B");
+        tables.add_item("INSA0102", "Salt Fine", "BAG", "TFC1");
+        tables.add_lot("2600233-4", "INSA0102", "TFC1", "K0802-4B", qty("12"));
+        tables.lot_master[1].date_expiry = chrono::NaiveDate::from_ymd_opt(2026, 3, 1).and_then(|date| date.and_hms_opt(0, 0, 0));
+        tables.lot_master[0].date_expiry = chrono::NaiveDate::from_ymd_opt(2026, 9, 1).and_then(|date| date.and_hms_opt(0, 0, 0));
+        tables.add_lot("2600107-1", "INBC1403", "TFC1", "K0803-1A", qty("50"));
+        tables.bins[0].aisle = "K08".to_string();
+        let service = PutawayService::with_repository(MemoryPutawayDatabase::new(tables));
+
+        let contents = service.get_bin_contents("TFC1", "K0802-4B").await.unwrap();
+        assert_eq!((contents.description.as_str(), contents.aisle.as_str()), ("Bin K0802-4B", "K08"));
+        let lots: Vec<_> = contents
+            .lots
+            .iter()
+            .map(|lot| (lot.lot_no.as_str(), lot.item_description.as_str(), lot.qty_available.clone(), lot.expiry_date.as_deref()))
+            .collect();
+        assert_eq!(lots, [
+            ("2600233-4", "Salt Fine", qty("12"), Some("2026-03-01")),
+            ("2600107-1", "Batter Mix", qty("1000"), Some("2026-09-01")),
+        ]);
+
+        assert!(service.get_bin_contents("TFC1", "WHKON1").await.unwrap().lots.is_empty());
+        let unknown = service.get_bin_contents("TFC1", "NOPE").await;
+        assert!(matches!(unknown, Err(PutawayError::InvalidBin { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_full_transfer_consolidates_into_existing_row() {
+        let mut tables = warehouse("K0802-4B");
+        tables.add_lot("2600107-1", "INBC1403", "TFC1", "WHKON1", qty("50"));
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+
+        // Rounded to the default 4 decimals the request is the whole available quantity -> full transfer
+        let result = service.execute_transfer(transfer(LOT, "K0802-4B", "WHKON1", "999.99995")).await.unwrap();
+        assert!(result.success, "{}", result.message);
+        assert!(result.message.contains("FULL TRANSFER"));
+
+        let tables = db.tables().await;
+        assert!(tables.lot(LOT, "K0802-4B").is_none());
+        assert_eq!(tables.lot(LOT, "WHKON1").unwrap().qty_on_hand, qty("1050"));
+    }
+
+    #[tokio::test]
+    async fn test_full_transfer_at_uom_decimals_leaves_no_residual() {
+        let mut tables = warehouse("K0802-4B");
+        // Two INUOMD rows for KG: the most precise one is used, whatever the row order
+        tables.uom_decimals.push(("KG".to_string(), 0));
+        tables.uom_decimals.push(("KG".to_string(), 3));
+        // Float residue left in LotMaster by an earlier system
+        tables.lot_master[0].qty_on_hand = qty("999.9999999");
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+
+        let lot = service.search_lot(LOT.lot_no).await.unwrap();
+        assert_eq!(lot.qty_available, qty("1000"));
+
+        let result = service.execute_transfer(transfer(LOT, "K0802-4B", "K0803-1A", "1000")).await.unwrap();
+        assert!(result.success, "{}", result.message);
+        assert!(result.message.contains("FULL TRANSFER"));
+
+        let tables = db.tables().await;
+        assert!(tables.lot(LOT, "K0802-4B").is_none());
+        assert_eq!(tables.lot(LOT, "K0803-1A").unwrap().qty_on_hand.to_string(), "1000.000");
+    }
+
+    #[tokio::test]
+    async fn test_transfer_over_available_at_uom_decimals_is_rejected() {
+        let mut tables = warehouse("K0802-4B");
+        tables.uom_decimals.push(("KG".to_string(), 3));
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+
+        let error = service.execute_transfer(transfer(LOT, "K0802-4B", "K0803-1A", "1000.0005")).await.unwrap_err();
+        assert!(matches!(
+            error,
+            PutawayError::InsufficientQuantity { ref requested, .. } if requested.to_string() == "1000.001"
+        ));
+        assert_eq!(db.tables().await.lot(LOT, "K0802-4B").unwrap().qty_on_hand, qty("1000"));
+    }
+
+    #[tokio::test]
+    async fn test_transfer_in_alternate_uom_moves_stock_uom_quantity() {
+        let mut tables = warehouse("K0802-4B");
+        tables.uom_decimals.push(("KG".to_string(), 3));
+        // 1 BAG = 25 KG; cartons are only defined the other way round (1 KG = 0.3 CTN)
+        tables.add_uom_conversion("BAG", "KG", qty("25"));
+        tables.add_uom_conversion("KG", "CTN", qty("0.3"));
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+
+        let result = service
+            .execute_transfer(BinTransferRequest { uom: Some("BAG".to_string()), ..transfer(LOT, "K0802-4B", "K0803-1A", "4") })
+            .await
+            .unwrap();
+        assert!(result.success, "{}", result.message);
+        assert_eq!((&result.entered_qty, result.entered_uom.as_str()), (&qty("4"), "BAG"));
+        assert_eq!((result.transfer_qty.to_string().as_str(), result.stock_uom.as_str()), ("100.000", "KG"));
+
+        // 1 CTN = 3.333... KG, rounded to the KG decimals
+        let result = service
+            .execute_transfer(BinTransferRequest { uom: Some("CTN".to_string()), ..transfer(LOT, "K0802-4B", "K0803-1A", "1") })
+            .await
+            .unwrap();
+        assert_eq!(result.transfer_qty.to_string(), "3.333");
+
+        let tables = db.tables().await;
+        assert_eq!(tables.lot(LOT, "K0802-4B").unwrap().qty_on_hand, qty("896.667"));
+        assert_eq!(tables.lot(LOT, "K0803-1A").unwrap().qty_on_hand, qty("103.333"));
+    }
+
+    #[tokio::test]
+    async fn test_transfer_in_unknown_uom_is_rejected() {
+        let db = MemoryPutawayDatabase::new(warehouse("K0802-4B"));
+        let service = PutawayService::with_repository(db.clone());
+
+        let error = service
+            .execute_transfer(BinTransferRequest { uom: Some("PALLET".to_string()), ..transfer(LOT, "K0802-4B", "K0803-1A", "1") })
+            .await
+            .unwrap_err();
+        assert!(matches!(error, PutawayError::ValidationError(ref message) if message.contains("PALLET")));
+        assert!(db.tables().await.lot_transactions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_transfer_rolls_back() {
+        let mut tables = warehouse("K0802-4B");
+        // No INLOC row -> the Mintxdh header cannot be written after the BT number is taken
+        tables.inloc.clear();
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+
+        let error = service.execute_transfer(transfer(LOT, "K0802-4B", "K0803-1A", "10")).await.unwrap_err();
+        assert!(matches!(error, PutawayError::DatabaseError(ref message) if message.contains("INLOC")), "{error:?}");
+
+        let tables = db.tables().await;
+        assert_eq!(tables.bt_sequence, 26112173);
+        assert!(tables.lot_transactions.is_empty());
+        assert_eq!(tables.lot(LOT, "K0802-4B").unwrap().qty_on_hand, qty("1000"));
+    }
+
+    #[tokio::test]
+    async fn test_transfers_are_refused_while_inventory_is_locked() {
+        let mut tables = warehouse("K0802-4B");
+        tables.lot_master[0].qty_commit_sales = qty("300");
+        tables.lot_transactions.push(pending_issue(17, LOT, "K0802-4B", "300"));
+        let frozen = MemoryTables { freeze_inventory: true, ..tables.clone() };
+        let mut counting = tables;
+        counting.physical_counts.push((LOT.item_key.to_string(), LOT.location.to_string()));
+
+        for tables in [frozen, counting] {
+            let db = MemoryPutawayDatabase::new(tables);
+            let service = PutawayService::with_repository(db.clone());
+
+            let single = service.execute_transfer(transfer(LOT, "K0802-4B", "K0803-1A", "10")).await;
+            assert!(matches!(single, Err(PutawayError::InventoryLocked { .. })), "{single:?}");
+            let committed = service.execute_committed_transfer(committed_transfer("WHKON1", "300", vec![17])).await;
+            assert!(matches!(committed, Err(PutawayError::InventoryLocked { .. })), "{committed:?}");
+            assert_eq!(db.tables().await.bt_sequence, 26112173);
+        }
+
+        // A freeze set after validation is still honoured by the write's own transaction
+        let db = MemoryPutawayDatabase::new(MemoryTables { freeze_inventory: true, ..warehouse("K0802-4B") });
+        let service = PutawayService::with_repository(db.clone());
+        let write = service
+            .execute_bin_transfer_transaction(LOT.lot_no, LOT.item_key, LOT.location, "K0802-4B", "K0803-1A", &qty("10"), "deachawat", "", "", false, None, identity)
+            .await;
+        assert!(matches!(write, Err(PutawayError::InventoryLocked { reason: InventoryLockReason::InventoryFrozen })), "{write:?}");
+        let tables = db.tables().await;
+        assert!(tables.lot_transactions.is_empty());
+        assert_eq!(tables.lot(LOT, "K0802-4B").unwrap().qty_on_hand, qty("1000"));
+    }
+
+    #[tokio::test]
+    async fn test_batch_transfer_writes_one_document_with_numbered_lines() {
+        let mut tables = warehouse("K0802-4B");
+        tables.add_item("INSA0102", "Salt Fine", "BAG", "TFC1");
+        tables.add_lot("2600233-4", "INSA0102", "TFC1", "WHKON1", qty("20"));
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+
+        let salt = BinTransferRequest {
+            lot_no: "2600233-4".to_string(),
+            item_key: "INSA0102".to_string(),
+            ..transfer(LOT, "WHKON1", "K0803-1A", "20")
+        };
+        let result = service
+            .execute_batch_transfer(BatchTransferRequest {
+                lines: vec![transfer(LOT, "K0802-4B", "K0803-1A", "400"), salt, transfer(LOT, "K0802-4B", "WHKON1", "100")],
+            })
+            .await;
+        // K0802-4B is touched twice
+        assert!(matches!(result, Err(PutawayError::ValidationError(ref message)) if message.starts_with("Line 3")), "{result:?}");
+
+        let salt = BinTransferRequest {
+            lot_no: "2600233-4".to_string(),
+            item_key: "INSA0102".to_string(),
+            ..transfer(LOT, "WHKON1", "K0803-1A", "20")
+        };
+        let result = service
+            .execute_batch_transfer(BatchTransferRequest { lines: vec![transfer(LOT, "K0802-4B", "K0803-1A", "400"), salt] })
+            .await
+            .unwrap();
+        assert_eq!(result.document_no, "BT-26112174");
+        let lines: Vec<_> = result.lines.iter().map(|line| (line.line_no, line.lot_no.as_str(), line.full_transfer)).collect();
+        assert_eq!(lines, [(1, "2600107-1", false), (2, "2600233-4", true)]);
+
+        let tables = db.tables().await;
+        assert_eq!(tables.bt_sequence, 26112174);
+        assert_eq!(tables.mintxdh.len(), 1);
+        let numbered: Vec<_> = tables
+            .lot_transactions
+            .iter()
+            .map(|row| (row.transaction_type, row.issue_doc_line_no.or(row.receipt_doc_line_no), row.issue_doc_no.clone().or(row.receipt_doc_no.clone())))
+            .collect();
+        let document = Some("BT-26112174".to_string());
+        assert_eq!(numbered, [(9, Some(1), document.clone()), (8, Some(1), document.clone()), (9, Some(2), document.clone()), (8, Some(2), document)]);
+        assert!(tables.bin_transfers.iter().all(|row| Some(row.in_trans_id) == tables.mintxdh[0].in_trans_id));
+        assert!(tables.lot(LotKey { lot_no: "2600233-4", item_key: "INSA0102", location: "TFC1" }, "WHKON1").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_batch_transfer_failing_on_any_line_writes_nothing() {
+        let db = MemoryPutawayDatabase::new(warehouse("K0802-4B"));
+        let service = PutawayService::with_repository(db.clone());
+
+        // Line 2 fails validation: nothing is written
+        let over = service
+            .execute_batch_transfer(BatchTransferRequest {
+                lines: vec![transfer(LOT, "K0802-4B", "K0803-1A", "400"), transfer(LOT, "K0803-1A", "WHKON1", "10")],
+            })
+            .await;
+        assert!(matches!(over, Err(PutawayError::ValidationError(ref message)) if message.starts_with("Line 2")), "{over:?}");
+
+        // Line 2 fails under the transaction, after line 1 was written: line 1 is rolled back with it
+        let lines = [transfer(LOT, "K0802-4B", "K0803-1A", "400"), transfer(LOT, "WHKON1", "K0803-1A", "10")];
+        let write = service.execute_batch_bin_transfer(&lines).await;
+        assert!(matches!(write, Err(PutawayError::InsufficientQuantity { ref available, .. }) if available == &qty("0")), "{write:?}");
+
+        let tables = db.tables().await;
+        assert_eq!(tables.bt_sequence, 26112173);
+        assert!(tables.lot_transactions.is_empty() && tables.bin_transfers.is_empty() && tables.mintxdh.is_empty());
+        assert_eq!(tables.lot(LOT, "K0802-4B").unwrap().qty_on_hand, qty("1000"));
+        assert!(tables.lot(LOT, "K0803-1A").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_batch_transfer_rules_are_validation_errors() {
+        let mut tables = warehouse("K0802-4B");
+        tables.add_lot("2600107-1", "INBC1403", "TFC1", "K0803-1A", qty("50"));
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+        let batch = |lines: Vec<BinTransferRequest>| service.execute_batch_transfer(BatchTransferRequest { lines });
+
+        let other_location = BinTransferRequest { location: "WHTAR".to_string(), ..transfer(LOT, "K0803-1A", "WHKON1", "10") };
+        let error = batch(vec![transfer(LOT, "K0802-4B", "K0803-1A", "10"), other_location]).await.unwrap_err();
+        assert!(matches!(error, PutawayError::ValidationError(ref message) if message.contains("same location")), "{error:?}");
+
+        let other_user = BinTransferRequest { user_id: "somchai".to_string(), ..transfer(LOT, "K0803-1A", "WHKON1", "10") };
+        let error = batch(vec![transfer(LOT, "K0802-4B", "K0803-1A", "10"), other_user]).await.unwrap_err();
+        assert!(matches!(error, PutawayError::ValidationError(ref message) if message.contains("same user")), "{error:?}");
+
+        // Line 2 takes stock out of the bin line 1 fills
+        let error = batch(vec![transfer(LOT, "K0802-4B", "K0803-1A", "10"), transfer(LOT, "K0803-1A", "WHKON1", "10")]).await.unwrap_err();
+        assert!(matches!(error, PutawayError::ValidationError(ref message) if message.contains("more than one line")), "{error:?}");
+
+        let too_many = (0..=MAX_BATCH_TRANSFER_LINES).map(|_| transfer(LOT, "K0802-4B", "K0803-1A", "1")).collect();
+        let error = batch(too_many).await.unwrap_err();
+        assert!(matches!(error, PutawayError::ValidationError(ref message) if message.contains("at most 100 lines")), "{error:?}");
+        assert!(matches!(batch(Vec::new()).await, Err(PutawayError::ValidationError(_))));
+
+        assert!(db.tables().await.lot_transactions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_evacuation_moves_uncommitted_stock_under_one_document() {
+        let mut tables = warehouse("K0802-4B");
+        for (lot_no, on_hand, committed) in [("2600108-1", "200", "200"), ("2600109-1", "50", "20")] {
+            tables.add_lot(lot_no, "INBC1403", "TFC1", "K0802-4B", qty(on_hand));
+            tables.lot_master.last_mut().unwrap().qty_commit_sales = qty(committed);
+        }
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+        let evacuation = |bin_to: &str| BinEvacuationRequest {
+            location: "TFC1".to_string(),
+            bin_from: "K0802-4B".to_string(),
+            bin_to: bin_to.to_string(),
+            user_id: "deachawat".to_string(),
+            remarks: Some("Rack repair".to_string()),
+            referenced: None,
+            override_capacity: false,
+        };
+
+        let result = service.evacuate_bin(evacuation("K0803-1A")).await.unwrap();
+        assert_eq!(result.document_no, "BT-26112174");
+        let moved: Vec<_> = result.moved.iter().map(|line| (line.line_no, line.lot_no.as_str(), line.transfer_qty.clone())).collect();
+        assert_eq!(moved, [(1, "2600107-1", qty("1000")), (2, "2600109-1", qty("30"))]);
+        let skipped: Vec<_> = result.skipped.iter().map(|lot| (lot.lot_no.as_str(), lot.qty_left.clone())).collect();
+        assert_eq!(skipped, [("2600108-1", qty("200")), ("2600109-1", qty("20"))]);
+        assert!(result.skipped[0].reason.starts_with("Fully committed"));
+
+        let tables = db.tables().await;
+        assert_eq!(tables.mintxdh.len(), 1);
+        let in_bin = |lot_no, bin_no| tables.lot(LotKey { lot_no, ..LOT }, bin_no).map(|row| row.qty_on_hand.clone());
+        assert_eq!(in_bin("2600107-1", "K0802-4B"), None);
+        assert_eq!(in_bin("2600108-1", "K0802-4B"), Some(qty("200")));
+        assert_eq!(in_bin("2600109-1", "K0802-4B"), Some(qty("20")));
+        assert_eq!(in_bin("2600109-1", "K0803-1A"), Some(qty("30")));
+
+        // Only committed stock is left
+        let again = service.evacuate_bin(evacuation("WHKON1")).await;
+        assert!(matches!(again, Err(PutawayError::ValidationError(ref message)) if message.starts_with("Nothing to move")), "{again:?}");
+    }
+
+    #[tokio::test]
+    async fn test_evacuation_of_more_lots_than_a_document_holds_is_refused() {
+        let mut tables = warehouse("K0802-4B");
+        for index in 0..MAX_BATCH_TRANSFER_LINES {
+            tables.add_lot(&format!("2601{index:03}-1"), "INBC1403", "TFC1", "K0802-4B", qty("1"));
+        }
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+
+        let error = service
+            .evacuate_bin(BinEvacuationRequest {
+                location: "TFC1".to_string(),
+                bin_from: "K0802-4B".to_string(),
+                bin_to: "K0803-1A".to_string(),
+                user_id: "deachawat".to_string(),
+                remarks: None,
+                referenced: None,
+                override_capacity: false,
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(error, PutawayError::ValidationError(ref message) if message.contains("101 lots to move")), "{error:?}");
+        assert!(db.tables().await.lot_transactions.is_empty());
+    }
+
+    /// putaway_idempotency_keys row for a transfer request
+    fn idempotency_key(request: &BinTransferRequest, status: &str, response_body: Option<String>, expires_at: chrono::NaiveDateTime) -> MemoryIdempotencyKey {
+        MemoryIdempotencyKey {
+            scope: IDEMPOTENCY_SCOPE_TRANSFER.to_string(),
+            user_id: request.user_id.clone(),
+            key: "scan-0042".to_string(),
+            request_hash: request_fingerprint(request),
+            status: status.to_string(),
+            response_body,
+            expires_at,
+            lease_expires_at: bangkok_now_naive() + chrono::Duration::seconds(constants::IDEMPOTENCY_KEY_LEASE_SECONDS),
+            attempt_id: "attempt-1".to_string(),
+            document_no: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_transfer_replays_the_first_result() {
+        let db = MemoryPutawayDatabase::new(warehouse("K0802-4B"));
+        let service = PutawayService::with_repository(db.clone());
+        let key = Some("scan-0042");
+
+        let first = service.execute_transfer_idempotent(transfer(LOT, "K0802-4B", "K0803-1A", "400"), key).await.unwrap();
+        let replay = service.execute_transfer_idempotent(transfer(LOT, "K0802-4B", "K0803-1A", "400"), key).await.unwrap();
+        assert_eq!((first.document_no.as_str(), replay.document_no.as_str()), ("BT-26112174", "BT-26112174"));
+        let tables = db.tables().await;
+        assert_eq!((tables.bt_sequence, tables.lot_transactions.len()), (26112174, 2));
+        assert_eq!(tables.lot(LOT, "K0802-4B").unwrap().qty_on_hand, qty("600"));
+
+        let other_body = service.execute_transfer_idempotent(transfer(LOT, "K0802-4B", "K0803-1A", "40"), key).await;
+        assert!(matches!(other_body, Err(PutawayError::IdempotencyKeyReused { .. })), "{other_body:?}");
+        // Another key is another request
+        let other_key = service.execute_transfer_idempotent(transfer(LOT, "K0802-4B", "K0803-1A", "400"), Some("scan-0043")).await.unwrap();
+        assert_eq!(other_key.document_no, "BT-26112175");
+        // Keys are per user: another picker's scan-0042 is their own request
+        let other_user = BinTransferRequest { user_id: "picker".to_string(), ..transfer(LOT, "K0802-4B", "K0803-1A", "40") };
+        let other_user = service.execute_transfer_idempotent(other_user, key).await.unwrap();
+        assert_eq!(other_user.document_no, "BT-26112176");
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_key_in_flight_or_failed() {
+        let request = transfer(LOT, "K0802-4B", "K0803-1A", "400");
+        let in_an_hour = bangkok_now_naive() + chrono::Duration::hours(1);
+        let mut tables = warehouse("K0802-4B");
+        tables.idempotency_keys.push(idempotency_key(&request, "pending", None, in_an_hour));
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+
+        let in_flight = service.execute_transfer_idempotent(request, Some("scan-0042")).await;
+        assert!(matches!(in_flight, Err(PutawayError::RequestInProgress { .. })), "{in_flight:?}");
+        assert_eq!(db.tables().await.bt_sequence, 26112173);
+
+        // An attempt that died without completing or releasing holds its key only for the lease
+        let request = transfer(LOT, "K0802-4B", "K0803-1A", "400");
+        let mut tables = warehouse("K0802-4B");
+        let mut abandoned = idempotency_key(&request, "pending", None, in_an_hour);
+        abandoned.lease_expires_at = bangkok_now_naive() - chrono::Duration::seconds(1);
+        tables.idempotency_keys.push(abandoned);
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+        let retried = service.execute_transfer_idempotent(request, Some("scan-0042")).await.unwrap();
+        assert_eq!(retried.document_no, "BT-26112174");
+        assert_eq!(db.tables().await.idempotency_keys[0].status, "completed");
+
+        // A failed attempt releases its key, so the corrected retry runs
+        let db = MemoryPutawayDatabase::new(warehouse("K0802-4B"));
+        let service = PutawayService::with_repository(db.clone());
+        let failed = service.execute_transfer_idempotent(transfer(LOT, "K0802-4B", "K0803-1A", "4000"), Some("scan-0042")).await;
+        assert!(matches!(failed, Err(PutawayError::InsufficientQuantity { .. })), "{failed:?}");
+        assert!(db.tables().await.idempotency_keys.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_key_with_a_document_is_never_taken_over() {
+        // The transfer committed but its response was not stored, and the lease has since run out
+        let request = transfer(LOT, "K0802-4B", "K0803-1A", "400");
+        let in_an_hour = bangkok_now_naive() + chrono::Duration::hours(1);
+        let mut tables = warehouse("K0802-4B");
+        let mut applied = idempotency_key(&request, "pending", None, in_an_hour);
+        applied.lease_expires_at = bangkok_now_naive() - chrono::Duration::seconds(1);
+        applied.document_no = Some("BT-26112174".to_string());
+        tables.idempotency_keys.push(applied);
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+
+        let retried = service.execute_transfer_idempotent(request, Some("scan-0042")).await;
+        assert!(
+            matches!(retried, Err(PutawayError::RequestAlreadyApplied { ref document_no, .. }) if document_no == "BT-26112174"),
+            "{retried:?}"
+        );
+        let tables = db.tables().await;
+        assert_eq!((tables.bt_sequence, tables.lot_transactions.len()), (26112173, 0));
+        assert_eq!(tables.lot(LOT, "K0802-4B").unwrap().qty_on_hand, qty("1000"));
+        assert_eq!(tables.idempotency_keys[0].status, "pending");
+    }
+
+    #[tokio::test]
+    async fn test_attempt_whose_lease_was_taken_over_cannot_complete() {
+        let request = transfer(LOT, "K0802-4B", "K0803-1A", "400");
+        let in_an_hour = bangkok_now_naive() + chrono::Duration::hours(1);
+        let mut tables = warehouse("K0802-4B");
+        let mut slow = idempotency_key(&request, "pending", None, in_an_hour);
+        slow.lease_expires_at = bangkok_now_naive() - chrono::Duration::seconds(1);
+        tables.idempotency_keys.push(slow);
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+
+        // The retry takes over the expired lease and moves the stock
+        let retried = service.execute_transfer_idempotent(transfer(LOT, "K0802-4B", "K0803-1A", "400"), Some("scan-0042")).await.unwrap();
+        assert_eq!(retried.document_no, "BT-26112174");
+
+        // The slow first attempt finishes later: its completion fails and its move rolls back
+        let stale = IdempotencyClaim {
+            scope: IDEMPOTENCY_SCOPE_TRANSFER,
+            user_id: request.user_id.clone(),
+            key: "scan-0042".to_string(),
+            attempt_id: "attempt-1".to_string(),
+        };
+        let late = service.execute_claimed_transfer(request, Some(&stale)).await;
+        assert!(matches!(late, Err(PutawayError::RequestInProgress { .. })), "{late:?}");
+        let tables = db.tables().await;
+        assert_eq!((tables.bt_sequence, tables.lot_transactions.len()), (26112174, 2));
+        assert_eq!(tables.lot(LOT, "K0802-4B").unwrap().qty_on_hand, qty("600"));
+        let [key] = &tables.idempotency_keys[..] else { panic!("expected one key") };
+        assert_eq!((key.status.as_str(), key.document_no.as_deref()), ("completed", Some("BT-26112174")));
+    }
+
+    #[tokio::test]
+    async fn test_expired_idempotency_key_executes_again() {
+        let request = transfer(LOT, "K0802-4B", "K0803-1A", "400");
+        let stored = TransferResult { document_no: "BT-26110001".to_string(), ..service_result(&request) };
+        let yesterday = bangkok_now_naive() - chrono::Duration::days(1);
+        let mut tables = warehouse("K0802-4B");
+        tables.idempotency_keys.push(idempotency_key(&request, "completed", serde_json::to_string(&stored).ok(), yesterday));
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+
+        let result = service.execute_transfer_idempotent(request, Some("scan-0042")).await.unwrap();
+        assert_eq!(result.document_no, "BT-26112174");
+        let tables = db.tables().await;
+        let [key] = &tables.idempotency_keys[..] else { panic!("expected the key to be claimed again") };
+        assert_eq!((key.status.as_str(), key.expires_at > bangkok_now_naive()), ("completed", true));
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_transfer_finishes_when_the_client_disconnects() {
+        let db = MemoryPutawayDatabase::new(warehouse("K0802-4B"));
+        let service = PutawayService::with_repository(db.clone());
+
+        // The client gives up while the transfer is running
+        let request = service.execute_transfer_idempotent(transfer(LOT, "K0802-4B", "K0803-1A", "400"), Some("scan-0042"));
+        assert!(poll_once(request).await.is_pending());
+
+        while db.tables().await.idempotency_keys.first().is_some_and(|key| key.status == "pending") {
+            tokio::task::yield_now().await;
+        }
+        let tables = db.tables().await;
+        assert_eq!(tables.idempotency_keys[0].status, "completed");
+        assert_eq!(tables.lot(LOT, "K0802-4B").unwrap().qty_on_hand, qty("600"));
+
+        // Its retry gets the stored result instead of 409 or a second transfer
+        let retry = service.execute_transfer_idempotent(transfer(LOT, "K0802-4B", "K0803-1A", "400"), Some("scan-0042")).await.unwrap();
+        assert_eq!(retry.document_no, "BT-26112174");
+        assert_eq!(db.tables().await.bt_sequence, 26112174);
+    }
+
+    /// Poll a future once and drop it, as axum does with a handler whose client went away
+    async fn poll_once<F: Future>(future: F) -> std::task::Poll<F::Output> {
+        let mut future = std::pin::pin!(future);
+        std::future::poll_fn(|cx| std::task::Poll::Ready(future.as_mut().poll(cx))).await
+    }
+
+    /// A successful result as stored for a request
+    fn service_result(request: &BinTransferRequest) -> TransferResult {
+        TransferResult {
+            success: true,
+            document_no: String::new(),
+            message: "Successfully transferred".to_string(),
+            timestamp: bangkok_now_rfc3339(),
+            source_lot_status: None,
+            destination_lot_status: None,
+            entered_qty: request.transfer_qty.clone(),
+            entered_uom: "KG".to_string(),
+            transfer_qty: request.transfer_qty.clone(),
+            stock_uom: "KG".to_string(),
+        }
+    }
+
+    fn reversal(reason: &str) -> TransferReversalRequest {
+        TransferReversalRequest { user_id: "supervisor".to_string(), reason: reason.to_string() }
+    }
+
+    #[tokio::test]
+    async fn test_reversal_moves_stock_back_under_a_linked_document() {
+        let db = MemoryPutawayDatabase::new(warehouse("K0802-4B"));
+        let service = PutawayService::with_repository(db.clone());
+        let original = service.execute_transfer(transfer(LOT, "K0802-4B", "K0803-1A", "400")).await.unwrap();
+
+        let result = service.reverse_transfer(&original.document_no.to_lowercase(), reversal("Wrong bin")).await.unwrap();
+        assert_eq!((result.original_document_no.as_str(), result.document_no.as_str()), ("BT-26112174", "BT-26112175"));
+        let [line] = &result.lines[..] else { panic!("expected one reversed line") };
+        assert_eq!((line.bin_from.as_str(), line.bin_to.as_str(), &line.qty), ("K0803-1A", "K0802-4B", &qty("400")));
+        assert!(!line.source_row_recreated);
+
+        let tables = db.tables().await;
+        assert_eq!(tables.lot(LOT, "K0802-4B").unwrap().qty_on_hand, qty("1000"));
+        assert!(tables.lot(LOT, "K0803-1A").is_none());
+        let reversal_row = tables.bin_transfers.last().unwrap();
+        assert_eq!((reversal_row.user1.as_str(), reversal_row.user5.as_str()), ("Wrong bin", "BT-26112174"));
+        assert_eq!(tables.bt_reversals, [("BT-26112174".to_string(), "BT-26112175".to_string(), "Wrong bin".to_string())]);
+
+        // One reversal per document, and a reversal is not reversed in turn
+        let twice = service.reverse_transfer("BT-26112174", reversal("Again")).await;
+        assert!(matches!(twice, Err(PutawayError::ValidationError(ref message)) if message.contains("already reversed by BT-26112175")), "{twice:?}");
+        let of_reversal = service.reverse_transfer("BT-26112175", reversal("Undo the undo")).await;
+        assert!(matches!(of_reversal, Err(PutawayError::ValidationError(ref message)) if message.contains("is the reversal of BT-26112174")), "{of_reversal:?}");
+        assert_eq!(db.tables().await.bt_sequence, 26112175);
+    }
+
+    #[tokio::test]
+    async fn test_reversal_of_a_full_transfer_recreates_the_source_row() {
+        let db = MemoryPutawayDatabase::new(warehouse("K0802-4B"));
+        let service = PutawayService::with_repository(db.clone());
+        let original = service.execute_transfer(transfer(LOT, "K0802-4B", "K0803-1A", "1000")).await.unwrap();
+        assert!(db.tables().await.lot(LOT, "K0802-4B").is_none());
+
+        let result = service.reverse_transfer(&original.document_no, reversal("Wrong bin")).await.unwrap();
+        assert!(result.lines[0].source_row_recreated);
+        let tables = db.tables().await;
+        assert_eq!(tables.lot(LOT, "K0802-4B").unwrap().qty_on_hand, qty("1000"));
+        assert!(tables.lot(LOT, "K0803-1A").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reversal_is_refused_for_committed_or_consumed_stock() {
+        let mut tables = warehouse("K0802-4B");
+        tables.lot_master[0].qty_commit_sales = qty("300");
+        tables.lot_transactions.push(pending_issue(17, LOT, "K0802-4B", "300"));
+        tables.add_lot("2600107-1", "INBC1403", "TFC1", "WHKON1", qty("100"));
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+
+        // The committed flow leaves its pair pending (Processed <> 'Y')
+        let committed = service.execute_committed_transfer(committed_transfer("K0803-1A", "300", vec![17])).await.unwrap();
+        let error = service.reverse_transfer(&committed.document_no, reversal("Wrong bin")).await.unwrap_err();
+        assert!(matches!(error, PutawayError::ValidationError(ref message) if message.contains("committed transfer")), "{error:?}");
+
+        // 750 of the 800 now in K0802-4B gets committed: less than the 100 moved there is available
+        let moved = service.execute_transfer(transfer(LOT, "WHKON1", "K0802-4B", "100")).await.unwrap();
+        let mut tables = db.tables().await;
+        tables.lot_master.iter_mut().find(|row| row.bin_no == "K0802-4B").unwrap().qty_commit_sales = qty("750");
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+        let error = service.reverse_transfer(&moved.document_no, reversal("Wrong bin")).await.unwrap_err();
+        assert!(matches!(error, PutawayError::ValidationError(ref message) if message.contains("consumed or committed")), "{error:?}");
+
+        assert!(matches!(service.reverse_transfer("BT-00000001", reversal("Typo")).await, Err(PutawayError::DocumentNotFound { .. })));
+        assert!(db.tables().await.bt_reversals.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_committed_transfer_moves_commitment_lines() {
+        // 300 of the lot in K0802-4B committed by pending issue line 17
+        let mut tables = warehouse("K0802-4B");
+        tables.lot_master[0].qty_commit_sales = qty("300");
+        tables.lot_transactions.push(pending_issue(17, LOT, "K0802-4B", "300"));
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+
+        // Entered past the 4 KG decimals: the message shows what was written
+        let result = service.execute_committed_transfer(committed_transfer("WHKON1", "300.00004", vec![17])).await.unwrap();
+        assert!(result.success, "{}", result.message);
+        assert!(result.message.starts_with("Successfully transferred 300.0000 to WHKON1"), "{}", result.message);
+        assert_eq!(result.moved_lines.len(), 1);
+
+        let tables = db.tables().await;
+        let source = tables.lot(LOT, "K0802-4B").unwrap();
+        assert_eq!((&source.qty_on_hand, &source.qty_commit_sales), (&qty("700"), &qty("0")));
+        let destination = tables.lot(LOT, "WHKON1").unwrap();
+        assert_eq!((&destination.qty_on_hand, &destination.qty_commit_sales), (&qty("300"), &qty("300")));
+
+        let picked = tables.lot_transactions.iter().find(|row| row.lot_tran_no == Some(17)).unwrap();
+        assert_eq!(picked.bin_no, "WHKON1");
+        assert_eq!(tables.commitment_moves.len(), 1);
+        // BinTransfer points at this document's issue row and Mintxdh header
+        let issue = tables.lot_transactions.iter().find(|row| row.issue_doc_no.as_deref() == Some(result.document_no.as_str())).unwrap();
+        assert_eq!(Some(tables.bin_transfers[0].lot_tran_no), issue.lot_tran_no);
+        assert_eq!(Some(tables.bin_transfers[0].in_trans_id), tables.mintxdh.last().unwrap().in_trans_id);
+
+        // The audit finds the BinTransfer row and shows the commitment moving with the stock
+        let audit = service.get_bt_document_audit(&result.document_no).await.unwrap();
+        assert_eq!((audit.bin_transfers.len(), audit.commitment_moves.len()), (1, 1));
+        let commits: Vec<_> = audit.lot_master.iter().map(|row| (row.qty_commit_before.clone(), row.qty_commit_after.clone())).collect();
+        assert_eq!(commits, [(Some(qty("300")), Some(qty("0"))), (Some(qty("0")), Some(qty("300")))]);
+
+        // The document's own pending issue row left in K0802-4B commits nothing in the lookups
+        let lot = service.search_lot(LOT.lot_no).await.unwrap();
+        let bins: Vec<_> = lot.bins.iter().map(|bin| (bin.bin_no.as_str(), bin.qty_committed.clone())).collect();
+        assert_eq!(bins, [("K0802-4B", qty("0")), ("WHKON1", qty("300"))]);
+        let contents = service.get_bin_contents("TFC1", "K0802-4B").await.unwrap();
+        assert_eq!((&contents.lots[0].qty_committed, &contents.lots[0].qty_available), (&qty("0"), &qty("700")));
+    }
+
+    #[tokio::test]
+    async fn test_committed_transfers_in_a_row_only_move_the_commitment_once() {
+        let mut tables = warehouse("K0802-4B");
+        tables.lot_master[0].qty_commit_sales = qty("300");
+        tables.lot_transactions.push(pending_issue(17, LOT, "K0802-4B", "300"));
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+
+        service.execute_committed_transfer(committed_transfer("WHKON1", "300", vec![17])).await.unwrap();
+        // The first document's own pending issue row in K0802-4B is not a commitment to move again
+        let again = service.execute_committed_transfer(committed_transfer("K0803-1A", "300", vec![17])).await;
+        assert!(matches!(again, Err(PutawayError::InsufficientQuantity { ref available, .. }) if *available == qty("0")), "{again:?}");
+        assert_eq!(db.tables().await.bt_sequence, 26112174);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_committed_transfers_write_nothing() {
+        let mut tables = warehouse("K0802-4B");
+        tables.lot_master[0].qty_commit_sales = qty("300");
+        tables.lot_transactions.push(pending_issue(17, LOT, "K0802-4B", "300"));
+        let db = MemoryPutawayDatabase::new(tables);
+        let service = PutawayService::with_repository(db.clone());
+
+        // Without its lines the commitment would leave the Mfg. Issue pointing at the source bin
+        let no_lines = service.execute_committed_transfer(committed_transfer("WHKON1", "300", Vec::new())).await;
+        assert!(matches!(no_lines, Err(PutawayError::ValidationError(ref message)) if message.contains("lot_tran_nos")), "{no_lines:?}");
+
+        let same_bin = service.execute_committed_transfer(committed_transfer("K0802-4B", "300", vec![17])).await;
+        assert!(matches!(same_bin, Err(PutawayError::ValidationError(_))), "{same_bin:?}");
+        let unknown_bin = service.execute_committed_transfer(committed_transfer("K9999-9Z", "300", vec![17])).await;
+        assert!(matches!(unknown_bin, Err(PutawayError::InvalidBin { .. })), "{unknown_bin:?}");
+
+        let tables = db.tables().await;
+        assert_eq!((tables.bt_sequence, tables.mintxdh.len(), tables.lot_transactions.len()), (26112173, 0, 1));
+    }
+
+    #[tokio::test]
+    async fn test_bt_document_audit_shows_rows_and_lot_master_changes() {
+        let mut tables = warehouse("K0802-4B");
+        tables.add_lot("2600107-1", "INBC1403", "TFC1", "K0803-1A", qty("50"));
+        let service = PutawayService::with_repository(MemoryPutawayDatabase::new(tables));
+        let request = BinTransferRequest { referenced: Some("PO-88".to_string()), ..transfer(LOT, "K0802-4B", "K0803-1A", "400") };
+        let result = service.execute_transfer(request).await.unwrap();
+
+        let audit = service.get_bt_document_audit(&format!(" {} ", result.document_no)).await.unwrap();
+        assert_eq!(audit.header.as_ref().map(|header| header.description.as_str()), Some("Bin Transfer"));
+        let lines: Vec<_> = audit.lot_transactions.iter().map(|row| (row.transaction_type, row.bin_no.as_str(), row.processed.as_str())).collect();
+        assert_eq!(lines, [(9, "K0802-4B", "Y"), (8, "K0803-1A", "Y")]);
+        let [bin_transfer] = &audit.bin_transfers[..] else { panic!("expected one BinTransfer row") };
+        assert_eq!((bin_transfer.remarks.as_str(), bin_transfer.referenced.as_str()), ("Putaway", "PO-88"));
+
+        let lot_master: Vec<_> = audit
+            .lot_master
+            .iter()
+            .map(|row| (row.side.as_str(), row.bin_no.as_str(), row.qty_on_hand_before.clone(), row.qty_on_hand_after.clone()))
+            .collect();
+        assert_eq!(lot_master, [("FROM", "K0802-4B", qty("1000"), qty("600")), ("TO", "K0803-1A", qty("50"), qty("450"))]);
+
+        let missing = service.get_bt_document_audit("BT-00000001").await;
+        assert!(matches!(missing, Err(PutawayError::DocumentNotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_pending_putaway_lists_receiving_bins_by_expiry() {
+        let mut tables = warehouse("K0802-4B");
+        tables.add_lot("2600201-1", "INBC1403", "TFC1", "RECV1", qty("40"));
+        tables.add_lot("2600202-1", "INBC1403", "TFC1", "RECV2", qty("60"));
+        tables.add_lot("2600203-1", "INBC1403", "TFC1", "RECV2", qty("10"));
+        let now = bangkok_now_naive();
+        tables.lot_master[1].date_received = Some(now - chrono::Duration::days(3));
+        tables.lot_master[1].date_expiry = Some(now + chrono::Duration::days(90));
+        tables.lot_master[2].date_expiry = Some(now + chrono::Duration::days(30));
+        tables.lot_master[3].lot_status = Some("H".to_string());
+        let service = PutawayService::with_repository(MemoryPutawayDatabase::new(tables));
+        let receiving_bins = ["RECV1".to_string(), "RECV2".to_string()];
+
+        // K0802-4B is a storage bin, so only the receiving lots are listed; no expiry sorts last
+        let (items, total) = service.get_pending_putaway_items(&receiving_bins, &[], None, 1, 20).await.unwrap();
+        assert_eq!(total, 3);
+        let lots: Vec<&str> = items.iter().map(|item| item.lot_no.as_str()).collect();
+        assert_eq!(lots, ["2600202-1", "2600201-1", "2600203-1"]);
+        assert_eq!(items[1].age_days, Some(3));
+
+        let (items, total) = service.get_pending_putaway_items(&receiving_bins, &["H".to_string()], Some("TFC1"), 1, 20).await.unwrap();
+        assert_eq!((total, items[0].lot_no.as_str()), (1, "2600203-1"));
+    }
+
+    #[tokio::test]
+    async fn test_transfer_history_filters_bt_documents() {
+        let service = PutawayService::with_repository(MemoryPutawayDatabase::new(warehouse("K0802-4B")));
+        service.execute_transfer(transfer(LOT, "K0802-4B", "K0803-1A", "400")).await.unwrap();
+        let second = BinTransferRequest { user_id: "somchai".to_string(), ..transfer(LOT, "K0803-1A", "WHKON1", "100") };
+        service.execute_transfer(second).await.unwrap();
+
+        let history = |query: TransferHistoryQuery| {
+            let service = &service;
+            async move { service.get_transfer_history(&query, 1, 20).await }
+        };
+
+        let (items, total) = history(TransferHistoryQuery::default()).await.unwrap();
+        assert_eq!(total, 2);
+        assert_eq!((items[0].document_no.as_str(), items[1].document_no.as_str()), ("BT-26112175", "BT-26112174"));
+        assert_eq!((items[1].bin_to.as_str(), &items[1].qty, items[1].remarks.as_str()), ("K0803-1A", &qty("400"), "Putaway"));
+
+        // Either side of the move matches the bin; user IDs compare like the SQL collation
+        let by_bin = TransferHistoryQuery { bin_no: Some("WHKON1".to_string()), ..Default::default() };
+        assert_eq!(history(by_bin).await.unwrap().1, 1);
+        let by_user = TransferHistoryQuery { user_id: Some("SOMCHAI".to_string()), ..Default::default() };
+        assert_eq!(history(by_user).await.unwrap().0[0].document_no, "BT-26112175");
+        let oldest_first = TransferHistoryQuery { order: Some("asc".to_string()), ..Default::default() };
+        assert_eq!(history(oldest_first).await.unwrap().0[0].document_no, "BT-26112174");
+
+        let tomorrow = (bangkok_now_naive().date() + chrono::Duration::days(1)).format("%Y-%m-%d").to_string();
+        let future = TransferHistoryQuery { date_from: Some(tomorrow), ..Default::default() };
+        assert_eq!(history(future).await.unwrap().1, 0);
+
+        let bad_date = TransferHistoryQuery { date_to: Some("16/10/2026".to_string()), ..Default::default() };
+        assert!(matches!(history(bad_date).await, Err(PutawayError::ValidationError(_))));
+        let bad_sort = TransferHistoryQuery { sort: Some("qty".to_string()), ..Default::default() };
+        assert!(matches!(history(bad_sort).await, Err(PutawayError::ValidationError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_transfer_dropped_mid_transaction_rolls_back() {
+        let db = MemoryPutawayDatabase::new(warehouse("K0802-4B"));
+        let service = PutawayService::with_repository(db.clone());
+
+        // Cancelled holding BT-26112174, before any row of the document is written
+        assert!(poll_once(service.execute_transfer(transfer(LOT, "K0802-4B", "K0803-1A", "400"))).await.is_pending());
+
+        let tables = db.tables().await;
+        assert_eq!((tables.bt_sequence, tables.lot_transactions.len()), (26112173, 0));
+        let next = service.execute_transfer(transfer(LOT, "K0802-4B", "K0803-1A", "400")).await.unwrap();
+        assert_eq!(next.document_no, "BT-26112174");
+    }
+
+    #[tokio::test]
+    async fn test_bt_gap_report_lists_unused_and_half_written_numbers() {
+        let mut tables = warehouse("K0802-4B");
+        // BT-26112170 got its Mintxdh header but no LotTransaction rows; 171-173 were never written
+        tables.mintxdh.push(MintxdhRecord {
+            in_trans_id: Some(5001),
+            item_key: LOT.item_key.to_string(),
+            location: LOT.location.to_string(),
+            doc_no: "BT-26112170".to_string(),
+            doc_date: bangkok_now_naive(),
+            trn_desc: "Bin Transfer".to_string(),
+            in_acct: "1410".to_string(),
+            rec_user_id: "deachawa".to_string(),
+            rec_date: bangkok_now_naive(),
+            std_cost: 0.0,
+        });
+        let service = PutawayService::with_repository(MemoryPutawayDatabase::new(tables));
+        service.execute_transfer(transfer(LOT, "K0802-4B", "K0803-1A", "400")).await.unwrap();
+        service.execute_transfer(transfer(LOT, "K0803-1A", "WHKON1", "100")).await.unwrap();
+
+        let report = service.get_bt_sequence_gaps(Some(26112170), None).await.unwrap();
+        assert_eq!((report.current_sequence, report.range_from, report.range_to), (26112175, 26112170, 26112175));
+        assert_eq!((report.checked_count, report.gap_count), (6, 4));
+        let gaps: Vec<_> = report.gaps.iter().map(|gap| (gap.document_no.as_str(), gap.has_mintxdh_header)).collect();
+        assert_eq!(gaps, [
+            ("BT-26112170", true),
+            ("BT-26112171", false),
+            ("BT-26112172", false),
+            ("BT-26112173", false),
+        ]);
+
+        // The range ends at the current sequence; the default window ends there too
+        let report = service.get_bt_sequence_gaps(None, Some(26112999)).await.unwrap();
+        assert_eq!((report.range_to, report.gaps.last().map(|gap| gap.document_no.as_str())), (26112175, Some("BT-26112173")));
+        let backwards = service.get_bt_sequence_gaps(Some(26112175), Some(26112174)).await;
+        assert!(matches!(backwards, Err(PutawayError::ValidationError(_))));
+        let too_large = service.get_bt_sequence_gaps(Some(1), None).await;
+        assert!(matches!(too_large, Err(PutawayError::ValidationError(ref message)) if message.contains("too large")));
+    }
+
+    fn committed_transfer(to_bin: &str, transfer_qty: &str, lot_tran_nos: Vec<i32>) -> CommittedTransferRequest {
+        CommittedTransferRequest {
+            lot_no: LOT.lot_no.to_string(),
+            item_key: LOT.item_key.to_string(),
+            location: LOT.location.to_string(),
+            source_bin: "K0802-4B".to_string(),
+            to_bin: to_bin.to_string(),
+            transfer_qty: qty(transfer_qty),
+            user_id: "deachawat".to_string(),
+            remarks: None,
+            referenced: None,
+            lot_tran_nos,
+            override_capacity: false,
+        }
+    }
+
+    fn bangkok_now_naive() -> chrono::NaiveDateTime {
+        crate::utils::bangkok_now().naive_local()
+    }
+}
@@ -0,0 +1,142 @@
+use crate::database::repository::PutawayRepository;
+use crate::models::putaway::{ScanData, ScanRequest, ScanResponse, ScanType};
+use crate::models::putaway_models::PutawayError;
+use crate::services::putaway_service::PutawayService;
+use crate::utils::gs1::{self, Gs1Data};
+
+/// Order a barcode is tried in when the scanner does not say what it is
+const AUTO_DETECT_ORDER: [ScanType; 3] = [ScanType::Lot, ScanType::Bin, ScanType::Item];
+
+/// Barcode scans: GS1-128 labels and plain lot, bin and item numbers,
+/// resolved against LotMaster, BINMaster and INMAST
+impl<R: PutawayRepository> PutawayService<R> {
+    /// Resolve one raw scan
+    pub async fn scan(&self, request: ScanRequest) -> Result<ScanResponse, PutawayError> {
+        let barcode = request.barcode.trim();
+        if barcode.is_empty() {
+            return Err(PutawayError::ValidationError("Barcode cannot be empty".to_string()));
+        }
+
+        // Inbound labels carry the lot as batch (10); only lot scans can be GS1
+        if matches!(request.scan_type, None | Some(ScanType::Lot)) && gs1::is_gs1(barcode) {
+            let label = gs1::decode(barcode).map_err(PutawayError::ValidationError)?;
+            return self.resolve_gs1(label).await;
+        }
+
+        let candidates = match request.scan_type {
+            Some(scan_type) => vec![scan_type],
+            None => AUTO_DETECT_ORDER.to_vec(),
+        };
+
+        for scan_type in candidates {
+            if let Some(data) = self.resolve(scan_type, barcode).await? {
+                return Ok(ScanResponse {
+                    valid: true,
+                    scan_type: Some(scan_type),
+                    message: format!("{scan_type:?} found"),
+                    data: Some(data),
+                    gs1: None,
+                });
+            }
+        }
+
+        Ok(ScanResponse {
+            valid: false,
+            scan_type: request.scan_type,
+            data: None,
+            gs1: None,
+            message: match request.scan_type {
+                Some(scan_type) => format!("{scan_type:?} '{barcode}' not found"),
+                None => format!("No lot, bin or item matches '{barcode}'"),
+            },
+        })
+    }
+
+    /// Lot of a GS1-128 label, looked up by its batch number
+    async fn resolve_gs1(&self, label: Gs1Data) -> Result<ScanResponse, PutawayError> {
+        let Some(batch) = label.batch.clone() else {
+            return Ok(ScanResponse {
+                valid: false,
+                scan_type: None,
+                data: None,
+                gs1: Some(label),
+                message: "GS1 label has no batch/lot number (AI 10)".to_string(),
+            });
+        };
+
+        let data = self.resolve(ScanType::Lot, &batch).await?;
+        Ok(ScanResponse {
+            valid: data.is_some(),
+            scan_type: data.as_ref().map(|_| ScanType::Lot),
+            message: if data.is_some() { "Lot found".to_string() } else { format!("Lot '{batch}' not found") },
+            data,
+            gs1: Some(label),
+        })
+    }
+
+    async fn resolve(&self, scan_type: ScanType, barcode: &str) -> Result<Option<ScanData>, PutawayError> {
+        match scan_type {
+            ScanType::Lot => match self.search_lot(barcode).await {
+                Ok(lot) => Ok(Some(ScanData::Lot(Box::new(lot)))),
+                Err(PutawayError::LotNotFound { .. }) => Ok(None),
+                Err(e) => Err(e),
+            },
+            ScanType::Bin => {
+                let bins = self.db.find_bins_by_number(barcode).await?;
+                Ok(bins.first().map(|bin| ScanData::Bin {
+                    bin_no: bin.bin_no.clone(),
+                    description: bin.description.clone(),
+                    locations: bins.iter().map(|bin| bin.location.clone()).collect(),
+                }))
+            }
+            ScanType::Item => {
+                let mut conn = self.db.connect().await?;
+                Ok(self.db.item_master(&mut conn, barcode).await?.map(|item| ScanData::Item {
+                    item_key: item.item_key,
+                    description: item.desc1,
+                    uom: item.stock_uom_code,
+                }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::memory_db::{warehouse, MemoryPutawayDatabase};
+    use crate::models::putaway::{ScanData, ScanRequest, ScanType};
+    use crate::services::PutawayService;
+
+    fn scan(barcode: &str) -> ScanRequest {
+        ScanRequest { barcode: barcode.to_string(), scan_type: None }
+    }
+
+    #[tokio::test]
+    async fn test_plain_barcodes_are_auto_detected() {
+        let service = PutawayService::with_repository(MemoryPutawayDatabase::new(warehouse("K0802-4B")));
+
+        let lot = service.scan(scan("2600107-1")).await.unwrap();
+        assert_eq!(lot.scan_type, Some(ScanType::Lot));
+        let bin = service.scan(scan("K0802-4B")).await.unwrap();
+        assert!(matches!(bin.data, Some(ScanData::Bin { ref locations, .. }) if locations == &["TFC1"]));
+        let item = service.scan(scan("INBC1403")).await.unwrap();
+        assert!(matches!(item.data, Some(ScanData::Item { ref uom, .. }) if uom == "KG"));
+
+        let unknown = service.scan(scan("NOPE")).await.unwrap();
+        assert!(!unknown.valid && unknown.data.is_none());
+        // An explicit type is not auto-detected further
+        let not_a_bin = service.scan(ScanRequest { scan_type: Some(ScanType::Bin), ..scan("2600107-1") }).await.unwrap();
+        assert!(!not_a_bin.valid);
+    }
+
+    #[tokio::test]
+    async fn test_gs1_label_resolves_lot_and_count() {
+        let service = PutawayService::with_repository(MemoryPutawayDatabase::new(warehouse("K0802-4B")));
+
+        let response = service.scan(scan("]C1010950123456789017261231102600107-1\u{1d}3740")).await.unwrap();
+        assert!(response.valid, "{}", response.message);
+        let Some(ScanData::Lot(lot)) = &response.data else { panic!("expected a lot") };
+        assert_eq!((lot.item_key.as_str(), lot.current_bin.as_str()), ("INBC1403", "K0802-4B"));
+        assert_eq!(response.gs1.as_ref().and_then(|label| label.count), Some(40));
+    }
+}
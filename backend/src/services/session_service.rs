@@ -0,0 +1,334 @@
+use crate::database::auth_session_db::SessionRepository;
+use crate::types::{LoginResponse, User};
+use crate::utils::auth::{hash_refresh_token, new_refresh_token, AuthSource};
+use crate::utils::timezone::BANGKOK_TZ;
+use crate::utils::{bangkok_now, AuthService};
+use anyhow::Result;
+use chrono::TimeZone;
+use std::future::Future;
+use tracing::{info, warn};
+
+/// Where a refresh looks up the roles a user holds now
+pub trait RoleDirectory: Send + Sync {
+    /// Current roles of the user in the directory they signed in against; None if the
+    /// account is gone, disabled or cannot be looked up - the user has to sign in again
+    fn current_roles(&self, user: &User, source: AuthSource) -> impl Future<Output = Result<Option<Vec<String>>>> + Send;
+}
+
+/// Login sessions: an access token plus a rotating refresh token each, ended by logout,
+/// refresh token reuse or an admin
+#[derive(Clone)]
+pub struct SessionService<S: SessionRepository> {
+    auth: AuthService,
+    sessions: S,
+}
+
+impl<S: SessionRepository> SessionService<S> {
+    pub fn new(auth: AuthService, sessions: S) -> Self {
+        Self { auth, sessions }
+    }
+
+    /// Sign the access token of a new login session and store its first refresh token.
+    /// If the session cannot be stored the user still gets an access token, just no refresh token.
+    pub async fn start_session(&self, user: User, source: AuthSource) -> Result<LoginResponse> {
+        let session_id = uuid::Uuid::new_v4().to_string();
+        let mut token = self.auth.generate_token(&user, &session_id)?;
+
+        let refresh_token = new_refresh_token();
+        match self
+            .sessions
+            .create_session(&session_id, &hash_refresh_token(&refresh_token), &user, source, self.auth.refresh_token_duration())
+            .await
+        {
+            Ok(_) => {
+                token.refresh_token = Some(refresh_token);
+                token.refresh_expires_at = Some((chrono::Utc::now() + self.auth.refresh_token_duration()).timestamp());
+            }
+            Err(e) => warn!("⚠️ Failed to store refresh token for {}: {:#}", user.username, e),
+        }
+
+        Ok(LoginResponse { token, user })
+    }
+
+    /// Exchange a refresh token for a new access token and a new refresh token (rotation), carrying
+    /// the roles the directory gives the user now. None means the caller has to sign in again.
+    /// Presenting an already exchanged token revokes the whole session - it was copied.
+    pub async fn refresh(&self, refresh_token: &str, directory: &impl RoleDirectory) -> Result<Option<LoginResponse>> {
+        let token_hash = hash_refresh_token(refresh_token.trim());
+        let Some(record) = self.sessions.find_refresh_token(&token_hash).await? else {
+            return Ok(None);
+        };
+
+        if record.revoked {
+            return Ok(None);
+        }
+
+        if record.replaced {
+            warn!("🚨 Refresh token reuse for {} - revoking session {}", record.user.username, record.session_id);
+            self.revoke_sessions(std::slice::from_ref(&record.session_id)).await;
+            return Ok(None);
+        }
+
+        if record.expires_at < bangkok_now().naive_local() {
+            return Ok(None);
+        }
+
+        // A demoted or disabled account keeps its old roles no longer than one access token
+        let roles = match record.source {
+            Some(source) => directory.current_roles(&record.user, source).await?,
+            None => None,
+        };
+        let (Some(source), Some(roles)) = (record.source, roles) else {
+            warn!("🚫 Could not confirm {} in the directory - ending session {}", record.user.username, record.session_id);
+            self.revoke_sessions(std::slice::from_ref(&record.session_id)).await;
+            return Ok(None);
+        };
+        let user = User { roles, ..record.user };
+
+        let new_refresh_token = new_refresh_token();
+        let rotated = self
+            .sessions
+            .rotate(
+                &token_hash,
+                &hash_refresh_token(&new_refresh_token),
+                &record.session_id,
+                &user,
+                source,
+                self.auth.refresh_token_duration(),
+            )
+            .await?;
+
+        // Lost the race against another exchange of the same token
+        if rotated.is_none() {
+            warn!("🚨 Concurrent refresh token reuse for {} - revoking session {}", user.username, record.session_id);
+            self.revoke_sessions(std::slice::from_ref(&record.session_id)).await;
+            return Ok(None);
+        }
+
+        let mut token = self.auth.generate_token(&user, &record.session_id)?;
+        token.refresh_token = Some(new_refresh_token);
+        token.refresh_expires_at = Some((chrono::Utc::now() + self.auth.refresh_token_duration()).timestamp());
+
+        info!("🔄 Refreshed session for {}", user.username);
+        Ok(Some(LoginResponse { token, user }))
+    }
+
+    /// Revoke the caller's session, named by the access token and/or the refresh token.
+    /// Returns false if neither belongs to a session.
+    pub async fn logout(&self, access_token: Option<&str>, refresh_token: Option<&str>) -> bool {
+        let mut session_ids = Vec::new();
+
+        if let Some(claims) = access_token.and_then(|token| self.auth.verify_token(token).ok()) {
+            session_ids.push(claims.sid);
+        }
+
+        if let Some(refresh_token) = refresh_token {
+            match self.sessions.find_refresh_token(&hash_refresh_token(refresh_token.trim())).await {
+                Ok(Some(record)) => session_ids.push(record.session_id),
+                Ok(None) => {}
+                Err(e) => warn!("⚠️ Refresh token lookup failed during logout: {:#}", e),
+            }
+        }
+
+        session_ids.retain(|sid| !sid.is_empty());
+        session_ids.sort();
+        session_ids.dedup();
+
+        if session_ids.is_empty() {
+            return false;
+        }
+
+        self.revoke_sessions(&session_ids).await;
+        true
+    }
+
+    /// Cut off every session of a user (lost or stolen handheld), returning their IDs
+    pub async fn revoke_user_sessions(&self, user_id: &str) -> Result<Vec<String>> {
+        let session_ids = self.sessions.revoke_user_sessions(user_id.trim()).await?;
+        for session_id in &session_ids {
+            self.auth.revoke_session(session_id);
+        }
+        Ok(session_ids)
+    }
+
+    /// Reload sessions revoked while their access tokens may still be valid (at startup),
+    /// each kept until the last of its access tokens has expired
+    pub async fn reload_revocations(&self) -> Result<usize> {
+        let token_duration = self.auth.token_duration();
+        let revoked = self.sessions.recently_revoked_sessions(token_duration).await?;
+
+        for (session_id, revoked_at) in &revoked {
+            let until = BANGKOK_TZ
+                .from_local_datetime(revoked_at)
+                .earliest()
+                .map_or_else(|| (chrono::Utc::now() + token_duration).timestamp(), |at| (at + token_duration).timestamp());
+            self.auth.mark_session_revoked(session_id, until);
+        }
+
+        Ok(revoked.len())
+    }
+
+    /// Revoke sessions in the in-memory revocation list and in the database
+    async fn revoke_sessions(&self, session_ids: &[String]) {
+        for session_id in session_ids {
+            self.auth.revoke_session(session_id);
+            if let Err(e) = self.sessions.revoke_session(session_id).await {
+                warn!("⚠️ Failed to persist revocation of session {}: {:#}", session_id, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::memory_db::MemorySessionDatabase;
+    use chrono::Duration;
+    use std::collections::HashMap;
+
+    /// Directory with the roles each account holds now; accounts not listed are gone
+    struct Directory(HashMap<&'static str, Vec<String>>);
+
+    impl RoleDirectory for Directory {
+        async fn current_roles(&self, user: &User, _source: AuthSource) -> Result<Option<Vec<String>>> {
+            Ok(self.0.get(user.user_id.as_str()).cloned())
+        }
+    }
+
+    fn directory(accounts: &[(&'static str, &[&str])]) -> Directory {
+        Directory(
+            accounts
+                .iter()
+                .map(|(user_id, roles)| (*user_id, roles.iter().map(|role| role.to_string()).collect()))
+                .collect(),
+        )
+    }
+
+    fn auth_service() -> AuthService {
+        AuthService::with_secret("putaway-test-secret-0123456789abcdef", "NWFTH-Putaway".to_string(), Duration::hours(8), Duration::hours(168))
+    }
+
+    fn user(user_id: &str, roles: &[&str]) -> User {
+        User {
+            user_id: user_id.to_string(),
+            username: user_id.to_string(),
+            email: format!("{user_id}@nwfth.com"),
+            display_name: user_id.to_string(),
+            is_active: true,
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+        }
+    }
+
+    async fn sign_in(service: &SessionService<MemorySessionDatabase>, user_id: &str, roles: &[&str]) -> (String, String) {
+        let login = service.start_session(user(user_id, roles), AuthSource::Ldap).await.unwrap();
+        (login.token.access_token, login.token.refresh_token.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_refresh_rotates_the_token_and_re_resolves_roles() {
+        let auth = auth_service();
+        let service = SessionService::new(auth.clone(), MemorySessionDatabase::default());
+        let (access_token, refresh_token) = sign_in(&service, "deachawat", &["supervisor"]).await;
+        let sid = auth.verify_token(&access_token).unwrap().sid;
+
+        // Taken out of the supervisors group since signing in
+        let refreshed = service.refresh(&refresh_token, &directory(&[("deachawat", &[])])).await.unwrap().unwrap();
+        let next_refresh_token = refreshed.token.refresh_token.clone().unwrap();
+        assert_ne!(next_refresh_token, refresh_token);
+        assert!(refreshed.user.roles.is_empty());
+
+        let claims = auth.verify_token(&refreshed.token.access_token).unwrap();
+        assert_eq!(claims.sid, sid);
+        assert!(claims.roles.is_empty());
+
+        let stored = service.sessions.tokens().await;
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].replaced_by.as_deref(), Some(hash_refresh_token(&next_refresh_token).as_str()));
+        assert!(stored[1].user.roles.is_empty());
+
+        // Promoted again: the next refresh picks it up
+        let promoted = service.refresh(&next_refresh_token, &directory(&[("deachawat", &["supervisor"])])).await.unwrap().unwrap();
+        assert_eq!(promoted.user.roles, ["supervisor"]);
+    }
+
+    #[tokio::test]
+    async fn test_reusing_a_refresh_token_revokes_the_session() {
+        let auth = auth_service();
+        let service = SessionService::new(auth.clone(), MemorySessionDatabase::default());
+        let accounts = directory(&[("deachawat", &[])]);
+        let (_, stolen) = sign_in(&service, "deachawat", &[]).await;
+
+        let refreshed = service.refresh(&stolen, &accounts).await.unwrap().unwrap();
+        assert!(service.refresh(&stolen, &accounts).await.unwrap().is_none());
+
+        // The legitimate holder is cut off as well: the whole session is gone
+        assert!(auth.verify_token(&refreshed.token.access_token).is_err());
+        let successor = refreshed.token.refresh_token.unwrap();
+        assert!(service.refresh(&successor, &accounts).await.unwrap().is_none());
+        assert!(service.sessions.tokens().await.iter().all(|token| token.revoked_at.is_some()));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_ends_sessions_of_accounts_gone_from_the_directory() {
+        let auth = auth_service();
+        let service = SessionService::new(auth.clone(), MemorySessionDatabase::default());
+        let (access_token, refresh_token) = sign_in(&service, "deachawat", &["supervisor"]).await;
+
+        assert!(service.refresh(&refresh_token, &directory(&[])).await.unwrap().is_none());
+        assert!(auth.verify_token(&access_token).is_err());
+        assert!(service.refresh("not-a-refresh-token", &directory(&[])).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_logout_revokes_only_the_callers_session() {
+        let auth = auth_service();
+        let service = SessionService::new(auth.clone(), MemorySessionDatabase::default());
+        let accounts = directory(&[("deachawat", &[])]);
+        let (handheld_access, handheld_refresh) = sign_in(&service, "deachawat", &[]).await;
+        let (desk_access, desk_refresh) = sign_in(&service, "deachawat", &[]).await;
+
+        assert!(service.logout(Some(&handheld_access), None).await);
+        assert!(auth.verify_token(&handheld_access).is_err());
+        assert!(service.refresh(&handheld_refresh, &accounts).await.unwrap().is_none());
+
+        // Logging out with only the refresh token ends that session too
+        assert!(auth.verify_token(&desk_access).is_ok());
+        assert!(service.logout(None, Some(&desk_refresh)).await);
+        assert!(auth.verify_token(&desk_access).is_err());
+
+        assert!(!service.logout(None, None).await);
+    }
+
+    #[tokio::test]
+    async fn test_admin_revocation_ends_every_session_of_the_user() {
+        let auth = auth_service();
+        let service = SessionService::new(auth.clone(), MemorySessionDatabase::default());
+        let accounts = directory(&[("deachawat", &[]), ("somchai", &[])]);
+        let (first_access, first_refresh) = sign_in(&service, "deachawat", &[]).await;
+        let (second_access, _) = sign_in(&service, "deachawat", &[]).await;
+        let (other_access, other_refresh) = sign_in(&service, "somchai", &[]).await;
+
+        let revoked = service.revoke_user_sessions(" deachawat ").await.unwrap();
+        assert_eq!(revoked.len(), 2);
+        assert!(auth.verify_token(&first_access).is_err());
+        assert!(auth.verify_token(&second_access).is_err());
+        assert!(service.refresh(&first_refresh, &accounts).await.unwrap().is_none());
+
+        assert!(auth.verify_token(&other_access).is_ok());
+        assert!(service.refresh(&other_refresh, &accounts).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_revocations_are_reloaded_at_startup() {
+        let sessions = MemorySessionDatabase::default();
+        let before_restart = SessionService::new(auth_service(), sessions.clone());
+        let (access_token, _) = sign_in(&before_restart, "deachawat", &[]).await;
+        before_restart.revoke_user_sessions("deachawat").await.unwrap();
+
+        let auth = auth_service();
+        let after_restart = SessionService::new(auth.clone(), sessions);
+        assert!(auth.verify_token(&access_token).is_ok());
+        assert_eq!(after_restart.reload_revocations().await.unwrap(), 1);
+        assert!(auth.verify_token(&access_token).is_err());
+    }
+}
@@ -48,6 +48,11 @@ pub struct AuthToken {
     pub expires_at: i64,
     pub user_id: String,
     pub username: String,
+    /// Rotating refresh token for POST /api/auth/refresh (absent if the session could not be stored)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_expires_at: Option<i64>,
 }
 
 /// User information structure
@@ -58,6 +63,8 @@ pub struct User {
     pub email: String,
     pub display_name: String,
     pub is_active: bool,
+    /// Putaway roles from AD groups / tbl_user.department (see AuthorizationConfig)
+    pub roles: Vec<String>,
 }
 
 /// Login response structure
@@ -1,8 +1,12 @@
 use anyhow::{Context, Result};
 use chrono::{Duration, Utc};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use rand::RngCore;
+use ring::digest::{digest, SHA256};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
+use std::sync::{Arc, RwLock};
 use tracing::info;
 
 use crate::types::{AuthToken, User};
@@ -18,6 +22,10 @@ pub struct Claims {
     pub exp: i64,           // Expiration time
     pub iat: i64,           // Issued at
     pub iss: String,        // Issuer
+    #[serde(default)]
+    pub roles: Vec<String>, // Putaway roles (tokens issued before roles existed carry none)
+    #[serde(default)]
+    pub sid: String,        // Login session (refresh token family) - revocable
 }
 
 #[derive(Clone)]
@@ -26,6 +34,9 @@ pub struct AuthService {
     decoding_key: DecodingKey,
     issuer: String,
     token_duration: Duration,
+    refresh_token_duration: Duration,
+    /// Revoked session IDs -> unix time after which no access token of the session can still be valid
+    revoked_sessions: Arc<RwLock<HashMap<String, i64>>>,
 }
 
 impl AuthService {
@@ -43,9 +54,6 @@ impl AuthService {
             ));
         }
 
-        let encoding_key = EncodingKey::from_secret(secret.as_bytes());
-        let decoding_key = DecodingKey::from_secret(secret.as_bytes());
-
         let issuer = env::var("JWT_ISSUER")
             .unwrap_or_else(|_| "NWFTH-BulkPicking".to_string());
 
@@ -57,19 +65,72 @@ impl AuthService {
 
         let token_duration = Duration::hours(token_duration_hours);
 
-        info!("🔐 JWT Authentication initialized with {}h token duration", token_duration_hours);
+        let refresh_token_duration_hours = env::var("JWT_REFRESH_DURATION_HOURS")
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .unwrap_or(168);
+
+        info!(
+            "🔐 JWT Authentication initialized with {}h token duration, {}h refresh tokens",
+            token_duration_hours, refresh_token_duration_hours
+        );
 
-        Ok(Self {
-            encoding_key,
-            decoding_key,
+        Ok(Self::with_secret(&secret, issuer, token_duration, Duration::hours(refresh_token_duration_hours)))
+    }
+
+    /// Service signing with the given secret (environment-independent; `new` reads it from JWT_SECRET)
+    pub fn with_secret(secret: &str, issuer: String, token_duration: Duration, refresh_token_duration: Duration) -> Self {
+        Self {
+            encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
             issuer,
             token_duration,
-        })
+            refresh_token_duration,
+            revoked_sessions: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Lifetime of an access token
+    pub fn token_duration(&self) -> Duration {
+        self.token_duration
+    }
+
+    /// Lifetime of a refresh token
+    pub fn refresh_token_duration(&self) -> Duration {
+        self.refresh_token_duration
+    }
+
+    /// Reject every access token of a session from now on (logout / stolen device)
+    pub fn revoke_session(&self, session_id: &str) {
+        let until = (Utc::now() + self.token_duration).timestamp();
+        self.mark_session_revoked(session_id, until);
+    }
+
+    /// Add a revoked session to the in-memory list until `until` (unix time), when its last
+    /// access token has expired. `SessionService::reload_revocations` uses it at startup.
+    pub fn mark_session_revoked(&self, session_id: &str, until: i64) {
+        if session_id.is_empty() {
+            return;
+        }
+        let now = Utc::now().timestamp();
+        let mut revoked = self.revoked_sessions.write().unwrap_or_else(|e| e.into_inner());
+        // Tokens of older revocations have expired by now - drop them
+        revoked.retain(|_, expires| *expires >= now);
+        revoked.insert(session_id.to_string(), until);
+    }
+
+    fn is_session_revoked(&self, session_id: &str) -> bool {
+        !session_id.is_empty()
+            && self
+                .revoked_sessions
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .contains_key(session_id)
     }
 
 
-    /// Generate JWT token for authenticated user
-    pub fn generate_token(&self, user: &User) -> Result<AuthToken> {
+    /// Generate JWT token for authenticated user within a login session
+    pub fn generate_token(&self, user: &User, session_id: &str) -> Result<AuthToken> {
         let now = Utc::now();
         let exp = now + self.token_duration;
 
@@ -81,6 +142,8 @@ impl AuthService {
             exp: exp.timestamp(),
             iat: now.timestamp(),
             iss: self.issuer.clone(),
+            roles: user.roles.clone(),
+            sid: session_id.to_string(),
         };
 
         let token_string = encode(&Header::default(), &claims, &self.encoding_key)
@@ -95,6 +158,8 @@ impl AuthService {
             expires_at: exp.timestamp(),
             user_id: user.user_id.clone(),
             username: user.username.clone(),
+            refresh_token: None,
+            refresh_expires_at: None,
         })
     }
 
@@ -116,6 +181,11 @@ impl AuthService {
             return Err(anyhow::anyhow!("Invalid token issuer"));
         }
 
+        // Logged out or cut off by a supervisor
+        if self.is_session_revoked(&token_data.claims.sid) {
+            return Err(anyhow::anyhow!("Session revoked"));
+        }
+
         Ok(token_data.claims)
     }
 
@@ -142,6 +212,7 @@ impl AuthService {
             },
             display_name: display_name.to_string(),
             is_active,
+            roles: Vec::new(),
         }
     }
 
@@ -153,6 +224,44 @@ impl AuthService {
             email: format!("{username}@nwfth.com"),
             display_name: username.to_string(),
             is_active: true,
+            roles: Vec::new(),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Directory a user signed in against. A refresh looks the user up there again for current roles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthSource {
+    Ldap,
+    Sql,
+}
+
+impl AuthSource {
+    /// Value stored in putaway_refresh_tokens.auth_source
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuthSource::Ldap => "ldap",
+            AuthSource::Sql => "sql",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [AuthSource::Ldap, AuthSource::Sql].into_iter().find(|source| source.name().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+/// New opaque refresh token (256 random bits, hex encoded)
+pub fn new_refresh_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+/// Refresh tokens are stored as SHA-256 hashes only
+pub fn hash_refresh_token(token: &str) -> String {
+    to_hex(digest(&SHA256, token.as_bytes()).as_ref())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::env;
+use tracing::info;
+
+/// Putaway operations that are restricted to specific roles.
+/// Anything not listed here (lookups, standard transfers) is open to every signed-in user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Permission {
+    /// POST /transfer/committed
+    CommittedTransfer,
+    /// POST /transfer/{document_no}/reverse
+    ReverseTransfer,
+    /// Supervisor reports, document audits and bin configuration (see `RESTRICTED_ROUTES`)
+    Admin,
+    /// Transfers flagged override_capacity that take a bin past its capacity
+    CapacityOverride,
+}
+
+impl Permission {
+    const ALL: [Permission; 4] = [
+        Permission::CommittedTransfer,
+        Permission::ReverseTransfer,
+        Permission::Admin,
+        Permission::CapacityOverride,
+    ];
+
+    /// Name used in PUTAWAY_PERMISSIONS
+    pub fn name(&self) -> &'static str {
+        match self {
+            Permission::CommittedTransfer => "committed_transfer",
+            Permission::ReverseTransfer => "reverse_transfer",
+            Permission::Admin => "admin",
+            Permission::CapacityOverride => "capacity_override",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Where roles come from and what each role may do.
+///
+/// - `PUTAWAY_ROLE_GROUPS`: `AD group CN=role` pairs separated by `;` (matched against memberOf)
+/// - `PUTAWAY_ROLE_DEPARTMENTS`: `tbl_user.department=role` pairs separated by `;`
+/// - `PUTAWAY_PERMISSIONS`: `permission=role|role` pairs separated by `;`
+#[derive(Debug, Clone)]
+pub struct AuthorizationConfig {
+    group_roles: Vec<(String, String)>,
+    department_roles: Vec<(String, String)>,
+    permissions: HashMap<Permission, Vec<String>>,
+}
+
+const DEFAULT_ROLE_GROUPS: &str = "Putaway Supervisors=supervisor";
+const DEFAULT_ROLE_DEPARTMENTS: &str = "Warehouse Supervisor=supervisor";
+const DEFAULT_PERMISSIONS: &str =
+    "committed_transfer=supervisor;reverse_transfer=supervisor;admin=supervisor;capacity_override=supervisor";
+
+impl AuthorizationConfig {
+    /// Load the mapping from the environment, falling back to supervisor-only defaults
+    pub fn from_env() -> Self {
+        let group_roles = env::var("PUTAWAY_ROLE_GROUPS").unwrap_or_else(|_| DEFAULT_ROLE_GROUPS.to_string());
+        let department_roles =
+            env::var("PUTAWAY_ROLE_DEPARTMENTS").unwrap_or_else(|_| DEFAULT_ROLE_DEPARTMENTS.to_string());
+        let permissions = env::var("PUTAWAY_PERMISSIONS").unwrap_or_else(|_| DEFAULT_PERMISSIONS.to_string());
+
+        let config = Self::parse(&group_roles, &department_roles, &permissions);
+        info!(
+            "🛡️ Putaway authorization: {} group mapping(s), {} department mapping(s), restricted: {:?}",
+            config.group_roles.len(),
+            config.department_roles.len(),
+            config.permissions
+        );
+        config
+    }
+
+    /// Build the mapping from the three variables' values
+    pub(crate) fn parse(group_roles: &str, department_roles: &str, permissions: &str) -> Self {
+        let permissions = parse_pairs(permissions)
+            .into_iter()
+            .filter_map(|(name, roles)| {
+                Permission::from_name(&name).map(|permission| {
+                    let roles = roles
+                        .split('|')
+                        .map(|role| role.trim().to_lowercase())
+                        .filter(|role| !role.is_empty())
+                        .collect();
+                    (permission, roles)
+                })
+            })
+            .collect();
+
+        Self {
+            group_roles: parse_pairs(group_roles),
+            department_roles: parse_pairs(department_roles),
+            permissions,
+        }
+    }
+
+    /// Roles granted by AD group membership (memberOf DNs or plain group names)
+    pub fn roles_for_groups(&self, member_of: &[String]) -> Vec<String> {
+        let groups: Vec<&str> = member_of.iter().map(|dn| group_common_name(dn)).collect();
+        let mut roles: Vec<String> = self
+            .group_roles
+            .iter()
+            .filter(|(group, _)| groups.iter().any(|g| g.eq_ignore_ascii_case(group)))
+            .map(|(_, role)| role.to_lowercase())
+            .collect();
+        roles.sort();
+        roles.dedup();
+        roles
+    }
+
+    /// Roles granted by the tbl_user.department of an SQL user
+    pub fn roles_for_department(&self, department: Option<&str>) -> Vec<String> {
+        let Some(department) = department.map(str::trim).filter(|d| !d.is_empty()) else {
+            return Vec::new();
+        };
+        let mut roles: Vec<String> = self
+            .department_roles
+            .iter()
+            .filter(|(dept, _)| dept.eq_ignore_ascii_case(department))
+            .map(|(_, role)| role.to_lowercase())
+            .collect();
+        roles.sort();
+        roles.dedup();
+        roles
+    }
+
+    /// Whether any of the roles carries the permission. Unmapped permissions are denied.
+    pub fn allows(&self, roles: &[String], permission: Permission) -> bool {
+        self.permissions
+            .get(&permission)
+            .is_some_and(|allowed| roles.iter().any(|role| allowed.iter().any(|a| a.eq_ignore_ascii_case(role))))
+    }
+
+    /// Every permission the roles carry
+    pub fn granted(&self, roles: &[String]) -> GrantedPermissions {
+        GrantedPermissions(Permission::ALL.into_iter().filter(|p| self.allows(roles, *p)).collect())
+    }
+}
+
+/// Permissions of the signed-in user, attached to putaway requests by the authorization
+/// middleware for handlers whose restriction depends on the request body
+#[derive(Debug, Clone, Default)]
+pub struct GrantedPermissions(Vec<Permission>);
+
+impl GrantedPermissions {
+    pub fn contains(&self, permission: Permission) -> bool {
+        self.0.contains(&permission)
+    }
+}
+
+/// Restricted putaway routes as registered in `create_putaway_routes` (below /api/putaway).
+/// Listed one by one so a new route is open or restricted on purpose, never by its path prefix.
+const RESTRICTED_ROUTES: &[(&str, &str, Permission)] = &[
+    ("POST", "/transfer/committed", Permission::CommittedTransfer),
+    ("POST", "/transfer/{document_no}/reverse", Permission::ReverseTransfer),
+    ("GET", "/reports/bt-gaps", Permission::Admin),
+    ("GET", "/audit/{document_no}", Permission::Admin),
+    ("PUT", "/admin/bins/capacity", Permission::Admin),
+    ("DELETE", "/admin/bins/capacity/{location}/{bin_no}", Permission::Admin),
+    ("POST", "/admin/bins/block", Permission::Admin),
+    ("POST", "/admin/bins/unblock", Permission::Admin),
+];
+
+/// Permission required for a putaway route, keyed on method and path below /api/putaway
+pub fn required_permission(method: &str, path: &str) -> Option<Permission> {
+    let path = path.strip_prefix("/api/putaway").unwrap_or(path);
+
+    RESTRICTED_ROUTES
+        .iter()
+        .find(|(route_method, route, _)| *route_method == method && route_matches(route, path))
+        .map(|(_, _, permission)| *permission)
+}
+
+/// Segment-wise match of a request path against a route; `{name}` matches any one segment
+fn route_matches(route: &str, path: &str) -> bool {
+    let mut route_segments = route.trim_end_matches('/').split('/');
+    let mut path_segments = path.trim_end_matches('/').split('/');
+    loop {
+        match (route_segments.next(), path_segments.next()) {
+            (None, None) => return true,
+            (Some(expected), Some(actual)) if expected.starts_with('{') && !actual.is_empty() => continue,
+            (Some(expected), Some(actual)) if expected == actual => continue,
+            _ => return false,
+        }
+    }
+}
+
+/// `CN=Putaway Supervisors,OU=Groups,DC=NWFTH,DC=com` -> `Putaway Supervisors`
+fn group_common_name(dn: &str) -> &str {
+    let first = dn.split(',').next().unwrap_or(dn).trim();
+    match first.split_once('=') {
+        Some((key, value)) if key.trim().eq_ignore_ascii_case("CN") => value.trim(),
+        _ => first,
+    }
+}
+
+fn parse_pairs(value: &str) -> Vec<(String, String)> {
+    value
+        .split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .filter(|(key, value)| !key.is_empty() && !value.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roles_from_groups_and_departments() {
+        let config = AuthorizationConfig::parse(
+            "Putaway Supervisors=supervisor; Warehouse Managers=Supervisor",
+            "Warehouse Supervisor=supervisor",
+            DEFAULT_PERMISSIONS,
+        );
+
+        let member_of = vec![
+            "CN=Domain Users,CN=Users,DC=NWFTH,DC=com".to_string(),
+            "CN=putaway supervisors,OU=Groups,DC=NWFTH,DC=com".to_string(),
+            "CN=Warehouse Managers,OU=Groups,DC=NWFTH,DC=com".to_string(),
+        ];
+        assert_eq!(config.roles_for_groups(&member_of), vec!["supervisor"]);
+        assert_eq!(config.roles_for_department(Some(" warehouse supervisor ")), vec!["supervisor"]);
+        assert!(config.roles_for_department(Some("Production")).is_empty());
+        assert!(config.roles_for_department(None).is_empty());
+    }
+
+    #[test]
+    fn test_route_permissions() {
+        let config = AuthorizationConfig::parse("", "", "committed_transfer=supervisor|lead;admin=supervisor");
+        let lead = vec!["lead".to_string()];
+
+        assert_eq!(required_permission("POST", "/transfer/committed"), Some(Permission::CommittedTransfer));
+        assert_eq!(required_permission("POST", "/api/putaway/transfer/BT-26112174/reverse"), Some(Permission::ReverseTransfer));
+        assert_eq!(required_permission("GET", "/reports/bt-gaps"), Some(Permission::Admin));
+        assert_eq!(required_permission("POST", "/transfer"), None);
+        assert_eq!(required_permission("GET", "/lot/2600107-1"), None);
+
+        // Document contents are restricted; the blocked-bins reports are for every signed-in user
+        assert_eq!(required_permission("GET", "/api/putaway/audit/BT-26112174"), Some(Permission::Admin));
+        assert_eq!(required_permission("GET", "/audit/"), None);
+        assert_eq!(required_permission("GET", "/api/putaway/reports/blocked-bins"), None);
+        assert_eq!(required_permission("GET", "/reports/bin-blocks/TFC1/K0802-4B/history"), None);
+        assert_eq!(required_permission("POST", "/admin/bins/block"), Some(Permission::Admin));
+        assert_eq!(required_permission("DELETE", "/admin/bins/capacity/TFC1/K0802-4B"), Some(Permission::Admin));
+        assert_eq!(required_permission("GET", "/transfer/committed"), None);
+
+        assert!(config.allows(&lead, Permission::CommittedTransfer));
+        assert!(!config.allows(&lead, Permission::Admin));
+        // Not mapped at all -> nobody
+        assert!(!config.allows(&["supervisor".to_string()], Permission::ReverseTransfer));
+
+        let granted = config.granted(&lead);
+        assert!(granted.contains(Permission::CommittedTransfer));
+        assert!(!granted.contains(Permission::CapacityOverride));
+    }
+}
@@ -0,0 +1,185 @@
+use chrono::{Datelike, NaiveDate};
+use serde::Serialize;
+
+/// FNC1 as transmitted by scanners in GS1-128 data (ASCII group separator)
+const GROUP_SEPARATOR: char = '\u{1d}';
+
+/// AIM symbology identifier a scanner may prefix to GS1-128 data
+const GS1_128_SYMBOLOGY_ID: &str = "]C1";
+
+/// Application identifiers of a GS1-128 label that the putaway scan uses
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Gs1Data {
+    /// AI 01
+    pub gtin: Option<String>,
+    /// AI 10
+    pub batch: Option<String>,
+    /// AI 17 - day 00 means the last day of the month
+    pub expiry_date: Option<NaiveDate>,
+    /// AI 37 - number of trade items in the logistic unit
+    pub count: Option<u32>,
+}
+
+/// Length of an AI's data: fixed, or variable up to a maximum (ended by FNC1)
+#[derive(Clone, Copy)]
+enum AiLength {
+    Fixed(usize),
+    Variable(usize),
+}
+
+/// AI and data length for the identifiers found on inbound pallet and carton labels
+fn ai_length(data: &str) -> Option<(&str, AiLength)> {
+    let two = data.get(..2)?;
+    let length = match two {
+        "00" => AiLength::Fixed(18),
+        "01" | "02" => AiLength::Fixed(14),
+        "11" | "12" | "13" | "15" | "16" | "17" => AiLength::Fixed(6),
+        "20" => AiLength::Fixed(2),
+        "10" | "21" => AiLength::Variable(20),
+        "30" | "37" => AiLength::Variable(8),
+        // 31nn-36nn: trade measures, 4-digit AI with 6 digits
+        "31" | "32" | "33" | "34" | "35" | "36" => return data.get(..4).map(|ai| (ai, AiLength::Fixed(6))),
+        "24" => return data.get(..3).filter(|ai| *ai == "240" || *ai == "241").map(|ai| (ai, AiLength::Variable(30))),
+        _ => return None,
+    };
+    Some((two, length))
+}
+
+/// Whether a raw scan looks like GS1-128 element strings rather than a plain lot/bin/item code
+pub fn is_gs1(raw: &str) -> bool {
+    let raw = raw.trim();
+    raw.starts_with(GS1_128_SYMBOLOGY_ID)
+        || raw.contains(GROUP_SEPARATOR)
+        || raw.starts_with('(')
+        // Unprefixed: a GTIN (01 + 14 digits) followed by more element strings
+        || (raw.len() > 16 && raw.starts_with("01") && raw.as_bytes()[..16].iter().all(u8::is_ascii_digit))
+}
+
+/// Decode GS1-128 element strings, either as scanned (FNC1-separated, optionally with the
+/// `]C1` symbology identifier) or in the human readable `(01)...(10)...` form
+pub fn decode(raw: &str) -> Result<Gs1Data, String> {
+    let raw = raw.trim();
+    let raw = raw.strip_prefix(GS1_128_SYMBOLOGY_ID).unwrap_or(raw);
+
+    let elements = if raw.starts_with('(') { split_bracketed(raw)? } else { split_scanned(raw)? };
+
+    let mut result = Gs1Data::default();
+    for (ai, value) in elements {
+        match ai.as_str() {
+            "01" => {
+                if value.len() != 14 || !value.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(format!("GTIN (01) must be 14 digits, got '{value}'"));
+                }
+                result.gtin = Some(value);
+            }
+            "10" => result.batch = Some(value),
+            "17" => result.expiry_date = Some(parse_gs1_date(&value)?),
+            "37" => {
+                result.count = Some(value.parse().map_err(|_| format!("Count (37) must be numeric, got '{value}'"))?);
+            }
+            // Other identifiers are read past but not used by putaway
+            _ => {}
+        }
+    }
+
+    if result == Gs1Data::default() {
+        return Err("No GS1 application identifiers found".to_string());
+    }
+    Ok(result)
+}
+
+/// `(01)09501234567890(10)LOT1` -> [("01", "09501234567890"), ("10", "LOT1")]
+fn split_bracketed(raw: &str) -> Result<Vec<(String, String)>, String> {
+    let mut elements = Vec::new();
+    let mut rest = raw;
+    while let Some(after_open) = rest.strip_prefix('(') {
+        let close = after_open.find(')').ok_or_else(|| "Unclosed '(' in GS1 data".to_string())?;
+        let ai = &after_open[..close];
+        let value_and_rest = &after_open[close + 1..];
+        let end = value_and_rest.find('(').unwrap_or(value_and_rest.len());
+        elements.push((ai.to_string(), value_and_rest[..end].trim_end_matches(GROUP_SEPARATOR).to_string()));
+        rest = &value_and_rest[end..];
+    }
+    if !rest.is_empty() {
+        return Err(format!("Unexpected text '{rest}' in GS1 data"));
+    }
+    Ok(elements)
+}
+
+/// Split element strings on the AI table: fixed-length data runs on, variable-length data ends at FNC1
+fn split_scanned(raw: &str) -> Result<Vec<(String, String)>, String> {
+    let mut elements = Vec::new();
+    let mut rest = raw.trim_start_matches(GROUP_SEPARATOR);
+    while !rest.is_empty() {
+        let (ai, length) = ai_length(rest).ok_or_else(|| {
+            format!("Unsupported GS1 application identifier at '{}'", rest.chars().take(4).collect::<String>())
+        })?;
+        let data = &rest[ai.len()..];
+        let (value, next) = match length {
+            AiLength::Fixed(len) => {
+                let value = data.get(..len).ok_or_else(|| format!("AI ({ai}) needs {len} characters"))?;
+                (value, &data[len..])
+            }
+            AiLength::Variable(max) => {
+                let end = data.find(GROUP_SEPARATOR).unwrap_or(data.len());
+                if end > max {
+                    return Err(format!("AI ({ai}) is longer than {max} characters"));
+                }
+                (&data[..end], &data[end..])
+            }
+        };
+        elements.push((ai.to_string(), value.to_string()));
+        rest = next.trim_start_matches(GROUP_SEPARATOR);
+    }
+    Ok(elements)
+}
+
+/// YYMMDD; DD = 00 is the last day of the month
+fn parse_gs1_date(value: &str) -> Result<NaiveDate, String> {
+    let invalid = || format!("Date must be YYMMDD, got '{value}'");
+    if value.len() != 6 || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let year = 2000 + value[..2].parse::<i32>().map_err(|_| invalid())?;
+    let month = value[2..4].parse::<u32>().map_err(|_| invalid())?;
+    let day = value[4..].parse::<u32>().map_err(|_| invalid())?;
+
+    if day == 0 {
+        let first = NaiveDate::from_ymd_opt(year, month, 1).ok_or_else(invalid)?;
+        let next_month = first.checked_add_months(chrono::Months::new(1)).ok_or_else(invalid)?;
+        return next_month.pred_opt().filter(|date| date.month() == month).ok_or_else(invalid);
+    }
+    NaiveDate::from_ymd_opt(year, month, day).ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_scanned_label() {
+        let raw = "]C10109501234567890172612001026001071\u{1d}3740";
+        let data = decode(raw).unwrap();
+        assert_eq!(data.gtin.as_deref(), Some("09501234567890"));
+        assert_eq!(data.expiry_date, NaiveDate::from_ymd_opt(2026, 12, 31));
+        assert_eq!(data.batch.as_deref(), Some("26001071"));
+        assert_eq!(data.count, Some(40));
+        assert!(is_gs1(raw));
+    }
+
+    #[test]
+    fn test_decode_bracketed_label() {
+        let data = decode("(01)09501234567890(10)2600107-1(17)270215(37)12").unwrap();
+        assert_eq!(data.batch.as_deref(), Some("2600107-1"));
+        assert_eq!(data.expiry_date, NaiveDate::from_ymd_opt(2027, 2, 15));
+        assert_eq!(data.count, Some(12));
+    }
+
+    #[test]
+    fn test_plain_codes_are_not_gs1() {
+        assert!(!is_gs1("2600107-1"));
+        assert!(!is_gs1("K0802-4B"));
+        assert!(decode("0109501234").is_err());
+        assert!(decode("(17)261340").is_err());
+    }
+}
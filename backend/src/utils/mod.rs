@@ -1,5 +1,26 @@
 pub mod auth;
+pub mod authorization;
+pub mod gs1;
+pub mod quantity;
 pub mod timezone;
 
 pub use auth::AuthService;
 pub use timezone::{bangkok_now, bangkok_now_rfc3339};
+
+/// User ID cut to the 8 characters BME's RecUserId columns hold (by character, so Thai names stay whole)
+pub fn truncate_user_id(user_id: &str) -> &str {
+    user_id.char_indices().nth(8).map_or(user_id, |(end, _)| &user_id[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_user_id_counts_characters() {
+        assert_eq!(truncate_user_id("deachawat"), "deachawa");
+        assert_eq!(truncate_user_id("picker"), "picker");
+        // 10 Thai characters of 3 bytes each: byte 8 falls inside the third one
+        assert_eq!(truncate_user_id("สมชายใจดีม"), "สมชายใจด");
+    }
+}
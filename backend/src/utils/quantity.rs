@@ -0,0 +1,86 @@
+use bigdecimal::BigDecimal;
+use serde::de::Error as _;
+use serde::ser::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::str::FromStr;
+
+/// Round a quantity to the UOM's decimal places (half away from zero, like BME's ROUND)
+/// and keep exactly that scale
+pub fn round_qty(qty: &BigDecimal, decimals: i64) -> BigDecimal {
+    qty.round(decimals).with_scale(decimals)
+}
+
+/// `serialize_with` for quantity fields - the API sends exact numbers (serde_json's arbitrary_precision
+/// keeps every digit), not BigDecimal's default string
+pub fn serialize_qty<S: Serializer>(qty: &BigDecimal, serializer: S) -> Result<S::Ok, S::Error> {
+    serde_json::Number::from_str(&qty.to_string()).map_err(S::Error::custom)?.serialize(serializer)
+}
+
+/// A quantity for `json!` bodies, serialized like the `serialize_qty` fields
+pub struct ExactQty<'a>(pub &'a BigDecimal);
+
+impl Serialize for ExactQty<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_qty(self.0, serializer)
+    }
+}
+
+/// `serialize_with` for optional quantity fields (null when absent)
+pub fn serialize_opt_qty<S: Serializer>(qty: &Option<BigDecimal>, serializer: S) -> Result<S::Ok, S::Error> {
+    match qty {
+        Some(qty) => serialize_qty(qty, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// `deserialize_with` for quantity fields: a JSON number read digit for digit, or a numeric string
+pub fn deserialize_qty<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigDecimal, D::Error> {
+    let text = match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Number(number) => number.to_string(),
+        serde_json::Value::String(text) => text,
+        other => return Err(D::Error::custom(format!("expected a quantity, got {other}"))),
+    };
+    BigDecimal::from_str(text.trim()).map_err(|e| D::Error::custom(format!("invalid quantity '{text}': {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_qty_to_uom_scale() {
+        let residual = BigDecimal::from_str("974.9999999").unwrap();
+        assert_eq!(round_qty(&residual, 3).to_string(), "975.000");
+        assert_eq!(round_qty(&BigDecimal::from_str("0.0004").unwrap(), 3).to_string(), "0.000");
+        assert_eq!(round_qty(&BigDecimal::from_str("-1.2345").unwrap(), 3).to_string(), "-1.235");
+        assert_eq!(round_qty(&BigDecimal::from(50), 2).to_string(), "50.00");
+    }
+
+    #[test]
+    fn test_quantities_round_trip_through_json() {
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Line {
+            #[serde(serialize_with = "serialize_qty", deserialize_with = "deserialize_qty")]
+            qty: BigDecimal,
+        }
+
+        // Numbers and strings are both accepted, digit for digit; the float noise of a JS client is dropped by rounding
+        let line: Line = serde_json::from_str(r#"{"qty": 0.30000000000000004}"#).unwrap();
+        assert_eq!(line.qty, BigDecimal::from_str("0.30000000000000004").unwrap());
+        assert_eq!(round_qty(&line.qty, 4), BigDecimal::from_str("0.3").unwrap());
+        let line: Line = serde_json::from_str(r#"{"qty": "1234.567"}"#).unwrap();
+        assert_eq!(serde_json::to_string(&line).unwrap(), r#"{"qty":1234.567}"#);
+
+        // Past what an f64 holds, both ways
+        let exact = r#"{"qty":12345678901234567.0123456789}"#;
+        let line: Line = serde_json::from_str(exact).unwrap();
+        assert_eq!(line.qty, BigDecimal::from_str("12345678901234567.0123456789").unwrap());
+        assert_eq!(serde_json::to_string(&line).unwrap(), exact);
+
+        let qty = BigDecimal::from_str("-0.0000000001").unwrap();
+        assert_eq!(serde_json::json!({ "qty": ExactQty(&qty) }).to_string(), r#"{"qty":-0.0000000001}"#);
+
+        assert!(serde_json::from_str::<Line>(r#"{"qty": "12 bags"}"#).is_err());
+        assert!(serde_json::from_str::<Line>(r#"{"qty": true}"#).is_err());
+    }
+}
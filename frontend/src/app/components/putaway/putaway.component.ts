@@ -468,21 +468,15 @@ export class PutawayComponent implements AfterViewInit {
       return;
     }
 
-    // Scenario 1: Input == QtyCommitSales - auto-execute transfer
-    if (Math.abs(putawayQty - qtyCommitSales) < 0.001) {
-      // Auto-execute without modal
-      this.executeCommittedTransfer(putawayQty);
-      return;
-    }
-
-    // Scenario 2: Input < QtyCommitSales - open modal for selection
+    // Input <= QtyCommitSales - open modal to pick the pending lines that move.
+    // The backend moves the commitment only together with the pending lines it belongs to.
     this.targetTransferQty.set(putawayQty);
     this.clearMessages();
     this.isTransactionModalOpen.set(true);
   }
 
-  // Helper method to execute committed transfer (used by both auto and modal paths)
-  private async executeCommittedTransfer(transferQty: number) {
+  // Helper method to execute committed transfer with the pending lines picked in the modal
+  private async executeCommittedTransfer(transferQty: number, lotTranNos: number[]) {
     const lot = this.selectedLot();
     if (!lot) return;
 
@@ -505,7 +499,8 @@ export class PutawayComponent implements AfterViewInit {
       transfer_qty: transferQty,
       user_id: this.authService.getCurrentUser()?.username || 'unknown',
       remarks: formValues.remarks || '',
-      referenced: formValues.referenced || ''
+      referenced: formValues.referenced || '',
+      lot_tran_nos: lotTranNos
     };
 
     try {
@@ -556,9 +551,10 @@ export class PutawayComponent implements AfterViewInit {
     this.targetTransferQty.set(0);  // Clear the signal to prevent button disabled state
   }
 
-  async onTransactionsSelected(selectedTotalQty: number) {
+  async onTransactionsSelected(selection: { totalQty: number; lotTranNos: number[] }) {
     // Modal has already validated that selected quantity matches target
-    // selectedTotalQty is the validated sum from the modal
+    // totalQty is the validated sum of the selected lines
+    const selectedTotalQty = selection.totalQty;
     const transferQty = this.targetTransferQty();
 
     if (Math.abs(selectedTotalQty - transferQty) > 0.001) {
@@ -570,7 +566,7 @@ export class PutawayComponent implements AfterViewInit {
       return;
     }
 
-    await this.executeCommittedTransfer(transferQty);
+    await this.executeCommittedTransfer(transferQty, selection.lotTranNos);
   }
 
   private fb = inject(FormBuilder);
@@ -854,6 +850,7 @@ export class PutawayComponent implements AfterViewInit {
       bin_from: lot.binNumber,
       bin_to: formValues.toBinNumber,
       transfer_qty: transferQty,
+      uom: lot.uom,
       user_id: userId,
       remarks: formValues.remarks || '',
       referenced: formValues.referenced || ''
@@ -894,8 +891,9 @@ export class PutawayComponent implements AfterViewInit {
         const transactionDetails = {
           document_no: result.document_no,
           lot_no: lot.lotNumber,
-          transfer_qty: formValues.putawayQty,
-          uom: lot.uom,
+          // What was written, in the stock UOM
+          transfer_qty: result.transfer_qty,
+          uom: result.stock_uom,
           bin_from: lot.binNumber,
           bin_to: formValues.toBinNumber,
           timestamp: new Date().toISOString(),
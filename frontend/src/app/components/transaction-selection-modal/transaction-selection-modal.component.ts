@@ -145,7 +145,7 @@ export class TransactionSelectionModalComponent {
   lotNo = input<string>('');
   binNo = input<string>('');
   targetQty = input<number>(0);
-  @Output() transactionsSelected = new EventEmitter<number>();
+  @Output() transactionsSelected = new EventEmitter<{ totalQty: number; lotTranNos: number[] }>();
   @Output() modalClosed = new EventEmitter<void>();
 
   private putawayService = inject(PutawayService);
@@ -249,8 +249,11 @@ export class TransactionSelectionModalComponent {
   }
 
   onConfirm() {
-    // Emit the total selected quantity (already validated to match target)
-    this.transactionsSelected.emit(this.selectedTotalQty());
+    // Emit the selected lines and their total quantity (already validated to match target)
+    this.transactionsSelected.emit({
+      totalQty: this.selectedTotalQty(),
+      lotTranNos: Array.from(this.selectedIds())
+    });
     this.onClose();
   }
 
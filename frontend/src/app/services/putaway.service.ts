@@ -12,10 +12,11 @@ export interface PutawayItem {
   qty_received: number;
   qty_on_hand: number;
   qty_available?: number; // New field from BME - calculated available quantity
-  date_received: string;
-  date_expiry: string;
+  date_received: string | null;
+  date_expiry: string | null;
   exp_date?: string; // New field from BME - formatted expiry date
   uom?: string; // New field from BME - Unit of Measure
+  age_days?: number; // Whole days since DateReceived
   vendor_key: string;
   vendor_lot_no: string;
   document_no: string;
@@ -23,6 +24,14 @@ export interface PutawayItem {
   rec_user_id: string;
 }
 
+export interface PaginatedPendingPutawayResponse {
+  items: PutawayItem[];
+  total: number;
+  page: number;
+  pages: number;
+  limit: number;
+}
+
 export interface PutawayRequest {
   lot_no: string;
   item_key: string;
@@ -58,15 +67,25 @@ export interface PutawayConfirmation {
   transaction_id?: number;
 }
 
+export type ScanType = 'Item' | 'Bin' | 'Lot';
+
 export interface ScanRequest {
-  barcode: string;
-  scan_type: 'Item' | 'Location' | 'Lot';
+  barcode: string; // Raw scanner string, GS1-128 labels included
+  scan_type?: ScanType; // Omit to auto-detect
+}
+
+export interface Gs1Data {
+  gtin: string | null; // AI 01
+  batch: string | null; // AI 10
+  expiry_date: string | null; // AI 17, YYYY-MM-DD
+  count: number | null; // AI 37
 }
 
 export interface ScanResponse {
   valid: boolean;
-  scan_type: 'Item' | 'Location' | 'Lot';
-  data?: ScanData;
+  scan_type: ScanType | null;
+  data: ScanData | null;
+  gs1: Gs1Data | null;
   message: string;
 }
 
@@ -74,45 +93,118 @@ export interface ScanData {
   Item?: {
     item_key: string;
     description: string;
-    unit: string;
-    uom?: string; // Unit of Measure from BME
+    uom: string;
   };
-  Location?: {
-    location_key: string;
+  Bin?: {
+    bin_no: string;
     description: string;
-    location_type: string;
-    available_bins?: string[]; // Available bins for BME search functionality
-  };
-  Lot?: {
-    lot_no: string;
-    item_key: string;
-    qty_on_hand: number;
-    qty_available?: number; // Available quantity from BME
-    exp_date?: string; // Expiry date from BME
-    uom?: string; // Unit of Measure from BME
+    locations: string[];
   };
+  Lot?: LotSearchResponse;
 }
 
 export interface PutawayHistory {
-  transaction_id: number;
+  document_no: string;
+  line_no: number;
   lot_no: string;
   item_key: string;
-  from_location: string;
-  to_location: string;
-  bin_no: string;
-  qty_moved: number;
-  transaction_date: string;
+  item_description: string;
+  location: string;
+  bin_from: string;
+  bin_to: string;
+  qty: number;
+  uom: string;
   user_id: string;
+  transaction_date: string;
+  remarks: string;
+  referenced: string;
+}
+
+export interface PutawayHistoryFilter {
+  user_id?: string;
+  lot_no?: string;
+  item_key?: string;
+  bin_no?: string;
+  location?: string;
+  date_from?: string;
+  date_to?: string;
+  sort?: 'date' | 'document_no' | 'lot_no' | 'item_key' | 'user_id';
+  order?: 'asc' | 'desc';
+  page?: number;
+  limit?: number;
+}
+
+export interface PaginatedPutawayHistoryResponse {
+  items: PutawayHistory[];
+  total: number;
+  page: number;
+  pages: number;
+  limit: number;
 }
 
-export interface TransactionAuditEntry {
-  transaction_id: number;
-  record_type: string;
+export interface BtDocumentAudit {
   document_no: string;
-  description: string;
-  user_id: string;
-  timestamp: string;
-  details?: Record<string, unknown>;
+  header: {
+    in_trans_id: number | null;
+    item_key: string;
+    location: string;
+    doc_date: string;
+    description: string;
+    in_acct: string;
+    user_id: string;
+    rec_date: string;
+  } | null;
+  lot_transactions: {
+    lot_tran_no: number | null;
+    transaction_type: number;
+    line_no: number | null;
+    lot_no: string;
+    item_key: string;
+    location: string;
+    bin_no: string;
+    qty: number;
+    processed: string;
+    user_id: string;
+    rec_date: string;
+  }[];
+  bin_transfers: {
+    lot_tran_no: number;
+    in_trans_id: number;
+    lot_no: string;
+    item_key: string;
+    location: string;
+    bin_from: string;
+    bin_to: string;
+    qty_on_hand: number;
+    transfer_qty: number;
+    remarks: string;
+    referenced: string;
+    user_id: string;
+    rec_date: string;
+  }[];
+  lot_master: {
+    line_no: number;
+    lot_tran_no: number | null;
+    lot_no: string;
+    bin_no: string;
+    side: 'FROM' | 'TO';
+    qty_on_hand_before: number;
+    qty_on_hand_after: number;
+    qty_commit_before: number | null;
+    qty_commit_after: number | null;
+  }[];
+  commitment_moves: {
+    lot_tran_no: number;
+    source_table: string;
+    doc_no: string;
+    doc_line_no: number | null;
+    transaction_type: number;
+    qty: number;
+    bin_from: string;
+    bin_to: string;
+  }[];
+  reversal_of: string | null;
+  reversed_by: string | null;
 }
 
 export interface ApiResponse<T> {
@@ -140,16 +232,22 @@ export class PutawayService {
   /**
    * Get all pending putaway items
    */
-  getPendingPutawayItems(params?: { limit?: number; status?: string }): Observable<ApiResponse<PutawayItem[]>> {
+  getPendingPutawayItems(params?: { page?: number; limit?: number; status?: string; location?: string }): Observable<PaginatedPendingPutawayResponse> {
     let httpParams = new HttpParams();
+    if (params?.page) {
+      httpParams = httpParams.set('page', params.page.toString());
+    }
     if (params?.limit) {
       httpParams = httpParams.set('limit', params.limit.toString());
     }
     if (params?.status) {
       httpParams = httpParams.set('status', params.status);
     }
+    if (params?.location) {
+      httpParams = httpParams.set('location', params.location);
+    }
 
-    return this.http.get<ApiResponse<PutawayItem[]>>(`${this.baseUrl}/putaway/pending`, {
+    return this.http.get<PaginatedPendingPutawayResponse>(`${this.baseUrl}/putaway/pending`, {
       params: httpParams
     });
   }
@@ -157,8 +255,8 @@ export class PutawayService {
   /**
    * Scan a barcode (item, location, or lot)
    */
-  scanBarcode(request: ScanRequest): Observable<ApiResponse<ScanResponse>> {
-    return this.http.post<ApiResponse<ScanResponse>>(`${this.baseUrl}/putaway/scan`, request);
+  scanBarcode(request: ScanRequest): Observable<ScanResponse> {
+    return this.http.post<ScanResponse>(`${this.baseUrl}/putaway/scan`, request);
   }
 
   /**
@@ -178,13 +276,15 @@ export class PutawayService {
   /**
    * Get putaway history
    */
-  getPutawayHistory(limit?: number): Observable<ApiResponse<PutawayHistory[]>> {
+  getPutawayHistory(filter?: PutawayHistoryFilter): Observable<PaginatedPutawayHistoryResponse> {
     let httpParams = new HttpParams();
-    if (limit) {
-      httpParams = httpParams.set('limit', limit.toString());
+    for (const [key, value] of Object.entries(filter ?? {})) {
+      if (value !== undefined && value !== null && value !== '') {
+        httpParams = httpParams.set(key, value.toString());
+      }
     }
 
-    return this.http.get<ApiResponse<PutawayHistory[]>>(`${this.baseUrl}/putaway/history`, {
+    return this.http.get<PaginatedPutawayHistoryResponse>(`${this.baseUrl}/putaway/history`, {
       params: httpParams
     });
   }
@@ -197,10 +297,10 @@ export class PutawayService {
   }
 
   /**
-   * Get transaction audit trail
+   * Get the audit trail of a BT document
    */
-  getTransactionAudit(transactionId: number): Observable<ApiResponse<TransactionAuditEntry[]>> {
-    return this.http.get<ApiResponse<TransactionAuditEntry[]>>(`${this.baseUrl}/putaway/audit/${transactionId}`);
+  getTransactionAudit(documentNo: string): Observable<BtDocumentAudit> {
+    return this.http.get<BtDocumentAudit>(`${this.baseUrl}/putaway/audit/${encodeURIComponent(documentNo)}`);
   }
 
   /**
@@ -227,12 +327,60 @@ export class PutawayService {
   }
 
   /**
-   * Get available bins for a location (BME functionality)
+   * Capacity-tracked bins of a location with space left in them
    */
-  getAvailableBins(locationKey: string): Observable<ApiResponse<{ bin_no: string; description?: string; capacity?: number; available_space?: number }[]>> {
+  getAvailableBins(locationKey: string): Observable<ApiResponse<BinSpace[]>> {
     const params = new HttpParams().set('location', locationKey);
 
-    return this.http.get<ApiResponse<{ bin_no: string; description?: string; capacity?: number; available_space?: number }[]>>(`${this.baseUrl}/putaway/bins/available`, { params });
+    return this.http.get<ApiResponse<BinSpace[]>>(`${this.baseUrl}/putaway/bins/available`, { params });
+  }
+
+  /**
+   * Set how much a bin holds (admin permission)
+   */
+  setBinCapacity(request: BinCapacityRequest): Observable<BinSpace> {
+    return this.http.put<BinSpace>(`${this.baseUrl}/putaway/admin/bins/capacity`, request);
+  }
+
+  /**
+   * Stop tracking a bin's capacity (admin permission)
+   */
+  clearBinCapacity(location: string, binNo: string): Observable<ApiResponse<void>> {
+    return this.http.delete<ApiResponse<void>>(
+      `${this.baseUrl}/putaway/admin/bins/capacity/${encodeURIComponent(location)}/${encodeURIComponent(binNo)}`
+    );
+  }
+
+  /**
+   * Block a bin for putaway with a reason and optional expiry (admin permission)
+   */
+  blockBin(request: BinBlockRequest): Observable<BinBlock> {
+    return this.http.post<BinBlock>(`${this.baseUrl}/putaway/admin/bins/block`, request);
+  }
+
+  /**
+   * Lift a bin's putaway block (admin permission)
+   */
+  unblockBin(request: BinUnblockRequest): Observable<BinBlockEvent> {
+    return this.http.post<BinBlockEvent>(`${this.baseUrl}/putaway/admin/bins/unblock`, request);
+  }
+
+  /**
+   * Bins currently blocked for putaway, optionally for one location
+   */
+  getBlockedBins(locationKey?: string): Observable<ApiResponse<BinBlock[]>> {
+    const params = locationKey ? new HttpParams().set('location', locationKey) : new HttpParams();
+
+    return this.http.get<ApiResponse<BinBlock[]>>(`${this.baseUrl}/putaway/reports/blocked-bins`, { params });
+  }
+
+  /**
+   * Who blocked and unblocked a bin, newest first
+   */
+  getBinBlockHistory(location: string, binNo: string): Observable<ApiResponse<BinBlockEvent[]>> {
+    return this.http.get<ApiResponse<BinBlockEvent[]>>(
+      `${this.baseUrl}/putaway/reports/bin-blocks/${encodeURIComponent(location)}/${encodeURIComponent(binNo)}/history`
+    );
   }
 
   // ========================================================================================
@@ -255,6 +403,13 @@ export class PutawayService {
     return this.http.get<BinValidationResponse>(`${this.baseUrl}/putaway/bin/${location}/${binNo}`);
   }
 
+  /**
+   * Everything in a bin: BINMaster attributes and every lot with stock
+   */
+  getBinContents(location: string, binNo: string): Observable<BinContents> {
+    return this.http.get<BinContents>(`${this.baseUrl}/putaway/bin/${location}/${binNo}/contents`);
+  }
+
   /**
    * Execute bin transfer using clean API
    * Replicates official app BT-25268027 transaction pattern
@@ -322,6 +477,28 @@ export class PutawayService {
     return this.http.get<PaginatedBinSearchResponse>(`${this.baseUrl}/putaway/bins/search`, { params });
   }
 
+  /**
+   * Ranked destination bins for a lot: consolidation targets first, then empty bins near binFrom
+   */
+  suggestBins(
+    lotContext: { lot_no: string; item_key: string; location: string },
+    binFrom?: string,
+    limit?: number
+  ): Observable<BinSuggestionResponse> {
+    let params = new HttpParams()
+      .set('lot_no', lotContext.lot_no)
+      .set('item_key', lotContext.item_key)
+      .set('location', lotContext.location);
+    if (binFrom) {
+      params = params.set('bin_from', binFrom);
+    }
+    if (limit) {
+      params = params.set('limit', limit.toString());
+    }
+
+    return this.http.get<BinSuggestionResponse>(`${this.baseUrl}/putaway/bins/suggest`, { params });
+  }
+
   /**
    * Get putaway service health status
    */
@@ -366,6 +543,121 @@ export interface LotSearchResponse {
   item_description: string;
   uom: string;
   lot_status: string;
+  bins: LotBinStock[];
+  locations: LotLocationTotal[];
+}
+
+export interface LotBinStock {
+  location: string;
+  bin_no: string;
+  qty_on_hand: number;
+  qty_committed: number;
+  qty_available: number;
+  lot_status: string;
+  expiry_date?: string;
+}
+
+export interface LotLocationTotal {
+  location: string;
+  bin_count: number;
+  qty_on_hand: number;
+  qty_committed: number;
+  qty_available: number;
+}
+
+export interface BinSuggestion {
+  bin_no: string;
+  location: string;
+  description: string;
+  aisle: string;
+  row: string;
+  rack: string;
+  lot_status?: string;
+  score: number;
+  reasons: { points: number; reason: string }[];
+}
+
+export interface BinSuggestionResponse {
+  lot_no: string;
+  item_key: string;
+  location: string;
+  bin_from: string | null;
+  suggestions: BinSuggestion[];
+}
+
+export interface BinContents {
+  location: string;
+  bin_no: string;
+  description: string;
+  aisle: string;
+  row: string;
+  rack: string;
+  lots: BinContentLot[];
+}
+
+export interface BinContentLot {
+  lot_no: string;
+  item_key: string;
+  item_description: string;
+  uom: string;
+  qty_on_hand: number;
+  qty_committed: number;
+  qty_available: number;
+  date_received?: string;
+  expiry_date?: string;
+  lot_status: string;
+}
+
+export type CapacityUnit = 'pallets' | 'weight' | 'quantity';
+
+export interface BinSpace {
+  bin_no: string;
+  location: string;
+  description: string;
+  unit: CapacityUnit;
+  capacity: number;
+  occupied: number;
+  available_space: number;
+  lot_count: number;
+}
+
+export interface BinCapacityRequest {
+  location: string;
+  bin_no: string;
+  unit: CapacityUnit;
+  capacity: number;
+}
+
+export interface BinBlock {
+  location: string;
+  bin_no: string;
+  reason: string;
+  blocked_until?: string;
+  rec_user_id: string;
+  rec_date: string;
+}
+
+export interface BinBlockEvent {
+  action: 'BLOCK' | 'UNBLOCK';
+  location: string;
+  bin_no: string;
+  reason: string;
+  blocked_until?: string;
+  user_id: string;
+  event_date: string;
+}
+
+export interface BinBlockRequest {
+  location: string;
+  bin_no: string;
+  reason: string;
+  blocked_until?: string; // Bangkok time, e.g. 2026-10-17T06:00:00
+}
+
+export interface BinUnblockRequest {
+  location: string;
+  bin_no: string;
+  reason?: string;
 }
 
 export interface BinValidationResponse {
@@ -381,10 +673,15 @@ export interface BinTransferRequest {
   location: string;
   bin_from: string;
   bin_to: string;
+  /** Quantity counted in `uom` */
   transfer_qty: number;
+  /** UOM the quantity was counted in (bags, cartons...); the item's stock UOM when omitted */
+  uom?: string;
   user_id: string;
   remarks?: string;
   referenced?: string;
+  /** Move past the destination bin's capacity (capacity_override permission) */
+  override_capacity?: boolean;
 }
 
 export interface TransactionResponse {
@@ -394,6 +691,12 @@ export interface TransactionResponse {
   timestamp: string;
   source_lot_status?: string;
   destination_lot_status?: string;
+  /** Quantity and UOM as entered */
+  entered_qty: number;
+  entered_uom: string;
+  /** Quantity moved, converted to the stock UOM and rounded to its decimals */
+  transfer_qty: number;
+  stock_uom: string;
 }
 
 export interface HealthResponse {
@@ -426,6 +729,8 @@ export interface BinSearchItem {
   row: string;
   rack: string;
   lot_status?: string; // Optional: lot status if this bin contains the specified lot
+  blocked: boolean;
+  block_reason?: string;
 }
 
 export interface PaginatedBinSearchResponse {
@@ -459,6 +764,7 @@ export interface CommittedTransferRequest {
   user_id: string;
   remarks?: string;
   referenced?: string;
+  lot_tran_nos: number[];
 }
 
 export interface CommittedTransferResult {